@@ -0,0 +1,159 @@
+//! Checks of the config against the history (`audit` subcommand)
+
+use git2::Repository;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::commit_scopes::{get_scope_map, CommitScope};
+use crate::config::{scopes_to_toml, Config};
+use crate::error::Result;
+use crate::scope_name::IgnoredScopes;
+
+/// Difference between the scopes declared in the config and the ones used in the history
+#[derive(Debug, Serialize, Eq, PartialEq)]
+pub struct ScopeAudit {
+    /// Used in the history, missing from the config
+    pub undeclared: Vec<String>,
+    /// Declared in the config, never used in the history
+    pub unused: Vec<String>,
+    /// `[scopes]` table with the undeclared scopes, to be pasted into the config
+    pub suggested_config: String,
+}
+
+impl ScopeAudit {
+    pub fn is_in_sync(&self) -> bool {
+        self.undeclared.is_empty() && self.unused.is_empty()
+    }
+}
+
+/// Compares the configured scopes with the ones from the history (or the cache). Ignored scopes
+/// are not reported
+pub fn audit_scopes(repo: &Repository, config: &Option<Config>) -> Result<ScopeAudit> {
+    let history = get_scope_map(repo, config)?
+        .unwrap_or_default()
+        .into_keys()
+        .map(|scope| scope.name)
+        .collect_vec();
+    let ignored = IgnoredScopes::from_config(config)?;
+    let declared = config
+        .as_ref()
+        .and_then(|c| c.commit_scopes.as_ref())
+        .map(|scopes| {
+            scopes
+                .iter()
+                .map(|scope| scope.name.clone())
+                .filter(|name| !ignored.contains(name))
+                .collect_vec()
+        })
+        .unwrap_or_default();
+
+    let undeclared = history
+        .iter()
+        .filter(|name| !declared.contains(name))
+        .cloned()
+        .sorted()
+        .collect_vec();
+    let unused = declared
+        .iter()
+        .filter(|name| !history.contains(name))
+        .cloned()
+        .sorted()
+        .collect_vec();
+    let suggested_config = match undeclared.is_empty() {
+        true => String::new(),
+        false => scopes_to_toml(&undeclared.iter().map(CommitScope::new).collect_vec()),
+    };
+
+    Ok(ScopeAudit {
+        undeclared,
+        unused,
+        suggested_config,
+    })
+}
+
+/// Human readable report
+pub fn to_text(audit: &ScopeAudit) -> String {
+    if audit.is_in_sync() {
+        return "Scopes in the config match the history\n".to_string();
+    }
+
+    let mut res = String::new();
+    if !audit.undeclared.is_empty() {
+        res.push_str("Used in the history, missing from the config:\n");
+        audit
+            .undeclared
+            .iter()
+            .for_each(|name| res.push_str(&format!("  {}\n", name)));
+    }
+    if !audit.unused.is_empty() {
+        res.push_str("Declared in the config, never used in the history:\n");
+        audit
+            .unused
+            .iter()
+            .for_each(|name| res.push_str(&format!("  {}\n", name)));
+    }
+    if !audit.suggested_config.is_empty() {
+        res.push_str("\nSuggested config additions:\n\n");
+        res.push_str(&audit.suggested_config);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    #[test]
+    fn audit() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &["init", "feat(cli): a", "fix(cache): b", "fix(tmp): c"],
+        );
+        let config = Config::from_str(indoc! {r#"
+            [scopes]
+            cli = "Command line"
+            docs = "Documentation"
+
+            [general.scopes]
+            ignored = ["tmp"]
+        "#})
+        .unwrap();
+
+        let audit = audit_scopes(&repo, &Some(config)).unwrap();
+
+        assert_eq!(audit.undeclared, vec!["cache"]);
+        assert_eq!(audit.unused, vec!["docs"]);
+        assert_eq!(audit.suggested_config, "[scopes]\ncache = \"\"\n");
+        assert_eq!(
+            to_text(&audit),
+            indoc! {r#"
+                Used in the history, missing from the config:
+                  cache
+                Declared in the config, never used in the history:
+                  docs
+
+                Suggested config additions:
+
+                [scopes]
+                cache = ""
+            "#}
+        );
+    }
+
+    #[test]
+    fn in_sync() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(cli): a"]);
+        let config = Config::from_str("[scopes]\ncli = \"\"\n").unwrap();
+
+        let audit = audit_scopes(&repo, &Some(config)).unwrap();
+
+        assert!(audit.is_in_sync());
+        assert_eq!(audit.suggested_config, "");
+    }
+}
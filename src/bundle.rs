@@ -0,0 +1,253 @@
+//! Everything the program knows about the repository's commit conventions in a single file.
+//!
+//! The bundle is meant to be carried between machines or attached to the onboarding docs: it
+//! holds the effective config, the mined scopes, the type statistics and the unreleased changes.
+//! Importing it adds the configured scopes and types to the repository config and seeds the cache
+//! so that the history does not have to be mined from scratch.
+
+use chrono::{DateTime, Utc};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::cache::{count_changes, seed_cache_for_repo, ScopeCounts};
+use crate::changelog;
+use crate::commit_scopes::commit::HistoryLimits;
+use crate::commit_scopes::CommitScope;
+use crate::commit_types::CommitType;
+use crate::config::{add_scopes_to_file, add_types_to_file, Config};
+use crate::error::{Error, Result};
+use crate::stats::{get_type_counts, TypeCount};
+use crate::utils::time;
+use crate::versioning;
+
+/// Version of the bundle format, bumped on incompatible changes
+pub const BUNDLE_FORMAT: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Bundle {
+    pub format: u32,
+    /// Version of the program that produced the bundle
+    pub producer: String,
+    pub created: DateTime<Utc>,
+    /// Commit the history was mined at
+    pub head: String,
+    /// Effective (merged) config, if there was any
+    pub config: Option<Config>,
+    /// Scopes mined from the history: number of commits and how many of them changed each file
+    pub scopes: BTreeMap<String, ScopeCounts>,
+    /// Commit types used in the history, most used first
+    pub types: Vec<TypeCount>,
+    /// Changelog of the commits since the latest release
+    pub changelog: String,
+}
+
+/// What [`import_bundle`] did
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ImportSummary {
+    pub added_scopes: Vec<CommitScope>,
+    pub added_types: Vec<CommitType>,
+    /// Whether the cache entry was created from the bundle
+    pub cache_seeded: bool,
+}
+
+pub fn create_bundle(repo: &Repository, config: &Option<Config>) -> Result<Bundle> {
+    let head = repo.head()?.target().ok_or_else(|| {
+        Error::RepoUnsupported(
+            "HEAD reference has no target. Are there commits in this repository?".to_string(),
+        )
+    })?;
+
+    let changelog_config = config
+        .as_ref()
+        .map(|c| c.changelog.clone())
+        .unwrap_or_default();
+    let template =
+        changelog::get_template(&changelog_config, &changelog::template_dir(repo, None))?;
+    let tag_pattern = versioning::get_target(config, None).tag_pattern();
+    let range = changelog::resolve_range(repo, None, tag_pattern.as_deref())?;
+    let release = changelog::Release {
+        version: "Unreleased".to_string(),
+        date: time::now().format("%Y-%m-%d").to_string(),
+        range: range.clone(),
+        compare_url: None,
+    };
+    let entries = changelog::get_entries(repo, config, range.as_deref())?;
+
+    Ok(Bundle {
+        format: BUNDLE_FORMAT,
+        producer: env!("CARGO_PKG_VERSION").to_string(),
+        created: time::now(),
+        head: head.to_string(),
+        config: config.clone(),
+        scopes: count_changes(
+            repo,
+            config,
+            head,
+            HistoryLimits::from_config(repo, config)?,
+        )?
+        .into_iter()
+        .map(|(scope, counts)| (scope.name, counts))
+        .collect(),
+        types: get_type_counts(repo, config, None)?,
+        changelog: changelog::render(&template, &release, &entries),
+    })
+}
+
+pub fn write_bundle(bundle: &Bundle, path: &Path) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(bundle)?).map_err(|source| Error::FileWrite {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub fn read_bundle(path: &Path) -> Result<Bundle> {
+    let content = std::fs::read_to_string(path).map_err(|source| Error::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let invalid = |message: String| Error::InvalidFile {
+        path: path.to_path_buf(),
+        message,
+    };
+    let not_a_bundle = |_| invalid("not a bundle".to_string());
+    let value: serde_json::Value = serde_json::from_str(&content).map_err(not_a_bundle)?;
+
+    // Checked first, other versions may have a different shape
+    match value.get("format").and_then(|f| f.as_u64()) {
+        Some(format) if format == BUNDLE_FORMAT as u64 => {}
+        Some(format) => {
+            return Err(invalid(format!(
+                "bundle format {} is not supported (expected {}), re-export it with this version",
+                format, BUNDLE_FORMAT
+            )))
+        }
+        None => return Err(invalid("not a bundle".to_string())),
+    }
+
+    serde_json::from_value(value).map_err(not_a_bundle)
+}
+
+/// Adds the scopes and types configured in the bundle to the config file (existing ones are kept)
+/// and seeds the cache of the repository with the bundle's scopes
+pub fn import_bundle(
+    repo: &Repository,
+    config: &Option<Config>,
+    bundle: Bundle,
+    config_path: &Path,
+) -> Result<ImportSummary> {
+    let bundle_config = bundle.config.unwrap_or_default();
+    let added_scopes = match bundle_config.commit_scopes {
+        Some(scopes) if !scopes.is_empty() => add_scopes_to_file(config_path, &scopes)?,
+        _ => Vec::new(),
+    };
+    let added_types = match bundle_config.commit_types {
+        Some(types) if !types.is_empty() => add_types_to_file(config_path, &types)?,
+        _ => Vec::new(),
+    };
+
+    let scope_counts: HashMap<CommitScope, ScopeCounts> = bundle
+        .scopes
+        .into_iter()
+        .map(|(name, counts)| (CommitScope::new(name), counts))
+        .collect();
+    let cache_seeded = seed_cache_for_repo(repo, config, &bundle.head, scope_counts)?;
+
+    Ok(ImportSummary {
+        added_scopes,
+        added_types,
+        cache_seeded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_CONFIG_PATH_IN_REPO;
+    use crate::test_utils::{setup_config_file_in_path, setup_repo_with_commits_and_files};
+    use indoc::indoc;
+    use testdir::testdir;
+
+    #[test]
+    fn roundtrip() {
+        let dir = testdir!();
+        let source_dir = dir.join("source");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        let repo = setup_repo_with_commits_and_files(
+            &source_dir,
+            &["init", "feat(foo): a", "fix(foo): b"],
+            &["init", "one", "two"],
+        );
+        setup_config_file_in_path(
+            &source_dir,
+            indoc! {r#"
+                [scopes]
+                bar = "Configured scope"
+                "#},
+        );
+        let config = Config::load(&repo, None).unwrap();
+
+        let bundle = create_bundle(&repo, &config).unwrap();
+        assert_eq!(bundle.scopes["foo"].commits, 2);
+        assert_eq!(
+            bundle.types,
+            vec![
+                TypeCount {
+                    commit_type: "feat".to_string(),
+                    commits: 1
+                },
+                TypeCount {
+                    commit_type: "fix".to_string(),
+                    commits: 1
+                },
+            ]
+        );
+        assert!(
+            bundle.changelog.contains("Unreleased"),
+            "{}",
+            bundle.changelog
+        );
+
+        let path = dir.join("bundle.json");
+        write_bundle(&bundle, &path).unwrap();
+        let read = read_bundle(&path).unwrap();
+        assert_eq!(read, bundle);
+
+        // Same repository, cloned elsewhere
+        let target_dir = dir.join("target");
+        let target = Repository::clone(source_dir.to_str().unwrap(), &target_dir).unwrap();
+        let target_config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let config_path = target_dir.join(DEFAULT_CONFIG_PATH_IN_REPO);
+        let summary = import_bundle(&target, &target_config, read, &config_path).unwrap();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added_scopes: vec![CommitScope::with_description("bar", "Configured scope")],
+                added_types: vec![],
+                cache_seeded: true,
+            }
+        );
+        let cache = crate::cache::Cache::load(&dir.join("cache.bin")).unwrap();
+        let entry = cache.get_scopes_for_repo(&target).unwrap();
+        assert_eq!(entry.head_commit_hash, bundle.head);
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let dir = testdir!();
+        let path = dir.join("bundle.json");
+        std::fs::write(&path, r#"{"format": 999}"#).unwrap();
+
+        let err = read_bundle(&path).unwrap_err();
+        assert!(err.to_string().contains("format 999"), "{}", err);
+    }
+}
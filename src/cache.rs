@@ -4,13 +4,17 @@
 //
 // 1. Cache lifecycle:
 //     - Creation is explicit by using a dedicated subcommand ($bin cache create)
-//     - Updates are emergent (recreate the whole darn thing)
+//     - Updates are emergent. Only the difference between the cached HEAD and the current one is
+//       mined: commits no longer reachable (rebase, reset) are dropped, new ones are added. If the
+//       cached HEAD is gone -- the whole darn thing is recreated
 //     - Destruction is explicit ($bin cache drop $repo_path)
 //     - Destruction should have a mode to nuke the whole cache ($bin cache nuke)
 //
 // 2. Cache usage:
 //     - before trying to scan through commit history, the commit_scopes logic will look for cache
 //     - if cache exists -- it will be assumed to be usable as is and not updated
+//     - entries remember the hash of the settings they were mined with, changing them makes the
+//       entry stale and it's mined again from scratch
 //
 //     => cache is read-only from non-`cache` subcommand
 //
@@ -18,25 +22,33 @@
 //     - One cache for all repositories
 //     - Data model:
 //
-//         <path to repo> OtM <scopes> OtM <changed files>
+//         <root commit of repo> OtM <scopes> OtM <changed files>
+//
+//         Scopes and files carry the number of commits they were seen in, so that commits can be
+//         dropped from the entry. Scopes also carry the time of the latest commit, for ranking.
 //
 //         repos don't have any relationship to each other, so this is basically a forest of
-//         isolated trees. Repo will be identified by the path.
+//         isolated trees. Repo is identified by the OID of its root commit.
 //
-//         Repo path: identification of the repository by path is not ideal and may break when
-//         dealing with symlinks or what have you
+//         Root commit: identification by path broke when dealing with symlinks, moved checkouts
+//         and linked worktrees. The root commit is the same for all of them. Entries still store
+//         the path they were last updated from, for display and pruning. Finding the root commit
+//         walks the whole history, so an entry last updated from the same path is used as is.
 //
-//         In the initial approach, cache is dropped for the whole repo, so I don't need to track
-//         individual commits. Later I might change this.
+//         Caches written before keyed the entries by path, they are migrated when loaded and saved
+//         right away, so that the repositories are walked once (see `LegacyCache`).
 //
 //         This data model maps well to what logic in `commit.rs` does currently and should be
 //         easier to implement.
 //
-//         For future I might also consider moving to <path to repo> OtM <changed files> OtM <scopes>.
+//         For future I might also consider moving to <repo> OtM <changed files> OtM <scopes>.
 //
 //
 // 4. Misc:
 //     - Cache is to be stored centrally in $XDG_CACHE_HOME
+//     - Alternatively, in a system-wide directory shared by all users of a machine (i.e. build
+//       servers with large checkouts). The directory is group-writable and setgid, the cache file is
+//       group-writable. Entries record the user that last updated them
 //         Potential alternative: store it in `.git/` dir
 //
 //         Pros:
@@ -49,68 +61,384 @@
 // First approach will use `serde`+`bincode` to store cache on disk. I have used serde before,
 // should be easier to get started
 
-use anyhow::{bail, Context, Result};
 use directories::ProjectDirs;
-use git2::Repository;
-use log::{debug, info, trace};
+use git2::{Oid, Repository, Sort};
+use itertools::Itertools;
+use log::{debug, info, trace, warn};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
-use crate::commit_scopes::commit::{get_scopes_x_changes, ChangedFiles};
+use crate::commit_message::pseudo_types_config;
+use crate::commit_scopes::commit::{
+    commit_time, get_changed_files_from_commit, get_commit_changes, get_scope_from_summary,
+    resolve_since_ref, ChangedFiles, HistoryLimits, ScopeChange,
+};
 use crate::commit_scopes::CommitScope;
+use crate::config::{CacheLocation, Config, DEFAULT_LOCK_RETRY_MS, DEFAULT_LOCK_TIMEOUT_MS};
+use crate::error::{Error, Result};
+use crate::scope_name::ScopeNameValidator;
+use crate::utils::{common_dir, head_commit, head_is_transient};
 
 use chrono::{DateTime, Utc};
 
 // Data Structures for the Cache
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CacheEntry {
-    pub scopes: HashMap<CommitScope, ChangedFiles>,
+    pub scope_counts: HashMap<CommitScope, ScopeCounts>,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub timestamp: DateTime<Utc>,
     pub head_commit_hash: String,
+    pub fingerprint: RepoFingerprint,
+    /// User that last updated the entry. Useful when the cache is shared
+    pub owner: String,
+    /// [`config_hash`] of the settings the entry was mined with
+    pub config_hash: u64,
+    /// Working directory the entry was last updated from
+    pub path: PathBuf,
+}
+
+impl CacheEntry {
+    /// Scope -> changed files view of the entry
+    pub fn scopes(&self) -> HashMap<CommitScope, ChangedFiles> {
+        scope_files(&self.scope_counts)
+    }
 }
 
-/// Repo identifier in the cache.
+/// Number of commits in a scope and how many of them changed each file.
 ///
-/// Path to the repository seems like a good first approach.
-type RepoID = PathBuf;
+/// Counting (instead of just collecting the files) allows dropping commits from the entry, i.e.
+/// after a rebase, without mining the whole history again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScopeCounts {
+    pub commits: usize,
+    pub files: HashMap<String, usize>,
+    /// Commit time of the latest commit in the scope. Not moved back when commits are dropped, so
+    /// it may be newer than the remaining ones until the entry is mined again
+    #[serde(default, with = "chrono::serde::ts_seconds")]
+    pub last_used: DateTime<Utc>,
+}
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Scope -> changed files view of the counts
+pub fn scope_files(
+    scope_counts: &HashMap<CommitScope, ScopeCounts>,
+) -> HashMap<CommitScope, ChangedFiles> {
+    scope_counts
+        .iter()
+        .map(|(scope, counts)| (scope.clone(), counts.files.keys().cloned().collect()))
+        .collect()
+}
+
+pub(crate) fn add_changes(
+    scope_counts: &mut HashMap<CommitScope, ScopeCounts>,
+    changes: Vec<ScopeChange>,
+) {
+    for ScopeChange { scope, files, time } in changes {
+        let counts = scope_counts.entry(scope).or_default();
+        counts.commits += 1;
+        counts.last_used = counts.last_used.max(time);
+        for file in files {
+            *counts.files.entry(file).or_default() += 1;
+        }
+    }
+}
+
+fn remove_changes(scope_counts: &mut HashMap<CommitScope, ScopeCounts>, changes: Vec<ScopeChange>) {
+    for ScopeChange { scope, files, .. } in changes {
+        let Some(counts) = scope_counts.get_mut(&scope) else {
+            continue;
+        };
+        counts.commits = counts.commits.saturating_sub(1);
+        for file in files {
+            if let Some(count) = counts.files.get_mut(&file) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        counts.files.retain(|_, count| *count > 0);
+        if counts.commits == 0 {
+            scope_counts.remove(&scope);
+        }
+    }
+}
+
+/// Hash of the settings the history is mined with: the pseudo-types, the scope name rules and the
+/// history limits. An entry mined with other settings is stale and is mined again from scratch.
+/// Settings applied when the entry is read (i.e. ignored scopes) don't invalidate it.
+///
+/// `since_ref` is hashed as the commit it resolves to, so a new release tag with "latest-tag"
+/// invalidates the entry.
+///
+/// The hash is only stable within a build, a new version may mine the history once more.
+pub fn config_hash(repo: &Repository, config: &Option<Config>) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    pseudo_types_config(config).hash(&mut hasher);
+    config
+        .as_ref()
+        .map(|c| c.scope_names.clone())
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    // Only if set, so that the entries mined without the limits stay valid
+    let limits = config
+        .as_ref()
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.scopes.as_ref())
+        .map(|s| {
+            let stop_at = match s.since_ref.as_deref() {
+                Some(rev) => resolve_since_ref(repo, config, rev)?.map(|oid| oid.to_string()),
+                None => None,
+            };
+            Ok::<_, Error>((s.max_commits, s.since.clone(), stop_at))
+        })
+        .transpose()?
+        .filter(|limits| *limits != (None, None, None));
+    if let Some(limits) = limits {
+        limits.hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+/// Identifies the repository regardless of its location on disk. Used when the repository is not
+/// found in the cache by path, i.e. if the checkout was moved.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct RepoFingerprint {
+    /// OID of the first root commit reachable from HEAD
+    pub root_commit: String,
+    /// URL of the "origin" remote, if there is one
+    pub remote_url: Option<String>,
+}
+
+/// OID of the first root commit reachable from HEAD. Walks the whole history
+fn root_commit(repo: &Repository) -> Result<String> {
+    let head = head_commit(repo)?
+        .ok_or_else(|| Error::RepoUnsupported("Repository has no commits".to_string()))?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(head)?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    let root_commit = revwalk
+        .next()
+        .ok_or_else(|| Error::RepoUnsupported("Repository has no commits".to_string()))??;
+
+    Ok(root_commit.to_string())
+}
+
+impl RepoFingerprint {
+    pub fn from_repo(repo: &Repository) -> Result<Self> {
+        let root_commit = root_commit(repo)?;
+        let remote_url = repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(|url| url.to_string()));
+
+        Ok(Self {
+            root_commit,
+            remote_url,
+        })
+    }
+}
+
+/// Repo identifier in the cache: OID of the root commit, the same for all the checkouts of the
+/// repository
+type RepoID = String;
+
+/// Leading bytes of the cache file since the entries are keyed by the root commit. Files without
+/// them are read as [`LegacyCache`]
+const CACHE_MAGIC: &[u8] = b"CCH-CACHE-2";
+
+/// Cache of the releases before [`CACHE_MAGIC`]: entries keyed by the path of the repository,
+/// scopes with just the files they changed
+#[derive(Serialize, Deserialize)]
+struct LegacyCache {
+    entries: HashMap<PathBuf, LegacyCacheEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LegacyCacheEntry {
+    scopes: HashMap<CommitScope, ChangedFiles>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    timestamp: DateTime<Utc>,
+    head_commit_hash: String,
+}
+
+impl From<LegacyCache> for Cache {
+    /// Re-keys the entries by the root commit of the repository at their path, entries of the
+    /// repositories that can't be opened anymore are dropped. Of the entries of several checkouts
+    /// of the same repository, the most recent one is kept.
+    ///
+    /// The number of commits was not stored, so every file counts once. The entries are marked as
+    /// mined with other settings, so that they are mined again on first use
+    fn from(legacy: LegacyCache) -> Self {
+        info!("Migrating the cache entries keyed by the path");
+        let entries = legacy
+            .entries
+            .into_iter()
+            .sorted_by_key(|(_, entry)| entry.timestamp)
+            .filter_map(|(path, entry)| {
+                let fingerprint = Repository::open(&path)
+                    .map_err(Error::from)
+                    .and_then(|repo| RepoFingerprint::from_repo(&repo));
+                let fingerprint = match fingerprint {
+                    Ok(fingerprint) => fingerprint,
+                    Err(e) => {
+                        debug!("Dropping the cache entry of {:?}: {:?}", path, e);
+                        return None;
+                    }
+                };
+                let scope_counts = entry
+                    .scopes
+                    .into_iter()
+                    .map(|(scope, files)| {
+                        let counts = ScopeCounts {
+                            commits: 1,
+                            files: files.into_iter().map(|file| (file, 1)).collect(),
+                            last_used: entry.timestamp,
+                        };
+                        (scope, counts)
+                    })
+                    .collect();
+                Some((
+                    fingerprint.root_commit.clone(),
+                    CacheEntry {
+                        scope_counts,
+                        timestamp: entry.timestamp,
+                        head_commit_hash: entry.head_commit_hash,
+                        fingerprint,
+                        owner: current_user(),
+                        config_hash: 0,
+                        path: path.components().collect(),
+                    },
+                ))
+            })
+            .collect();
+
+        Self { entries }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Cache {
-    // Mapping of <repo path> OtM <cache entry>
+    // Mapping of <root commit> OtM <cache entry>
     pub entries: HashMap<RepoID, CacheEntry>,
 }
 
 impl Cache {
     /// Returns None if cache does not exist
-    pub fn load() -> Result<Self> {
-        let cache_path = get_cache_path()?;
+    pub fn load(cache_path: &Path) -> Result<Self> {
         if cache_path.exists() {
-            let data = std::fs::read(cache_path)?;
-            let cache: Cache = bincode::deserialize(&data)?;
-            Ok(cache)
+            let data = std::fs::read(cache_path).map_err(|source| Error::CacheRead {
+                path: cache_path.to_path_buf(),
+                source,
+            })?;
+            let corrupt = |source| Error::CacheCorrupt {
+                path: cache_path.to_path_buf(),
+                source,
+            };
+            match data.strip_prefix(CACHE_MAGIC) {
+                Some(data) => bincode::deserialize(data).map_err(corrupt),
+                // Nothing in an old cache is worth failing for, it is recreated on the next save
+                None => match bincode::deserialize::<LegacyCache>(&data) {
+                    Ok(legacy) => {
+                        let cache = Cache::from(legacy);
+                        cache.save_migrated(cache_path);
+                        Ok(cache)
+                    }
+                    Err(e) => {
+                        info!("Dropping the unreadable cache without the header: {:?}", e);
+                        Ok(Cache::new())
+                    }
+                },
+            }
         } else {
-            bail!("Cache does not exist")
+            Err(Error::CacheNotFound)
         }
     }
 
-    pub fn lock() -> Result<()> {
+    /// Takes the lock of the cache, to be held across loading, modifying and saving it. If
+    /// another process holds it, retries every `cache.lock_retry_ms` for up to
+    /// `cache.lock_timeout_ms`
+    pub fn lock(cache_path: &Path, config: &Option<Config>) -> Result<CacheLock> {
         trace!("Acquiring lock on the cache");
-        let cache_path = get_cache_path()?;
-        let options = file_lock::FileOptions::new().write(true).create(true);
-        let _ = file_lock::FileLock::lock(&cache_path, false, options)
-            .context("Failed to acquire cache file lock")?;
+        let cache_config = config.as_ref().map(|c| &c.cache);
+        let timeout_ms = cache_config
+            .and_then(|c| c.lock_timeout_ms)
+            .unwrap_or(DEFAULT_LOCK_TIMEOUT_MS);
+        let retry = Duration::from_millis(
+            cache_config
+                .and_then(|c| c.lock_retry_ms)
+                .unwrap_or(DEFAULT_LOCK_RETRY_MS),
+        );
+        let lock_path = lock_path(cache_path);
 
-        Ok(())
+        let created = !lock_path.exists();
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        loop {
+            let options = file_lock::FileOptions::new().write(true).create(true);
+            match file_lock::FileLock::lock(&lock_path, false, options) {
+                Ok(lock) => {
+                    if created && get_cache_location(config) == CacheLocation::System {
+                        set_shared_permissions(&lock_path)?;
+                    }
+                    held_locks().push(lock_path.clone());
+                    return Ok(CacheLock {
+                        _lock: lock,
+                        path: lock_path,
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::CacheLocked {
+                            path: cache_path.to_path_buf(),
+                            timeout_ms,
+                        });
+                    }
+                    debug!("Cache is locked by another process, waiting");
+                    std::thread::sleep(retry.min(deadline - now));
+                }
+                Err(e) => return Err(Error::CacheLock(e)),
+            }
+        }
     }
 
-    pub fn save(&self) -> Result<()> {
-        let cache_path = get_cache_path()?;
-        let data = bincode::serialize(self)?;
-        std::fs::write(cache_path, data)?;
-        Ok(())
+    /// Saves the cache migrated from the old format, so that the repositories are not walked again
+    /// on the next load. Best effort: if another process holds the lock (it's likely to save the
+    /// cache anyway) or the cache is not writable, it stays in the old format
+    fn save_migrated(&self, cache_path: &Path) {
+        let lock_path = lock_path(cache_path);
+        // Taking the lock held by this process again would release it on drop
+        let _lock = match held_locks().contains(&lock_path) {
+            true => None,
+            false => {
+                let options = file_lock::FileOptions::new().write(true).create(true);
+                match file_lock::FileLock::lock(&lock_path, false, options) {
+                    Ok(lock) => Some(lock),
+                    Err(e) => {
+                        info!("Not saving the migrated cache, no lock: {}", e);
+                        return;
+                    }
+                }
+            }
+        };
+        match self.save(cache_path) {
+            Ok(()) => info!("Migrated cache saved"),
+            Err(e) => info!("Could not save the migrated cache: {:?}", e),
+        }
+    }
+
+    pub fn save(&self, cache_path: &Path) -> Result<()> {
+        let data = [
+            CACHE_MAGIC,
+            &bincode::serialize(self).map_err(Error::CacheSerialize)?,
+        ]
+        .concat();
+        std::fs::write(cache_path, data).map_err(|source| Error::CacheWrite {
+            path: cache_path.to_path_buf(),
+            source,
+        })
     }
 
     pub fn new() -> Self {
@@ -119,41 +447,227 @@ impl Cache {
         }
     }
 
+    /// Looks up the entry of the repository, see [`Cache::repo_id`]
     pub fn get_scopes_for_repo(&self, repo: &Repository) -> Option<&CacheEntry> {
-        self.entries.get(&get_repo_id(repo))
+        self.entries.get(&self.repo_id(repo)?)
+    }
+
+    /// Key of the entry of the repository, None if it has no commits. The entry last updated from
+    /// the same path is taken if its HEAD is in the repository, so that the history is not walked
+    /// for the root commit on every lookup
+    pub fn repo_id(&self, repo: &Repository) -> Option<RepoID> {
+        let path = repo_path(repo);
+        let same_path = self.entries.iter().find(|(_, entry)| {
+            entry.path == path
+                && Oid::from_str(&entry.head_commit_hash)
+                    .is_ok_and(|head| repo.find_commit(head).is_ok())
+        });
+        if let Some((repo_id, _)) = same_path {
+            return Some(repo_id.clone());
+        }
+
+        debug!("Repository not found in the cache by path, looking up the root commit");
+        match root_commit(repo) {
+            Ok(root_commit) => Some(root_commit),
+            Err(e) => {
+                warn!("Could not find the root commit of the repository: {:?}", e);
+                None
+            }
+        }
     }
 }
 
 const CACHE_FILE: &str = "commit_scope_cache.bin";
 
+/// Exclusive lock of the cache (`<cache file>.lock` next to it), so that concurrent updates don't
+/// overwrite each other. Released on drop
+pub struct CacheLock {
+    _lock: file_lock::FileLock,
+    path: PathBuf,
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        held_locks().retain(|path| *path != self.path);
+    }
+}
+
+/// Lock files of the cache held by this process. The locks are per process (`fcntl`), so taking
+/// one of them again would release it when dropped
+static HELD_LOCKS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn held_locks() -> MutexGuard<'static, Vec<PathBuf>> {
+    HELD_LOCKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// `<cache file>.lock` next to the cache
+fn lock_path(cache_path: &Path) -> PathBuf {
+    let mut lock_name = cache_path.file_name().unwrap_or_default().to_os_string();
+    lock_name.push(".lock");
+    cache_path.with_file_name(lock_name)
+}
+
+/// Held while the entry of a repository is regenerated, so that concurrent requests (i.e. two
+/// shells completing at once) don't walk the whole history at the same time. Released on drop
+pub struct RegenerationLock {
+    _lock: Option<file_lock::FileLock>,
+}
+
+/// Tries to take the regeneration lock of the repository without waiting. Returns None if another
+/// process is regenerating the entry already.
+///
+/// The lock is per entry (see [`Cache::repo_id`]), so that the checkouts of the same repository
+/// don't regenerate it at once. The lock file lives next to the cache. If there is no cache
+/// directory or the repository has no entry, there is nothing to regenerate concurrently and no
+/// lock is taken.
+pub fn try_lock_regeneration(
+    cache: &Cache,
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<RegenerationLock>> {
+    let cache_path = get_cache_path(config)?;
+    let (Some(dir), Some(repo_id)) = (
+        cache_path.parent().filter(|dir| dir.is_dir()),
+        cache.repo_id(repo),
+    ) else {
+        return Ok(Some(RegenerationLock { _lock: None }));
+    };
+
+    let lock_path = dir.join(format!("regenerate-{}.lock", repo_id));
+
+    let created = !lock_path.exists();
+    let options = file_lock::FileOptions::new().write(true).create(true);
+    match file_lock::FileLock::lock(&lock_path, false, options) {
+        Ok(lock) => {
+            if created && get_cache_location(config) == CacheLocation::System {
+                set_shared_permissions(&lock_path)?;
+            }
+            Ok(Some(RegenerationLock { _lock: Some(lock) }))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(Error::CacheLock(e)),
+    }
+}
+
+/// Directory of the cache shared by all users (`cache.location = "system"`)
+const SYSTEM_CACHE_DIR: &str = "/var/cache/conventional-commit-helper";
+
+/// Directory of the cache inside the git directory, see [`repo_cache_path`]
+const REPO_CACHE_DIR: &str = "conventional-commit-helper";
+
+fn get_cache_location(config: &Option<Config>) -> CacheLocation {
+    config
+        .as_ref()
+        .map(|c| c.cache.location)
+        .unwrap_or_default()
+}
+
+fn is_read_only(config: &Option<Config>) -> bool {
+    config.as_ref().is_some_and(|c| c.cache.read_only)
+}
+
+/// Bails if the cache must not be written to
+fn ensure_writable(config: &Option<Config>) -> Result<()> {
+    if is_read_only(config) {
+        return Err(Error::CacheReadOnly);
+    }
+    Ok(())
+}
+
+/// Name of the user running the program, recorded in the cache entries
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Makes a freshly created part of the shared cache writable by the group, so that other users
+/// can update the cache. Directories also get setgid to keep the group of the files in them.
+#[cfg(unix)]
+fn set_shared_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = if path.is_dir() { 0o2775 } else { 0o664 };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(|source| {
+        Error::CacheWrite {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn set_shared_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Cache file of `cache.location = "repo"`: inside the git directory, so that it goes along with
+/// the checkout and is never committed. Linked worktrees use the one of the main repository
+pub fn repo_cache_path(repo: &Repository) -> PathBuf {
+    common_dir(repo).join(REPO_CACHE_DIR).join(CACHE_FILE)
+}
+
 /// Retrieve the cache path.
-/// Should be in XDG_CACHE_HOME.
-fn get_cache_path() -> Result<PathBuf> {
+/// Explicitly configured path wins, then the system-wide location if configured. Otherwise it
+/// should be in XDG_CACHE_HOME.
+///
+/// `cache.location = "repo"` is resolved into `cache.path` when the config is loaded, see
+/// [`repo_cache_path`]
+pub fn get_cache_path(config: &Option<Config>) -> Result<PathBuf> {
     debug!("Looking for the cache");
-    if let Some(proj_dirs) = ProjectDirs::from("com", "vtimofeenko", "conventional-commit-helper") {
+    if let Some(path) = config.as_ref().and_then(|c| c.cache.path.as_ref()) {
+        trace!("Cache path is set explicitly: '{:?}'", path);
+        Ok(path.clone())
+    } else if get_cache_location(config) == CacheLocation::Repo {
+        Err(Error::CacheLocationNoRepo)
+    } else if get_cache_location(config) == CacheLocation::System {
+        let res = Path::new(SYSTEM_CACHE_DIR).join(CACHE_FILE);
+        trace!("Using the system-wide cache: '{:?}'", res);
+        Ok(res)
+    } else if let Some(proj_dirs) =
+        ProjectDirs::from("com", "vtimofeenko", "conventional-commit-helper")
+    {
         let cache_dir = proj_dirs.cache_dir();
         let res = cache_dir.join(CACHE_FILE);
         trace!("Cache path: '{:?}'", res);
         Ok(res)
     } else {
-        bail!("Unable to get cache directory from XDG")
+        Err(Error::CacheLocationUnknown)
     }
 }
 
-fn get_repo_id(repo: &Repository) -> RepoID {
-    repo.path().parent().expect("Parent of repo's path should always exist unless the repo is bare. This might be a bug").to_path_buf()
+/// Working directory of the repository, the git directory of a bare one. Linked worktrees resolve
+/// to the main repository through the common git directory, so that they share its entry.
+/// Without the trailing slash
+fn repo_path(repo: &Repository) -> PathBuf {
+    let main_repo = match repo.is_worktree() {
+        true => Repository::open(common_dir(repo)).ok(),
+        false => None,
+    };
+    let repo = main_repo.as_ref().unwrap_or(repo);
+    repo.workdir().unwrap_or(repo.path()).components().collect()
 }
 
 /// Create the cache. It makes very little sense to create just an empty cache, so takes a repo.
-pub fn create_cache() -> Result<PathBuf> {
+pub fn create_cache(config: &Option<Config>) -> Result<PathBuf> {
     info!("Creating the cache");
-    let cache_path = get_cache_path()?;
+    ensure_writable(config)?;
+    let cache_path = get_cache_path(config)?;
+    let shared = get_cache_location(config) == CacheLocation::System;
 
     // Create parent directory if it does not exist
     if let Some(parent) = cache_path.parent() {
         if !parent.exists() {
             debug!("Creating the parent dir to contain the cache");
-            std::fs::create_dir_all(parent)?;
+            std::fs::create_dir_all(parent).map_err(|source| Error::CacheWrite {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+            if shared {
+                set_shared_permissions(parent)?;
+            }
         }
     }
 
@@ -161,84 +675,883 @@ pub fn create_cache() -> Result<PathBuf> {
     if !cache_path.exists() {
         info!("Creating empty cache");
         let cache = Cache::new();
-        cache.save()?;
+        cache.save(&cache_path)?;
+        if shared {
+            debug!("Making the shared cache writable by the group");
+            set_shared_permissions(&cache_path)?;
+        }
     }
 
     Ok(cache_path)
 }
 
-/// Update the cache for specific repo
-pub fn update_cache_for_repo(repo: &Repository) -> Result<()> {
-    let repo_id = get_repo_id(repo);
-    info!("Updating the scope cache for repo '{:?}'", repo_id);
+/// Mines the history reachable from `head`, as far back as the limits allow (i.e.
+/// [`HistoryLimits::from_config`])
+pub fn count_changes(
+    repo: &Repository,
+    config: &Option<Config>,
+    head: Oid,
+    limits: HistoryLimits,
+) -> Result<HashMap<CommitScope, ScopeCounts>> {
+    let mut scope_counts = HashMap::new();
+    add_changes(
+        &mut scope_counts,
+        get_commit_changes(repo, config, head, None, limits)?,
+    );
+    Ok(scope_counts)
+}
+
+/// Creates the cache entry of the repo from the counts mined elsewhere (i.e. on another machine)
+/// at `head`. The next update mines only the difference between `head` and the current HEAD.
+///
+/// Existing entries are left alone. The entry is not created if `head` is not in the repository.
+/// Returns whether the entry was created.
+pub fn seed_cache_for_repo(
+    repo: &Repository,
+    config: &Option<Config>,
+    head: &str,
+    scope_counts: HashMap<CommitScope, ScopeCounts>,
+) -> Result<bool> {
+    ensure_writable(config)?;
+    if Oid::from_str(head)
+        .and_then(|oid| repo.find_commit(oid))
+        .is_err()
+    {
+        info!(
+            "Commit {} is not in the repository, not seeding the cache",
+            head
+        );
+        return Ok(false);
+    }
+
+    let cache_path = create_cache(config)?;
+    let _lock = Cache::lock(&cache_path, config)?;
+    let mut cache = Cache::load(&cache_path)?;
+    if cache.get_scopes_for_repo(repo).is_some() {
+        info!("Repository is already cached, not seeding the cache");
+        return Ok(false);
+    }
+
+    let fingerprint = RepoFingerprint::from_repo(repo)?;
+    cache.entries.insert(
+        fingerprint.root_commit.clone(),
+        CacheEntry {
+            scope_counts,
+            timestamp: crate::utils::time::now(),
+            head_commit_hash: head.to_string(),
+            fingerprint,
+            owner: current_user(),
+            config_hash: config_hash(repo, config)?,
+            path: repo_path(repo),
+        },
+    );
+    cache.save(&cache_path)?;
+
+    Ok(true)
+}
+
+/// Point from which the entry can be updated without mining the whole history
+struct IncrementalBase {
+    cached_head: Oid,
+    /// Merge base of the cached HEAD and the current one. Same as the cached HEAD unless the
+    /// history was rewritten
+    merge_base: Oid,
+    scope_counts: HashMap<CommitScope, ScopeCounts>,
+}
+
+/// Returns None if the cached HEAD is gone (i.e. garbage collected after a rebase), has nothing
+/// in common with the current one or the entry was mined with other settings.
+///
+/// Also None with `max_commits` or `since`: the commits falling out of the window would stay in
+/// the entry, the window is mined again instead. It's not the whole history anyway.
+fn get_incremental_base(
+    repo: &Repository,
+    config: &Option<Config>,
+    entry: &CacheEntry,
+    head: Oid,
+    limits: HistoryLimits,
+) -> Option<IncrementalBase> {
+    if entry.config_hash != config_hash(repo, config).ok()? {
+        info!("Config changed since the entry was mined, mining the whole history");
+        return None;
+    }
+    if limits.max_commits.is_some() || limits.since.is_some() {
+        info!("History is mined within a window, mining it again");
+        return None;
+    }
+    let cached_head = Oid::from_str(&entry.head_commit_hash).ok()?;
+    repo.find_commit(cached_head).ok()?;
+    let merge_base = repo.merge_base(cached_head, head).ok()?;
+
+    Some(IncrementalBase {
+        cached_head,
+        merge_base,
+        scope_counts: entry.scope_counts.clone(),
+    })
+}
+
+/// Update the cache for specific repo, returning the updated counts.
+///
+/// If the cached HEAD is still around, only the difference is mined: commits that are no longer
+/// reachable from HEAD (i.e. after a rebase or a reset) are dropped from the entry and the new
+/// ones are added. Otherwise the whole history is mined.
+pub fn update_cache_for_repo(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<HashMap<CommitScope, ScopeCounts>> {
+    info!("Updating the scope cache for repo '{:?}'", repo_path(repo));
+    ensure_writable(config)?;
+    let cache_path = get_cache_path(config)?;
 
-    Cache::lock()?;
+    let _lock = Cache::lock(&cache_path, config)?;
 
     // Load the cache
-    let mut cache = Cache::load()?;
-
-    debug!("Getting scopes x changes from the repo");
-    let scopes_changes = get_scopes_x_changes(repo)?;
-
-    match scopes_changes {
-        Some(scopes_changes) => {
-            debug!("Writing scopes x changes into the cache");
-            cache.entries.insert(
-                repo_id,
-                CacheEntry {
-                    scopes: scopes_changes,
-                    timestamp: crate::utils::time::now(),
-                    head_commit_hash: repo
-                        .head()?
-                        .target()
-                        .ok_or_else(|| anyhow::anyhow!("HEAD reference has no target. Are there commits in this repository?"))?
-                        .to_string(),
-                },
+    let mut cache = Cache::load(&cache_path)?;
+
+    let head = head_commit(repo)?
+        .ok_or_else(|| Error::RepoUnsupported("Repository has no commits".to_string()))?;
+
+    let limits = HistoryLimits::from_config(repo, config)?;
+    let repo_id = cache.repo_id(repo);
+    let base = repo_id
+        .as_ref()
+        .and_then(|repo_id| cache.entries.get(repo_id))
+        .and_then(|entry| get_incremental_base(repo, config, entry, head, limits));
+
+    let scope_counts = match base {
+        Some(IncrementalBase {
+            cached_head,
+            merge_base,
+            mut scope_counts,
+        }) => {
+            if cached_head != merge_base {
+                info!(
+                    "Cached HEAD {} is not reachable from HEAD, dropping the commits since {}",
+                    cached_head, merge_base
+                );
+                remove_changes(
+                    &mut scope_counts,
+                    get_commit_changes(repo, config, cached_head, Some(merge_base), limits)?,
+                );
+            }
+            info!("Mining only the commits since {}", merge_base);
+            add_changes(
+                &mut scope_counts,
+                get_commit_changes(repo, config, head, Some(merge_base), limits)?,
             );
+            scope_counts
         }
         None => {
-            bail!("No scopes detected in the repo")
+            debug!("Getting scopes x changes from the repo");
+            count_changes(repo, config, head, limits)?
         }
     };
 
-    cache.save()?;
+    if scope_counts.is_empty() {
+        return Err(Error::NoScopes);
+    }
+
+    debug!("Writing scopes x changes into the cache");
+    let fingerprint = RepoFingerprint::from_repo(repo)?;
+    let entry = CacheEntry {
+        scope_counts,
+        timestamp: crate::utils::time::now(),
+        head_commit_hash: head.to_string(),
+        fingerprint,
+        owner: current_user(),
+        config_hash: config_hash(repo, config)?,
+        path: repo_path(repo),
+    };
+    let scope_counts = entry.scope_counts.clone();
+    // The entry found by the path may belong to another repository that was there before
+    if let Some(stale_id) = repo_id.filter(|repo_id| *repo_id != entry.fingerprint.root_commit) {
+        cache.entries.remove(&stale_id);
+    }
+    cache
+        .entries
+        .insert(entry.fingerprint.root_commit.clone(), entry);
+
+    cache.save(&cache_path)?;
     info!("Cache saved");
-    Ok(())
+    Ok(scope_counts)
+}
+
+/// Appends the HEAD commit to the cache entry of the repo. Meant to be run from the post-commit
+/// hook so that the cache stays fresh without full refreshes.
+///
+/// The entry is only updated if it was up to date before the commit (i.e. its HEAD is the parent
+/// of the current one). Otherwise it's left alone for the regular staleness check to handle.
+///
+/// Returns whether the entry was updated.
+pub fn append_head_to_cache(repo: &Repository, config: &Option<Config>) -> Result<bool> {
+    if is_read_only(config) {
+        info!("Cache is read-only, not updating it");
+        return Ok(false);
+    }
+    if head_is_transient(repo) {
+        info!("{:?} in progress, not updating the cache", repo.state());
+        return Ok(false);
+    }
+    let cache_path = get_cache_path(config)?;
+    if !cache_path.exists() {
+        info!("No cache, nothing to update");
+        return Ok(false);
+    }
+
+    let _lock = Cache::lock(&cache_path, config)?;
+    let mut cache = Cache::load(&cache_path)?;
+
+    let Some(entry) = cache
+        .repo_id(repo)
+        .and_then(|repo_id| cache.entries.get_mut(&repo_id))
+    else {
+        info!(
+            "Repo '{:?}' is not cached, nothing to update",
+            repo_path(repo)
+        );
+        return Ok(false);
+    };
+
+    let limits = HistoryLimits::from_config(repo, config)?;
+    if limits.max_commits.is_some() || limits.since.is_some() {
+        info!("History is mined within a window, leaving it to the staleness check");
+        return Ok(false);
+    }
+    let head = repo.head()?.peel_to_commit()?;
+    if head.parent_count() > 1 {
+        info!("HEAD is a merge, leaving the merged commits to the incremental update");
+        return Ok(false);
+    }
+    let parent_id = head.parent_id(0).ok().map(|id| id.to_string());
+    if parent_id.as_ref() != Some(&entry.head_commit_hash) {
+        info!("Cache entry does not point to the parent of HEAD, not updating it incrementally");
+        return Ok(false);
+    }
+    if entry.config_hash != config_hash(repo, config)? {
+        info!("Config changed since the entry was mined, not updating it incrementally");
+        return Ok(false);
+    }
+
+    let summary = head.summary().unwrap_or_default();
+    let scope = get_scope_from_summary(summary, &pseudo_types_config(config));
+    let scope_names = ScopeNameValidator::from_config(config)?;
+    if let Some(scope) = scope.filter(|scope| match scope_names.parse(scope) {
+        Ok(_) => true,
+        Err(e) => {
+            info!(
+                "Not caching scope {:?} of {}: the name {}",
+                scope,
+                head.id(),
+                e
+            );
+            false
+        }
+    }) {
+        debug!(
+            "Appending the changes of {} to scope {:?}",
+            head.id(),
+            scope
+        );
+        add_changes(
+            &mut entry.scope_counts,
+            vec![ScopeChange {
+                scope: CommitScope::new(scope),
+                files: get_changed_files_from_commit(&head, repo)?,
+                time: commit_time(&head),
+            }],
+        );
+    }
+    entry.head_commit_hash = head.id().to_string();
+    entry.timestamp = crate::utils::time::now();
+    entry.owner = current_user();
+    entry.path = repo_path(repo);
+
+    cache.save(&cache_path)?;
+    info!("Cache updated with commit {}", head.id());
+    Ok(true)
 }
 
 /// Drop cache for individual repo
-pub fn drop_cache_for_repo(repo: &Repository) -> Result<Option<PathBuf>> {
-    let repo_id = get_repo_id(repo);
-    info!("Dropping the scope cache for repo '{:?}'", repo_id);
+pub fn drop_cache_for_repo(repo: &Repository, config: &Option<Config>) -> Result<Option<PathBuf>> {
+    info!("Dropping the scope cache for repo '{:?}'", repo_path(repo));
+    ensure_writable(config)?;
+    let cache_path = get_cache_path(config)?;
 
-    Cache::lock()?;
+    let _lock = Cache::lock(&cache_path, config)?;
 
     // Load the cache
-    let mut cache = Cache::load()?;
+    let mut cache = Cache::load(&cache_path)?;
 
-    let res = if cache.entries.remove(&repo_id).is_some() {
-        Some(repo.path().to_path_buf())
-    } else {
-        None
-    };
+    let res = cache
+        .repo_id(repo)
+        .and_then(|repo_id| cache.entries.remove(&repo_id))
+        .map(|_| repo.path().to_path_buf());
 
-    cache.save()?;
+    cache.save(&cache_path)?;
 
     Ok(res)
 }
 
-pub fn nuke_cache() -> Result<bool> {
+pub fn nuke_cache(config: &Option<Config>) -> Result<bool> {
     info!("Destroying the whole cache");
-    let cache_path = get_cache_path()?;
+    ensure_writable(config)?;
+    let cache_path = get_cache_path(config)?;
     if cache_path.exists() {
-        std::fs::remove_file(cache_path)?;
+        std::fs::remove_file(&cache_path).map_err(|source| Error::CacheWrite {
+            path: cache_path,
+            source,
+        })?;
         Ok(true)
     } else {
         Ok(false)
     }
 }
 
-pub fn show_cache() -> Result<Cache> {
+pub fn show_cache(config: &Option<Config>) -> Result<Cache> {
     info!("Showing cached repos");
-    Cache::load()
+    Cache::load(&get_cache_path(config)?)
+}
+
+/// Paths of the repositories with an entry in the cache, sorted. Meant for updating them all, so
+/// fails if the cache must not be written to
+pub fn cached_repos(config: &Option<Config>) -> Result<Vec<PathBuf>> {
+    ensure_writable(config)?;
+    let cache = Cache::load(&get_cache_path(config)?)?;
+
+    Ok(cache
+        .entries
+        .into_values()
+        .map(|entry| entry.path)
+        .sorted()
+        .dedup()
+        .collect())
+}
+
+/// Removes the entries of the repositories that no longer exist or are no longer git
+/// repositories (i.e. deleted temporary checkouts). Returns their paths, sorted
+pub fn prune_cache(config: &Option<Config>) -> Result<Vec<PathBuf>> {
+    info!("Pruning the cache");
+    ensure_writable(config)?;
+    let cache_path = get_cache_path(config)?;
+
+    let _lock = Cache::lock(&cache_path, config)?;
+    let mut cache = Cache::load(&cache_path)?;
+
+    let pruned: Vec<(RepoID, PathBuf)> = cache
+        .entries
+        .iter()
+        .filter(|(_, entry)| Repository::open(&entry.path).is_err())
+        .map(|(repo_id, entry)| (repo_id.clone(), entry.path.clone()))
+        .sorted_by(|(_, a), (_, b)| a.cmp(b))
+        .collect();
+    if pruned.is_empty() {
+        return Ok(Vec::new());
+    }
+    for (repo_id, repo_path) in &pruned {
+        debug!("Pruning the entry of {:?}", repo_path);
+        cache.entries.remove(repo_id);
+    }
+
+    cache.save(&cache_path)?;
+    Ok(pruned.into_iter().map(|(_, repo_path)| repo_path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_commit, setup_repo_with_commits};
+    use itertools::Itertools;
+    use testdir::testdir;
+
+    fn mk_entry(repo: &Repository) -> CacheEntry {
+        let mut scope_counts = HashMap::new();
+        add_changes(
+            &mut scope_counts,
+            vec![ScopeChange {
+                scope: CommitScope::new("foo".to_string()),
+                files: ChangedFiles::from(["default_file".to_string()]),
+                time: crate::utils::time::now(),
+            }],
+        );
+
+        CacheEntry {
+            scope_counts,
+            timestamp: crate::utils::time::now(),
+            head_commit_hash: repo.head().unwrap().target().unwrap().to_string(),
+            fingerprint: RepoFingerprint::from_repo(repo).unwrap(),
+            owner: "alice".to_string(),
+            config_hash: config_hash(repo, &None).unwrap(),
+            path: repo_path(repo),
+        }
+    }
+
+    /// The entry mined since the latest tag is stale once there's a newer one
+    #[test]
+    fn config_hash_follows_latest_tag() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): a"]);
+        let config =
+            Some(Config::from_str("[general.scopes]\nsince_ref = \"latest-tag\"\n").unwrap());
+        let tag = |name: &str| {
+            let head = repo.revparse_single("HEAD").unwrap();
+            repo.tag_lightweight(name, &head, false).unwrap();
+        };
+
+        let untagged = config_hash(&repo, &config).unwrap();
+        assert_eq!(untagged, config_hash(&repo, &None).unwrap());
+        tag("v0.1.0");
+        let tagged = config_hash(&repo, &config).unwrap();
+        assert_ne!(tagged, untagged);
+        add_commit(&repo, "feat(foo): b", "b");
+        assert_eq!(config_hash(&repo, &config).unwrap(), tagged);
+        tag("v0.2.0");
+        assert_ne!(config_hash(&repo, &config).unwrap(), tagged);
+    }
+
+    /// Shared cache is writable by the group
+    #[cfg(unix)]
+    #[test]
+    fn shared_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = testdir!();
+        let cache_path = dir.join("shared").join("cache.bin");
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(cache_path.clone()),
+                location: CacheLocation::System,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&cache_path), 0o664);
+        assert_eq!(mode(&dir.join("shared")), 0o2775);
+    }
+
+    /// Moved checkout is still found in the cache
+    #[test]
+    fn moved_checkout() {
+        let dir = testdir!();
+        let old_path = dir.join("old");
+        let new_path = dir.join("new");
+        let repo = setup_repo_with_commits(&old_path, &["init", "feat(foo): bar"]);
+        let mut cache = Cache::new();
+        cache
+            .entries
+            .insert(root_commit(&repo).unwrap(), mk_entry(&repo));
+
+        std::fs::rename(&old_path, &new_path).unwrap();
+        let moved_repo = Repository::open(&new_path).unwrap();
+
+        assert!(cache.get_scopes_for_repo(&moved_repo).is_some());
+    }
+
+    /// Unrelated repository is not matched, even if it's where the cached one was
+    #[test]
+    fn other_repo() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir.join("one"), &["init"]);
+        let other_repo = setup_repo_with_commits(&dir.join("other"), &["other init"]);
+        let mut cache = Cache::new();
+        cache
+            .entries
+            .insert(root_commit(&repo).unwrap(), mk_entry(&repo));
+
+        assert!(cache.get_scopes_for_repo(&other_repo).is_none());
+
+        std::fs::remove_dir_all(dir.join("one")).unwrap();
+        let replaced_repo = setup_repo_with_commits(&dir.join("one"), &["replaced init"]);
+        assert!(cache.get_scopes_for_repo(&replaced_repo).is_none());
+    }
+
+    /// Repository opened through a symlink and from a linked worktree shares the entry
+    #[cfg(unix)]
+    #[test]
+    fn same_root_commit() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir.join("repo"), &["init", "feat(foo): bar"]);
+        let mut cache = Cache::new();
+        cache
+            .entries
+            .insert(root_commit(&repo).unwrap(), mk_entry(&repo));
+
+        std::os::unix::fs::symlink(dir.join("repo"), dir.join("link")).unwrap();
+        let linked_repo = Repository::open(dir.join("link")).unwrap();
+        assert!(cache.get_scopes_for_repo(&linked_repo).is_some());
+
+        let worktree = repo.worktree("wt", &dir.join("wt"), None).unwrap();
+        let worktree_repo = Repository::open_from_worktree(&worktree).unwrap();
+        assert!(cache.get_scopes_for_repo(&worktree_repo).is_some());
+    }
+
+    /// Linked worktrees share the entry and the repo cache of the main repository
+    #[test]
+    fn worktree() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir.join("repo"), &["init", "feat(foo): bar"]);
+        let worktree = repo.worktree("wt", &dir.join("wt"), None).unwrap();
+        let worktree_repo = Repository::open_from_worktree(&worktree).unwrap();
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+
+        update_cache_for_repo(&worktree_repo, &config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+        assert_eq!(cached_repos(&config).unwrap(), vec![dir.join("repo")]);
+
+        add_commit(&worktree_repo, "fix(baz): qux", "new_file");
+        assert!(append_head_to_cache(&worktree_repo, &config).unwrap());
+        let cache = Cache::load(&get_cache_path(&config).unwrap()).unwrap();
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get_scopes_for_repo(&repo).is_some());
+
+        assert_eq!(repo_cache_path(&worktree_repo), repo_cache_path(&repo));
+    }
+
+    /// Cache keyed by the path is re-keyed by the root commit, the most recent entry of the
+    /// repository wins
+    #[test]
+    fn legacy_migration() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir.join("repo"), &["init", "feat(foo): bar"]);
+        Repository::clone(dir.join("repo").to_str().unwrap(), dir.join("clone")).unwrap();
+        let head_commit_hash = repo.head().unwrap().target().unwrap().to_string();
+        let legacy_entry = |timestamp: i64, file: &str| LegacyCacheEntry {
+            scopes: HashMap::from([(
+                CommitScope::new("foo".to_string()),
+                ChangedFiles::from([file.to_string()]),
+            )]),
+            timestamp: DateTime::from_timestamp(timestamp, 0).unwrap(),
+            head_commit_hash: head_commit_hash.clone(),
+        };
+        let legacy = LegacyCache {
+            entries: HashMap::from([
+                // Trailing slash, as the working directory was stored
+                (dir.join("repo").join(""), legacy_entry(200, "new_file")),
+                (dir.join("clone"), legacy_entry(100, "old_file")),
+                (dir.join("removed"), legacy_entry(300, "removed_file")),
+            ]),
+        };
+        let cache_path = dir.join("cache.bin");
+        std::fs::write(&cache_path, bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let cache = Cache::load(&cache_path).unwrap();
+        assert_eq!(cache.entries.len(), 1);
+        let migrated = cache.get_scopes_for_repo(&repo).unwrap();
+        assert_eq!(migrated.path, dir.join("repo"));
+        assert_eq!(
+            migrated.scopes(),
+            HashMap::from([(
+                CommitScope::new("foo".to_string()),
+                ChangedFiles::from(["new_file".to_string()]),
+            )])
+        );
+        // Mined again on first use
+        assert_ne!(migrated.config_hash, config_hash(&repo, &None).unwrap());
+
+        // Saved in the new format right away
+        assert!(std::fs::read(&cache_path).unwrap().starts_with(CACHE_MAGIC));
+        assert_eq!(Cache::load(&cache_path).unwrap().entries.len(), 1);
+    }
+
+    /// Migrated cache is saved under the lock held around the load too, which stays held
+    #[test]
+    fn legacy_migration_locked() {
+        let dir = testdir!();
+        let cache_path = dir.join("cache.bin");
+        let legacy = LegacyCache {
+            entries: HashMap::new(),
+        };
+        std::fs::write(&cache_path, bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let lock = Cache::lock(&cache_path, &None).unwrap();
+        Cache::load(&cache_path).unwrap();
+        assert!(std::fs::read(&cache_path).unwrap().starts_with(CACHE_MAGIC));
+        assert!(held_locks().contains(&lock_path(&cache_path)));
+
+        drop(lock);
+        assert!(!held_locks().contains(&lock_path(&cache_path)));
+    }
+
+    /// Unreadable cache without the header is dropped, one with it is reported
+    #[test]
+    fn legacy_unreadable() {
+        let dir = testdir!();
+        let cache_path = dir.join("cache.bin");
+        std::fs::write(&cache_path, b"garbage").unwrap();
+        assert!(Cache::load(&cache_path).unwrap().entries.is_empty());
+
+        std::fs::write(&cache_path, [CACHE_MAGIC, b"garbage"].concat()).unwrap();
+        assert!(matches!(
+            Cache::load(&cache_path),
+            Err(Error::CacheCorrupt { .. })
+        ));
+    }
+
+    /// Post-commit hook appends the new commit to an up to date entry only
+    #[test]
+    fn append_head() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): bar"]);
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        add_commit(&repo, "fix(foo): baz", "new_file");
+        assert!(append_head_to_cache(&repo, &config).unwrap());
+
+        let cache = Cache::load(&get_cache_path(&config).unwrap()).unwrap();
+        let entry = cache.get_scopes_for_repo(&repo).unwrap();
+        assert_eq!(
+            entry.head_commit_hash,
+            repo.head().unwrap().target().unwrap().to_string()
+        );
+        assert_eq!(
+            entry.scopes()[&CommitScope::new("foo".to_string())],
+            ChangedFiles::from(["default_file".to_string(), "new_file".to_string()])
+        );
+
+        // Two commits without the hook => entry is left for the staleness check
+        add_commit(&repo, "fix(foo): one", "one");
+        add_commit(&repo, "fix(foo): two", "two");
+        assert!(!append_head_to_cache(&repo, &config).unwrap());
+    }
+
+    /// Commits falling out of `max_commits` are dropped from the entry on update
+    #[test]
+    fn update_within_limits() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(a): a", "feat(b): b"]);
+        let mut config = Config::from_str("[general.scopes]\nmax_commits = 2\n").unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let config = Some(config);
+        create_cache(&config).unwrap();
+        let scopes = |counts: HashMap<CommitScope, ScopeCounts>| {
+            counts
+                .into_keys()
+                .map(|scope| scope.name)
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            scopes(update_cache_for_repo(&repo, &config).unwrap()),
+            ["a", "b"]
+        );
+
+        add_commit(&repo, "feat(c): c", "c");
+        assert!(!append_head_to_cache(&repo, &config).unwrap());
+        assert_eq!(
+            scopes(update_cache_for_repo(&repo, &config).unwrap()),
+            ["b", "c"]
+        );
+    }
+
+    /// Merge commit is left to the incremental update, which mines the merged branch too
+    #[test]
+    fn append_head_merge() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(core): bar"]);
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        let side = repo
+            .find_commit(add_commit(&repo, "feat(side): s", "side"))
+            .unwrap();
+        repo.reset(base.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+        let main = repo
+            .find_commit(add_commit(&repo, "feat(other): o", "other"))
+            .unwrap();
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        let mut index = repo.merge_commits(&main, &side, None).unwrap();
+        let tree = repo.find_tree(index.write_tree_to(&repo).unwrap()).unwrap();
+        let sig = git2::Signature::now("nobody", "nobody@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            "Merge side",
+            &tree,
+            &[&main, &side],
+        )
+        .unwrap();
+        assert!(!append_head_to_cache(&repo, &config).unwrap());
+
+        update_cache_for_repo(&repo, &config).unwrap();
+        let cache = Cache::load(&get_cache_path(&config).unwrap()).unwrap();
+        assert_eq!(
+            cache
+                .get_scopes_for_repo(&repo)
+                .unwrap()
+                .scopes()
+                .into_keys()
+                .map(|scope| scope.name)
+                .sorted()
+                .collect::<Vec<_>>(),
+            ["core", "other", "side"]
+        );
+    }
+
+    /// Only the commits after the cached HEAD are mined when updating
+    #[test]
+    fn incremental_update() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): bar"]);
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let cache_path = create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        // Tamper with the cached scopes: if the whole history was mined, "foo" would come back
+        let mut cache = Cache::load(&cache_path).unwrap();
+        let entry = cache.entries.get_mut(&root_commit(&repo).unwrap()).unwrap();
+        entry.scope_counts.clear();
+        add_changes(
+            &mut entry.scope_counts,
+            vec![ScopeChange {
+                scope: CommitScope::new("tampered".to_string()),
+                files: ChangedFiles::new(),
+                time: crate::utils::time::now(),
+            }],
+        );
+        cache.save(&cache_path).unwrap();
+
+        add_commit(&repo, "fix(baz): qux", "new_file");
+        let res = update_cache_for_repo(&repo, &config).unwrap();
+
+        assert_eq!(
+            res.keys()
+                .map(|s| s.name.as_str())
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec!["baz", "tampered"]
+        );
+    }
+
+    /// Commits dropped by a history rewrite are removed from the entry, the rest is kept
+    #[test]
+    fn rewritten_history() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): bar"]);
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        // Same file changed by two scopes, one of the commits will be dropped
+        add_commit(&repo, "feat(foo): shared", "shared");
+        add_commit(&repo, "feat(dropped): shared", "shared");
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        // "Rebase": reset to the base and commit something else
+        repo.reset(base.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+        add_commit(&repo, "feat(foo): shared", "shared");
+        add_commit(&repo, "fix(new): rewritten", "other");
+        let res = update_cache_for_repo(&repo, &config).unwrap();
+
+        assert_eq!(
+            res.keys()
+                .map(|s| s.name.as_str())
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec!["foo", "new"]
+        );
+        assert_eq!(
+            scope_files(&res)[&CommitScope::new("foo".to_string())],
+            ChangedFiles::from(["default_file".to_string(), "shared".to_string()])
+        );
+    }
+
+    /// Entries of deleted repositories and of directories that are no longer repositories go
+    #[test]
+    fn prune() {
+        let dir = testdir!();
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+        let kept = dir.join("kept");
+        let deleted = dir.join("deleted");
+        let not_repo = dir.join("not_repo");
+        for repo_path in [&kept, &deleted, &not_repo] {
+            // Distinct root commits, otherwise these are the same repository
+            let init = format!("init {:?}", repo_path);
+            let repo = setup_repo_with_commits(repo_path, &[&init, "feat(foo): bar"]);
+            update_cache_for_repo(&repo, &config).unwrap();
+        }
+        std::fs::remove_dir_all(&deleted).unwrap();
+        std::fs::remove_dir_all(not_repo.join(".git")).unwrap();
+
+        assert_eq!(prune_cache(&config).unwrap(), vec![deleted, not_repo]);
+        assert_eq!(cached_repos(&config).unwrap(), vec![kept]);
+        assert!(prune_cache(&config).unwrap().is_empty());
+    }
+
+    #[test]
+    fn counts() {
+        let foo = CommitScope::new("foo".to_string());
+        let change = |files: &[&str], time: i64| {
+            vec![ScopeChange {
+                scope: foo.clone(),
+                files: files
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<ChangedFiles>(),
+                time: DateTime::from_timestamp(time, 0).unwrap(),
+            }]
+        };
+        let mut scope_counts = HashMap::new();
+
+        add_changes(&mut scope_counts, change(&["a", "b"], 200));
+        add_changes(&mut scope_counts, change(&["a"], 100));
+        remove_changes(&mut scope_counts, change(&["a", "b"], 200));
+
+        assert_eq!(
+            scope_counts[&foo],
+            ScopeCounts {
+                commits: 1,
+                files: HashMap::from([("a".to_string(), 1)]),
+                // Not recalculated on removal
+                last_used: DateTime::from_timestamp(200, 0).unwrap(),
+            }
+        );
+
+        remove_changes(&mut scope_counts, change(&["a"], 100));
+        assert!(scope_counts.is_empty());
+    }
 }
@@ -0,0 +1,615 @@
+//! Changelog generation from the conventional commits history
+//!
+//! Rendering is driven by a [`ChangelogTemplate`]: a handful of line templates with `{placeholder}`
+//! substitution. Templates come from built-in presets and can be (partially) overridden by a TOML
+//! file specified in the config.
+
+use git2::Repository;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::commit_message::{parse_message, pseudo_types_config, ConventionalCommit};
+use crate::config::{ChangelogConfig, ChangelogPreset, Config};
+use crate::error::{Error, Result};
+use crate::history::{latest_tag, latest_tag_from, revwalk_for_range, split_range};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
+pub struct ChangelogSection {
+    pub title: String,
+    /// Commit types that go into the section
+    pub types: Vec<String>,
+}
+
+/// Set of line templates the changelog is rendered with
+#[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
+pub struct ChangelogTemplate {
+    /// Release header. Placeholders: {version}, {date}
+    pub header: String,
+    /// Section header. Placeholders: {title}
+    pub section: String,
+    /// Scope subsection header, used only if group_by_scope is set. Placeholders: {scope}
+    pub scope_section: String,
+    /// Placeholders: {type}, {scope}, {scope_prefix}, {description}, {hash}, {short_hash}
+    pub entry: String,
+    /// Header of the breaking changes section
+    pub breaking_section: String,
+    /// Same placeholders as entry, {description} is the BREAKING CHANGE footer (if any)
+    pub breaking_entry: String,
+    /// Rendered after the release if compare_url is configured.
+    /// Placeholders: {version}, {from}, {to}, {compare_url}
+    pub compare: String,
+    pub group_by_scope: bool,
+    /// Sections in the order they appear in. Commits of types not listed here are omitted
+    pub sections: Vec<ChangelogSection>,
+}
+
+fn section(title: &str, types: &[&str]) -> ChangelogSection {
+    ChangelogSection {
+        title: title.to_string(),
+        types: types.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+impl ChangelogPreset {
+    pub fn template(&self) -> ChangelogTemplate {
+        match self {
+            ChangelogPreset::Angular => ChangelogTemplate {
+                header: "## {version} ({date})".to_string(),
+                section: "### {title}".to_string(),
+                scope_section: "#### {scope}".to_string(),
+                entry: "- {scope_prefix}{description} ({short_hash})".to_string(),
+                breaking_section: "### ⚠ BREAKING CHANGES".to_string(),
+                breaking_entry: "- {scope_prefix}{description}".to_string(),
+                compare: "[{version}]: {compare_url}".to_string(),
+                group_by_scope: false,
+                sections: vec![
+                    section("Features", &["feat"]),
+                    section("Bug Fixes", &["fix"]),
+                    section("Performance Improvements", &["perf"]),
+                    section("Reverts", &["revert"]),
+                ],
+            },
+            ChangelogPreset::KeepAChangelog => ChangelogTemplate {
+                header: "## [{version}] - {date}".to_string(),
+                section: "### {title}".to_string(),
+                scope_section: "#### {scope}".to_string(),
+                entry: "- {scope_prefix}{description}".to_string(),
+                breaking_section: "### Breaking changes".to_string(),
+                breaking_entry: "- {scope_prefix}{description}".to_string(),
+                compare: "[{version}]: {compare_url}".to_string(),
+                group_by_scope: false,
+                sections: vec![
+                    section("Added", &["feat"]),
+                    section("Changed", &["perf", "refactor"]),
+                    section("Fixed", &["fix"]),
+                    section("Removed", &["revert"]),
+                ],
+            },
+        }
+    }
+}
+
+/// Directory `changelog.template` is relative to: the one of the config file given explicitly,
+/// otherwise the root of the repository (the git directory for a bare one)
+pub fn template_dir(repo: &Repository, config_path: Option<&Path>) -> PathBuf {
+    match config_path {
+        Some(path) => path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        None => repo.workdir().unwrap_or(repo.path()).to_path_buf(),
+    }
+}
+
+/// Resolves the template: preset, with the keys from the template file (if any) on top. The file
+/// is looked up in `dir`, see [`template_dir`]
+pub fn get_template(config: &ChangelogConfig, dir: &Path) -> Result<ChangelogTemplate> {
+    let preset = config.preset.template();
+
+    let Some(template_path) = &config.template else {
+        return Ok(preset);
+    };
+
+    let template_path = dir.join(template_path);
+    debug!("Loading changelog template from {:?}", template_path);
+    let content = std::fs::read_to_string(&template_path).map_err(|source| Error::FileRead {
+        path: template_path.clone(),
+        source,
+    })?;
+    let invalid = |e: toml::de::Error| Error::InvalidFile {
+        path: template_path.clone(),
+        message: format!("not a changelog template: {}", e.message()),
+    };
+    let overrides: toml::Table = toml::from_str(&content).map_err(invalid)?;
+
+    let mut template = toml::Table::try_from(preset)?;
+    template.extend(overrides);
+
+    toml::Value::Table(template).try_into().map_err(invalid)
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangelogEntry {
+    pub commit: ConventionalCommit,
+    pub hash: String,
+    pub author_name: String,
+    pub author_email: String,
+}
+
+/// Walks the range and parses the commits. Non-conventional commits are skipped.
+pub fn get_entries(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<ChangelogEntry>> {
+    let pseudo_types = pseudo_types_config(config);
+    let mut res = Vec::new();
+
+    for oid in revwalk_for_range(repo, range)? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(message) = commit.message() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        match parse_message(message, &pseudo_types)? {
+            Some(parsed) => res.push(ChangelogEntry {
+                commit: parsed,
+                hash: commit.id().to_string(),
+                author_name: commit.author().name().unwrap_or_default().to_string(),
+                author_email: commit.author().email().unwrap_or_default().to_string(),
+            }),
+            None => debug!(
+                "Commit {} is not a conventional commit, skipping",
+                commit.id()
+            ),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Keeps only the entries of a single scope
+pub fn filter_by_scope(entries: Vec<ChangelogEntry>, scope: &str) -> Vec<ChangelogEntry> {
+    entries
+        .into_iter()
+        .filter(|e| e.commit.header.scope.as_deref() == Some(scope))
+        .collect()
+}
+
+/// Figures out the range to generate the changelog for: either the one explicitly given or
+/// "everything since the latest tag"
+pub fn resolve_range(
+    repo: &Repository,
+    range: Option<String>,
+    tag_pattern: Option<&str>,
+) -> Result<Option<String>> {
+    if range.is_some() {
+        return Ok(range);
+    }
+
+    Ok(match latest_tag(repo, tag_pattern)? {
+        Some(tag) => {
+            info!("Generating changelog since {:?}", tag);
+            Some(format!("{}..HEAD", tag))
+        }
+        None => {
+            info!("No tags found, generating changelog for the whole history");
+            None
+        }
+    })
+}
+
+/// Range for the GitHub release notes of a tag.
+///
+/// If the tag exists -- commits between the previous tag and it, otherwise (i.e. the notes are
+/// prepared before tagging) the commits since the latest tag.
+pub fn resolve_github_release_range(
+    repo: &Repository,
+    tag: &str,
+    tag_pattern: Option<&str>,
+) -> Result<Option<String>> {
+    if repo.revparse_single(tag).is_err() {
+        debug!("Tag {:?} does not exist yet", tag);
+        return resolve_range(repo, None, tag_pattern);
+    }
+
+    Ok(Some(
+        match latest_tag_from(repo, &format!("{}^", tag), tag_pattern)? {
+            Some(previous) => format!("{}..{}", previous, tag),
+            // First release
+            None => tag.to_string(),
+        },
+    ))
+}
+
+/// Adjusts the template for GitHub release notes.
+///
+/// Full hashes are used since GitHub autolinks them; `#123` references are left as is for the same
+/// reason.
+pub fn github_release_template(template: ChangelogTemplate) -> ChangelogTemplate {
+    ChangelogTemplate {
+        entry: "- {scope_prefix}{description} ({hash})".to_string(),
+        compare: "**Full Changelog**: {compare_url}".to_string(),
+        ..template
+    }
+}
+
+/// GitHub handle from the noreply email (`123+user@users.noreply.github.com`), if it's one
+fn github_handle(email: &str) -> Option<&str> {
+    let local = email.strip_suffix("@users.noreply.github.com")?;
+    Some(local.split_once('+').map_or(local, |(_, handle)| handle))
+}
+
+/// Renders the contributors section: GitHub handles where they can be figured out from the
+/// emails, names otherwise
+pub fn render_contributors(entries: &[ChangelogEntry]) -> String {
+    let contributors: Vec<String> = entries
+        .iter()
+        .map(|e| match github_handle(&e.author_email) {
+            Some(handle) => format!("@{}", handle),
+            None => e.author_name.clone(),
+        })
+        .filter(|c| !c.is_empty())
+        .unique()
+        .sorted()
+        .collect();
+
+    if contributors.is_empty() {
+        return String::new();
+    }
+
+    let mut lines = vec![String::new(), "### Contributors".to_string(), String::new()];
+    lines.extend(contributors.iter().map(|c| format!("- {}", c)));
+    lines.join("\n") + "\n"
+}
+
+/// Metadata of the release the changelog is generated for
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub version: String,
+    pub date: String,
+    pub range: Option<String>,
+    pub compare_url: Option<String>,
+}
+
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |acc, (key, value)| {
+            acc.replace(&format!("{{{}}}", key), value)
+        })
+}
+
+fn render_entry(template: &str, entry: &ChangelogEntry, description: &str) -> String {
+    let header = &entry.commit.header;
+    let scope = header.scope.clone().unwrap_or_default();
+    let scope_prefix = match &header.scope {
+        Some(scope) => format!("**{}:** ", scope),
+        None => String::new(),
+    };
+    let short_hash: String = entry.hash.chars().take(7).collect();
+
+    fill(
+        template,
+        &[
+            ("type", &header.commit_type),
+            ("scope_prefix", &scope_prefix),
+            ("scope", &scope),
+            ("description", description),
+            ("short_hash", &short_hash),
+            ("hash", &entry.hash),
+        ],
+    )
+}
+
+/// Renders the entries into one block of lines, grouping by scope if requested
+fn render_entries(template: &ChangelogTemplate, entries: &[&ChangelogEntry]) -> Vec<String> {
+    let plain = |entries: &[&ChangelogEntry]| -> Vec<String> {
+        entries
+            .iter()
+            .map(|e| render_entry(&template.entry, e, &e.commit.header.description))
+            .collect()
+    };
+
+    if !template.group_by_scope {
+        return plain(entries);
+    }
+
+    // Entries without scope go first, then the scopes alphabetically
+    let grouped = entries
+        .iter()
+        .copied()
+        .into_group_map_by(|e| e.commit.header.scope.clone());
+
+    grouped
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .flat_map(|(scope, entries)| {
+            let mut lines = Vec::new();
+            if let Some(scope) = scope {
+                lines.push(String::new());
+                lines.push(fill(&template.scope_section, &[("scope", &scope)]));
+                lines.push(String::new());
+            }
+            lines.extend(plain(&entries));
+            lines
+        })
+        .collect()
+}
+
+pub fn render(
+    template: &ChangelogTemplate,
+    release: &Release,
+    entries: &[ChangelogEntry],
+) -> String {
+    let mut lines = vec![fill(
+        &template.header,
+        &[("version", &release.version), ("date", &release.date)],
+    )];
+
+    let breaking: Vec<String> = entries
+        .iter()
+        .filter(|e| e.commit.is_breaking())
+        .map(|e| {
+            let description = e
+                .commit
+                .breaking_change_footer()
+                .map(|f| f.value.clone())
+                .unwrap_or_else(|| e.commit.header.description.clone());
+            render_entry(&template.breaking_entry, e, &description)
+        })
+        .collect();
+
+    if !breaking.is_empty() {
+        lines.extend([
+            String::new(),
+            template.breaking_section.clone(),
+            String::new(),
+        ]);
+        lines.extend(breaking);
+    }
+
+    for section in &template.sections {
+        let section_entries: Vec<&ChangelogEntry> = entries
+            .iter()
+            .filter(|e| section.types.contains(&e.commit.header.commit_type))
+            .collect();
+
+        if section_entries.is_empty() {
+            continue;
+        }
+
+        lines.extend([
+            String::new(),
+            fill(&template.section, &[("title", &section.title)]),
+            String::new(),
+        ]);
+        lines.extend(render_entries(template, &section_entries));
+    }
+
+    if let (Some(compare_url), Some(range)) = (&release.compare_url, &release.range) {
+        if let (Some(from), to) = split_range(range) {
+            let compare_url = fill(compare_url, &[("from", from), ("to", to)]);
+            lines.extend([
+                String::new(),
+                fill(
+                    &template.compare,
+                    &[
+                        ("version", &release.version),
+                        ("from", from),
+                        ("to", to),
+                        ("compare_url", &compare_url),
+                    ],
+                ),
+            ]);
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    fn mk_entry(message: &str, hash: &str) -> ChangelogEntry {
+        ChangelogEntry {
+            commit: parse_message(message, &Default::default())
+                .unwrap()
+                .unwrap(),
+            hash: hash.to_string(),
+            author_name: "nobody".to_string(),
+            author_email: "nobody@example.com".to_string(),
+        }
+    }
+
+    fn mk_release(range: Option<&str>) -> Release {
+        Release {
+            version: "1.0.0".to_string(),
+            date: "2024-01-01".to_string(),
+            range: range.map(String::from),
+            compare_url: Some("https://example.com/compare/{from}...{to}".to_string()),
+        }
+    }
+
+    #[test]
+    fn renders_angular() {
+        let entries = vec![
+            mk_entry(
+                "feat(foo)!: one\n\nBREAKING CHANGE: it broke",
+                "1111111aaaa",
+            ),
+            mk_entry("fix: two", "2222222bbbb"),
+            mk_entry("chore: three", "3333333cccc"),
+        ];
+
+        let res = render(
+            &ChangelogPreset::Angular.template(),
+            &mk_release(Some("v0.1.0..HEAD")),
+            &entries,
+        );
+
+        assert_eq!(
+            res,
+            indoc! {"
+                ## 1.0.0 (2024-01-01)
+
+                ### ⚠ BREAKING CHANGES
+
+                - **foo:** it broke
+
+                ### Features
+
+                - **foo:** one (1111111)
+
+                ### Bug Fixes
+
+                - two (2222222)
+
+                [1.0.0]: https://example.com/compare/v0.1.0...HEAD
+            "}
+        );
+    }
+
+    #[test]
+    fn renders_grouped_by_scope() {
+        let entries = vec![
+            mk_entry("feat(foo): one", "1111111"),
+            mk_entry("feat: two", "2222222"),
+            mk_entry("feat(bar): three", "3333333"),
+        ];
+        let mut template = ChangelogPreset::KeepAChangelog.template();
+        template.group_by_scope = true;
+
+        let res = render(&template, &mk_release(None), &entries);
+
+        assert_eq!(
+            res,
+            indoc! {"
+                ## [1.0.0] - 2024-01-01
+
+                ### Added
+
+                - two
+
+                #### bar
+
+                - **bar:** three
+
+                #### foo
+
+                - **foo:** one
+            "}
+        );
+    }
+
+    #[test]
+    fn renders_contributors() {
+        let mut entries = vec![
+            mk_entry("feat: one", "1111111"),
+            mk_entry("feat: two", "2222222"),
+            mk_entry("feat: three", "3333333"),
+        ];
+        entries[1].author_email = "123+octocat@users.noreply.github.com".to_string();
+
+        assert_eq!(
+            render_contributors(&entries),
+            "\n### Contributors\n\n- @octocat\n- nobody\n"
+        );
+    }
+
+    #[test]
+    fn github_release_range() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat: one", "fix: two"]);
+        repo.tag_lightweight("v0.1.0", &repo.revparse_single("HEAD~1").unwrap(), false)
+            .unwrap();
+
+        // Not tagged yet
+        assert_eq!(
+            resolve_github_release_range(&repo, "v0.2.0", None).unwrap(),
+            Some("v0.1.0..HEAD".to_string())
+        );
+        // First tag
+        assert_eq!(
+            resolve_github_release_range(&repo, "v0.1.0", None).unwrap(),
+            Some("v0.1.0".to_string())
+        );
+
+        repo.tag_lightweight("v0.2.0", &repo.revparse_single("HEAD").unwrap(), false)
+            .unwrap();
+        assert_eq!(
+            resolve_github_release_range(&repo, "v0.2.0", None).unwrap(),
+            Some("v0.1.0..v0.2.0".to_string())
+        );
+    }
+
+    #[test]
+    fn filters_by_scope() {
+        let entries = vec![
+            mk_entry("feat(foo): one", "1111111"),
+            mk_entry("feat: two", "2222222"),
+            mk_entry("feat(bar): three", "3333333"),
+        ];
+
+        let res = filter_by_scope(entries, "foo");
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].hash, "1111111");
+    }
+
+    #[test]
+    fn template_file_overrides_preset() {
+        let dir = testdir!();
+        std::fs::write(
+            dir.join("template.toml"),
+            indoc! {r##"
+                header = "# {version}"
+                "##},
+        )
+        .unwrap();
+        let config = ChangelogConfig {
+            preset: ChangelogPreset::KeepAChangelog,
+            template: Some("template.toml".into()),
+            ..Default::default()
+        };
+
+        let res = get_template(&config, &dir).unwrap();
+
+        assert_eq!(res.header, "# {version}");
+        assert_eq!(
+            res.sections,
+            ChangelogPreset::KeepAChangelog.template().sections
+        );
+    }
+
+    #[test]
+    fn template_dir_of_bare_repo_and_config_file() {
+        let dir = testdir!();
+        let repo = Repository::init_bare(dir.join("bare.git")).unwrap();
+
+        assert_eq!(template_dir(&repo, None), repo.path());
+        assert_eq!(
+            template_dir(&repo, Some(&dir.join("conf/config.toml"))),
+            dir.join("conf")
+        );
+    }
+
+    #[test]
+    fn entries_since_latest_tag() {
+        let dir = testdir!();
+        let repo =
+            setup_repo_with_commits(&dir, &["init", "feat: one", "not conventional", "fix: two"]);
+        let tagged = repo.revparse_single("HEAD~2").unwrap();
+        repo.tag_lightweight("v0.1.0", &tagged, false).unwrap();
+
+        let range = resolve_range(&repo, None, None).unwrap();
+        assert_eq!(range, Some("v0.1.0..HEAD".to_string()));
+
+        let res = get_entries(&repo, &None, range.as_deref()).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].commit.header.description, "two");
+    }
+}
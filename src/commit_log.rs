@@ -0,0 +1,91 @@
+//! Search through the history by the conventional commit fields
+
+use git2::Repository;
+use log::warn;
+use serde::Serialize;
+
+use crate::commit_message::{parse_message, pseudo_types_config};
+use crate::config::Config;
+use crate::error::Result;
+use crate::history::revwalk_for_range;
+
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct LogEntry {
+    pub hash: String,
+    pub commit_type: String,
+    pub scope: Option<String>,
+    pub subject: String,
+}
+
+/// Lists conventional commits in the range, newest first, optionally filtering by scope and type.
+///
+/// Pseudo-types are handled according to the config, so a revert mapped onto a type is found by
+/// that type and the reverted commit's scope.
+pub fn get_log(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+    scope: Option<&str>,
+    commit_type: Option<&str>,
+) -> Result<Vec<LogEntry>> {
+    let pseudo_types = pseudo_types_config(config);
+    let mut res = Vec::new();
+
+    for oid in revwalk_for_range(repo, range)? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(message) = commit.message() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        let Some(parsed) = parse_message(message, &pseudo_types)? else {
+            continue;
+        };
+
+        if scope.is_some_and(|s| parsed.header.scope.as_deref() != Some(s))
+            || commit_type.is_some_and(|t| parsed.header.commit_type != t)
+        {
+            continue;
+        }
+
+        res.push(LogEntry {
+            hash: commit.id().to_string(),
+            commit_type: parsed.header.commit_type,
+            scope: parsed.header.scope,
+            subject: commit.summary().unwrap_or("").to_string(),
+        });
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use rstest::rstest;
+    use testdir::testdir;
+
+    #[rstest]
+    #[case::scope(Some("foo"), None, vec!["fix(foo): c", "feat(foo): a"])]
+    #[case::scope_and_type(Some("foo"), Some("fix"), vec!["fix(foo): c"])]
+    #[case::type_only(None, Some("fix"), vec!["fix(foo): c", "fix(bar): b"])]
+    #[case::nothing_found(Some("baz"), None, vec![])]
+    fn filters(
+        #[case] scope: Option<&str>,
+        #[case] commit_type: Option<&str>,
+        #[case] expected: Vec<&str>,
+    ) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &["init", "feat(foo): a", "fix(bar): b", "fix(foo): c"],
+        );
+
+        let res = get_log(&repo, &None, None, scope, commit_type).unwrap();
+
+        assert_eq!(
+            res.iter().map(|e| e.subject.as_str()).collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! Parsing of commit messages that is not specific to any single consumer (scopes, types, etc.)
+
+use fancy_regex::Regex;
+use log::debug;
+use serde::Serialize;
+
+use crate::config::{Config, PseudoTypeAction, PseudoTypesConfig};
+use crate::error::{Error, Result};
+use crate::sanitize::sanitize_scope;
+
+/// Subjects that git generates itself and that are not conventional commits
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PseudoType {
+    /// `Revert "<original subject>"`, as produced by `git revert`
+    Revert,
+    /// `Merge branch 'foo'`, `Merge pull request #1 from ...`, etc.
+    Merge,
+}
+
+/// What's left of the subject after the pseudo-type handling
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ResolvedSubject<'a> {
+    /// Not a pseudo-type, should be parsed as is
+    Regular(&'a str),
+    /// Pseudo-type mapped onto a commit type. `inner` is the part of the subject that can still be
+    /// parsed as a conventional commit (i.e. the reverted subject)
+    Mapped {
+        commit_type: String,
+        inner: Option<&'a str>,
+    },
+    /// Pseudo-type that should not be considered at all
+    Ignored,
+}
+
+const REVERT_PREFIX: &str = "Revert \"";
+const MERGE_PREFIX: &str = "Merge ";
+
+/// Checks if the summary is one of the pseudo-types.
+///
+/// Returns the pseudo-type and (for reverts) the reverted subject.
+pub fn detect_pseudo_type(summary: &str) -> Option<(PseudoType, Option<&str>)> {
+    if let Some(rest) = summary.strip_prefix(REVERT_PREFIX) {
+        // Nested reverts produce `Revert "Revert "foo""`, so the last quote is the closing one
+        let inner = rest.strip_suffix('"');
+        return Some((PseudoType::Revert, inner));
+    }
+
+    if summary.starts_with(MERGE_PREFIX) {
+        return Some((PseudoType::Merge, None));
+    }
+
+    None
+}
+
+/// Applies the configured pseudo-type handling to a commit summary.
+///
+/// Errors out if the pseudo-type is configured with "error".
+pub fn resolve_subject<'a>(
+    summary: &'a str,
+    config: &PseudoTypesConfig,
+) -> Result<ResolvedSubject<'a>> {
+    let Some((pseudo_type, inner)) = detect_pseudo_type(summary) else {
+        return Ok(ResolvedSubject::Regular(summary));
+    };
+
+    let action = match pseudo_type {
+        PseudoType::Revert => &config.revert,
+        PseudoType::Merge => &config.merge,
+    };
+    debug!(
+        "Subject {:?} is a {:?} pseudo-type, handling it as {:?}",
+        summary, pseudo_type, action
+    );
+
+    match action {
+        PseudoTypeAction::Ignore => Ok(ResolvedSubject::Ignored),
+        PseudoTypeAction::Error => Err(Error::PseudoTypeNotAllowed {
+            pseudo_type,
+            subject: summary.to_string(),
+        }),
+        PseudoTypeAction::Type(commit_type) => Ok(ResolvedSubject::Mapped {
+            commit_type: commit_type.clone(),
+            inner,
+        }),
+    }
+}
+
+/// First line of a conventional commit: `type(scope)!: description`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Header {
+    pub commit_type: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+/// Parses the subject as a conventional commit header. Returns None if it does not look like one.
+pub fn parse_header(subject: &str) -> Option<Header> {
+    let regex = Regex::new(
+        r"^(?<type>[\w-]+)(?:\((?<scope>[\w -]+)\))?(?<breaking>!)?: (?<description>.+)$",
+    )
+    .unwrap();
+
+    let captures = regex.captures(subject.trim()).ok().flatten()?;
+
+    Some(Header {
+        commit_type: captures["type"].to_string(),
+        scope: captures
+            .name("scope")
+            .and_then(|m| sanitize_scope(m.as_str())),
+        breaking: captures.name("breaking").is_some(),
+        description: captures["description"].to_string(),
+    })
+}
+
+/// Fully parsed conventional commit message
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConventionalCommit {
+    pub header: Header,
+    pub footers: Vec<Footer>,
+}
+
+impl ConventionalCommit {
+    /// Breaking either through `!` in the header or through a `BREAKING CHANGE` footer
+    pub fn is_breaking(&self) -> bool {
+        self.header.breaking || self.breaking_change_footer().is_some()
+    }
+
+    pub fn breaking_change_footer(&self) -> Option<&Footer> {
+        self.footers.iter().find(|f| f.is_breaking_change())
+    }
+}
+
+/// Parses only the subject, applying the pseudo-type handling. Cheaper than [`parse_message`]
+/// when the footers are not needed.
+pub fn parse_subject(subject: &str, pseudo_types: &PseudoTypesConfig) -> Result<Option<Header>> {
+    Ok(match resolve_subject(subject, pseudo_types)? {
+        ResolvedSubject::Regular(subject) => parse_header(subject),
+        ResolvedSubject::Mapped { commit_type, inner } => Some(Header {
+            commit_type,
+            scope: inner.and_then(parse_header).and_then(|h| h.scope),
+            breaking: false,
+            description: inner.unwrap_or(subject).to_string(),
+        }),
+        ResolvedSubject::Ignored => None,
+    })
+}
+
+/// Parses the full commit message, applying the pseudo-type handling to the subject.
+///
+/// Reverts mapped onto a type produce a commit of that type with the reverted header's scope and
+/// the reverted subject as the description.
+pub fn parse_message(
+    message: &str,
+    pseudo_types: &PseudoTypesConfig,
+) -> Result<Option<ConventionalCommit>> {
+    let subject = message.lines().next().unwrap_or("");
+    let header = parse_subject(subject, pseudo_types)?;
+
+    Ok(header.map(|header| ConventionalCommit {
+        header,
+        footers: parse_footers(message),
+    }))
+}
+
+/// Trailer of the commit message, i.e. `Refs: #123` or `BREAKING CHANGE: foo`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Footer {
+    pub token: String,
+    pub value: String,
+}
+
+impl Footer {
+    pub fn is_breaking_change(&self) -> bool {
+        self.token == "BREAKING CHANGE" || self.token == "BREAKING-CHANGE"
+    }
+}
+
+/// Extracts the footers from the full commit message.
+///
+/// Per the spec, footers are in the last paragraph of the message (and the subject can't be a
+/// footer). Each footer is either `Token: value` or `Token #value`; lines that do not look like a
+/// footer are considered to be continuations of the previous footer's value.
+pub fn parse_footers(message: &str) -> Vec<Footer> {
+    let regex = Regex::new(r"^(BREAKING[ -]CHANGE|[\w-]+)(?:: | #)(.*)$").unwrap();
+
+    let paragraphs: Vec<&str> = message
+        .trim()
+        .split("\n\n")
+        .filter(|p| !p.trim().is_empty())
+        .collect();
+
+    // Only subject => no footers
+    if paragraphs.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut res: Vec<Footer> = Vec::new();
+    for line in paragraphs.last().unwrap().lines() {
+        match regex.captures(line).ok().flatten() {
+            Some(captures) => res.push(Footer {
+                token: captures[1].to_string(),
+                // `Token #value` form keeps the hash as a part of the value
+                value: match line[captures[1].len()..].starts_with(" #") {
+                    true => format!("#{}", &captures[2]),
+                    false => captures[2].to_string(),
+                },
+            }),
+            None => match res.last_mut() {
+                Some(footer) => {
+                    footer.value.push('\n');
+                    footer.value.push_str(line);
+                }
+                // First line is not a footer => the paragraph is just the body
+                None => return Vec::new(),
+            },
+        }
+    }
+
+    res
+}
+
+/// Lines of the footers paragraph that look like footers, but break the syntax, with the reason.
+///
+/// Only the last paragraph is checked. A misspelled `BREAKING CHANGE` is reported anywhere in it,
+/// tokens with spaces are only reported if the paragraph starts with a footer (otherwise it's
+/// likely a part of the body).
+pub fn malformed_footers(message: &str) -> Vec<(String, &'static str)> {
+    let breaking = Regex::new(r"(?i)^breaking[ -]change(?:: | #)").unwrap();
+    let spaced_token = Regex::new(r"^[\w-]+(?: [\w-]+)+(?:: | #)").unwrap();
+
+    let paragraphs: Vec<&str> = message
+        .trim()
+        .split(
+            "
+
+",
+        )
+        .filter(|p| !p.trim().is_empty())
+        .collect();
+    if paragraphs.len() < 2 {
+        return Vec::new();
+    }
+    let starts_with_footer = !parse_footers(message).is_empty();
+
+    paragraphs
+        .last()
+        .unwrap()
+        .lines()
+        .filter_map(|line| {
+            let is_match = |regex: &Regex| regex.is_match(line).unwrap_or(false);
+            if is_match(&breaking) {
+                (!line.starts_with("BREAKING CHANGE") && !line.starts_with("BREAKING-CHANGE"))
+                    .then_some("BREAKING CHANGE must be uppercase")
+            } else if starts_with_footer && is_match(&spaced_token) {
+                Some("footer tokens use - instead of spaces")
+            } else {
+                None
+            }
+            .map(|reason| (line.to_string(), reason))
+        })
+        .collect()
+}
+
+/// Drops the comment lines git would strip from a message being edited, including everything
+/// below the `git commit --verbose` scissors line
+pub fn strip_comments(message: &str) -> String {
+    message
+        .lines()
+        .take_while(|line| !line.starts_with("# ------------------------ >8"))
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Same as `git commit --cleanup=strip`: drops the comments, the trailing whitespace and the
+/// repeated blank lines, as well as the blank lines at the start and at the end
+pub fn cleanup_message(message: &str) -> String {
+    let stripped = strip_comments(message);
+    let mut lines: Vec<&str> = Vec::new();
+    for line in stripped.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+/// Shortcut to get the pseudo-type settings from an optional config
+pub fn pseudo_types_config(config: &Option<Config>) -> PseudoTypesConfig {
+    config
+        .as_ref()
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.pseudo_types.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::regular("feat(foo): bar", None)]
+    #[case::revert(r#"Revert "feat(foo): bar""#, Some((PseudoType::Revert, Some("feat(foo): bar"))))]
+    #[case::revert_of_revert(
+        r#"Revert "Revert "feat(foo): bar"""#,
+        Some((PseudoType::Revert, Some(r#"Revert "feat(foo): bar""#)))
+    )]
+    #[case::merge_branch("Merge branch 'foo' into master", Some((PseudoType::Merge, None)))]
+    #[case::merge_pr("Merge pull request #1 from foo/bar", Some((PseudoType::Merge, None)))]
+    // Not a pseudo-type, just happens to start with the word
+    #[case::mergeable("Mergeable things", None)]
+    fn detects_pseudo_types(
+        #[case] summary: &str,
+        #[case] expected: Option<(PseudoType, Option<&str>)>,
+    ) {
+        assert_eq!(detect_pseudo_type(summary), expected);
+    }
+
+    #[test]
+    fn default_handling() {
+        let config = PseudoTypesConfig::default();
+
+        assert_eq!(
+            resolve_subject(r#"Revert "feat(foo): bar""#, &config).unwrap(),
+            ResolvedSubject::Mapped {
+                commit_type: "revert".to_string(),
+                inner: Some("feat(foo): bar")
+            }
+        );
+        assert_eq!(
+            resolve_subject("Merge branch 'foo'", &config).unwrap(),
+            ResolvedSubject::Ignored
+        );
+        assert_eq!(
+            resolve_subject("feat: foo", &config).unwrap(),
+            ResolvedSubject::Regular("feat: foo")
+        );
+    }
+
+    #[rstest]
+    #[case::simple("feat: foo", Some(("feat", None, false, "foo")))]
+    #[case::scope("fix(bar baz): foo", Some(("fix", Some("bar baz"), false, "foo")))]
+    #[case::breaking("feat(bar)!: foo", Some(("feat", Some("bar"), true, "foo")))]
+    #[case::breaking_no_scope("feat!: foo: bar", Some(("feat", None, true, "foo: bar")))]
+    #[case::no_space("feat:foo", None)]
+    #[case::not_conventional("Initial commit", None)]
+    fn parses_headers(
+        #[case] subject: &str,
+        #[case] expected: Option<(&str, Option<&str>, bool, &str)>,
+    ) {
+        let expected = expected.map(|(commit_type, scope, breaking, description)| Header {
+            commit_type: commit_type.to_string(),
+            scope: scope.map(String::from),
+            breaking,
+            description: description.to_string(),
+        });
+
+        assert_eq!(parse_header(subject), expected);
+    }
+
+    #[test]
+    fn parses_reverts() {
+        let res = parse_message(
+            "Revert \"feat(foo): bar\"\n\nThis reverts commit abc.",
+            &PseudoTypesConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(res.header.commit_type, "revert");
+        assert_eq!(res.header.scope, Some("foo".to_string()));
+        assert_eq!(res.header.description, "feat(foo): bar");
+    }
+
+    #[test]
+    fn breaking_from_footer() {
+        let res = parse_message(
+            "feat: foo\n\nBREAKING CHANGE: bar",
+            &PseudoTypesConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(!res.header.breaking);
+        assert!(res.is_breaking());
+        assert_eq!(res.breaking_change_footer().unwrap().value, "bar");
+    }
+
+    #[rstest]
+    #[case::subject_only("feat: foo", vec![])]
+    #[case::body_only("feat: foo\n\nSome body", vec![])]
+    #[case::colon("feat: foo\n\nbody\n\nRefs: #123", vec![("Refs", "#123")])]
+    #[case::hash("feat: foo\n\nCloses #12\nReviewed-by: Z", vec![("Closes", "#12"), ("Reviewed-by", "Z")])]
+    #[case::breaking(
+        "feat: foo\n\nBREAKING CHANGE: bar\n  continued",
+        vec![("BREAKING CHANGE", "bar\n  continued")]
+    )]
+    fn parses_footers(#[case] message: &str, #[case] expected: Vec<(&str, &str)>) {
+        let expected: Vec<Footer> = expected
+            .into_iter()
+            .map(|(token, value)| Footer {
+                token: token.to_string(),
+                value: value.to_string(),
+            })
+            .collect();
+
+        assert_eq!(parse_footers(message), expected);
+    }
+
+    #[rstest]
+    #[case::valid("feat: foo\n\nbody\n\nRefs: #1\nBREAKING CHANGE: bar", vec![])]
+    #[case::body_sentence("feat: foo\n\nNote that this is: fine", vec![])]
+    #[case::lowercase_breaking("feat: foo\n\nbreaking change: bar", vec!["breaking change: bar"])]
+    #[case::spaced_token("feat: foo\n\nRefs: #1\nReviewed by: Z", vec!["Reviewed by: Z"])]
+    fn finds_malformed_footers(#[case] message: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(
+            malformed_footers(message)
+                .iter()
+                .map(|(line, _)| line.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn strips_comments() {
+        let message = "feat: foo\n# Please enter the commit message\n\nbody\n# ------------------------ >8 ------------------------\ndiff";
+
+        assert_eq!(strip_comments(message), "feat: foo\n\nbody\n");
+    }
+
+    #[rstest]
+    #[case::empty("", "")]
+    #[case::comments_only("# Type: feat\n#\n", "")]
+    #[case::surrounding_blank_lines("\n\nbody\n\n", "body")]
+    #[case::repeated_blank_lines("body\n\n\n\nRefs: #1\n", "body\n\nRefs: #1")]
+    #[case::trailing_whitespace("body  \n \nRefs: #1", "body\n\nRefs: #1")]
+    #[case::comments_between("body\n# comment\n\nRefs: #1", "body\n\nRefs: #1")]
+    fn cleans_up(#[case] message: &str, #[case] expected: &str) {
+        assert_eq!(cleanup_message(message), expected);
+    }
+
+    #[test]
+    fn error_handling() {
+        let config = PseudoTypesConfig {
+            revert: PseudoTypeAction::Ignore,
+            merge: PseudoTypeAction::Error,
+        };
+
+        assert_eq!(
+            resolve_subject(r#"Revert "feat(foo): bar""#, &config).unwrap(),
+            ResolvedSubject::Ignored
+        );
+        assert!(resolve_subject("Merge branch 'foo'", &config).is_err());
+    }
+}
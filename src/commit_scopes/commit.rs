@@ -1,13 +1,26 @@
-use anyhow::{Context, Result};
+//! Things that deal with the repository go here
+
+use chrono::{DateTime, Utc};
 use fancy_regex::Regex;
-use git2::{Commit, Repository, Status};
+use git2::{
+    Commit, DescribeFormatOptions, DescribeOptions, Oid, Repository, Sort, Status, StatusOptions,
+};
 use itertools::any;
-use log::{info, trace, warn};
+use log::{debug, info, trace, warn};
+use rayon::prelude::*;
 use std::collections::{HashMap, HashSet};
 
 use super::CommitScope;
-
-/// Things that deal with the repository go here
+use crate::cache::{count_changes, ScopeCounts};
+use crate::commit_message::{pseudo_types_config, resolve_subject, ResolvedSubject};
+use crate::config::{
+    Config, MiningStrategy, PseudoTypesConfig, DEFAULT_CACHE_REQUIRED_THRESHOLD,
+    DEFAULT_RECENT_THRESHOLD, DEFAULT_RECENT_WINDOW, SINCE_LATEST_TAG,
+};
+use crate::error::{Error, Result};
+use crate::sanitize::sanitize_scope;
+use crate::scope_name::ScopeNameValidator;
+use crate::utils::{head_commit, parse_since};
 
 // As a design decision, I am working with file names and not paths. The key point of this
 // structure is to be able to quickly compare two sets of changed files by names. As a first
@@ -17,6 +30,23 @@ use super::CommitScope;
 // structure to hashset of paths.
 pub type ChangedFiles = HashSet<String>;
 
+/// Below this many commits to diff, the diffs are computed on the calling thread. Opening the
+/// repository in the worker threads is not worth it for the incremental updates
+const PARALLEL_DIFF_THRESHOLD: usize = 64;
+
+/// Files changed by a commit with a scope
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScopeChange {
+    pub scope: CommitScope,
+    pub files: ChangedFiles,
+    /// Commit time
+    pub time: DateTime<Utc>,
+}
+
+pub(crate) fn commit_time(commit: &Commit) -> DateTime<Utc> {
+    DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default()
+}
+
 /// Returns the list of changed files
 ///
 /// Using hashset to explicitly denote that there is no order
@@ -32,14 +62,16 @@ fn get_changed_files_from_diff(diff: &git2::Diff) -> ChangedFiles {
     res
 }
 
-pub(super) fn get_changed_files_from_commit(
+pub(crate) fn get_changed_files_from_commit(
     commit: &Commit,
     repo: &Repository,
 ) -> Result<ChangedFiles> {
     let mut res = HashSet::new();
-    let this_commit_tree = commit
-        .tree()
-        .with_context(|| format!("Failed to get tree for commit {}", commit.id()))?;
+    let changed_files_error = |source| Error::ChangedFiles {
+        commit: commit.id().to_string(),
+        source,
+    };
+    let this_commit_tree = commit.tree().map_err(changed_files_error)?;
 
     if commit.parent_count() == 0 {
         // Handle initial commit by diffing against an empty tree
@@ -47,9 +79,7 @@ pub(super) fn get_changed_files_from_commit(
         res.extend(get_changed_files_from_diff(&diff));
     } else {
         for parent in commit.parents() {
-            let parent_tree = parent
-                .tree()
-                .with_context(|| format!("Failed to get tree for parent commit {}", parent.id()))?;
+            let parent_tree = parent.tree().map_err(changed_files_error)?;
             let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&this_commit_tree), None)?;
             res.extend(get_changed_files_from_diff(&diff));
         }
@@ -62,6 +92,10 @@ pub(super) fn get_changed_files_from_commit(
 ///
 /// No files staged -- return None
 pub fn get_staged_files(repo: &Repository) -> Result<Option<ChangedFiles>> {
+    // Bare repositories have no index to stage into
+    if repo.is_bare() {
+        return Ok(None);
+    }
     let needed_statuses = [
         Status::INDEX_NEW,                            // new staged files
         Status::INDEX_MODIFIED,                       // files fully staged for commit
@@ -99,6 +133,60 @@ pub fn get_staged_files(repo: &Repository) -> Result<Option<ChangedFiles>> {
     Ok((!paths.is_empty()).then_some(paths))
 }
 
+/// Files with only some of their changes staged (i.e. with `git add -p`). Those usually are what
+/// the commit is about, unlike the fully staged files that may be incidental
+pub fn get_partially_staged_files(repo: &Repository) -> Result<ChangedFiles> {
+    if repo.is_bare() {
+        return Ok(ChangedFiles::new());
+    }
+    let needed_statuses = [
+        Status::INDEX_MODIFIED | Status::WT_MODIFIED,
+        Status::INDEX_NEW | Status::WT_MODIFIED,
+    ];
+
+    Ok(repo
+        .statuses(None)?
+        .iter()
+        .filter(|x| needed_statuses.contains(&x.status()))
+        .filter_map(|x| x.path().map(|p| p.to_string()))
+        .collect())
+}
+
+/// Tracked files with changes in the worktree that are not staged
+pub fn get_unstaged_files(repo: &Repository) -> Result<ChangedFiles> {
+    if repo.is_bare() {
+        return Ok(ChangedFiles::new());
+    }
+    let unstaged =
+        Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE;
+
+    Ok(repo
+        .statuses(None)?
+        .iter()
+        .filter(|x| x.status().intersects(unstaged))
+        .filter_map(|x| x.path().map(|p| p.to_string()))
+        .collect())
+}
+
+/// New files in the worktree that were not `git add`ed yet. Ignored files are not included
+pub fn get_untracked_files(repo: &Repository) -> Result<ChangedFiles> {
+    if repo.is_bare() {
+        return Ok(ChangedFiles::new());
+    }
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+
+    Ok(repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter(|x| x.status() == Status::WT_NEW)
+        .filter_map(|x| x.path().map(|p| p.to_string()))
+        .collect())
+}
+
 /// Given a single commit message, tries to find a scope in it
 fn get_scope_from_commit_message(message: &str) -> Option<String> {
     trace!("Checking git commit message {:?}", message);
@@ -125,88 +213,364 @@ fn get_scope_from_commit_message(message: &str) -> Option<String> {
             warn!("Returning None");
             None
         })
-        .map(|m| m.as_str().to_string())
+        .and_then(|m| sanitize_scope(m.as_str()))
 }
 
+/// Same as [`get_scope_from_commit_message`], but applies the pseudo-types handling first.
+///
+/// Reverts and merges are not parsed as is, see the pseudo_types config. Pseudo-types configured
+/// as "error" are skipped like the ignored ones: the ones already in the history should not fail
+/// the mining.
+pub fn get_scope_from_summary(summary: &str, pseudo_types: &PseudoTypesConfig) -> Option<String> {
+    match resolve_subject(summary, pseudo_types) {
+        Ok(ResolvedSubject::Regular(subject)) => get_scope_from_commit_message(subject),
+        Ok(ResolvedSubject::Mapped { inner, .. }) => inner.and_then(get_scope_from_commit_message),
+        Ok(ResolvedSubject::Ignored) => None,
+        Err(e) => {
+            info!("Skipping the subject: {}", e);
+            None
+        }
+    }
+}
+
+/// Mines the history without the cache: the files changed in each scope, how many commits used it
+/// and when it was last used. How far back is up to the mining strategy, see [`strategy_limits`]
 pub fn get_scopes_x_changes(
     repo: &Repository,
-) -> Result<Option<HashMap<CommitScope, ChangedFiles>>> {
+    config: &Option<Config>,
+) -> Result<Option<HashMap<CommitScope, ScopeCounts>>> {
+    let Some(head) = head_commit(repo)? else {
+        info!("No commits yet, nothing to mine");
+        return Ok(None);
+    };
+
+    let res = count_changes(repo, config, head, strategy_limits(repo, config, head)?)?;
+
+    Ok((!res.is_empty()).then_some(res))
+}
+
+/// How far back [`get_commit_changes`] walks, see `general.scopes.max_commits`,
+/// `general.scopes.since` and `general.scopes.since_ref`. Not limited by default
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct HistoryLimits {
+    /// Commits walked, with a scope or not
+    pub max_commits: Option<usize>,
+    /// Commits authored before are skipped
+    pub since: Option<DateTime<Utc>>,
+    /// Commits reachable from this one are not walked
+    pub stop_at: Option<Oid>,
+}
+
+/// Latest tag reachable from HEAD, matching `versioning.tag_prefix` if set
+fn latest_release_tag(repo: &Repository, config: &Option<Config>) -> Result<Option<Oid>> {
+    let mut options = DescribeOptions::new();
+    options.describe_tags();
+    let pattern = config
+        .as_ref()
+        .and_then(|c| c.versioning.tag_prefix.as_ref())
+        .map(|prefix| format!("{}*", prefix));
+    if let Some(pattern) = &pattern {
+        options.pattern(pattern);
+    }
+
+    let Some(head) = head_commit(repo)? else {
+        return Ok(None);
+    };
+    let tag = match repo.find_commit(head)?.as_object().describe(&options) {
+        Ok(describe) => describe.format(Some(DescribeFormatOptions::new().abbreviated_size(0)))?,
+        Err(e) => {
+            info!(
+                "No release tag found ({}), mining the whole history",
+                e.message()
+            );
+            return Ok(None);
+        }
+    };
+    debug!("Mining the history since {:?}", tag);
+
+    Ok(Some(repo.revparse_single(&tag)?.peel_to_commit()?.id()))
+}
+
+/// Commit `general.scopes.since_ref` points to. None if it's not set or does not exist
+pub(crate) fn resolve_since_ref(
+    repo: &Repository,
+    config: &Option<Config>,
+    rev: &str,
+) -> Result<Option<Oid>> {
+    if rev == SINCE_LATEST_TAG {
+        return latest_release_tag(repo, config);
+    }
+
+    match repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+    {
+        Ok(commit) => Ok(Some(commit.id())),
+        Err(e) => {
+            warn!(
+                "general.scopes.since_ref {:?} not found ({}), mining the whole history",
+                rev,
+                e.message()
+            );
+            Ok(None)
+        }
+    }
+}
+
+impl HistoryLimits {
+    pub fn from_config(repo: &Repository, config: &Option<Config>) -> Result<Self> {
+        let Some(scopes) = config
+            .as_ref()
+            .and_then(|c| c.general.as_ref())
+            .and_then(|g| g.scopes.as_ref())
+        else {
+            return Ok(Self::default());
+        };
+        let since = match scopes.since.as_deref() {
+            Some(since) => Some(parse_since(since).ok_or_else(|| Error::ConfigInvalid {
+                path: None,
+                line: None,
+                message: format!(
+                    "general.scopes.since: {:?} is neither a date nor a time ago",
+                    since
+                ),
+            })?),
+            None => None,
+        };
+
+        let stop_at = match scopes.since_ref.as_deref() {
+            Some(rev) => resolve_since_ref(repo, config, rev)?,
+            None => None,
+        };
+
+        Ok(Self {
+            max_commits: scopes.max_commits,
+            since,
+            stop_at,
+        })
+    }
+}
+
+/// Commits reachable from `head`, counted up to `limit`
+fn count_commits(repo: &Repository, head: Oid, limit: usize) -> Result<usize> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(head)?;
+    Ok(revwalk.take(limit).count())
+}
+
+/// Limits of mining the history reachable from `head` without the cache, according to
+/// `general.scopes.strategy`. Fails with [`Error::CacheRequired`] if the history should not be
+/// mined at all
+pub fn strategy_limits(
+    repo: &Repository,
+    config: &Option<Config>,
+    head: Oid,
+) -> Result<HistoryLimits> {
+    let limits = HistoryLimits::from_config(repo, config)?;
+    let scopes = config
+        .as_ref()
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.scopes.clone())
+        .unwrap_or_default();
+    let recent_window = scopes.recent_window.unwrap_or(DEFAULT_RECENT_WINDOW);
+
+    let strategy = match scopes.strategy.unwrap_or_default() {
+        MiningStrategy::Auto
+            if scopes.max_commits.is_some()
+                || scopes.since.is_some()
+                || scopes.since_ref.is_some() =>
+        {
+            debug!("History limits are configured, mining within them");
+            MiningStrategy::Full
+        }
+        MiningStrategy::Auto => {
+            let recent_threshold = scopes.recent_threshold.unwrap_or(DEFAULT_RECENT_THRESHOLD);
+            let cache_required_threshold = scopes
+                .cache_required_threshold
+                .unwrap_or(DEFAULT_CACHE_REQUIRED_THRESHOLD);
+            // Counting stops at the largest threshold, there's no need to walk further
+            let commits =
+                count_commits(repo, head, recent_threshold.max(cache_required_threshold))?;
+            let strategy = match commits {
+                n if n >= cache_required_threshold => MiningStrategy::CacheRequired,
+                n if n >= recent_threshold => MiningStrategy::Recent,
+                _ => MiningStrategy::Full,
+            };
+            info!(
+                "{} commits (or more), mining strategy {:?}",
+                commits, strategy
+            );
+            strategy
+        }
+        strategy => strategy,
+    };
+
+    match strategy {
+        MiningStrategy::Auto | MiningStrategy::Full => Ok(limits),
+        MiningStrategy::Recent => Ok(HistoryLimits {
+            max_commits: Some(
+                limits
+                    .max_commits
+                    .map_or(recent_window, |max| max.min(recent_window)),
+            ),
+            ..limits
+        }),
+        MiningStrategy::CacheRequired => Err(Error::CacheRequired),
+    }
+}
+
+fn author_time(commit: &Commit) -> DateTime<Utc> {
+    DateTime::from_timestamp(commit.author().when().seconds(), 0).unwrap_or_default()
+}
+
+/// Returns the scope and the changed files of every scoped commit reachable from `from`, but not
+/// from `hide`, within the limits
+pub fn get_commit_changes(
+    repo: &Repository,
+    config: &Option<Config>,
+    from: Oid,
+    hide: Option<Oid>,
+    limits: HistoryLimits,
+) -> Result<Vec<ScopeChange>> {
     // idea:
     // Have an accumulator
     // Walk through the repo using reflog?
     // For every commit, if there is a scope in the message -- get its diff and append to the
     // accumulator
 
+    let pseudo_types = pseudo_types_config(config);
+    let scope_names = ScopeNameValidator::from_config(config)?;
+
     let mut revwalk = repo.revwalk()?;
-    // Set the walk from the HEAD
-    revwalk.push_head()?;
-
-    let res = revwalk.fold(
-        // let res = repo.revwalk()?.push_head().iter().fold(
-        HashMap::<CommitScope, ChangedFiles>::new(),
-        |mut acc, revwalk_entry| {
-            match revwalk_entry {
-                Ok(oid) => {
-                    // Record the scope and the changed files in the accumulator.
-                    // If scope does not exist -- insert it
-                    // If it exists -- append the changed files to the set
-
-                    // PERF: this looks like a potentially unneeded lookup. If performance starts to suffer --
-                    // might be worth refactoring this
-                    let commit = match repo.find_commit(oid) {
-                        Ok(c) => c,
-                        Err(e) => {
-                            warn!("Failed to find commit {}: {}", oid, e);
-                            return acc;
-                        }
-                    };
-
-                    trace!("Checking commit OID {:?}", commit.id());
-                    let summary = if let Some(s) = commit.summary() {
-                        s
-                    } else {
-                        warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
-                        return acc;
-                    };
-                    let scope = get_scope_from_commit_message(summary);
-                    if let Some(extracted_scope) = scope {
-                        let scope_obj = CommitScope::new(extracted_scope);
-                        let changed_files = match get_changed_files_from_commit(&commit, repo) {
-                            Ok(files) => files,
-                            Err(e) => {
-                                warn!(
-                                    "Failed to get changed files for commit {}: {}",
-                                    commit.id(),
-                                    e
-                                );
-                                return acc;
-                            }
-                        };
-
-                        if let Some(existing_changed_files) = acc.get_mut(&scope_obj) {
-                            existing_changed_files.extend(changed_files);
-                        } else {
-                            acc.insert(scope_obj, changed_files);
-                        }
-                    };
-                }
-                Err(e) => {
-                    warn!("Encountered error {:?}", e);
-                    // Short circuit back
-                }
+    revwalk.push(from)?;
+    if let Some(hide) = hide {
+        revwalk.hide(hide)?;
+    }
+    if let Some(stop_at) = limits.stop_at {
+        revwalk.hide(stop_at)?;
+    }
+    if limits.since.is_some() {
+        revwalk.set_sorting(Sort::TIME)?;
+    }
+
+    let mut candidates = Vec::new();
+    let mut walked = 0;
+    for revwalk_entry in revwalk.take(limits.max_commits.unwrap_or(usize::MAX)) {
+        walked += 1;
+        let oid = match revwalk_entry {
+            Ok(oid) => oid,
+            Err(e) => {
+                warn!("Encountered error {:?}", e);
+                continue;
+            }
+        };
+        // PERF: this looks like a potentially unneeded lookup. If performance starts to suffer --
+        // might be worth refactoring this
+        let commit = match repo.find_commit(oid) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Failed to find commit {}: {}", oid, e);
+                continue;
             }
+        };
 
-            acc
-        },
-    );
+        if let Some(since) = limits.since {
+            // Newest commits go first, the rest were committed (and so authored) before `since`
+            if commit_time(&commit) < since {
+                info!("Reached the commits before {}, stopping", since);
+                break;
+            }
+            if author_time(&commit) < since {
+                continue;
+            }
+        }
 
-    Ok((!res.is_empty()).then_some(res))
+        trace!("Checking commit OID {:?}", commit.id());
+        let summary = if let Some(s) = commit.summary() {
+            s
+        } else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        let scope = get_scope_from_summary(summary, &pseudo_types);
+        if let Some(extracted_scope) = scope {
+            if let Err(e) = scope_names.parse(&extracted_scope) {
+                info!(
+                    "Skipping scope {:?} of commit {}: the name {}",
+                    extracted_scope,
+                    commit.id(),
+                    e
+                );
+                continue;
+            }
+            candidates.push((commit.id(), extracted_scope, commit_time(&commit)));
+        };
+    }
+    if limits.max_commits == Some(walked) {
+        info!("Walked {} commits, the older ones are not mined", walked);
+    }
+
+    Ok(diff_commits(repo, candidates))
+}
+
+/// Files changed by every commit, in the same order. Commits whose diff fails are skipped.
+///
+/// Diffing takes most of the time of a cold scan, so long lists are split between threads. A
+/// [`Repository`] can't be shared between threads, every thread opens its own
+fn diff_commits(
+    repo: &Repository,
+    candidates: Vec<(Oid, String, DateTime<Utc>)>,
+) -> Vec<ScopeChange> {
+    let diff = |repo: &Repository, (oid, scope, time): (Oid, String, DateTime<Utc>)| match repo
+        .find_commit(oid)
+        .map_err(Error::from)
+        .and_then(|commit| get_changed_files_from_commit(&commit, repo))
+    {
+        Ok(files) => Some(ScopeChange {
+            scope: CommitScope::new(scope),
+            files,
+            time,
+        }),
+        Err(e) => {
+            warn!("Failed to get changed files for commit {}: {}", oid, e);
+            None
+        }
+    };
+
+    if candidates.len() < PARALLEL_DIFF_THRESHOLD {
+        return candidates
+            .into_iter()
+            .filter_map(|candidate| diff(repo, candidate))
+            .collect();
+    }
+
+    debug!("Diffing {} commits in parallel", candidates.len());
+    let path = repo.path().to_path_buf();
+    candidates
+        .into_par_iter()
+        .map_init(
+            || Repository::open(&path),
+            |thread_repo, candidate| match thread_repo {
+                Ok(thread_repo) => diff(thread_repo, candidate),
+                Err(e) => {
+                    warn!("Failed to open {:?} in a worker thread: {}", path, e);
+                    None
+                }
+            },
+        )
+        .flatten()
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use conventional_commit_helper::test_utils::setup_repo_with_commits_and_files;
+    use crate::cache::scope_files;
+    use crate::test_utils::{
+        replay_fixture, setup_repo_with_commits, setup_repo_with_commits_and_files,
+    };
+    use indoc::indoc;
+    use itertools::Itertools;
     use rstest::rstest;
     use std::fs::OpenOptions;
     use std::io::Write;
@@ -264,6 +628,40 @@ mod tests {
         let expected = expected.map(|s| HashSet::from([s.to_string()]));
 
         assert_eq!(get_staged_files(&repo).unwrap(), expected);
+        assert_eq!(
+            get_partially_staged_files(&repo).unwrap(),
+            match (stage_full_file, filename) {
+                (Some(false), Some(filename)) => mk_set([filename]),
+                _ => HashSet::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_untracked_files() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        std::fs::create_dir_all(dir.join("new_dir")).unwrap();
+        std::fs::write(dir.join("new_dir/file"), "").unwrap();
+        std::fs::write(dir.join("ignored"), "").unwrap();
+        std::fs::write(dir.join(".gitignore"), "ignored\n").unwrap();
+        std::fs::write(dir.join("default_file"), "modified").unwrap();
+
+        assert_eq!(
+            get_untracked_files(&repo).unwrap(),
+            mk_set(["new_dir/file", ".gitignore"])
+        );
+    }
+
+    #[test]
+    fn test_unstaged_files() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(&dir, &["init", "foo"], &["one", "two"]);
+        std::fs::write(dir.join("one"), "modified").unwrap();
+        std::fs::remove_file(dir.join("two")).unwrap();
+        std::fs::write(dir.join("untracked"), "").unwrap();
+
+        assert_eq!(get_unstaged_files(&repo).unwrap(), mk_set(["one", "two"]));
     }
 
     fn mk_set(s: impl IntoIterator<Item = impl AsRef<str>>) -> HashSet<String> {
@@ -330,7 +728,9 @@ mod tests {
             &["init", "one", "two"],           // files
         );
 
-        let res = get_scopes_x_changes(&repo).unwrap();
+        let res = get_scopes_x_changes(&repo, &None)
+            .unwrap()
+            .map(|counts| scope_files(&counts));
 
         let expected: HashMap<CommitScope, ChangedFiles> =
             HashMap::from([(CommitScope::new("foz".to_string()), mk_set(["one"]))]);
@@ -353,7 +753,9 @@ mod tests {
             &["init", "one", "two", "three", "two"], // files
         );
 
-        let res = get_scopes_x_changes(&repo).unwrap();
+        let res = get_scopes_x_changes(&repo, &None)
+            .unwrap()
+            .map(|counts| scope_files(&counts));
 
         let expected: HashMap<CommitScope, ChangedFiles> = HashMap::from([
             (CommitScope::new("foz".to_string()), mk_set(["one", "two"])),
@@ -365,4 +767,337 @@ mod tests {
 
         assert_eq!(res, Some(expected));
     }
+
+    /// Reverts keep the scope of the reverted commit, merges are ignored by default
+    #[test]
+    fn test_get_scopes_x_files_pseudo_types() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &[
+                "init",
+                r#"Revert "foo(foz): bar""#,
+                "Merge branch 'foo(baz): bar'",
+            ], // commit msgs
+            &["init", "one", "two"], // files
+        );
+
+        let res = get_scopes_x_changes(&repo, &None)
+            .unwrap()
+            .map(|counts| scope_files(&counts));
+
+        let expected: HashMap<CommitScope, ChangedFiles> =
+            HashMap::from([(CommitScope::new("foz".to_string()), mk_set(["one"]))]);
+
+        assert_eq!(res, Some(expected));
+    }
+
+    /// Pseudo-types not allowed by the config are skipped, the rest of the history is mined
+    #[test]
+    fn test_get_scopes_x_files_pseudo_type_error() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &["init", "feat(foo): bar", "Merge branch 'feat(baz): qux'"],
+            &["init", "one", "two"],
+        );
+        let config = Config::from_str(indoc! {r#"
+            [general.pseudo_types]
+            merge = "error"
+            "#})
+        .unwrap();
+
+        let res = get_scopes_x_changes(&repo, &Some(config))
+            .unwrap()
+            .map(|counts| scope_files(&counts));
+
+        let expected: HashMap<CommitScope, ChangedFiles> =
+            HashMap::from([(CommitScope::new("foo".to_string()), mk_set(["one"]))]);
+
+        assert_eq!(res, Some(expected));
+    }
+
+    /// Scopes with names not matching `[scope_names]` are not mined
+    #[test]
+    fn test_get_scopes_x_files_scope_names() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &["init", "feat(foo): bar", "feat(Foo_Bar): baz"],
+            &["init", "one", "two"],
+        );
+        let config = Config::from_str(indoc! {r#"
+            [scope_names]
+            charset = "a-z"
+            "#})
+        .unwrap();
+
+        let res = get_scopes_x_changes(&repo, &Some(config))
+            .unwrap()
+            .map(|counts| scope_files(&counts));
+
+        let expected: HashMap<CommitScope, ChangedFiles> =
+            HashMap::from([(CommitScope::new("foo".to_string()), mk_set(["one"]))]);
+
+        assert_eq!(res, Some(expected));
+    }
+
+    /// Nothing to mine before the first commit, mining from a detached HEAD works as usual
+    #[test]
+    fn test_get_scopes_x_files_head_states() {
+        let dir = testdir!();
+        let empty = Repository::init(dir.join("empty")).unwrap();
+        assert_eq!(get_scopes_x_changes(&empty, &None).unwrap(), None);
+
+        let repo =
+            setup_repo_with_commits(&dir.join("repo"), &["init", "feat(foo): a", "feat(bar): b"]);
+        repo.set_head_detached(repo.revparse_single("HEAD~1").unwrap().id())
+            .unwrap();
+        assert_eq!(
+            get_scopes_x_changes(&repo, &None)
+                .unwrap()
+                .unwrap_or_default()
+                .into_keys()
+                .map(|scope| scope.name)
+                .collect::<Vec<_>>(),
+            vec!["foo"]
+        );
+    }
+
+    /// Long histories are diffed in parallel, the result is the same
+    #[test]
+    fn test_get_scopes_x_files_parallel() {
+        let dir = testdir!();
+        let count = PARALLEL_DIFF_THRESHOLD * 2;
+        let messages = (0..count)
+            .map(|i| format!("feat(s{}): {}", i % 3, i))
+            .collect::<Vec<_>>();
+        let files = (0..count).map(|i| format!("f{}", i)).collect::<Vec<_>>();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &messages.iter().map(String::as_str).collect::<Vec<_>>(),
+            &files.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+
+        let res = get_scopes_x_changes(&repo, &None)
+            .unwrap()
+            .map(|counts| scope_files(&counts));
+
+        let expected: HashMap<CommitScope, ChangedFiles> = (0..3)
+            .map(|scope| {
+                (
+                    CommitScope::new(format!("s{}", scope)),
+                    files.iter().skip(scope).step_by(3).cloned().collect(),
+                )
+            })
+            .collect();
+        assert_eq!(res, Some(expected));
+    }
+
+    /// Only the newest commits are mined with `max_commits` and `since`
+    #[rstest]
+    #[case::max_commits("max_commits = 2", &["bar", "baz"])]
+    #[case::since_date("since = \"2023-11-14T22:14:00Z\"", &["bar", "baz"])]
+    #[case::both("max_commits = 1\nsince = \"2023-11-14\"", &["baz"])]
+    #[case::since_ago("since = \"1 day\"", &[])]
+    fn test_get_scopes_x_files_limits(#[case] limits: &str, #[case] expected: &[&str]) {
+        let dir = testdir!();
+        // Commits are a minute apart, starting at 2023-11-14T22:13:20Z
+        let repo = replay_fixture(
+            &dir,
+            &serde_json::from_str(
+                r#"{"commits": [
+                    {"message": "feat(foo): a", "files": ["one"]},
+                    {"message": "feat(bar): b", "files": ["two"]},
+                    {"message": "feat(baz): c", "files": ["three"]}
+                ]}"#,
+            )
+            .unwrap(),
+        );
+        let config = Config::from_str(&format!("[general.scopes]\n{}\n", limits)).unwrap();
+
+        let res = get_scopes_x_changes(&repo, &Some(config)).unwrap();
+
+        assert_eq!(
+            res.unwrap_or_default()
+                .into_keys()
+                .map(|scope| scope.name)
+                .sorted()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    /// Commits before the latest tag (or the ref) are not mined with `since_ref`
+    #[rstest]
+    #[case::latest_tag("since_ref = \"latest-tag\"", &["baz"])]
+    #[case::tag_prefix(
+        "since_ref = \"latest-tag\"\n[versioning]\ntag_prefix = \"v\"",
+        &["bar", "baz"]
+    )]
+    #[case::rev("since_ref = \"HEAD~2\"", &["bar", "baz"])]
+    #[case::missing_rev("since_ref = \"nope\"", &["bar", "baz", "foo"])]
+    fn test_get_scopes_x_files_since_ref(#[case] limits: &str, #[case] expected: &[&str]) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &["init", "feat(foo): a", "feat(bar): b", "feat(baz): c"],
+            &["init", "one", "two", "three"],
+        );
+        let tag = |name: &str, rev: &str| {
+            let object = repo.revparse_single(rev).unwrap();
+            repo.tag_lightweight(name, &object, false).unwrap();
+        };
+        tag("v0.1.0", "HEAD~2");
+        tag("cache-v0.1.0", "HEAD~1");
+        let config = Config::from_str(&format!("[general.scopes]\n{}\n", limits)).unwrap();
+
+        let res = get_scopes_x_changes(&repo, &Some(config)).unwrap();
+
+        assert_eq!(
+            res.unwrap_or_default()
+                .into_keys()
+                .map(|scope| scope.name)
+                .sorted()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    /// The strategy picked by the size of the repository limits the mining
+    #[rstest]
+    #[case::small_repo("recent_threshold = 5\nrecent_window = 1", Some(&["bar", "baz", "foo"][..]))]
+    #[case::medium_repo("recent_threshold = 4\nrecent_window = 1", Some(&["baz"][..]))]
+    #[case::large_repo("cache_required_threshold = 4", None)]
+    #[case::configured_limits("cache_required_threshold = 4\nmax_commits = 2", Some(&["bar", "baz"][..]))]
+    #[case::full("strategy = \"full\"\ncache_required_threshold = 1", Some(&["bar", "baz", "foo"][..]))]
+    #[case::recent("strategy = \"recent\"\nrecent_window = 2", Some(&["bar", "baz"][..]))]
+    #[case::cache_required("strategy = \"cache_required\"", None)]
+    fn test_get_scopes_x_files_strategy(#[case] strategy: &str, #[case] expected: Option<&[&str]>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &["init", "feat(foo): a", "feat(bar): b", "feat(baz): c"],
+            &["init", "one", "two", "three"],
+        );
+        let config = Config::from_str(&format!("[general.scopes]\n{}\n", strategy)).unwrap();
+
+        let res = get_scopes_x_changes(&repo, &Some(config));
+
+        match expected {
+            Some(expected) => assert_eq!(
+                res.unwrap()
+                    .unwrap_or_default()
+                    .into_keys()
+                    .map(|scope| scope.name)
+                    .sorted()
+                    .collect::<Vec<_>>(),
+                expected
+            ),
+            None => assert!(matches!(res, Err(Error::CacheRequired))),
+        }
+    }
+
+    #[test]
+    fn invalid_since() {
+        assert!(Config::from_str("[general.scopes]\nsince = \"last tuesday\"\n").is_err());
+    }
+
+    /// Property tests: random messages built from the characters the parser cares about
+    mod properties {
+        use super::*;
+        use crate::config::PseudoTypeAction;
+
+        const ITERATIONS: usize = 256;
+        const PIECES: &[&str] = &[
+            "feat",
+            "(",
+            ")",
+            "!",
+            ":",
+            " ",
+            "-",
+            "_",
+            "\"",
+            "\n",
+            "Revert \"",
+            "Merge ",
+            "scope",
+            "é",
+            "🦀",
+            "\\",
+            "((",
+            "):",
+            ")!:",
+        ];
+
+        fn gen_message(rng: &mut fastrand::Rng) -> String {
+            (0..rng.usize(0..20))
+                .map(|_| match rng.bool() {
+                    true => rng.choice(PIECES).unwrap().to_string(),
+                    false => rng.char(..).to_string(),
+                })
+                .collect()
+        }
+
+        fn gen_word(rng: &mut fastrand::Rng) -> String {
+            (0..rng.usize(1..10))
+                .map(|_| match rng.u8(0..10) {
+                    0 => '-',
+                    1 => '_',
+                    _ => rng.alphanumeric(),
+                })
+                .collect()
+        }
+
+        /// Parser never panics, whatever the pseudo-type handling is
+        #[test]
+        fn never_panics() {
+            let mut rng = fastrand::Rng::with_seed(2734);
+            let actions = [
+                PseudoTypeAction::Ignore,
+                PseudoTypeAction::Error,
+                PseudoTypeAction::Type("revert".to_string()),
+            ];
+
+            for _ in 0..ITERATIONS {
+                let message = gen_message(&mut rng);
+                let pseudo_types = PseudoTypesConfig {
+                    revert: rng.choice(&actions).unwrap().clone(),
+                    merge: rng.choice(&actions).unwrap().clone(),
+                };
+
+                let _ = get_scope_from_summary(&message, &pseudo_types);
+            }
+        }
+
+        /// Scope of a well-formed header is always found, the extracted scope is always clean
+        #[test]
+        fn well_formed_header() {
+            let mut rng = fastrand::Rng::with_seed(2734);
+
+            for _ in 0..ITERATIONS {
+                let scope = gen_word(&mut rng);
+                let breaking = if rng.bool() { "!" } else { "" };
+                let message = format!(
+                    "{}({}){}: {}",
+                    gen_word(&mut rng),
+                    scope,
+                    breaking,
+                    gen_message(&mut rng)
+                );
+                assert_eq!(
+                    get_scope_from_commit_message(&message),
+                    Some(scope),
+                    "{:?}",
+                    message
+                );
+
+                let message = gen_message(&mut rng);
+                if let Some(scope) = get_scope_from_commit_message(&message) {
+                    assert_eq!(sanitize_scope(&scope), Some(scope.clone()), "{:?}", message);
+                }
+            }
+        }
+    }
 }
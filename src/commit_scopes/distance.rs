@@ -1,101 +1,215 @@
-use itertools::sorted;
+//! This file contains the logic to help with calculating the most appropriate scope
+//!
+//! Public as part of the library API: [`find_closest_neighbor_weighted`] (or
+//! [`find_closest_neighbor`] when nothing is partially staged) picks the scope for a set of staged
+//! files, [`find_scopes_for_file`] the scopes of a single file. Both take the scope ->
+//! files map, i.e. from [`super::get_scope_map`].
+//!
+//! The idea put broadly is:
+//! Given a set of files staged for commit("set_1") and a set of pairs {(scope: { files previously
+//! changed as part of that scope }) ... }("set_2"), which of the "scope" entries' files set is
+//! closest to the set of currently staged files set_1?
+//!
+//! The plan is:
+//! 1. ✓ Write closest match search naive implementation
+//! 2. ✓ Use code from here to actually guess the scope
+//! 3. ✓ Consider making the searc path-aware
+//! 4. Maybe generalize the algorithm to turn into a metric (see below)
+//!
+//! Some thoughts on the implementation:
+//!
+//! S = "scope", F = "set of files matching the scope":
+//! set_2 := { (S_1, F_1), ... }
+//!
+//! Trivial cases:
+//!
+//! (math symbols used the way author's grug brain remembers/understands them)
+//!
+//! 1. Complete match:
+//!
+//! ```text
+//! ∃F_i ∈ set_2 | set_1 = F_i => S_i
+//! ```
+//!
+//! 2. Staged files are a subset of one of the scopes' sets:
+//!
+//! ```text
+//! ∃F_i ∈ set_2 | set_1 ⊆ F_i
+//!   & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
+//! => S_i
+//! ```
+//!
+//! 3. If staged files are a superset of one of the scopes' sets, then find the set F which has the
+//!    biggest overlap with F without considering the partial order(is that the right term?) of the
+//!    F sets.
+//!
+//! ```text
+//! ∃F_i ∈ set_2 | set_1 ⊃ F_i
+//!   & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
+//! => S_i
+//! ```
+//!
+//! 4. If set_1 does not overlap with any F_i, then find the biggest overlap
+//!
+//! ```text
+//! ∃F_i ∈ set_2 | set_1 ⊅ F_i & set_1 ⊄ F_i
+//!   & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
+//! => S_i
+//! ```
+//!
+//! This all rolls up to a simple statement "find me all sets F from set_2 that have the largest
+//! overlap with set_1".
+//!
+//! Naive tie breaker: if multiple scopes are found -- alphanumeric sort
+//!
+//! Path awareness logic:
+//!
+//! Since sets of paths in the form of { "foo", "bar/baz", "quot/qux/qoz", ... }, the _path_
+//! similarity could be considered for selecting the closest neighbor. I.e. if I changed something
+//! in "bar/baz" and I have a scope that has changed something in "bar/foz" -- they might match.
+//!
+//! How about this:
+//!
+//! 1. Construct a prefix tree from the all F sets in set_2. The nodes will be annotated with the scopes
+//! 2. Construct a prefix tree from set_1
+//! 3. Find scopes that annotating nodes from the overlap
+//!
+//! ?
+//!
+//! Degenerate case would need to be handled -- there could be two or more scopes with exactly one
+//! overlap...
+//!
+//! On the technical level this could be implemented by prefix trees. Note to self -- don't just
+//! split the string on "/", poor unfortunate souls on Windows won't be happy.
+//!
+//! Implemented as [`PathTrie`]: directories of all F sets, each annotated with the scopes that
+//! changed something under it. The staged files are walked down the tree, every scope met on the
+//! way scores the depth of the deepest directory it shares with the file. Exact file overlap still
+//! wins, the directories are only looked at if no staged file was ever changed in any scope.
+//!
+//! Stretch goals:
+//!
+//! Turn "closest" neighbor into more of a metric so that the list of scopes can be sorted in terms
+//! of how close of a match it is.
+//!
+//! Naive approach is to recursively sort the set_2 by comparing set_1 with set_2 / {(S_i, F_i)}
+//! where S_i was chosen on previous step. This is probably horrible performance-wise.
+
+use itertools::{sorted, Itertools};
 use log::info;
+use serde::Serialize;
 
 use super::CommitScope;
 
 use super::commit::ChangedFiles;
 use std::cmp::Ordering::{Equal, Greater, Less};
 use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
 
-/// This file contains the logic to help with calculating the most appropriate scope
-///
-/// The idea put broadly is:
-/// Given a set of files staged for commit("set_1") and a set of pairs {(scope: { files previously
-/// changed as part of that scope }) ... }("set_2"), which of the "scope" entries' files set is
-/// closest to the set of currently staged files set_1?
-///
-/// The plan is:
-/// 1. ✓ Write closest match search naive implementation
-/// 2. ✓ Use code from here to actually guess the scope
-/// 3. Consider making the searc path-aware
-/// 4. Maybe generalize the algorithm to turn into a metric (see below)
-///
-/// Some thoughts on the implementation:
-///
-/// S = "scope", F = "set of files matching the scope":
-/// set_2 := { (S_1, F_1), ... }
-///
-/// Trivial cases:
-///
-/// (math symbols used the way author's grug brain remembers/understands them)
-///
-/// 1. Complete match:
-///
-///     ∃F_i ∈ set_2 | set_1 = F_i => S_i
-///
-/// 2. Staged files are a subset of one of the scopes' sets:
-///
-///     ∃F_i ∈ set_2 | set_1 ⊆ F_i
-///       & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
-///     => S_i
-///
-/// 3. If staged files are a superset of one of the scopes' sets, then find the set F which has the
-///    biggest overlap with F without considering the partial order(is that the right term?) of the
-///    F sets.
-///
-///     ∃F_i ∈ set_2 | set_1 ⊃ F_i
-///       & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
-///     => S_i
-///
-/// 4. If set_1 does not overlap with any F_i, then find the biggest overlap
-///
-///     ∃F_i ∈ set_2 | set_1 ⊅ F_i & set_1 ⊄ F_i
-///       & (∀F_j F_j != F_i | |set_1 - F_i| < |set_1 - F_j| )
-///     => S_i
-///
-/// This all rolls up to a simple statement "find me all sets F from set_2 that have the largest
-/// overlap with set_1".
-///
-/// Naive tie breaker: if multiple scopes are found -- alphanumeric sort
-///
-/// Path awareness logic:
-///
-/// Since sets of paths in the form of { "foo", "bar/baz", "quot/qux/qoz", ... }, the _path_
-/// similarity could be considered for selecting the closest neighbor. I.e. if I changed something
-/// in "bar/baz" and I have a scope that has changed something in "bar/foz" -- they might match.
-///
-/// How about this:
-///
-/// 1. Construct a prefix tree from the all F sets in set_2. The nodes will be annotated with the scopes
-/// 2. Construct a prefix tree from set_1
-/// 3. Find scopes that annotating nodes from the overlap
-///
-/// ?
-///
-/// Degenerate case would need to be handled -- there could be two or more scopes with exactly one
-/// overlap...
-///
-/// On the technical level this could be implemented by prefix trees. Note to self -- don't just
-/// split the string on "/", poor unfortunate souls on Windows won't be happy.
-///
-/// Stretch goals:
-///
-/// Turn "closest" neighbor into more of a metric so that the list of scopes can be sorted in terms
-/// of how close of a match it is.
-///
-/// Naive approach is to recursively sort the set_2 by comparing set_1 with set_2 / {(S_i, F_i)}
-/// where S_i was chosen on previous step. This is probably horrible performance-wise.
-///
+/// How much a partially staged file counts towards the overlap, compared to a fully staged one
+const PARTIALLY_STAGED_WEIGHT: usize = 2;
+
+fn file_weight(file: &str, partially_staged_files: &ChangedFiles) -> usize {
+    match partially_staged_files.contains(file) {
+        true => PARTIALLY_STAGED_WEIGHT,
+        false => 1,
+    }
+}
+
+/// Directories of the path, from the topmost one. Split by the platform rules
+fn dirs(path: &Path) -> impl Iterator<Item = &OsStr> {
+    path.parent()
+        .into_iter()
+        .flat_map(|parent| parent.components())
+        .map(|component| component.as_os_str())
+}
+
+/// Prefix tree of the directories of the files changed in the scopes
+#[derive(Debug, Default)]
+struct PathTrie {
+    children: HashMap<OsString, PathTrie>,
+    /// Scopes that changed something under the directory
+    scopes: HashSet<CommitScope>,
+}
+
+impl PathTrie {
+    fn new(scope_set: &HashMap<CommitScope, ChangedFiles>) -> Self {
+        let mut root = Self::default();
+        for (scope, files) in scope_set {
+            for file in files {
+                let mut node = &mut root;
+                for dir in dirs(Path::new(file)) {
+                    node = node.children.entry(dir.to_os_string()).or_default();
+                    node.scopes.insert(scope.clone());
+                }
+            }
+        }
+        root
+    }
+
+    /// Scopes sharing directories with the file -> number of the shared directories
+    fn shared_dirs(&self, file: &str) -> HashMap<&CommitScope, usize> {
+        let mut res = HashMap::new();
+        let mut node = self;
+        for (depth, dir) in dirs(Path::new(file)).enumerate() {
+            let Some(child) = node.children.get(dir) else {
+                break;
+            };
+            node = child;
+            res.extend(node.scopes.iter().map(|scope| (scope, depth + 1)));
+        }
+        res
+    }
+}
+
+/// Scope -> sum of the directories shared with the staged files, weighted. Scopes sharing none
+/// are not in the map
+fn path_scores<'a>(
+    trie: &'a PathTrie,
+    staged_files: &ChangedFiles,
+    partially_staged_files: &ChangedFiles,
+) -> HashMap<&'a CommitScope, usize> {
+    let mut scores = HashMap::new();
+    for file in staged_files {
+        let weight = file_weight(file, partially_staged_files);
+        for (scope, depth) in trie.shared_dirs(file) {
+            *scores.entry(scope).or_default() += depth * weight;
+        }
+    }
+    scores
+}
+
+/// Scopes with the largest sum of the directories shared with the staged files
+fn find_by_path(
+    staged_files: &ChangedFiles,
+    partially_staged_files: &ChangedFiles,
+    scope_set: &HashMap<CommitScope, ChangedFiles>,
+) -> HashSet<CommitScope> {
+    let trie = PathTrie::new(scope_set);
+    let scores = path_scores(&trie, staged_files, partially_staged_files);
+
+    let max_score = scores.values().max().copied().unwrap_or(0);
+    scores
+        .into_iter()
+        .filter(|(_, score)| *score == max_score)
+        .map(|(scope, _)| scope.clone())
+        .collect()
+}
 
 fn find_by_overlap(
-    staged_files: ChangedFiles,
-    scope_set: HashMap<CommitScope, ChangedFiles>,
+    staged_files: &ChangedFiles,
+    partially_staged_files: &ChangedFiles,
+    scope_set: &HashMap<CommitScope, ChangedFiles>,
 ) -> HashSet<CommitScope> {
     scope_set
         .iter()
-        // Go through the set, constructing pairs (scope, count_of_overlapping_items)
+        // Go through the set, constructing pairs (scope, weighted_count_of_overlapping_items)
         .map(|(scope, set)| {
-            let overlap = staged_files.intersection(set).count();
+            let overlap = staged_files
+                .intersection(set)
+                .map(|file| file_weight(file, partially_staged_files))
+                .sum::<usize>();
             (scope, overlap)
         })
         .fold(
@@ -118,16 +232,164 @@ fn find_by_overlap(
         .1 // return only the aggregated hashset
 }
 
-pub fn find_closest_neighbor(
+/// Finds the scope whose files overlap the most with the staged files. Partially staged files (a
+/// subset of the staged ones) weigh more.
+///
+/// If none of the staged files was changed in a scope before (i.e. they are new), the scope
+/// sharing the most directories with them is picked
+pub fn find_closest_neighbor_weighted(
     staged_files: ChangedFiles,
+    partially_staged_files: &ChangedFiles,
     scope_set: HashMap<CommitScope, ChangedFiles>,
 ) -> Option<CommitScope> {
-    info!("Staged files: {:?}", staged_files);
-    let res = find_by_overlap(staged_files, scope_set);
+    info!(
+        "Staged files: {:?}, partially staged: {:?}",
+        staged_files, partially_staged_files
+    );
+    let mut res = find_by_overlap(&staged_files, partially_staged_files, &scope_set);
+    if res.is_empty() {
+        info!("No scope changed the staged files, matching by the directories");
+        res = find_by_path(&staged_files, partially_staged_files, &scope_set);
+    }
 
     sorted(res).collect::<Vec<CommitScope>>().first().cloned()
 }
 
+/// Same as [`find_closest_neighbor_weighted`] with no partially staged files
+pub fn find_closest_neighbor(
+    staged_files: ChangedFiles,
+    scope_set: HashMap<CommitScope, ChangedFiles>,
+) -> Option<CommitScope> {
+    find_closest_neighbor_weighted(staged_files, &ChangedFiles::new(), scope_set)
+}
+
+/// Weights of the two sources of the files of a scope in [`find_closest_neighbor_combined`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SourceWeights {
+    /// Files changed in the scope before
+    pub history: usize,
+    /// Files matching the paths declared for the scope
+    pub paths: usize,
+}
+
+/// Same as [`find_closest_neighbor_weighted`], with the staged files matching the paths declared
+/// for the scopes (`declared_set`) counted too. The overlap with each of the sets is weighted with
+/// `weights` and summed, so a scope whose history drifted from its declared paths is still found
+/// by either of them.
+///
+/// The directories are only looked at in the history, and only if nothing overlaps
+pub fn find_closest_neighbor_combined(
+    staged_files: ChangedFiles,
+    partially_staged_files: &ChangedFiles,
+    scope_set: HashMap<CommitScope, ChangedFiles>,
+    declared_set: &HashMap<CommitScope, ChangedFiles>,
+    weights: SourceWeights,
+) -> Option<CommitScope> {
+    let overlap = |files: &ChangedFiles| {
+        staged_files
+            .intersection(files)
+            .map(|file| file_weight(file, partially_staged_files))
+            .sum::<usize>()
+    };
+    let scores: HashMap<&CommitScope, usize> = scope_set
+        .keys()
+        .chain(declared_set.keys())
+        .unique()
+        .map(|scope| {
+            let history = scope_set.get(scope).map_or(0, overlap);
+            let declared = declared_set.get(scope).map_or(0, overlap);
+            (scope, weights.history * history + weights.paths * declared)
+        })
+        .collect();
+
+    let max_score = scores.values().max().copied().unwrap_or(0);
+    let res = match max_score {
+        0 => {
+            info!("No scope matches the staged files, matching by the directories");
+            find_by_path(&staged_files, partially_staged_files, &scope_set)
+        }
+        _ => scores
+            .into_iter()
+            .filter(|(_, score)| *score == max_score)
+            .map(|(scope, _)| scope.clone())
+            .collect(),
+    };
+
+    sorted(res).next()
+}
+
+/// Raw numbers [`find_closest_neighbor_weighted`] decides on, for clients doing their own scoring
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct MatchMetrics {
+    /// Staged files that were changed in the scope before
+    pub overlap: usize,
+    /// Same as `overlap` with the partially staged files counted twice
+    pub weighted_overlap: usize,
+    /// Directories shared with the staged files, summed over the staged files and weighted the
+    /// same way
+    pub shared_dirs: usize,
+    /// Files ever changed in the scope
+    pub files: usize,
+}
+
+/// [`MatchMetrics`] of every scope in the set
+pub fn match_metrics(
+    staged_files: &ChangedFiles,
+    partially_staged_files: &ChangedFiles,
+    scope_set: &HashMap<CommitScope, ChangedFiles>,
+) -> HashMap<CommitScope, MatchMetrics> {
+    let trie = PathTrie::new(scope_set);
+    let shared_dirs = path_scores(&trie, staged_files, partially_staged_files);
+
+    scope_set
+        .iter()
+        .map(|(scope, files)| {
+            let overlap = staged_files.intersection(files).collect_vec();
+            let metrics = MatchMetrics {
+                overlap: overlap.len(),
+                weighted_overlap: overlap
+                    .iter()
+                    .map(|file| file_weight(file, partially_staged_files))
+                    .sum(),
+                shared_dirs: shared_dirs.get(scope).copied().unwrap_or_default(),
+                files: files.len(),
+            };
+            (scope.clone(), metrics)
+        })
+        .collect()
+}
+
+/// Finds the scopes a single file belongs to.
+///
+/// Scopes that changed the file itself win. If there are none -- the scopes that changed files
+/// sharing the deepest common directory with it are returned. Results are sorted alphanumerically.
+pub fn find_scopes_for_file(
+    file: &str,
+    scope_set: &HashMap<CommitScope, ChangedFiles>,
+) -> Vec<CommitScope> {
+    let exact: Vec<CommitScope> = scope_set
+        .iter()
+        .filter(|(_, files)| files.contains(file))
+        .map(|(scope, _)| scope.clone())
+        .collect();
+
+    if !exact.is_empty() {
+        return sorted(exact).collect();
+    }
+
+    let trie = PathTrie::new(scope_set);
+    // No common directories at all is not a match, the map is empty then
+    let shared_dirs = trie.shared_dirs(file);
+    let depth = shared_dirs.values().max().copied().unwrap_or(0);
+
+    shared_dirs
+        .into_iter()
+        .filter(|(_, d)| *d == depth)
+        .map(|(scope, _)| scope.clone())
+        .sorted()
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use rstest::{fixture, rstest};
@@ -282,4 +544,253 @@ mod test {
 
         assert_eq!(find_closest_neighbor(staged_files, haystack), None);
     }
+
+    /// Partially staged file outweighs a fully staged one
+    #[rstest]
+    fn test_partially_staged_weighs_more(
+        needle: CommitScope,
+        cruft: CommitScope,
+        staged_files: ChangedFiles,
+    ) {
+        let haystack = HashMap::from([
+            (needle.clone(), HashSet::from(["foo".to_string()])),
+            (cruft.clone(), HashSet::from(["bar".to_string()])),
+        ]);
+
+        // Tie is broken alphanumerically
+        assert_eq!(
+            find_closest_neighbor(staged_files.clone(), haystack.clone()),
+            Some(cruft)
+        );
+        assert_eq!(
+            find_closest_neighbor_weighted(
+                staged_files,
+                &HashSet::from(["foo".to_string()]),
+                haystack
+            ),
+            Some(needle)
+        );
+    }
+
+    /// Declared paths and the history add up, each with its weight
+    #[rstest]
+    #[case::history_only(1, 0, Some("cruft"))]
+    #[case::paths_only(0, 1, Some("needle"))]
+    #[case::combined(1, 1, Some("needle"))]
+    #[case::history_weighs_more(3, 1, Some("cruft"))]
+    fn test_combined_with_declared_paths(
+        needle: CommitScope,
+        cruft: CommitScope,
+        #[case] history: usize,
+        #[case] paths: usize,
+        #[case] expected: Option<&str>,
+    ) {
+        let staged: ChangedFiles = HashSet::from(["foo", "bar", "baz"].map(String::from));
+        // The history of needle drifted away from the staged files
+        let haystack = HashMap::from([
+            (needle.clone(), HashSet::from(["foo".to_string()])),
+            (cruft, HashSet::from(["foo", "bar"].map(String::from))),
+        ]);
+        let declared = HashMap::from([(needle, HashSet::from(["bar", "baz"].map(String::from)))]);
+
+        assert_eq!(
+            find_closest_neighbor_combined(
+                staged,
+                &ChangedFiles::new(),
+                haystack,
+                &declared,
+                SourceWeights { history, paths }
+            )
+            .map(|scope| scope.name),
+            expected.map(String::from)
+        );
+    }
+
+    /// New files match the scopes by their directories
+    #[rstest]
+    #[case::same_dir(&["src/cache/new.rs"], Some("needle"))]
+    #[case::deepest_dir(&["src/cache/nested/new.rs"], Some("needle"))]
+    #[case::most_shared_dirs(&["src/cache/new.rs", "src/cli/new.rs", "docs/new.md"], Some("cruft"))]
+    #[case::top_level(&["new.rs"], None)]
+    fn test_match_by_path(
+        needle: CommitScope,
+        cruft: CommitScope,
+        #[case] staged: &[&str],
+        #[case] expected: Option<&str>,
+    ) {
+        let haystack = HashMap::from([
+            (needle, HashSet::from(["src/cache/a.rs".to_string()])),
+            (
+                cruft,
+                HashSet::from(["src/cli/b.rs".to_string(), "docs/c.md".to_string()]),
+            ),
+        ]);
+
+        assert_eq!(
+            find_closest_neighbor(staged.iter().map(|s| s.to_string()).collect(), haystack)
+                .map(|scope| scope.name),
+            expected.map(String::from)
+        );
+    }
+
+    #[rstest]
+    fn test_match_metrics(needle: CommitScope, cruft: CommitScope) {
+        let haystack = HashMap::from([
+            (
+                needle.clone(),
+                HashSet::from(["src/a.rs".to_string(), "src/b.rs".to_string()]),
+            ),
+            (cruft.clone(), HashSet::from(["docs/c.md".to_string()])),
+        ]);
+        let staged = HashSet::from(["src/a.rs".to_string(), "src/new.rs".to_string()]);
+
+        let res = match_metrics(&staged, &HashSet::from(["src/a.rs".to_string()]), &haystack);
+
+        assert_eq!(
+            res[&needle],
+            MatchMetrics {
+                overlap: 1,
+                weighted_overlap: 2,
+                shared_dirs: 3,
+                files: 2,
+            }
+        );
+        assert_eq!(
+            res[&cruft],
+            MatchMetrics {
+                files: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::exact("src/foo/a.rs", vec!["needle"])]
+    #[case::same_dir("src/foo/new.rs", vec!["needle"])]
+    #[case::common_parent("src/new/new.rs", vec!["cruft", "needle"])]
+    #[case::no_common_dirs("new.rs", vec![])]
+    fn test_scopes_for_file(
+        needle: CommitScope,
+        cruft: CommitScope,
+        #[case] file: &str,
+        #[case] expected: Vec<&str>,
+    ) {
+        let haystack = HashMap::from([
+            (needle, HashSet::from(["src/foo/a.rs".to_string()])),
+            (
+                cruft,
+                HashSet::from(["src/bar/b.rs".to_string(), "README.md".to_string()]),
+            ),
+        ]);
+
+        assert_eq!(
+            find_scopes_for_file(file, &haystack)
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    /// Property tests over random scope sets
+    mod properties {
+        use super::*;
+
+        const ITERATIONS: usize = 500;
+
+        fn gen_path(rng: &mut fastrand::Rng) -> String {
+            // Few names so that the paths overlap
+            const NAMES: &[&str] = &["src", "lib", "foo", "bar", "a.rs", "b.rs", "c.md"];
+            (0..rng.usize(1..4))
+                .map(|_| *rng.choice(NAMES).unwrap())
+                .collect::<Vec<_>>()
+                .join("/")
+        }
+
+        fn gen_files(rng: &mut fastrand::Rng) -> ChangedFiles {
+            (0..rng.usize(0..6)).map(|_| gen_path(rng)).collect()
+        }
+
+        fn gen_scope_set(rng: &mut fastrand::Rng) -> HashMap<CommitScope, ChangedFiles> {
+            (0..rng.usize(0..6))
+                .map(|i| (CommitScope::new(format!("scope{}", i)), gen_files(rng)))
+                .collect()
+        }
+
+        /// Same scope set built in a different order
+        fn reordered(
+            rng: &mut fastrand::Rng,
+            scope_set: &HashMap<CommitScope, ChangedFiles>,
+        ) -> HashMap<CommitScope, ChangedFiles> {
+            let mut entries = scope_set.clone().into_iter().collect::<Vec<_>>();
+            rng.shuffle(&mut entries);
+            entries.into_iter().collect()
+        }
+
+        /// Number of leading directories the two paths have in common
+        fn common_dirs(left: &str, right: &str) -> usize {
+            dirs(Path::new(left))
+                .zip(dirs(Path::new(right)))
+                .take_while(|(l, r)| l == r)
+                .count()
+        }
+
+        /// Suggested scope is one of the candidates with the largest overlap or, without any
+        /// overlap, one sharing directories with the staged files. The result does not depend on
+        /// the order of the candidates
+        #[test]
+        fn closest_neighbor() {
+            let mut rng = fastrand::Rng::with_seed(2734);
+
+            for _ in 0..ITERATIONS {
+                let staged = gen_files(&mut rng);
+                let scope_set = gen_scope_set(&mut rng);
+                let overlap = |scope: &CommitScope| staged.intersection(&scope_set[scope]).count();
+                let max_overlap = scope_set.keys().map(overlap).max().unwrap_or(0);
+                let shares_dirs = |scope: &CommitScope| {
+                    scope_set[scope]
+                        .iter()
+                        .any(|file| staged.iter().any(|s| common_dirs(s, file) > 0))
+                };
+
+                let res = find_closest_neighbor(staged.clone(), scope_set.clone());
+
+                match &res {
+                    Some(scope) => {
+                        assert!(scope_set.contains_key(scope));
+                        assert_eq!(overlap(scope), max_overlap);
+                        assert!(max_overlap > 0 || shares_dirs(scope));
+                    }
+                    None => {
+                        assert_eq!(max_overlap, 0);
+                        assert!(!scope_set.keys().any(shares_dirs));
+                    }
+                }
+                assert_eq!(
+                    find_closest_neighbor(staged.clone(), reordered(&mut rng, &scope_set)),
+                    res
+                );
+            }
+        }
+
+        /// Scopes found for a file are sorted candidates, independent of their order
+        #[test]
+        fn scopes_for_file() {
+            let mut rng = fastrand::Rng::with_seed(2734);
+
+            for _ in 0..ITERATIONS {
+                let file = gen_path(&mut rng);
+                let scope_set = gen_scope_set(&mut rng);
+
+                let res = find_scopes_for_file(&file, &scope_set);
+
+                assert!(res.iter().all(|scope| scope_set.contains_key(scope)));
+                assert!(res.windows(2).all(|pair| pair[0] < pair[1]));
+                assert_eq!(
+                    find_scopes_for_file(&file, &reordered(&mut rng, &scope_set)),
+                    res
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,587 @@
+//! Scopes derived from the layout of the repository ([`Source::InferredPath`]), so that there are
+//! useful scopes even without a conventional history or a handwritten config.
+//!
+//! Every provider lists scopes with the paths they cover. The scopes are offered as if they were
+//! configured (the configured ones win) and their paths are matched against the staged files as if
+//! they were in `[scope_paths]`. Providers are toggled in the `[providers]` section of the config.
+//!
+//! The files are read from the index: they are the ones tracked or staged, the untracked ones (i.e.
+//! `node_modules`) are not looked at.
+//!
+//! [`Source::InferredPath`]: crate::entity::Source::InferredPath
+use git2::{Index, Pathspec, PathspecFlags, Repository};
+use log::debug;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::config::{Config, DirectoriesProviderConfig, DEFAULT_IGNORED_DIRECTORIES};
+use crate::error::Result;
+use crate::scope_name::ScopeNameValidator;
+
+use super::CommitScope;
+
+/// Scope found in the layout of the repository
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InferredScope {
+    pub scope: CommitScope,
+    /// Git pathspecs of the files of the scope, as in `[scope_paths]`
+    pub paths: Vec<String>,
+}
+
+/// Content of the file in the index, None if it's not there
+fn read_indexed(repo: &Repository, index: &Index, path: &str) -> Result<Option<String>> {
+    let Some(entry) = index.get_path(Path::new(path), 0) else {
+        return Ok(None);
+    };
+    let blob = repo.find_blob(entry.id)?;
+
+    Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+}
+
+/// Items of the `packages` list of `pnpm-workspace.yaml`. Only the block (`- item`) and the flow
+/// (`[item, ...]`) lists of plain strings are understood, which is what pnpm documents
+fn pnpm_packages(yaml: &str) -> Vec<String> {
+    let unquote = |item: &str| item.trim().trim_matches(['"', '\'']).to_string();
+    let mut res = Vec::new();
+    let mut lines = yaml
+        .lines()
+        .skip_while(|line| !line.starts_with("packages:"));
+    let Some(key) = lines.next() else {
+        return res;
+    };
+
+    match key["packages:".len()..].trim() {
+        "" => {
+            for line in lines {
+                let line = line.split(" #").next().unwrap_or_default();
+                match line.trim_start().strip_prefix('-') {
+                    Some(item) => res.push(unquote(item)),
+                    None if line.trim().is_empty() => (),
+                    // Next key
+                    None => break,
+                }
+            }
+        }
+        flow => res.extend(
+            flow.trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(unquote)
+                .filter(|item| !item.is_empty()),
+        ),
+    }
+
+    res
+}
+
+/// Workspace patterns of the root `package.json` (npm, yarn: a list or `{"packages": [...]}`)
+/// and of `pnpm-workspace.yaml`
+fn workspace_patterns(repo: &Repository, index: &Index) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+
+    if let Some(content) = read_indexed(repo, index, "package.json")? {
+        match serde_json::from_str::<Value>(&content) {
+            Ok(package) => {
+                let workspaces = &package["workspaces"];
+                let patterns = workspaces
+                    .as_array()
+                    .or_else(|| workspaces["packages"].as_array());
+                res.extend(
+                    patterns
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|pattern| pattern.as_str().map(String::from)),
+                );
+            }
+            Err(e) => debug!("Skipping the invalid package.json: {}", e),
+        }
+    }
+
+    if let Some(content) = read_indexed(repo, index, "pnpm-workspace.yaml")? {
+        res.extend(pnpm_packages(&content));
+    }
+
+    Ok(res)
+}
+
+/// Package names of the JS workspaces, without the npm scope (`@acme/ui` is "ui"). The
+/// description comes from the `package.json` of the package
+fn js_workspaces(
+    repo: &Repository,
+    index: &Index,
+    validator: &ScopeNameValidator,
+) -> Result<Vec<InferredScope>> {
+    let patterns = workspace_patterns(repo, index)?;
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+    debug!("Found the JS workspaces {:?}", patterns);
+    // `!pattern` excludes the packages. Empty pathspec matches everything, so it's not built
+    let (excluded, included): (Vec<&String>, Vec<&String>) = patterns
+        .iter()
+        .partition(|pattern| pattern.starts_with('!'));
+    let to_pathspec = |patterns: Vec<&str>| {
+        (!patterns.is_empty())
+            .then(|| Pathspec::new(patterns))
+            .transpose()
+    };
+    let included = to_pathspec(included.iter().map(|p| p.as_str()).collect())?;
+    let excluded = to_pathspec(excluded.iter().map(|p| &p[1..]).collect())?;
+    let matches = |pathspec: &Option<Pathspec>, dir: &str| {
+        pathspec
+            .as_ref()
+            .is_some_and(|pathspec| pathspec.matches_path(Path::new(dir), PathspecFlags::DEFAULT))
+    };
+
+    let package_dirs: Vec<String> = index
+        .iter()
+        .filter_map(|entry| String::from_utf8(entry.path).ok())
+        .filter_map(|path| path.strip_suffix("/package.json").map(String::from))
+        .filter(|dir| !dir.split('/').any(|component| component == "node_modules"))
+        .filter(|dir| matches(&included, dir) && !matches(&excluded, dir))
+        .collect();
+
+    let mut res = Vec::new();
+    for dir in package_dirs {
+        let Some(content) = read_indexed(repo, index, &format!("{}/package.json", dir))? else {
+            continue;
+        };
+        let package: Value = match serde_json::from_str(&content) {
+            Ok(package) => package,
+            Err(e) => {
+                debug!("Skipping the invalid {}/package.json: {}", dir, e);
+                continue;
+            }
+        };
+        let Some(name) = package["name"].as_str() else {
+            continue;
+        };
+        let name = name.rsplit('/').next().unwrap_or(name);
+        if let Err(e) = validator.parse(name) {
+            debug!("Skipping the package {:?} of {}: {}", name, dir, e);
+            continue;
+        }
+
+        res.push(InferredScope {
+            scope: CommitScope::with_description(
+                name,
+                package["description"].as_str().unwrap_or_default(),
+            ),
+            paths: vec![format!("{}/*", dir)],
+        });
+    }
+
+    Ok(res)
+}
+
+/// Locations of `CODEOWNERS` in the order GitHub looks them up, the first one found is used
+const CODEOWNERS_PATHS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Git pathspecs matching the same files as the `CODEOWNERS` (gitignore-like) pattern with the last
+/// path segment without wildcards, `/src/cache/` is "cache". None if there's no such segment (i.e.
+/// `*.md`)
+fn codeowners_pattern(pattern: &str) -> Option<(String, Vec<String>)> {
+    let is_glob = |segment: &str| segment.contains(['*', '?', '[']);
+    let trimmed = pattern.trim_end_matches("/**").trim_end_matches('/');
+    // Patterns with a slash (but at the end) are relative to the root, the others (and `**/...`)
+    // match at any level
+    let (anchored, trimmed) = match trimmed.strip_prefix('/') {
+        Some(trimmed) => (true, trimmed),
+        None => match trimmed.strip_prefix("**/") {
+            Some(trimmed) => (false, trimmed),
+            None => (trimmed.contains('/'), trimmed),
+        },
+    };
+    let name = trimmed
+        .split('/')
+        .rfind(|segment| !segment.is_empty() && !is_glob(segment))?;
+    let paths = match anchored {
+        true => vec![trimmed.to_string()],
+        false => vec![trimmed.to_string(), format!("*/{}", trimmed)],
+    };
+
+    Some((name.to_string(), paths))
+}
+
+/// Scopes from the path patterns of `CODEOWNERS`, see [`codeowners_pattern`]. The description is
+/// the owners of the first pattern of the scope
+fn codeowners(
+    repo: &Repository,
+    index: &Index,
+    validator: &ScopeNameValidator,
+) -> Result<Vec<InferredScope>> {
+    let mut content = None;
+    for path in CODEOWNERS_PATHS {
+        content = read_indexed(repo, index, path)?;
+        if content.is_some() {
+            debug!("Reading the owners from {}", path);
+            break;
+        }
+    }
+
+    let mut res: Vec<InferredScope> = Vec::new();
+    for line in content.unwrap_or_default().lines() {
+        let mut tokens = line.split_whitespace();
+        let Some(pattern) = tokens.next().filter(|token| !token.starts_with('#')) else {
+            continue;
+        };
+        let owners = tokens
+            .take_while(|token| !token.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let Some((name, paths)) = codeowners_pattern(pattern) else {
+            debug!("No scope in the CODEOWNERS pattern {:?}", pattern);
+            continue;
+        };
+        if let Err(e) = validator.parse(&name) {
+            debug!("Skipping the CODEOWNERS pattern {:?}: {}", pattern, e);
+            continue;
+        }
+
+        match res.iter_mut().find(|inferred| inferred.scope.name == name) {
+            Some(inferred) => inferred.paths.extend(paths),
+            None => res.push(InferredScope {
+                scope: CommitScope::with_description(&name, &owners),
+                paths,
+            }),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Names of the directories at `depth` that have files in the index. Directories with the same
+/// name (i.e. `apps/core/` and `libs/core/` at depth 2) are one scope covering all of them
+fn directories(
+    index: &Index,
+    config: &DirectoriesProviderConfig,
+    validator: &ScopeNameValidator,
+) -> Result<Vec<InferredScope>> {
+    let depth = config.depth.unwrap_or(1);
+    let ignore = match &config.ignore {
+        Some(ignore) => ignore.clone(),
+        None => DEFAULT_IGNORED_DIRECTORIES
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+    // Empty pathspec matches everything
+    let ignore = (!ignore.is_empty())
+        .then(|| Pathspec::new(ignore))
+        .transpose()?;
+    let is_ignored = |name: &str| {
+        ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.matches_path(Path::new(name), PathspecFlags::DEFAULT))
+    };
+
+    let mut dirs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in index.iter() {
+        let Ok(path) = String::from_utf8(entry.path) else {
+            continue;
+        };
+        let components: Vec<&str> = path.split('/').collect();
+        // The last component is the file itself
+        if components.len() <= depth || components[..depth].iter().any(|c| is_ignored(c)) {
+            continue;
+        }
+        let pattern = format!("{}/*", components[..depth].join("/"));
+        let paths = dirs.entry(components[depth - 1].to_string()).or_default();
+        if !paths.contains(&pattern) {
+            paths.push(pattern);
+        }
+    }
+
+    Ok(dirs
+        .into_iter()
+        .filter(|(name, _)| match validator.parse(name) {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("Skipping the directory {:?}: {}", name, e);
+                false
+            }
+        })
+        .map(|(name, paths)| InferredScope {
+            scope: CommitScope::new(&name),
+            paths,
+        })
+        .collect())
+}
+
+/// Scopes of the enabled providers, sorted by name. If several providers list a scope, the first
+/// one wins
+pub fn get_inferred_scopes(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Vec<InferredScope>> {
+    if repo.is_bare() {
+        return Ok(Vec::new());
+    }
+    let providers = config
+        .as_ref()
+        .map(|c| c.providers.clone())
+        .unwrap_or_default();
+    let validator = ScopeNameValidator::from_config(config)?;
+    let index = repo.index()?;
+
+    let mut res = Vec::new();
+    if providers.workspaces.unwrap_or(true) {
+        res.extend(js_workspaces(repo, &index, &validator)?);
+    }
+    if providers.codeowners.unwrap_or_default() {
+        res.extend(codeowners(repo, &index, &validator)?);
+    }
+    if providers.directories.enabled.unwrap_or_default() {
+        res.extend(directories(&index, &providers.directories, &validator)?);
+    }
+
+    let mut seen = HashSet::new();
+    res.retain(|inferred| seen.insert(inferred.scope.name.clone()));
+    res.sort_by(|a, b| a.scope.name.cmp(&b.scope.name));
+
+    Ok(res)
+}
+
+/// Adds the inferred scopes missing from the configured ones to the end
+pub fn add_inferred_scopes(
+    scopes: Option<Vec<CommitScope>>,
+    inferred: &[InferredScope],
+) -> Option<Vec<CommitScope>> {
+    if inferred.is_empty() {
+        return scopes;
+    }
+    let mut scopes = scopes.unwrap_or_default();
+    let known: HashSet<String> = scopes.iter().map(|scope| scope.name.clone()).collect();
+    scopes.extend(
+        inferred
+            .iter()
+            .filter(|inferred| !known.contains(&inferred.scope.name))
+            .map(|inferred| inferred.scope.clone()),
+    );
+
+    Some(scopes)
+}
+
+/// Config with the paths of the inferred scopes in `scope_paths`, the configured paths of a scope
+/// win
+pub fn with_inferred_paths(config: Option<Config>, inferred: &[InferredScope]) -> Option<Config> {
+    if inferred.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    let mut scope_paths: BTreeMap<String, Vec<String>> = inferred
+        .iter()
+        .map(|inferred| (inferred.scope.name.clone(), inferred.paths.clone()))
+        .collect();
+    scope_paths.extend(config.scope_paths.unwrap_or_default());
+    config.scope_paths = Some(scope_paths);
+
+    Some(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use rstest::rstest;
+    use std::fs;
+    use testdir::testdir;
+
+    fn add_files(repo: &Repository, files: &[(&str, &str)]) {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (path, content) in files {
+            let path = Path::new(path);
+            fs::create_dir_all(workdir.join(path).parent().unwrap()).unwrap();
+            fs::write(workdir.join(path), content).unwrap();
+            index.add_path(path).unwrap();
+        }
+        index.write().unwrap();
+    }
+
+    fn names(inferred: &[InferredScope]) -> Vec<(&str, &str, &str)> {
+        inferred
+            .iter()
+            .map(|i| {
+                (
+                    i.scope.name.as_str(),
+                    i.scope.description.as_str(),
+                    i.paths[0].as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case::block("packages:\n  - 'packages/*'\n  - \"apps/web\" # the site\n\nother: 1\n")]
+    #[case::flow("packages: ['packages/*', apps/web]\n")]
+    fn pnpm(#[case] yaml: &str) {
+        assert_eq!(pnpm_packages(yaml), vec!["packages/*", "apps/web"]);
+    }
+
+    #[rstest]
+    #[case::npm(&[("package.json", r#"{"workspaces": ["packages/*", "apps/web", "!packages/skipped"]}"#)])]
+    #[case::yarn(&[("package.json", r#"{"workspaces": {"packages": ["packages/*", "apps/web", "!packages/skipped"]}}"#)])]
+    #[case::pnpm(&[("pnpm-workspace.yaml", "packages:\n  - packages/*\n  - apps/web\n  - '!packages/skipped'\n")])]
+    #[case::no_exclusions(&[("package.json", r#"{"workspaces": ["packages/[ua]*", "apps/web"]}"#)])]
+    fn workspaces(#[case] manifests: &[(&str, &str)]) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_files(&repo, manifests);
+        add_files(
+            &repo,
+            &[
+                (
+                    "packages/ui/package.json",
+                    r#"{"name": "@acme/ui", "description": "Components"}"#,
+                ),
+                ("packages/api/package.json", r#"{"name": "api"}"#),
+                ("packages/skipped/package.json", r#"{"name": "skipped"}"#),
+                ("packages/broken/package.json", "{"),
+                (
+                    "packages/ui/node_modules/dep/package.json",
+                    r#"{"name": "dep"}"#,
+                ),
+                ("apps/web/package.json", r#"{"name": "web"}"#),
+                ("apps/other/package.json", r#"{"name": "other"}"#),
+            ],
+        );
+
+        assert_eq!(
+            names(&get_inferred_scopes(&repo, &None).unwrap()),
+            vec![
+                ("api", "", "packages/api/*"),
+                ("ui", "Components", "packages/ui/*"),
+                ("web", "", "apps/web/*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_files(
+            &repo,
+            &[
+                ("package.json", r#"{"workspaces": ["packages/*"]}"#),
+                ("packages/ui/package.json", r#"{"name": "ui"}"#),
+            ],
+        );
+        let config = Some(
+            Config::from_str(indoc! {r#"
+                [providers]
+                workspaces = false
+            "#})
+            .unwrap(),
+        );
+
+        assert!(get_inferred_scopes(&repo, &config).unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case::anchored_dir("/src/cache/", Some(("cache", vec!["src/cache"])))]
+    #[case::anchored_glob("/apps/web/**", Some(("web", vec!["apps/web"])))]
+    #[case::relative("docs/*", Some(("docs", vec!["docs/*"])))]
+    #[case::anywhere("logs/", Some(("logs", vec!["logs", "*/logs"])))]
+    #[case::anywhere_globstar("**/logs", Some(("logs", vec!["logs", "*/logs"])))]
+    #[case::extension("src/*.rs", Some(("src", vec!["src/*.rs"])))]
+    #[case::no_segment("*.md", None)]
+    #[case::root("/", None)]
+    fn codeowners_patterns(#[case] pattern: &str, #[case] expected: Option<(&str, Vec<&str>)>) {
+        assert_eq!(
+            codeowners_pattern(pattern),
+            expected.map(|(name, paths)| (
+                name.to_string(),
+                paths.into_iter().map(String::from).collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn codeowners_scopes() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_files(
+            &repo,
+            &[
+                (
+                    ".github/CODEOWNERS",
+                    indoc! {"
+                        # Owners
+                        *       @org/everyone
+                        /src/cache/ @alice @org/cache-team # the cache
+                        docs/   @bob
+                        /lib/cache/ @alice
+                    "},
+                ),
+                ("CODEOWNERS", "/ignored/ @carol\n"),
+            ],
+        );
+        let config = Some(Config::from_str("[providers]\ncodeowners = true\n").unwrap());
+
+        let inferred = get_inferred_scopes(&repo, &config).unwrap();
+
+        assert_eq!(
+            names(&inferred),
+            vec![
+                ("cache", "@alice @org/cache-team", "src/cache"),
+                ("docs", "@bob", "docs"),
+            ]
+        );
+        assert_eq!(inferred[0].paths, vec!["src/cache", "lib/cache"]);
+        let pathspec = Pathspec::new(&inferred[0].paths).unwrap();
+        for (file, matches) in [
+            ("src/cache/mod.rs", true),
+            ("src/cache/disk/file.rs", true),
+            ("lib/cache/mod.rs", true),
+            ("src/cache.rs", false),
+        ] {
+            assert_eq!(
+                pathspec.matches_path(Path::new(file), PathspecFlags::DEFAULT),
+                matches,
+                "{}",
+                file
+            );
+        }
+        assert!(get_inferred_scopes(&repo, &None).unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case::top_level("", vec![("apps", "", "apps/*"), ("docs", "", "docs/*"), ("libs", "", "libs/*")])]
+    #[case::depth("depth = 2", vec![("core", "", "apps/core/*"), ("web", "", "apps/web/*")])]
+    #[case::ignore(r#"ignore = ["docs", "l*"]"#, vec![(".github", "", ".github/*"), ("apps", "", "apps/*"), ("target", "", "target/*")])]
+    fn directories(#[case] config: &str, #[case] expected: Vec<(&str, &str, &str)>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_files(
+            &repo,
+            &[
+                ("apps/web/index.js", ""),
+                ("apps/core/main.rs", ""),
+                ("libs/core/lib.rs", ""),
+                ("libs/Not A Scope/lib.rs", ""),
+                ("docs/index.md", ""),
+                ("target/debug/out", ""),
+                (".github/workflows/ci.yml", ""),
+                ("README.md", ""),
+            ],
+        );
+        let config = Some(
+            Config::from_str(&format!(
+                "[scope_names]\ncharset = \"a-z.\"\n[providers.directories]\nenabled = true\n{}\n",
+                config
+            ))
+            .unwrap(),
+        );
+
+        let inferred = get_inferred_scopes(&repo, &config).unwrap();
+
+        assert_eq!(names(&inferred), expected);
+        if let Some(core) = inferred.iter().find(|i| i.scope.name == "core") {
+            assert_eq!(core.paths, vec!["apps/core/*", "libs/core/*"]);
+        }
+    }
+}
@@ -1,111 +1,201 @@
-use crate::cache::{update_cache_for_repo, Cache};
-use crate::config::{Config, RegenerateOnStale};
-use crate::utils::PrintableEntity;
-use anyhow::Result;
+use crate::cache::{
+    config_hash, create_cache, get_cache_path, scope_files, try_lock_regeneration,
+    update_cache_for_repo, Cache, ScopeCounts,
+};
+use crate::config::{
+    Config, OnStaleNoRegen, RegenerateOnStale, DEFAULT_HISTORY_WEIGHT, DEFAULT_PATHS_WEIGHT,
+};
+use crate::entity::{Entity, Scope, Source};
+use crate::error::{Error, Result};
+use crate::i18n::{Message, Messages};
+use crate::scope_name::{IgnoredScopes, ScopeNameValidator};
+use crate::warnings::{warning, WarningKind};
 use dialoguer::Confirm;
-use git2::Repository;
-use itertools::sorted;
-use log::{debug, info, warn};
-use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::Hash;
+use git2::{Pathspec, PathspecFlags, Repository};
+use itertools::{sorted, Itertools};
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 
 pub mod commit;
 
-use self::commit::{get_scopes_x_changes, get_staged_files, ChangedFiles};
-use self::distance::find_closest_neighbor;
+use self::commit::{
+    get_partially_staged_files, get_scopes_x_changes, get_staged_files, get_unstaged_files,
+    get_untracked_files, ChangedFiles,
+};
+use self::distance::{
+    find_closest_neighbor_combined, find_closest_neighbor_weighted, find_scopes_for_file,
+    match_metrics, MatchMetrics, SourceWeights,
+};
 
-mod distance;
+pub mod distance;
+pub mod inferred;
+mod rerank;
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash, Ord, PartialOrd)]
-pub struct CommitScope {
-    pub name: String,
-    pub description: String,
+pub type CommitScope = Entity<Scope>;
+
+enum CacheResult {
+    Valid(HashMap<CommitScope, ScopeCounts>),
+    /// Regenerated scopes or None if the cache was not (or failed to be) regenerated and the
+    /// history should be used
+    Stale(Option<HashMap<CommitScope, ScopeCounts>>),
+    /// Cache was not regenerated, its outdated content should be used
+    Outdated(HashMap<CommitScope, ScopeCounts>),
+    /// Cache was not regenerated, scopes from history should not be used at all
+    Skip,
+    NotFound,
 }
 
-impl CommitScope {
-    pub fn new(name: String) -> Self {
-        Self {
-            name,
-            description: "".to_string(),
+use chrono::{DateTime, Duration, Utc};
+
+use crate::utils::{head_commit, head_is_transient, time};
+
+/// Regenerates the cache, returning the fresh scopes.
+///
+/// Cache regeneration failure (i.e. read-only cache location) should not stop the suggestions, None
+/// is returned then for the history to be mined directly.
+fn regenerate_cache(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Option<HashMap<CommitScope, ScopeCounts>> {
+    match update_cache_for_repo(repo, config) {
+        Ok(scopes) => Some(scopes),
+        Err(e) => {
+            warning(
+                WarningKind::CacheUnavailable,
+                format!("Failed to regenerate the cache: {:?}", e),
+            );
+            None
         }
     }
 }
 
-impl PrintableEntity for CommitScope {
-    fn name(&self) -> &str {
-        &self.name
-    }
-    fn description(&self) -> &str {
-        &self.description
+/// What to do with the stale cache that was not regenerated
+fn handle_stale_no_regen(
+    scopes: HashMap<CommitScope, ScopeCounts>,
+    config: &Option<Config>,
+) -> CacheResult {
+    let on_stale_no_regen = config
+        .as_ref()
+        .map(|c| c.cache.on_stale_no_regen.clone())
+        .unwrap_or_default();
+
+    match on_stale_no_regen {
+        OnStaleNoRegen::UseStale => {
+            warning(WarningKind::CacheStale, "Cache is stale, using it anyway");
+            CacheResult::Outdated(scopes)
+        }
+        OnStaleNoRegen::Skip => {
+            warning(
+                WarningKind::CacheStale,
+                "Cache is stale, scopes from history are skipped",
+            );
+            CacheResult::Skip
+        }
+        OnStaleNoRegen::FallbackToHistory => {
+            warning(
+                WarningKind::CacheStale,
+                "Cache is stale, falling back to the history",
+            );
+            CacheResult::Stale(None)
+        }
     }
 }
 
-enum CacheResult {
-    Valid(Vec<CommitScope>),
-    Stale(Option<HashMap<CommitScope, ChangedFiles>>),
-    NotFound,
+/// Drops the scopes the scope name rules reject, for the cache entries mined with other rules
+fn refilter_scopes(
+    scopes: HashMap<CommitScope, ScopeCounts>,
+    config: &Option<Config>,
+) -> Result<HashMap<CommitScope, ScopeCounts>> {
+    let scope_names = ScopeNameValidator::from_config(config)?;
+    Ok(scopes
+        .into_iter()
+        .filter(|(scope, _)| scope_names.parse(&scope.name).is_ok())
+        .collect())
 }
 
-use chrono::Duration;
-
-use crate::utils::time;
-
 fn try_get_scopes_from_cache(repo: &Repository, config: &Option<Config>) -> Result<CacheResult> {
-    match Cache::load() {
+    match get_cache_path(config).and_then(|path| Cache::load(&path)) {
         Ok(cache) => {
             info!("Loading scopes from cache");
             if let Some(entry) = cache.get_scopes_for_repo(repo) {
-                let head_commit_hash = repo
-                    .head()?
-                    .target()
-                    .ok_or_else(|| {
-                        anyhow::anyhow!(
-                            "HEAD reference has no target. Are there commits in this repository?"
-                        )
-                    })?
-                    .to_string();
+                let Some(head) = head_commit(repo)? else {
+                    info!("No commits yet, the cache entry does not apply");
+                    return Ok(CacheResult::NotFound);
+                };
+                let head_commit_hash = head.to_string();
+
+                let config_changed = entry.config_hash != config_hash(repo, config)?;
+                // Served if the cache is not regenerated
+                let outdated = match config_changed {
+                    true => {
+                        info!("Config changed since the cache was created");
+                        refilter_scopes(entry.scope_counts.clone(), config)?
+                    }
+                    false => entry.scope_counts.clone(),
+                };
 
                 if time::now().signed_duration_since(entry.timestamp)
                     < Duration::seconds(TTL as i64)
                     && entry.head_commit_hash == head_commit_hash
+                    && !config_changed
                 {
                     debug!("Cache is valid");
-                    return Ok(CacheResult::Valid(
-                        entry.scopes.keys().cloned().collect::<Vec<_>>(),
-                    ));
+                    Ok(CacheResult::Valid(entry.scope_counts.clone()))
                 } else {
                     info!("Cache is stale");
+                    if head_is_transient(repo) {
+                        info!("{:?} in progress, serving the cache as is", repo.state());
+                        return Ok(CacheResult::Outdated(outdated));
+                    }
 
-                    let regenerate_on_stale = config
-                        .as_ref()
-                        .map(|c| c.cache.regenerate_on_stale.clone())
-                        .unwrap_or_default();
+                    let regenerate_on_stale = match config.as_ref() {
+                        Some(c) if c.cache.read_only => {
+                            info!("Cache is read-only");
+                            RegenerateOnStale::Never
+                        }
+                        Some(c) => c.cache.regenerate_on_stale.clone(),
+                        None => RegenerateOnStale::default(),
+                    };
+
+                    // Waiting for another process walking the history would make the suggestions
+                    // as slow as walking it
+                    let _lock = match regenerate_on_stale {
+                        RegenerateOnStale::Never => None,
+                        _ => match try_lock_regeneration(&cache, repo, config)? {
+                            Some(lock) => Some(lock),
+                            None => {
+                                warning(
+                                    WarningKind::CacheStale,
+                                    "Another process is refreshing the cache, serving stale data",
+                                );
+                                return Ok(CacheResult::Outdated(outdated));
+                            }
+                        },
+                    };
 
                     match regenerate_on_stale {
                         RegenerateOnStale::Always => {
                             info!("Regenerating cache");
-                            update_cache_for_repo(repo)?;
-                            let scopes = get_scopes_x_changes(repo)?;
-                            Ok(CacheResult::Stale(Some(scopes.unwrap_or_default())))
+                            Ok(CacheResult::Stale(regenerate_cache(repo, config)))
                         }
                         RegenerateOnStale::Prompt => {
                             if Confirm::new()
-                                .with_prompt("Cache is stale. Regenerate?")
+                                .with_prompt(
+                                    Messages::from_config(config).get(Message::CacheStalePrompt),
+                                )
                                 .interact()?
                             {
                                 info!("Regenerating cache");
-                                update_cache_for_repo(repo)?;
-                                let scopes = get_scopes_x_changes(repo)?;
-                                Ok(CacheResult::Stale(Some(scopes.unwrap_or_default())))
+                                Ok(CacheResult::Stale(regenerate_cache(repo, config)))
                             } else {
-                                Ok(CacheResult::Stale(None))
+                                Ok(handle_stale_no_regen(outdated, config))
                             }
                         }
                         RegenerateOnStale::Never => {
                             info!("Not regenerating cache");
-                            Ok(CacheResult::Stale(None))
+                            Ok(handle_stale_no_regen(outdated, config))
                         }
                     }
                 }
@@ -113,30 +203,126 @@ fn try_get_scopes_from_cache(repo: &Repository, config: &Option<Config>) -> Resu
                 Ok(CacheResult::NotFound)
             }
         }
+        // Not a problem by itself, the lookup in the history warns about the slowness
+        Err(Error::CacheNotFound) => {
+            info!("There's no cache yet");
+            Ok(CacheResult::NotFound)
+        }
         Err(e) => {
-            warn!("Cache could not be loaded because of {:?}", e);
+            warning(
+                WarningKind::CacheUnavailable,
+                format!("Cache could not be loaded because of {:?}", e),
+            );
             Ok(CacheResult::NotFound)
         }
     }
 }
 
+/// Scopes a file belongs to according to the history
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct FileScopes {
+    pub file: String,
+    pub scopes: Vec<String>,
+}
+
 const TTL: u64 = 86400; // 24 hours
 
+/// Share of the number of commits in the ranking score, the rest is the recency of the last use
+const FREQUENCY_WEIGHT: f64 = 0.5;
+/// The recency part of the ranking score halves every this many days since the last use
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Ranking score of a scope in 0..=1: the number of its commits relative to the most used scope,
+/// weighted with how recently it was used
+fn usage_score(counts: &ScopeCounts, max_commits: usize, now: DateTime<Utc>) -> f64 {
+    let frequency = counts.commits as f64 / max_commits.max(1) as f64;
+    let age_days = now
+        .signed_duration_since(counts.last_used)
+        .num_seconds()
+        .max(0) as f64
+        / 86400.0;
+    let recency = 0.5_f64.powf(age_days / RECENCY_HALF_LIFE_DAYS);
+
+    FREQUENCY_WEIGHT * frequency + (1.0 - FREQUENCY_WEIGHT) * recency
+}
+
+/// Orders the scopes by [`usage_score`], the scopes never used in the history (i.e. the ones only
+/// in the config) go last. Ties are ordered by name
+fn rank_scopes(
+    scopes: Vec<CommitScope>,
+    history_scopes: &HashMap<CommitScope, ScopeCounts>,
+) -> Vec<CommitScope> {
+    let now = time::now();
+    let max_commits = history_scopes
+        .values()
+        .map(|counts| counts.commits)
+        .max()
+        .unwrap_or_default();
+    let scores: HashMap<&str, f64> = history_scopes
+        .iter()
+        .map(|(scope, counts)| (scope.name.as_str(), usage_score(counts, max_commits, now)))
+        .collect();
+    let score = |scope: &CommitScope| scores.get(scope.name.as_str()).copied().unwrap_or(0.0);
+
+    scopes
+        .into_iter()
+        .sorted_by(|a, b| score(b).total_cmp(&score(a)).then(a.cmp(b)))
+        .collect()
+}
+
+/// Scopes as they should be shown to the user
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScopeSuggestions {
+    /// All known scopes, the suggested one (if any) goes first, then the most used and most
+    /// recently used ones
+    pub scopes: Vec<CommitScope>,
+    /// Scope that matches the currently staged files best
+    pub suggested: Option<CommitScope>,
+    /// Where the scopes come from, by name
+    pub sources: HashMap<String, Source>,
+}
+
+/// Sources of the scopes: [`Source::Config`] for the ones in the config, `other` for the rest
+pub fn scope_sources(
+    config: &Option<Config>,
+    scopes: &[CommitScope],
+    other: Source,
+) -> HashMap<String, Source> {
+    let config_scopes = config
+        .as_ref()
+        .and_then(|c| c.commit_scopes.as_ref())
+        .map(|scopes| scopes.iter().map(|s| s.name.as_str()).collect_vec())
+        .unwrap_or_default();
+
+    scopes
+        .iter()
+        .map(|scope| {
+            let source = match config_scopes.contains(&scope.name.as_str()) {
+                true => Source::Config,
+                false => other,
+            };
+            (scope.name.clone(), source)
+        })
+        .collect()
+}
+
 /// The main entry point to retrieve commit scopes from a git repository at location
 /// This function should not panic.
 pub fn try_get_commit_scopes_from_repo(
     repo: &Repository,
     config: Option<Config>,
 ) -> Result<Option<Vec<CommitScope>>> {
-    debug!("Looking for scopes in config");
-    let mut hasher = DefaultHasher::new();
-    config.hash(&mut hasher);
+    Ok(try_get_scope_suggestions(repo, config)?.map(|s| s.scopes))
+}
 
-    let ignored_scopes = config
-        .as_ref()
-        .and_then(|c| c.general.as_ref())
-        .and_then(|g| g.scopes.as_ref())
-        .and_then(|s| s.ignored.clone());
+/// Same as [`try_get_commit_scopes_from_repo`], but also reports which scope (if any) was
+/// suggested based on the staged files
+pub fn try_get_scope_suggestions(
+    repo: &Repository,
+    config: Option<Config>,
+) -> Result<Option<ScopeSuggestions>> {
+    debug!("Looking for scopes in config");
+    let ignored_scopes = IgnoredScopes::from_config(&config)?;
 
     let disable_history_search = config
         .as_ref()
@@ -145,16 +331,18 @@ pub fn try_get_commit_scopes_from_repo(
         .and_then(|s| s.disable_history_search)
         .unwrap_or(false);
 
+    let inferred_scopes = inferred::get_inferred_scopes(repo, &config)?;
+    let config = inferred::with_inferred_paths(config, &inferred_scopes);
     let scopes_from_config = config.as_ref().and_then(|c| c.commit_scopes.clone());
+    // Inferred scopes are offered as if they were configured, the configured ones win
+    let scopes_from_config = inferred::add_inferred_scopes(scopes_from_config, &inferred_scopes);
 
+    let deprecated_scopes = get_deprecated_scopes(&config);
     let scopes_from_config = scopes_from_config.map(|scopes| {
         scopes
             .into_iter()
-            .filter(|scope| {
-                ignored_scopes
-                    .as_ref()
-                    .map_or(true, |ignored| !ignored.contains(&scope.name))
-            })
+            .filter(|scope| !ignored_scopes.contains(&scope.name))
+            .filter(|scope| !deprecated_scopes.contains_key(&scope.name))
             .collect()
     });
 
@@ -163,34 +351,26 @@ pub fn try_get_commit_scopes_from_repo(
     // 1. Cache failed to load/does not exist -- log error and fall back to history
     // 2. Cache loaded OK but does not have entry for current repo -- log and fall back
     // 3. Cache loaded OK and has entry for current repo -- use that entry
-    let scopes_from_cache = match try_get_scopes_from_cache(repo, &config)? {
-        CacheResult::Valid(scopes) => return Ok(Some(scopes)),
-        CacheResult::Stale(scopes) => scopes,
-        CacheResult::NotFound => None,
-    };
-
-    let other_scopes = if disable_history_search {
-        debug!("Config setting disabled search in history");
-        None
-    } else {
-        scopes_from_cache.or_else(|| {
-            warn!(
-                "Git history scope lookups are a bit slow. Consider using the cache (see --help)"
-            );
-            info!("Falling back to searching scopes in history");
-            get_scopes_x_changes(repo).unwrap_or(None)
-        })
+    let (other_scopes, other_source) = match try_get_scopes_from_cache(repo, &config)? {
+        CacheResult::Valid(scopes) | CacheResult::Outdated(scopes) => (Some(scopes), Source::Cache),
+        CacheResult::Skip => (None, Source::Cache),
+        _ if disable_history_search => {
+            debug!("Config setting disabled search in history");
+            (None, Source::History)
+        }
+        CacheResult::Stale(Some(scopes)) => (Some(scopes), Source::Cache),
+        CacheResult::Stale(None) => (get_scopes_from_history(repo, &config)?, Source::History),
+        CacheResult::NotFound => get_uncached_scopes(repo, &config)?,
     };
 
     let other_scopes = other_scopes.map(|scopes| {
-        scopes
-            .into_iter()
-            .filter(|(scope, _)| {
-                ignored_scopes
-                    .as_ref()
-                    .map_or(true, |ignored| !ignored.contains(&scope.name))
-            })
-            .collect::<HashMap<_, _>>()
+        replace_deprecated(
+            scopes
+                .into_iter()
+                .filter(|(scope, _)| !ignored_scopes.contains(&scope.name))
+                .collect(),
+            &deprecated_scopes,
+        )
     });
 
     // This can be written more concisely but I will trade it off for readability
@@ -203,30 +383,17 @@ pub fn try_get_commit_scopes_from_repo(
         // One is Some() -- return it
         (Some(x), None) => {
             info!("Found scopes only in config");
-            // There's no need to sort this, no scopes_from_history found
-            Some(x)
+            // There's no need to sort this, no scopes_from_history found. The staged files can
+            // still match the `scope_paths`, i.e. in a repository without commits
+            Some(suggest_from_staged(repo, &config, x, HashMap::new())?)
         }
         (None, Some(history_scopes)) => {
             debug!("Found scopes only in history or cache");
 
-            let mut scopes = sorted(history_scopes.keys().cloned()).collect::<Vec<CommitScope>>();
+            let scopes = rank_scopes(history_scopes.keys().cloned().collect(), &history_scopes);
 
             // check the current staged changes, push closest match to the front
-            if let Some(staged_files) = get_staged_files(repo)? {
-                let matched_scope = find_closest_neighbor(staged_files, history_scopes);
-
-                match matched_scope {
-                    Some(matched_scope) => {
-                        info!("Found a scope matching '{:?}'", matched_scope);
-                        scopes = push_to_first(scopes, matched_scope);
-                    }
-                    None => {
-                        info!("No scope matches currently staged files");
-                    }
-                };
-            }
-
-            Some(scopes)
+            Some(suggest_from_staged(repo, &config, scopes, history_scopes)?)
         }
         // Both are Some -- smart merge
         (Some(config_scopes), Some(history_scopes)) => {
@@ -240,50 +407,514 @@ pub fn try_get_commit_scopes_from_repo(
                 .cloned()
                 .collect();
 
-            let mut scopes = [config_scopes, filtered_scopes_from_commit_history].concat();
-            scopes.sort();
+            let scopes = rank_scopes(
+                [config_scopes, filtered_scopes_from_commit_history].concat(),
+                &history_scopes,
+            );
 
             // Now, I can check the currently staged files and push the needed scope to the front.
-            if let Some(staged_files) = get_staged_files(repo)? {
-                let matched_scope = find_closest_neighbor(staged_files, history_scopes);
+            Some(suggest_from_staged(repo, &config, scopes, history_scopes)?)
+        }
+    };
 
-                match matched_scope {
-                    Some(matched_scope) => {
-                        info!("Found a scope matching '{:?}'", matched_scope);
-                        scopes = push_to_first(scopes, matched_scope);
-                    }
-                    None => {
-                        info!("No scope matches currently staged files");
-                    }
-                };
+    let (res, other_source) = match (res, fallback_scopes(&config)) {
+        (Some(suggestions), _) if !suggestions.scopes.is_empty() => {
+            (Some(suggestions), other_source)
+        }
+        (res, None) => (res, other_source),
+        (_, Some(fallback)) => {
+            info!("No scopes found, using general.scopes.fallback");
+            let suggestions = suggest_from_staged(repo, &config, fallback, HashMap::new())?;
+            (Some(suggestions), Source::Default)
+        }
+    };
+    let res = res.map(|suggestions| {
+        let mut sources = scope_sources(&config, &suggestions.scopes, other_source);
+        for inferred in &inferred_scopes {
+            if let Some(source) = sources
+                .get_mut(&inferred.scope.name)
+                .filter(|source| **source != Source::Config)
+            {
+                *source = Source::InferredPath;
             }
+        }
+        ScopeSuggestions {
+            sources,
+            ..suggestions
+        }
+    });
+
+    let rerank_command = config
+        .as_ref()
+        .and_then(|c| c.suggest.rerank_command.as_ref());
+
+    match (res, rerank_command) {
+        (Some(suggestions), Some(command)) => {
+            let staged_files =
+                sorted(get_files_to_match(repo, &config)?.unwrap_or_default()).collect_vec();
+            Ok(Some(rerank::rerank(command, suggestions, &staged_files)))
+        }
+        (res, _) => Ok(res),
+    }
+}
+
+/// `general.scopes.fallback`, None if not set or empty
+pub fn fallback_scopes(config: &Option<Config>) -> Option<Vec<CommitScope>> {
+    config
+        .as_ref()
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.scopes.as_ref())
+        .and_then(|s| s.fallback.as_ref())
+        .filter(|fallback| !fallback.is_empty())
+        .map(|fallback| fallback.iter().map(CommitScope::new).collect())
+}
+
+/// `general.scopes.deprecated`: deprecated scope -> its replacement
+pub fn get_deprecated_scopes(config: &Option<Config>) -> BTreeMap<String, String> {
+    config
+        .as_ref()
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.scopes.as_ref())
+        .and_then(|s| s.deprecated.clone())
+        .unwrap_or_default()
+}
+
+/// Counts of the deprecated scopes added to the ones of their replacements
+fn replace_deprecated(
+    scopes: HashMap<CommitScope, ScopeCounts>,
+    deprecated: &BTreeMap<String, String>,
+) -> HashMap<CommitScope, ScopeCounts> {
+    if deprecated.is_empty() {
+        return scopes;
+    }
+    let mut res: HashMap<CommitScope, ScopeCounts> = HashMap::new();
+    for (scope, counts) in scopes {
+        let scope = match deprecated.get(&scope.name) {
+            Some(replacement) => CommitScope::new(replacement),
+            None => scope,
+        };
+        let merged = res.entry(scope).or_default();
+        merged.commits += counts.commits;
+        merged.last_used = merged.last_used.max(counts.last_used);
+        for (file, count) in counts.files {
+            *merged.files.entry(file).or_default() += count;
+        }
+    }
 
-            // check the current staged changes, push closest neighbor to the front
-            Some(scopes)
+    res
+}
+
+/// Fast path for completions: scopes from the config and the cache entry for the repo, without
+/// checking whether the entry is fresh and without looking at the staged files. If the entry was
+/// mined with other settings, its scopes are checked against the current scope name rules.
+///
+/// Returns None if there is no cache entry for the repo.
+pub fn try_get_cached_scopes(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<Vec<CommitScope>>> {
+    let cache = match get_cache_path(config).and_then(|path| Cache::load(&path)) {
+        Ok(cache) => cache,
+        Err(e) => {
+            info!("Cache could not be loaded because of {:?}", e);
+            return Ok(None);
         }
     };
+    let Some(entry) = cache.get_scopes_for_repo(repo) else {
+        return Ok(None);
+    };
+
+    let ignored_scopes = IgnoredScopes::from_config(config)?;
+    let deprecated_scopes = get_deprecated_scopes(config);
+    let config_scopes = config
+        .as_ref()
+        .and_then(|c| c.commit_scopes.clone())
+        .unwrap_or_default();
+    let scope_names = match entry.config_hash != config_hash(repo, config)? {
+        true => Some(ScopeNameValidator::from_config(config)?),
+        false => None,
+    };
+    let history_scopes = entry
+        .scope_counts
+        .keys()
+        .filter(|scope| {
+            scope_names
+                .as_ref()
+                .is_none_or(|rules| rules.parse(&scope.name).is_ok())
+        })
+        .map(|scope| match deprecated_scopes.get(&scope.name) {
+            Some(replacement) => CommitScope::new(replacement),
+            None => scope.clone(),
+        })
+        .filter(|scope| !config_scopes.iter().any(|s| s.name == scope.name))
+        .collect::<Vec<_>>();
+
+    Ok(Some(
+        config_scopes
+            .into_iter()
+            .chain(history_scopes)
+            .filter(|scope| {
+                !ignored_scopes.contains(&scope.name)
+                    && !deprecated_scopes.contains_key(&scope.name)
+            })
+            .sorted()
+            .dedup()
+            .collect(),
+    ))
+}
+
+/// Scope -> files map from the cache (if it's usable) or from the history, without the ignored
+/// scopes
+pub fn get_scope_map(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<HashMap<CommitScope, ChangedFiles>>> {
+    Ok(get_scope_counts(repo, config)?.map(|scopes| scope_files(&scopes)))
+}
+
+/// Same as [`get_scope_map`] with the number of commits and the last use of the scopes
+fn get_scope_counts(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<HashMap<CommitScope, ScopeCounts>>> {
+    let ignored_scopes = IgnoredScopes::from_config(config)?;
+
+    let scopes = match try_get_scopes_from_cache(repo, config)? {
+        CacheResult::Valid(scopes)
+        | CacheResult::Stale(Some(scopes))
+        | CacheResult::Outdated(scopes) => Some(scopes),
+        CacheResult::Skip => None,
+        CacheResult::Stale(None) | CacheResult::NotFound => get_scopes_x_changes(repo, config)?,
+    };
+
+    Ok(scopes.map(|scopes| {
+        replace_deprecated(
+            scopes
+                .into_iter()
+                .filter(|(scope, _)| !ignored_scopes.contains(&scope.name))
+                .collect(),
+            &get_deprecated_scopes(config),
+        )
+    }))
+}
+
+/// Data the ranking and the suggestion of a scope are based on
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct ScopeDetails {
+    /// Commits in the scope
+    pub commits: usize,
+    /// Commit time of the latest commit in the scope, None if the scope was never used
+    pub last_used: Option<DateTime<Utc>>,
+    /// Files matched against the scopes (staged ones, see `suggest` config), the same for all
+    /// scopes
+    pub staged_files: usize,
+    /// Staged files matching the `scope_paths` of the scope
+    pub declared_files: usize,
+    #[serde(flatten)]
+    pub metrics: MatchMetrics,
+}
+
+/// [`ScopeDetails`] of each of the scopes, looked up in the history (or the cache) by name
+pub fn get_scope_details(
+    repo: &Repository,
+    config: &Option<Config>,
+    scopes: &[CommitScope],
+) -> Result<Vec<ScopeDetails>> {
+    let scope_counts = get_scope_counts(repo, config)?.unwrap_or_default();
+    let staged_files = get_files_to_match(repo, config)?.unwrap_or_default();
+    let partially_staged_files = get_partially_staged_files(repo)?;
+    let metrics = match_metrics(
+        &staged_files,
+        &partially_staged_files,
+        &scope_files(&scope_counts),
+    );
+    let inferred_scopes = inferred::get_inferred_scopes(repo, config)?;
+    let declared_files = get_declared_files(
+        &inferred::with_inferred_paths(config.clone(), &inferred_scopes),
+        &staged_files,
+    )?;
+
+    Ok(scopes
+        .iter()
+        .map(|scope| {
+            // Scopes from the history have no description
+            let scope = CommitScope::new(&scope.name);
+            let counts = scope_counts.get(&scope);
+            ScopeDetails {
+                commits: counts.map_or(0, |c| c.commits),
+                last_used: counts.map(|c| c.last_used),
+                staged_files: staged_files.len(),
+                declared_files: declared_files.get(&scope).map_or(0, |files| files.len()),
+                metrics: metrics.get(&scope).cloned().unwrap_or_default(),
+            }
+        })
+        .collect())
+}
+
+/// Files historically associated with the scope, sorted. None if the scope was never used.
+pub fn get_files_for_scope(
+    repo: &Repository,
+    config: &Option<Config>,
+    name: &str,
+) -> Result<Option<Vec<String>>> {
+    Ok(get_scope_map(repo, config)?
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(scope, _)| scope.name == name)
+        .map(|(_, files)| files.into_iter().sorted().collect()))
+}
+
+/// Finds the best matching scopes for each of the files
+pub fn get_scopes_for_files(
+    repo: &Repository,
+    config: &Option<Config>,
+    files: &[String],
+) -> Result<Vec<FileScopes>> {
+    let scope_map = get_scope_map(repo, config)?.unwrap_or_default();
+
+    Ok(files
+        .iter()
+        .map(|file| FileScopes {
+            file: file.clone(),
+            scopes: find_scopes_for_file(file, &scope_map)
+                .into_iter()
+                .map(|s| s.name)
+                .collect(),
+        })
+        .collect())
+}
+
+/// Scopes mined from the history, None if mining failed. Only a repository too large to be mined
+/// without the cache is an error
+fn get_scopes_from_history(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<HashMap<CommitScope, ScopeCounts>>> {
+    warning(
+        WarningKind::HistorySlow,
+        "Git history scope lookups are a bit slow. Consider using the cache (see --help)",
+    );
+    info!("Falling back to searching scopes in history");
+    match get_scopes_x_changes(repo, config) {
+        Err(Error::CacheRequired) => Err(Error::CacheRequired),
+        res => Ok(res.unwrap_or(None)),
+    }
+}
+
+/// Scopes of a repository that is not in the cache with where they come from. With
+/// `cache.auto_create`, they are mined into the cache (created if needed) so that the next lookup
+/// is fast, if that fails -- from the history as without it
+fn get_uncached_scopes(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<(Option<HashMap<CommitScope, ScopeCounts>>, Source)> {
+    if !config.as_ref().is_some_and(|c| c.cache.auto_create) {
+        return Ok((get_scopes_from_history(repo, config)?, Source::History));
+    }
+
+    info!("Repository is not cached, mining its history into the cache");
+    match create_cache(config).and_then(|_| update_cache_for_repo(repo, config)) {
+        Ok(scopes) => Ok((Some(scopes), Source::Cache)),
+        Err(Error::NoScopes) => Ok((None, Source::Cache)),
+        Err(e) => {
+            warning(
+                WarningKind::CacheUnavailable,
+                format!("Failed to create the cache: {:?}", e),
+            );
+            Ok((get_scopes_from_history(repo, config)?, Source::History))
+        }
+    }
+}
+
+/// Staged files and, with `suggest.include_untracked`, the untracked ones. If there are none and
+/// `suggest.fallback_to_worktree` is set, the files with unstaged changes. None if there are none
+fn get_files_to_match(repo: &Repository, config: &Option<Config>) -> Result<Option<ChangedFiles>> {
+    let suggest_config = config
+        .as_ref()
+        .map(|c| c.suggest.clone())
+        .unwrap_or_default();
+
+    let mut files = get_staged_files(repo)?.unwrap_or_default();
+    if suggest_config.include_untracked {
+        files.extend(get_untracked_files(repo)?);
+    }
+    if files.is_empty() && suggest_config.fallback_to_worktree {
+        info!("Nothing staged, matching the changes in the worktree");
+        files = get_unstaged_files(repo)?;
+    }
+
+    Ok((!files.is_empty()).then_some(files))
+}
+
+/// Scope -> the files matching its `scope_paths`. Scopes matching none are not in the map
+fn get_declared_files(
+    config: &Option<Config>,
+    files: &ChangedFiles,
+) -> Result<HashMap<CommitScope, ChangedFiles>> {
+    let scope_paths = config
+        .as_ref()
+        .and_then(|c| c.scope_paths.clone())
+        .unwrap_or_default();
+
+    let mut res = HashMap::new();
+    for (scope, paths) in scope_paths {
+        let pathspec = Pathspec::new(&paths)?;
+        let matching: ChangedFiles = files
+            .iter()
+            .filter(|file| pathspec.matches_path(Path::new(file), PathspecFlags::DEFAULT))
+            .cloned()
+            .collect();
+        if !matching.is_empty() {
+            res.insert(CommitScope::new(scope), matching);
+        }
+    }
+    for (scope, files) in get_rule_files(config, files)? {
+        res.entry(scope).or_default().extend(files);
+    }
 
     Ok(res)
 }
 
-fn push_to_first<T: Ord>(mut v: Vec<T>, first: T) -> Vec<T> {
-    if let Some(index) = v.iter().position(|s| s == &first) {
-        v.remove(index);
-        v.insert(0, first);
+/// Scope -> the files matching its `scope_rules`. A file belongs to the most specific (longest)
+/// rule it matches. Ignored scopes and scopes matching none are not in the map
+fn get_rule_files(
+    config: &Option<Config>,
+    files: &ChangedFiles,
+) -> Result<HashMap<CommitScope, ChangedFiles>> {
+    let scope_rules = config
+        .as_ref()
+        .and_then(|c| c.scope_rules.clone())
+        .unwrap_or_default();
+    let ignored_scopes = IgnoredScopes::from_config(config)?;
+
+    let mut rules = Vec::new();
+    for (pattern, scope) in scope_rules {
+        if !ignored_scopes.contains(&scope) {
+            rules.push((Pathspec::new([&pattern])?, pattern, scope));
+        }
+    }
+    rules.sort_by(|(_, a, _), (_, b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+    let mut res: HashMap<CommitScope, ChangedFiles> = HashMap::new();
+    for file in files {
+        if let Some((_, pattern, scope)) = rules
+            .iter()
+            .find(|(pathspec, _, _)| pathspec.matches_path(Path::new(file), PathspecFlags::DEFAULT))
+        {
+            debug!("{} matches the scope rule {:?}", file, pattern);
+            res.entry(CommitScope::new(scope))
+                .or_default()
+                .insert(file.clone());
+        }
+    }
+
+    Ok(res)
+}
+
+/// Scope matching the most of the files by the `scope_rules`, ties go to the alphabetically first
+/// scope. None if no rule matches
+fn get_rule_scope(config: &Option<Config>, files: &ChangedFiles) -> Result<Option<CommitScope>> {
+    Ok(get_rule_files(config, files)?
+        .into_iter()
+        .max_by(|(a_scope, a), (b_scope, b)| {
+            a.len().cmp(&b.len()).then(b_scope.name.cmp(&a_scope.name))
+        })
+        .map(|(scope, _)| scope))
+}
+
+/// Checks the currently staged files and pushes the closest matching scope to the front. Partially
+/// staged files weigh more, they usually reflect the intent of the commit. The `scope_paths` of
+/// the scopes are matched together with the history, see [`find_closest_neighbor_combined`].
+/// If the files match the `scope_rules`, the scope of the rules is suggested instead (and added to
+/// the scopes if it's not there)
+fn suggest_from_staged(
+    repo: &Repository,
+    config: &Option<Config>,
+    scopes: Vec<CommitScope>,
+    history_scopes: HashMap<CommitScope, ScopeCounts>,
+) -> Result<ScopeSuggestions> {
+    let Some(staged_files) = get_files_to_match(repo, config)? else {
+        return Ok(ScopeSuggestions {
+            scopes,
+            ..Default::default()
+        });
+    };
+
+    let partially_staged_files = get_partially_staged_files(repo)?;
+    let declared_files = get_declared_files(config, &staged_files)?;
+    let rule_scope = get_rule_scope(config, &staged_files)?;
+    let mut scopes = scopes;
+    if let Some(rule_scope) = &rule_scope {
+        if !scopes.iter().any(|s| s.name == rule_scope.name) {
+            scopes.push(rule_scope.clone());
+        }
     }
 
-    v
+    let matched_scope = match declared_files.is_empty() {
+        _ if rule_scope.is_some() => rule_scope,
+        true => find_closest_neighbor_weighted(
+            staged_files,
+            &partially_staged_files,
+            scope_files(&history_scopes),
+        ),
+        false => {
+            let suggest_config = config.as_ref().map(|c| &c.suggest);
+            let weights = SourceWeights {
+                history: suggest_config
+                    .and_then(|s| s.history_weight)
+                    .unwrap_or(DEFAULT_HISTORY_WEIGHT),
+                paths: suggest_config
+                    .and_then(|s| s.paths_weight)
+                    .unwrap_or(DEFAULT_PATHS_WEIGHT),
+            };
+            find_closest_neighbor_combined(
+                staged_files,
+                &partially_staged_files,
+                scope_files(&history_scopes),
+                &declared_files,
+                weights,
+            )
+        }
+    };
+
+    match matched_scope {
+        Some(matched_scope) => {
+            info!("Found a scope matching '{:?}'", matched_scope);
+            // Scopes from history have no description, the one from the list should be returned
+            let suggested = scopes
+                .iter()
+                .position(|s| s.name == matched_scope.name)
+                .map(|index| {
+                    let scope = scopes.remove(index);
+                    scopes.insert(0, scope.clone());
+                    scope
+                });
+            Ok(ScopeSuggestions {
+                scopes,
+                suggested,
+                ..Default::default()
+            })
+        }
+        None => {
+            info!("No scope matches currently staged files");
+            Ok(ScopeSuggestions {
+                scopes,
+                ..Default::default()
+            })
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cache::create_cache;
-    use conventional_commit_helper::test_utils::{
-        mk_config_with_scopes_only, setup_config_file_in_path, setup_repo_with_commits,
+    use crate::test_utils::{
+        add_commit, mk_config_with_scopes_only, setup_config_file_in_path, setup_repo_with_commits,
     };
     use indoc::indoc;
     use rstest::{fixture, rstest};
+    use std::collections::BTreeMap;
     use testdir::testdir;
 
     #[fixture]
@@ -332,10 +963,463 @@ mod tests {
         assert_eq!(res.first().unwrap().description, "baz");
     }
 
-    use crate::utils::time::mock_time;
+    /// Ignored patterns filter both the config and the history scopes
+    #[test]
+    fn ignored_patterns() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "chore(release-12): bump",
+                "chore(deps-dev): bump",
+                "fix(core): a",
+            ],
+        );
+        setup_config_file_in_path(
+            &dir,
+            indoc! {r#"
+                [scopes]
+                deps = "Dependencies"
+                docs = "Documentation"
+
+                [general.scopes]
+                ignored = ["release-.*", "deps.*"]
+                "#},
+        );
+        let mut config = Config::load(&repo, None).unwrap();
+        config.as_mut().unwrap().cache.path = Some(dir.join("cache.bin"));
+        let unfiltered = try_get_commit_scopes_from_repo(&repo, None)
+            .unwrap()
+            .unwrap();
+        assert!(unfiltered.iter().any(|s| s.name == "release-12"));
+
+        let res = try_get_commit_scopes_from_repo(&repo, config)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.iter().map(|s| s.name.as_str()).sorted().collect_vec(),
+            vec!["core", "docs"]
+        );
+    }
+
+    /// Most used scopes first, the ones only in the config last
+    #[test]
+    fn ranked_by_usage() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &["init", "feat(bar): a", "fix(baz): b", "fix(baz): c"],
+        );
+        setup_config_file_in_path(&dir, "[scopes]\naaa = \"\"\nbar = \"Bar\"\n");
+        let config = Config::load(&repo, None).unwrap();
+
+        let res = try_get_commit_scopes_from_repo(&repo, config)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            res.iter().map(|s| s.name.as_str()).collect_vec(),
+            vec!["baz", "bar", "aaa"]
+        );
+    }
+
+    #[test]
+    fn recency_outweighs_frequency() {
+        let now = time::now();
+        let counts = |commits, days_ago| ScopeCounts {
+            commits,
+            last_used: now - Duration::days(days_ago),
+            ..Default::default()
+        };
+        let history = HashMap::from([
+            (CommitScope::new("stale"), counts(10, 365)),
+            (CommitScope::new("fresh"), counts(6, 1)),
+            (CommitScope::new("rare"), counts(1, 100)),
+        ]);
+
+        let res = rank_scopes(history.keys().cloned().collect(), &history);
+
+        assert_eq!(
+            res.iter().map(|s| s.name.as_str()).collect_vec(),
+            vec!["fresh", "stale", "rare"]
+        );
+    }
+
+    /// Untracked file that was changed under a scope before only matches with include_untracked
+    #[rstest]
+    #[case::staged_only(false, None)]
+    #[case::include_untracked(true, Some("foo"))]
+    fn test_include_untracked(#[case] include_untracked: bool, #[case] expected: Option<&str>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_commit(&repo, "feat(foo): add", "foo_file");
+        add_commit(&repo, "feat(bar): change", "default_file");
+
+        // Remove the file, then bring it back without staging
+        let mut index = repo.index().unwrap();
+        index.remove_path(std::path::Path::new("foo_file")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("nobody", "nobody@example.com").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "chore: remove", &tree, &[&head])
+            .unwrap();
+        std::fs::write(dir.join("foo_file"), "back").unwrap();
+
+        let config = Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            suggest: crate::config::SuggestConfig {
+                include_untracked,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let res = try_get_scope_suggestions(&repo, Some(config))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case::no_fallback(false, None)]
+    #[case::fallback_to_worktree(true, Some("foo"))]
+    fn test_fallback_to_worktree(
+        #[case] fallback_to_worktree: bool,
+        #[case] expected: Option<&str>,
+    ) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_commit(&repo, "feat(foo): add", "foo_file");
+        add_commit(&repo, "feat(bar): change", "default_file");
+        std::fs::write(dir.join("foo_file"), "unstaged change").unwrap();
+
+        let config = Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            suggest: crate::config::SuggestConfig {
+                fallback_to_worktree,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let res = try_get_scope_suggestions(&repo, Some(config))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), expected);
+    }
+
+    /// Before the first commit the scopes come from the config, the staged files still match the
+    /// `scope_paths`
+    #[test]
+    fn zero_commits() {
+        let dir = testdir!();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("main.rs"), "new").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("main.rs")).unwrap();
+        index.write().unwrap();
+        let mut config = Config::from_str(indoc! {r#"
+            [scopes]
+            core = "Core"
+            docs = "Docs"
+
+            [scope_paths]
+            docs = ["*.md"]
+        "#})
+        .unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+
+        let res = try_get_scope_suggestions(&repo, Some(config.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.scopes.len(), 2);
+        assert_eq!(res.suggested, None);
+
+        config.scope_paths = Some(BTreeMap::from([(
+            "core".to_string(),
+            vec!["*.rs".to_string()],
+        )]));
+        let res = try_get_scope_suggestions(&repo, Some(config))
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), Some("core"));
+        assert_eq!(res.scopes[0].name, "core");
+    }
+
+    /// Fallback scopes are offered only if there are no others
+    #[test]
+    fn fallback() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        let mut config = Config::from_str(indoc! {r#"
+            [general]
+            scopes.fallback = ["core", "deps"]
+        "#})
+        .unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let config = Some(config);
+
+        let res = try_get_scope_suggestions(&repo, config.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            res.scopes,
+            vec![CommitScope::new("core"), CommitScope::new("deps")]
+        );
+        assert_eq!(res.sources["core"], Source::Default);
+
+        add_commit(&repo, "feat(foo): a", "new_file");
+        let res = try_get_scope_suggestions(&repo, config).unwrap().unwrap();
+        assert_eq!(res.scopes, vec![CommitScope::new("foo")]);
+    }
+
+    /// Scopes in the config come from it even if they are in the history too
+    #[test]
+    fn sources() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foz): a", "feat(bar): b"]);
+        let mut config = Config::from_str("[scopes]\nfoz = \"baz\"\n").unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let config = Some(config);
+
+        let res = try_get_scope_suggestions(&repo, config.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            res.sources,
+            HashMap::from([
+                ("foz".to_string(), Source::Config),
+                ("bar".to_string(), Source::History)
+            ])
+        );
+
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+        let res = try_get_scope_suggestions(&repo, config).unwrap().unwrap();
+        assert_eq!(res.sources["bar"], Source::Cache);
+    }
+
+    /// With `cache.auto_create` the first lookup mines the history into a new cache
+    #[rstest]
+    #[case::off(false)]
+    #[case::on(true)]
+    fn auto_create_cache(#[case] auto_create: bool) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): a"]);
+        let cache_path = dir.join("cache").join("cache.bin");
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(cache_path.clone()),
+                auto_create,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let res = try_get_commit_scopes_from_repo(&repo, config)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res, vec![CommitScope::new("foo")]);
+        assert_eq!(
+            Cache::load(&cache_path).is_ok_and(|cache| cache.get_scopes_for_repo(&repo).is_some()),
+            auto_create
+        );
+    }
+
+    /// Mid-rebase the stale cache is served as is instead of being regenerated on every step
+    #[test]
+    fn stale_cache_mid_rebase() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): a"]);
+        let config = Some(Config {
+            cache: crate::config::CacheConfig {
+                path: Some(dir.join("cache.bin")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+        add_commit(&repo, "feat(bar): b", "bar_file");
+        let names = |config: &Option<Config>| {
+            try_get_commit_scopes_from_repo(&repo, config.clone())
+                .unwrap()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|s| s.name)
+                .sorted()
+                .collect_vec()
+        };
+
+        std::fs::create_dir(repo.path().join("rebase-merge")).unwrap();
+        assert_eq!(names(&config), vec!["foo"]);
+
+        std::fs::remove_dir(repo.path().join("rebase-merge")).unwrap();
+        assert_eq!(names(&config), vec!["bar", "foo"]);
+    }
+
+    /// Declared paths of a scope count together with its history
+    #[rstest]
+    #[case::no_paths("", Some("bar"))]
+    #[case::paths("[scope_paths]\nfoo = [\"*.md\"]\n", Some("foo"))]
+    #[case::paths_ignored(
+        "[scope_paths]\nfoo = [\"*.md\"]\n[suggest]\npaths_weight = 0\n",
+        Some("bar")
+    )]
+    fn test_scope_paths(#[case] config: &str, #[case] expected: Option<&str>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_commit(&repo, "feat(foo): add", "foo_file");
+        add_commit(&repo, "feat(bar): change", "foo_file");
+        std::fs::write(dir.join("foo_file"), "staged change").unwrap();
+        std::fs::write(dir.join("notes.md"), "new").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("foo_file")).unwrap();
+        index.add_path(Path::new("notes.md")).unwrap();
+        index.write().unwrap();
+
+        let mut config = Config::from_str(config).unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let res = try_get_scope_suggestions(&repo, Some(config))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), expected);
+    }
+
+    /// Scope rules take precedence over the history and the declared paths
+    #[rstest]
+    #[case::no_rules("", Some("foo"))]
+    #[case::rule("[scope_rules]\n\"src/cache/**\" = \"cache\"\n", Some("cache"))]
+    #[case::most_files(
+        "[scope_rules]\n\"*\" = \"core\"\n\"src/cache/**\" = \"cache\"\n",
+        Some("cache")
+    )]
+    #[case::catch_all("[scope_rules]\n\"*\" = \"core\"\n", Some("core"))]
+    #[case::over_paths(
+        "[scope_paths]\nfoo = [\"src/\"]\n[scope_rules]\n\"src/cache/**\" = \"cache\"\n",
+        Some("cache")
+    )]
+    #[case::ignored(
+        "[scope_rules]\n\"src/cache/**\" = \"cache\"\n[general.scopes]\nignored = [\"cache\"]\n",
+        Some("foo")
+    )]
+    fn test_scope_rules(#[case] config: &str, #[case] expected: Option<&str>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_commit(&repo, "feat(foo): add", "foo_file");
+        add_commit(&repo, "feat(foo): change", "foo_file");
+        std::fs::create_dir_all(dir.join("src/cache")).unwrap();
+        let mut index = repo.index().unwrap();
+        for file in ["foo_file", "src/cache/a.rs", "src/cache/b.rs"] {
+            std::fs::write(dir.join(file), "staged change").unwrap();
+            index.add_path(Path::new(file)).unwrap();
+        }
+        index.write().unwrap();
+
+        let mut config = Config::from_str(config).unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let res = try_get_scope_suggestions(&repo, Some(config))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), expected);
+        assert_eq!(res.scopes[0].name, expected.unwrap());
+    }
+
+    /// Deprecated scopes are not offered, their history counts for the replacement
+    #[test]
+    fn deprecated() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        add_commit(&repo, "feat(cfg): add", "config_file");
+        add_commit(&repo, "feat(other): change", "other_file");
+        std::fs::write(dir.join("config_file"), "staged change").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("config_file")).unwrap();
+        index.write().unwrap();
+        let mut config = Config::from_str(indoc! {r#"
+            [scopes]
+            cfg = "Old name of the config"
+
+            [general.scopes]
+            deprecated = { cfg = "config" }
+            "#})
+        .unwrap();
+        config.cache.path = Some(dir.join("cache.bin"));
+        let config = Some(config);
+
+        let res = try_get_scope_suggestions(&repo, config.clone())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.suggested.map(|s| s.name).as_deref(), Some("config"));
+        assert_eq!(
+            res.scopes.iter().map(|s| s.name.as_str()).collect_vec(),
+            vec!["config", "other"]
+        );
+
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+        assert_eq!(
+            try_get_cached_scopes(&repo, &config)
+                .unwrap()
+                .unwrap()
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect_vec(),
+            vec!["config", "other"]
+        );
+    }
+
+    use crate::test_utils::freeze_time;
     use chrono::Utc;
     use std::env;
 
+    /// Scopes from a valid cache are merged with the configured ones and matched against the
+    /// staged files, same as the ones from the history
+    #[test]
+    fn valid_cache_is_merged() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(scope1): message"]);
+        mk_config_with_scopes_only(&dir);
+        let config = Config::load(&repo, None).unwrap();
+        env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+        assert!(matches!(
+            try_get_scopes_from_cache(&repo, &config).unwrap(),
+            CacheResult::Valid(_)
+        ));
+
+        std::fs::write(dir.join("default_file"), "changed").unwrap();
+        let mut index = repo.index().unwrap();
+        index
+            .add_path(std::path::Path::new("default_file"))
+            .unwrap();
+        index.write().unwrap();
+
+        let res = try_get_scope_suggestions(&repo, config).unwrap().unwrap();
+        assert_eq!(
+            res.scopes
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["scope1", "foz"]
+        );
+        assert_eq!(res.suggested.unwrap().name, "scope1");
+    }
+
     #[test]
     fn test_cache_is_stale_after_ttl() {
         let dir = testdir!();
@@ -345,17 +1429,97 @@ mod tests {
         env::set_var("XDG_CACHE_HOME", &testdir!());
 
         // Create a cache
-        create_cache().unwrap();
-        update_cache_for_repo(&repo).unwrap();
+        let mut clock = freeze_time(Utc::now());
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
 
-        // Mock the time to be in the future
-        let future_time = Utc::now() + Duration::seconds(TTL as i64 + 1);
-        mock_time::set(future_time);
+        // Right before the TTL runs out the cache is still valid
+        clock.advance(Duration::seconds(TTL as i64 - 1));
+        let result = try_get_scopes_from_cache(&repo, &config).unwrap();
+        assert!(matches!(result, CacheResult::Valid(_)));
 
         // Check that the cache is stale
+        clock.advance(Duration::seconds(2));
         let result = try_get_scopes_from_cache(&repo, &config).unwrap();
         assert!(matches!(result, CacheResult::Stale(_)));
+    }
+
+    /// Stale cache that is not regenerated is handled according to the config
+    #[rstest]
+    #[case::use_stale("use_stale", Some(vec!["scope1"]))]
+    #[case::skip("skip", None)]
+    #[case::fallback_to_history("fallback_to_history", Some(vec!["scope1", "scope2"]))]
+    fn test_stale_no_regen(#[case] on_stale_no_regen: &str, #[case] expected: Option<Vec<&str>>) {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(scope1): message"]);
+        let cache_path = dir.join("cache.bin");
+        setup_config_file_in_path(
+            &dir,
+            &format!(
+                "[cache]\npath = {:?}\nregenerate_on_stale = \"never\"\non_stale_no_regen = {:?}",
+                cache_path.to_string_lossy(),
+                on_stale_no_regen
+            ),
+        );
+        let config = Config::load(&repo, None).unwrap();
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        // New commit makes the cache stale
+        add_commit(&repo, "feat(scope2): message", "new_file");
+
+        let res = try_get_commit_scopes_from_repo(&repo, config).unwrap();
+
+        assert_eq!(
+            res.map(|scopes| scopes.into_iter().map(|s| s.name).collect::<Vec<_>>()),
+            expected.map(|e| e.into_iter().map(String::from).collect())
+        );
+    }
+
+    /// Changed settings of the mining make the cache stale. The outdated entry is re-filtered,
+    /// the regenerated one is mined from scratch
+    #[rstest]
+    #[case::never("never")]
+    #[case::always("always")]
+    fn test_cache_is_stale_after_config_change(#[case] regenerate_on_stale: &str) {
+        let dir = testdir!();
+        let repo =
+            setup_repo_with_commits(&dir, &["init", "feat(scope1): message", "fix(ab): message"]);
+        let cache_config = format!(
+            "[cache]\npath = {:?}\nregenerate_on_stale = {:?}\non_stale_no_regen = \"use_stale\"\n",
+            dir.join("cache.bin").to_string_lossy(),
+            regenerate_on_stale
+        );
+        setup_config_file_in_path(&dir, &cache_config);
+        let config = Config::load(&repo, None).unwrap();
+        create_cache(&config).unwrap();
+        update_cache_for_repo(&repo, &config).unwrap();
+
+        setup_config_file_in_path(&dir, &(cache_config + "[scope_names]\nmax_length = 2\n"));
+        let config = Config::load(&repo, None).unwrap();
+
+        let scopes = match try_get_scopes_from_cache(&repo, &config).unwrap() {
+            CacheResult::Outdated(scopes) | CacheResult::Stale(Some(scopes)) => scopes,
+            _ => panic!("Cache should be stale"),
+        };
+        assert_eq!(
+            scopes.into_keys().map(|s| s.name).collect::<Vec<_>>(),
+            vec!["ab"]
+        );
+        assert_eq!(
+            try_get_cached_scopes(&repo, &config)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>(),
+            vec!["ab"]
+        );
 
-        mock_time::clear();
+        let valid = matches!(
+            try_get_scopes_from_cache(&repo, &config).unwrap(),
+            CacheResult::Valid(_)
+        );
+        assert_eq!(valid, regenerate_on_stale == "always");
     }
 }
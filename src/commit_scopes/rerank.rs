@@ -0,0 +1,160 @@
+//! User-provided reranking of the scope suggestions.
+//!
+//! The command from `suggest.rerank_command` is run through `sh -c`. It gets a JSON object on
+//! stdin:
+//!
+//! ```json
+//! {"candidates": [{"name": "...", "description": "..."}], "suggested": "...", "staged_files": ["..."]}
+//! ```
+//!
+//! and should print a JSON array of scope names in the desired order. Candidates not mentioned in
+//! the output keep their relative order after the mentioned ones. The first mentioned scope
+//! becomes the suggested one.
+
+use log::debug;
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{CommitScope, ScopeSuggestions};
+use crate::error::{Error, Result};
+use crate::warnings::{warning, WarningKind};
+
+#[derive(Debug, Serialize)]
+struct RerankInput<'a> {
+    candidates: &'a [CommitScope],
+    suggested: Option<&'a str>,
+    staged_files: &'a [String],
+}
+
+fn run_command(command: &str, input: &RerankInput) -> Result<Vec<String>> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|source| Error::Command {
+            command: command.to_string(),
+            source,
+        })?;
+
+    {
+        // Scoped so that stdin is closed before waiting on the command
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        // The command does not have to read the input, so a closed pipe is fine
+        let _ = stdin.write_all(serde_json::to_string(input)?.as_bytes());
+    }
+
+    let output = child.wait_with_output().map_err(|source| Error::Command {
+        command: command.to_string(),
+        source,
+    })?;
+    let failed = |message: String| Error::CommandFailed {
+        command: command.to_string(),
+        message,
+    };
+    if !output.status.success() {
+        return Err(failed(format!("exited with {}", output.status)));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|_| failed("did not print a JSON array of scope names".to_string()))
+}
+
+/// Applies the order returned by the command
+fn reorder(suggestions: ScopeSuggestions, order: &[String]) -> ScopeSuggestions {
+    let (mut mentioned, rest): (Vec<CommitScope>, Vec<CommitScope>) = suggestions
+        .scopes
+        .into_iter()
+        .partition(|s| order.contains(&s.name));
+
+    for name in order
+        .iter()
+        .filter(|name| !mentioned.iter().any(|s| &s.name == *name))
+    {
+        warning(
+            WarningKind::RerankFailed,
+            format!("Rerank command returned an unknown scope {:?}", name),
+        );
+    }
+
+    mentioned.sort_by_key(|s| order.iter().position(|name| name == &s.name));
+
+    ScopeSuggestions {
+        suggested: mentioned.first().cloned().or(suggestions.suggested),
+        scopes: [mentioned, rest].concat(),
+        sources: suggestions.sources,
+    }
+}
+
+/// Reranks the suggestions with the command. If the command fails -- the suggestions are returned
+/// as is.
+pub fn rerank(
+    command: &str,
+    suggestions: ScopeSuggestions,
+    staged_files: &[String],
+) -> ScopeSuggestions {
+    let input = RerankInput {
+        candidates: &suggestions.scopes,
+        suggested: suggestions.suggested.as_ref().map(|s| s.name.as_str()),
+        staged_files,
+    };
+
+    match run_command(command, &input) {
+        Ok(order) => {
+            debug!("Rerank command returned {:?}", order);
+            reorder(suggestions, &order)
+        }
+        Err(e) => {
+            warning(
+                WarningKind::RerankFailed,
+                format!(
+                    "Failed to rerank the scopes, keeping the original order: {}",
+                    e
+                ),
+            );
+            suggestions
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn mk_suggestions() -> ScopeSuggestions {
+        ScopeSuggestions {
+            scopes: ["a", "b", "c"]
+                .iter()
+                .map(|s| CommitScope::new(s.to_string()))
+                .collect(),
+            suggested: Some(CommitScope::new("a".to_string())),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::reordered(r#"echo '["c", "b"]'"#, vec!["c", "b", "a"], "c")]
+    #[case::unknown_scope(r#"echo '["x", "b"]'"#, vec!["b", "a", "c"], "b")]
+    #[case::nothing_mentioned("echo '[]'", vec!["a", "b", "c"], "a")]
+    #[case::reads_input(
+        r#"grep -q '"staged_files":\["foo.rs"\]' && echo '["b"]'"#,
+        vec!["b", "a", "c"],
+        "b"
+    )]
+    #[case::failed("exit 1", vec!["a", "b", "c"], "a")]
+    #[case::garbage("echo nope", vec!["a", "b", "c"], "a")]
+    fn reranks(#[case] command: &str, #[case] expected: Vec<&str>, #[case] suggested: &str) {
+        let res = rerank(command, mk_suggestions(), &["foo.rs".to_string()]);
+
+        assert_eq!(
+            res.scopes
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(res.suggested.unwrap().name, suggested);
+    }
+}
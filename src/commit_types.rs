@@ -1,23 +1,15 @@
-use crate::config::Config;
-use crate::utils::PrintableEntity;
-use anyhow::Result;
-use log::info;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash, Ord, PartialOrd)]
-pub struct CommitType {
-    pub name: String,
-    pub description: String,
-}
+use crate::commit_message::detect_pseudo_type;
+use crate::commit_scopes::commit::get_staged_files;
+use crate::config::{Config, TypeRule};
+use crate::entity::{Entity, Source, Type};
+use crate::error::Result;
+use crate::utils::head_commit;
+use git2::{Pathspec, PathspecFlags, Repository};
+use itertools::Itertools;
+use log::{debug, info};
+use std::path::Path;
 
-impl PrintableEntity for CommitType {
-    fn name(&self) -> &str {
-        &self.name
-    }
-    fn description(&self) -> &str {
-        &self.description
-    }
-}
+pub type CommitType = Entity<Type>;
 
 #[derive(Clone, Debug)]
 pub struct CommitTypeRef<'a> {
@@ -68,35 +60,191 @@ pub const DEFAULT_COMMIT_TYPES: &[CommitTypeRef] = &[
     },
 ];
 
-pub fn get_commit_types_from_repo_or_default(config: Option<Config>) -> Result<Vec<CommitType>> {
-    match config {
-        Some(config) => {
-            info!("Found config, returning its commit_types");
-            Ok(config.commit_types.unwrap_or_else(get_default_commit_types))
+/// How many recent commits are looked at for the types used in the repository
+const HISTORY_DEPTH: usize = 1000;
+
+/// Types used in fewer commits are not learned, they are likely typos
+const MIN_HISTORY_COMMITS: usize = 2;
+
+/// Types of the config. If there are none -- the default types followed by the other types used
+/// in the recent history, see [`get_commit_types_from_history`]
+pub fn get_commit_types_from_repo_or_default(
+    repo: &Repository,
+    config: Option<Config>,
+) -> Result<Vec<CommitType>> {
+    if let Some(commit_types) = config.and_then(|c| c.commit_types) {
+        info!("Found config, returning its commit_types");
+        return Ok(commit_types);
+    }
+
+    info!("No custom commit types found, returning default and the ones from history");
+    let mut commit_types = get_default_commit_types();
+    let from_history = get_commit_types_from_history(repo, &commit_types)?;
+    commit_types.extend(from_history);
+
+    Ok(commit_types)
+}
+
+/// Leading word of a `type(scope): ...`, `type!: ...` or `type: ...` subject. Cheaper than
+/// parsing the whole header, which matters for long histories
+fn subject_type(summary: &str) -> Option<&str> {
+    let end = summary.find(['(', '!', ':'])?;
+    let commit_type = &summary[..end];
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+    (!commit_type.is_empty() && commit_type.chars().all(is_word) && summary[end..].contains(": "))
+        .then_some(commit_type)
+}
+
+/// Types of the last [`HISTORY_DEPTH`] commits (but git-generated subjects) that are not `known`,
+/// used in [`MIN_HISTORY_COMMITS`] commits at least. Most used first
+pub fn get_commit_types_from_history(
+    repo: &Repository,
+    known: &[CommitType],
+) -> Result<Vec<CommitType>> {
+    let mut revwalk = repo.revwalk()?;
+    if let Some(head) = head_commit(repo)? {
+        revwalk.push(head)?;
+    }
+
+    let mut types = Vec::new();
+    for oid in revwalk.take(HISTORY_DEPTH) {
+        let commit = repo.find_commit(oid?)?;
+        if let Some(commit_type) = commit
+            .summary()
+            .filter(|summary| detect_pseudo_type(summary).is_none())
+            .and_then(subject_type)
+        {
+            types.push(commit_type.to_string());
         }
-        None => {
-            info!("No custom commit types found, returning default");
-            Ok(get_default_commit_types())
+    }
+
+    Ok(types
+        .into_iter()
+        .filter(|commit_type| !known.iter().any(|t| &t.name == commit_type))
+        .counts()
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_HISTORY_COMMITS)
+        .sorted_by(|(a_type, a), (b_type, b)| b.cmp(a).then(a_type.cmp(b_type)))
+        .map(|(commit_type, _)| CommitType::new(commit_type))
+        .collect())
+}
+
+/// Where the type of [`get_commit_types_from_repo_or_default`] comes from
+pub fn commit_type_source(config: &Option<Config>, commit_type: &CommitType) -> Source {
+    match config.as_ref().is_some_and(|c| c.commit_types.is_some()) {
+        true => Source::Config,
+        false
+            if DEFAULT_COMMIT_TYPES
+                .iter()
+                .any(|t| t.name == commit_type.name) =>
+        {
+            Source::Default
         }
+        false => Source::History,
     }
 }
 
 pub fn get_default_commit_types() -> Vec<CommitType> {
     DEFAULT_COMMIT_TYPES
         .iter()
-        .map(|c| CommitType {
-            name: c.name.to_string(),
-            description: c.description.to_string(),
+        .map(|c| CommitType::with_description(c.name, c.description))
+        .collect()
+}
+
+/// Rules of `type --suggest` checked after the configured ones: type -> git pathspecs
+pub const DEFAULT_TYPE_RULES: &[(&str, &[&str])] = &[
+    ("docs", &["*.md", "*.rst", "*.adoc", "docs/", "doc/"]),
+    (
+        "test",
+        &["tests/", "test/", "*_test.*", "*.test.*", "*.spec.*"],
+    ),
+    (
+        "ci",
+        &[".github/workflows/", ".gitlab-ci.yml", ".circleci/"],
+    ),
+    (
+        "build",
+        &[
+            "Cargo.toml",
+            "*/Cargo.toml",
+            "Cargo.lock",
+            "package.json",
+            "*/package.json",
+            "package-lock.json",
+            "yarn.lock",
+            "pnpm-lock.yaml",
+            "go.mod",
+            "go.sum",
+            "flake.nix",
+            "flake.lock",
+        ],
+    ),
+];
+
+fn default_type_rules() -> Vec<TypeRule> {
+    DEFAULT_TYPE_RULES
+        .iter()
+        .map(|(commit_type, paths)| TypeRule {
+            commit_type: commit_type.to_string(),
+            paths: paths.iter().map(|p| p.to_string()).collect(),
         })
         .collect()
 }
 
+/// Type of the first rule (configured ones first, then [`DEFAULT_TYPE_RULES`]) matching all the
+/// files. Only the known types are suggested
+pub fn suggest_type_for_files(
+    files: &[String],
+    types: &[CommitType],
+    config: &Option<Config>,
+) -> Result<Option<CommitType>> {
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let configured_rules = config
+        .as_ref()
+        .map(|c| c.suggest.type_rules.clone())
+        .unwrap_or_default();
+
+    for rule in configured_rules.into_iter().chain(default_type_rules()) {
+        let Some(commit_type) = types.iter().find(|t| t.name == rule.commit_type) else {
+            continue;
+        };
+        let pathspec = Pathspec::new(&rule.paths)?;
+        if files
+            .iter()
+            .all(|file| pathspec.matches_path(Path::new(file), PathspecFlags::DEFAULT))
+        {
+            debug!("Staged files match the rule {:?}", rule);
+            return Ok(Some(commit_type.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The most likely type of the staged changes, see [`suggest_type_for_files`]. None if nothing is
+/// staged or no rule matches
+pub fn try_suggest_commit_type(
+    repo: &Repository,
+    config: &Option<Config>,
+) -> Result<Option<CommitType>> {
+    let Some(staged_files) = get_staged_files(repo)? else {
+        return Ok(None);
+    };
+
+    suggest_type_for_files(
+        &staged_files.into_iter().collect::<Vec<_>>(),
+        &get_commit_types_from_repo_or_default(repo, config.clone())?,
+        config,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use conventional_commit_helper::test_utils::{
-        setup_config_file_in_path, setup_repo_with_commits,
-    };
+    use crate::test_utils::{setup_config_file_in_path, setup_repo_with_commits};
     use indoc::indoc;
     use rstest::{fixture, rstest};
     use std::sync::Once;
@@ -130,7 +278,7 @@ mod tests {
         let repo = setup_repo_with_commits(&dir, &["init"]);
         let config = Config::load(&repo, None).unwrap();
 
-        let res = get_commit_types_from_repo_or_default(config);
+        let res = get_commit_types_from_repo_or_default(&repo, config);
 
         assert_eq!(res.unwrap(), get_default_commit_types())
     }
@@ -144,9 +292,101 @@ mod tests {
         setup_config_file_in_path(&dir, &mk_types());
         let config = Config::load(&repo, None).unwrap();
 
-        let res = get_commit_types_from_repo_or_default(config).unwrap();
+        let res = get_commit_types_from_repo_or_default(&repo, config).unwrap();
 
         assert_eq!(res.len(), 1);
         assert_eq!(res.first().unwrap().name, "foo");
     }
+
+    #[rstest]
+    #[case::scope("wip(ui): foo", Some("wip"))]
+    #[case::breaking("hotfix!: foo", Some("hotfix"))]
+    #[case::plain("chore-deps: foo", Some("chore-deps"))]
+    #[case::no_colon("wip(ui) foo", None)]
+    #[case::not_a_word("Fix the typo: foo", None)]
+    #[case::empty(": foo", None)]
+    fn types_in_subjects(#[case] summary: &str, #[case] expected: Option<&str>) {
+        assert_eq!(subject_type(summary), expected);
+    }
+
+    /// Types used more than once in the history follow the default ones, unless the config has
+    /// its own
+    #[test]
+    fn types_from_history() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "wip: a",
+                "hotfix(ui): b",
+                "feat: c",
+                "wip(ui): d",
+                "hotfix!: e",
+                "feta: typo",
+                "Revert \"feta: typo\"",
+                "Revert \"feta: typo again\"",
+            ],
+        );
+
+        let res = get_commit_types_from_repo_or_default(&repo, None).unwrap();
+
+        assert_eq!(
+            res[..DEFAULT_COMMIT_TYPES.len()],
+            get_default_commit_types()
+        );
+        assert_eq!(
+            res[DEFAULT_COMMIT_TYPES.len()..]
+                .iter()
+                .map(|t| (t.name.as_str(), commit_type_source(&None, t)))
+                .collect::<Vec<_>>(),
+            vec![("hotfix", Source::History), ("wip", Source::History)]
+        );
+        assert_eq!(commit_type_source(&None, &res[0]), Source::Default);
+
+        setup_config_file_in_path(&dir, &mk_types());
+        let config = Config::load(&repo, None).unwrap();
+        let res = get_commit_types_from_repo_or_default(&repo, config.clone()).unwrap();
+        assert_eq!(res, vec![CommitType::with_description("foo", "bar")]);
+        assert_eq!(commit_type_source(&config, &res[0]), Source::Config);
+    }
+
+    #[rstest]
+    #[case::docs(&["README.md", "docs/usage.txt"], Some("docs"))]
+    #[case::tests(&["tests/cli.rs", "src/foo_test.go"], Some("test"))]
+    #[case::build(&["Cargo.toml", "Cargo.lock"], Some("build"))]
+    #[case::nested_manifest(&["crates/foo/Cargo.toml"], Some("build"))]
+    #[case::ci(&[".github/workflows/ci.yml"], Some("ci"))]
+    #[case::mixed(&["README.md", "src/main.rs"], None)]
+    #[case::nothing_staged(&[], None)]
+    fn suggest_type(#[case] files: &[&str], #[case] expected: Option<&str>) {
+        let files = files.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+        let res = suggest_type_for_files(&files, &get_default_commit_types(), &None).unwrap();
+
+        assert_eq!(res.map(|t| t.name), expected.map(String::from));
+    }
+
+    #[rstest]
+    #[case::configured_first(&["docs/index.md"], Some("feat"))]
+    #[case::unknown_type_skipped(&["nix/module.nix"], None)]
+    #[case::default_rules_still_apply(&["README.md"], Some("docs"))]
+    fn suggest_type_configured(#[case] files: &[&str], #[case] expected: Option<&str>) {
+        let config = Config::from_str(indoc! {r#"
+            [[suggest.type_rules]]
+            type = "feat"
+            paths = ["docs/"]
+
+            [[suggest.type_rules]]
+            type = "nix"
+            paths = ["*.nix"]
+        "#})
+        .unwrap();
+        let files = files.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+        let res =
+            suggest_type_for_files(&files, &get_default_commit_types(), &Some(config)).unwrap();
+
+        assert_eq!(res.map(|t| t.name), expected.map(String::from));
+    }
 }
@@ -0,0 +1,377 @@
+//! Composing and creating a conventional commit (`commit` subcommand).
+//!
+//! The parts known upfront (i.e. given on the command line) are not asked for, so the wizard can
+//! be driven by scripts as well. The commit is created through libgit2: git hooks are not run.
+use anyhow::{bail, Context, Result};
+use dialoguer::{Confirm, Editor, Input, Select};
+use git2::{ErrorCode, Oid, Repository};
+
+use conventional_commit_helper::commit_message::cleanup_message;
+
+use conventional_commit_helper::commit_scopes::CommitScope;
+use conventional_commit_helper::commit_types::CommitType;
+use conventional_commit_helper::i18n::{Message, Messages};
+
+/// Parts of the commit message
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CommitDraft {
+    pub commit_type: String,
+    pub scope: Option<String>,
+    pub subject: String,
+    pub body: Option<String>,
+    /// Marks the header with `!`
+    pub breaking: bool,
+}
+
+impl CommitDraft {
+    /// `type(scope)!: subject`
+    pub fn header(&self) -> String {
+        format!(
+            "{}{}{}: {}",
+            self.commit_type,
+            self.scope
+                .as_ref()
+                .map(|scope| format!("({})", scope))
+                .unwrap_or_default(),
+            if self.breaking { "!" } else { "" },
+            self.subject.trim()
+        )
+    }
+
+    pub fn message(&self) -> String {
+        match self
+            .body
+            .as_deref()
+            .map(str::trim)
+            .filter(|b| !b.is_empty())
+        {
+            Some(body) => format!("{}\n\n{}\n", self.header(), body),
+            None => format!("{}\n", self.header()),
+        }
+    }
+}
+
+/// Parts of the commit given before the wizard starts
+#[derive(Debug, Clone, Default)]
+pub struct Answers {
+    pub commit_type: Option<String>,
+    pub scope: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    /// The question is only asked if this is false
+    pub breaking: bool,
+    /// Write the body and the footers in `$EDITOR` instead of a single line prompt
+    pub edit: bool,
+}
+
+/// Completes the draft without asking anything: the type and the subject are required, the rest
+/// is optional
+pub fn draft_from_answers(answers: Answers, messages: &Messages) -> Result<CommitDraft> {
+    let Some(commit_type) = answers.commit_type else {
+        bail!("{}", messages.get(Message::TypeRequired));
+    };
+    let Some(subject) = answers.subject.filter(|s| !s.trim().is_empty()) else {
+        bail!("{}", messages.get(Message::SubjectRequired));
+    };
+
+    Ok(CommitDraft {
+        commit_type,
+        scope: answers.scope,
+        subject,
+        body: answers.body,
+        breaking: answers.breaking,
+    })
+}
+
+/// Asks for the missing parts of the commit. `scopes` are offered in the given order, the
+/// `suggested` one is preselected
+pub fn prompt(
+    answers: Answers,
+    types: &[CommitType],
+    scopes: &[CommitScope],
+    suggested: Option<&str>,
+    messages: &Messages,
+) -> Result<CommitDraft> {
+    let commit_type = match answers.commit_type {
+        Some(commit_type) => commit_type,
+        None => {
+            let idx = Select::new()
+                .with_prompt(messages.get(Message::TypePrompt))
+                .items(types)
+                .default(0)
+                .interact()?;
+            types[idx].name.clone()
+        }
+    };
+
+    let scope = match answers.scope {
+        Some(scope) => Some(scope),
+        None if scopes.is_empty() => Some(
+            Input::<String>::new()
+                .with_prompt(messages.get(Message::ScopeInputPrompt))
+                .allow_empty(true)
+                .interact_text()?,
+        )
+        .filter(|s| !s.trim().is_empty()),
+        None => {
+            let items: Vec<String> = std::iter::once(messages.get(Message::NoScope).to_string())
+                .chain(scopes.iter().map(ToString::to_string))
+                .collect();
+            let default = scopes
+                .iter()
+                .position(|s| Some(s.name.as_str()) == suggested)
+                .map_or(0, |idx| idx + 1);
+            let idx = Select::new()
+                .with_prompt(messages.get(Message::ScopePrompt))
+                .items(&items)
+                .default(default)
+                .interact()?;
+            idx.checked_sub(1).map(|idx| scopes[idx].name.clone())
+        }
+    };
+
+    let subject = match answers.subject {
+        Some(subject) => subject,
+        None => Input::<String>::new()
+            .with_prompt(messages.get(Message::SubjectPrompt))
+            .validate_with(|s: &String| match s.trim().is_empty() {
+                true => Err(messages.get(Message::SubjectEmpty)),
+                false => Ok(()),
+            })
+            .interact_text()?,
+    };
+
+    let mut draft = CommitDraft {
+        commit_type,
+        scope,
+        subject,
+        body: None,
+        breaking: answers.breaking,
+    };
+
+    draft.body = match (answers.body, answers.edit) {
+        (body, true) => edit_body(&draft, body.as_deref().unwrap_or_default(), messages)?,
+        (Some(body), false) => Some(body),
+        (None, false) => Some(
+            Input::<String>::new()
+                .with_prompt(messages.get(Message::BodyPrompt))
+                .allow_empty(true)
+                .interact_text()?,
+        ),
+    };
+
+    draft.breaking = answers.breaking
+        || Confirm::new()
+            .with_prompt(messages.get(Message::BreakingPrompt))
+            .default(false)
+            .interact()?;
+
+    Ok(draft)
+}
+
+/// Text to edit the body in: the body so far followed by comments naming the picked type and scope
+fn body_template(draft: &CommitDraft, body: &str, messages: &Messages) -> String {
+    let comments = [
+        String::new(),
+        messages.format(Message::BodyEditorHelp, &[("header", &draft.header())]),
+        String::new(),
+        format!(
+            "{}: {}",
+            messages.get(Message::TypePrompt),
+            draft.commit_type
+        ),
+        format!(
+            "{}: {}",
+            messages.get(Message::ScopePrompt),
+            draft
+                .scope
+                .as_deref()
+                .unwrap_or(messages.get(Message::NoScope))
+        ),
+    ];
+
+    let body = body.trim_end();
+    let separator = if body.is_empty() { "" } else { "\n" };
+    format!("{}{}\n", body, separator)
+        + &comments
+            .iter()
+            .map(|line| format!("# {}", line).trim_end().to_string() + "\n")
+            .collect::<String>()
+}
+
+/// Body and footers edited in `$EDITOR` (`$VISUAL` takes precedence), with the comments stripped
+/// the way git does. The body is left as is if the editor is closed without saving
+fn edit_body(draft: &CommitDraft, body: &str, messages: &Messages) -> Result<Option<String>> {
+    let edited = Editor::new()
+        .extension(".txt")
+        .edit(&body_template(draft, body, messages))?;
+
+    Ok(match edited {
+        Some(edited) => Some(cleanup_message(&edited)),
+        None => Some(body.to_string()),
+    })
+}
+
+/// Asks whether to use the replacement of the deprecated scope of the draft instead, see
+/// `general.scopes.deprecated`
+pub fn offer_replacement(
+    draft: &mut CommitDraft,
+    replacement: &str,
+    messages: &Messages,
+) -> Result<()> {
+    let Some(scope) = &draft.scope else {
+        return Ok(());
+    };
+    if Confirm::new()
+        .with_prompt(messages.format(
+            Message::ReplaceDeprecatedScopePrompt,
+            &[
+                ("scope", &format!("{:?}", scope)),
+                ("replacement", &format!("{:?}", replacement)),
+            ],
+        ))
+        .default(true)
+        .interact()?
+    {
+        draft.scope = Some(replacement.to_string());
+    }
+
+    Ok(())
+}
+
+/// Commits the staged changes on top of HEAD with the repository's `user.name` and `user.email`
+pub fn create_commit(repo: &Repository, message: &str, messages: &Messages) -> Result<Oid> {
+    let mut index = repo.index()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let nothing_staged = match &parent {
+        Some(parent) => parent.tree_id() == tree.id(),
+        None => index.is_empty(),
+    };
+    if nothing_staged {
+        bail!("{}", messages.get(Message::NothingToCommit));
+    }
+
+    let signature = repo
+        .signature()
+        .context(messages.get(Message::NoAuthor).to_string())?;
+    let parents: Vec<_> = parent.iter().collect();
+
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conventional_commit_helper::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use rstest::rstest;
+    use std::path::Path;
+    use testdir::testdir;
+
+    fn mk_draft(scope: Option<&str>, body: Option<&str>, breaking: bool) -> CommitDraft {
+        CommitDraft {
+            commit_type: "feat".to_string(),
+            scope: scope.map(str::to_string),
+            subject: " add foo ".to_string(),
+            body: body.map(str::to_string),
+            breaking,
+        }
+    }
+
+    #[rstest]
+    #[case::minimal(mk_draft(None, None, false), "feat: add foo\n")]
+    #[case::scope(mk_draft(Some("cli"), None, false), "feat(cli): add foo\n")]
+    #[case::breaking(mk_draft(Some("cli"), None, true), "feat(cli)!: add foo\n")]
+    #[case::body(mk_draft(None, Some("Details\n"), false), "feat: add foo\n\nDetails\n")]
+    #[case::empty_body(mk_draft(None, Some(" "), false), "feat: add foo\n")]
+    fn message(#[case] draft: CommitDraft, #[case] expected: &str) {
+        assert_eq!(draft.message(), expected);
+    }
+
+    #[test]
+    fn body_template_names_the_type_and_scope() {
+        let draft = mk_draft(Some("cli"), None, false);
+
+        assert_eq!(
+            body_template(&draft, "", &Messages::default()),
+            indoc! {r##"
+
+                #
+                # Body and footers of feat(cli): add foo. Lines starting with "#" are ignored
+                #
+                # Type: feat
+                # Scope: cli
+                "##}
+        );
+        assert_eq!(
+            cleanup_message(&body_template(&draft, "Details\n", &Messages::default())),
+            "Details"
+        );
+    }
+
+    #[test]
+    fn required_answers() {
+        let err = draft_from_answers(
+            Answers {
+                commit_type: Some("feat".to_string()),
+                ..Default::default()
+            },
+            &Messages::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Subject is required when not running in a terminal"
+        );
+
+        assert_eq!(
+            draft_from_answers(
+                Answers {
+                    commit_type: Some("feat".to_string()),
+                    subject: Some("add foo".to_string()),
+                    ..Default::default()
+                },
+                &Messages::default()
+            )
+            .unwrap()
+            .message(),
+            "feat: add foo\n"
+        );
+    }
+
+    #[test]
+    fn commits_staged_changes() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "nobody").unwrap();
+        config.set_str("user.email", "nobody@example.com").unwrap();
+
+        let err = create_commit(&repo, "feat: nothing", &Messages::default()).unwrap_err();
+        assert!(err.to_string().contains("Nothing to commit"), "{}", err);
+
+        std::fs::write(dir.join("foo"), "foo").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("foo")).unwrap();
+        index.write().unwrap();
+
+        let id = create_commit(&repo, "feat: add foo\n", &Messages::default()).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.id(), id);
+        assert_eq!(head.summary(), Some("feat: add foo"));
+        assert_eq!(head.parent_count(), 1);
+    }
+}
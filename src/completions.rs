@@ -0,0 +1,121 @@
+//! Shell completions (`completions` subcommand).
+//!
+//! Besides the scripts generated by clap, the zsh and fish scripts get a function completing
+//! commit headers: `feat(<TAB>` offers the known scopes. The function calls back into the program
+//! with `completions --query`, which only reads the config and the cache so that it stays fast
+//! enough to run on every keypress.
+use clap_complete::Shell;
+use std::io::Write;
+
+use crate::commit_scopes::CommitScope;
+use crate::commit_types::CommitType;
+
+/// Completion script for the shell, written to `out`
+pub fn generate(shell: Shell, command: &mut clap::Command, bin_name: &str, out: &mut dyn Write) {
+    clap_complete::generate(shell, command, bin_name, out);
+    if let Some(extra) = header_completion(shell, bin_name) {
+        let _ = out.write_all(extra.as_bytes());
+    }
+}
+
+/// Function completing the commit headers for `git commit -m`
+fn header_completion(shell: Shell, bin_name: &str) -> Option<String> {
+    match shell {
+        Shell::Fish => Some(format!(
+            "\n# Conventional commit headers for `git commit -m`\n\
+             complete -c git -n '__fish_seen_subcommand_from commit' -s m -l message -x \
+             -a '({bin_name} completions --query (commandline -ct) 2>/dev/null)'\n"
+        )),
+        // zsh's git completion has no hook for the message, the function is meant to be called
+        // from a custom completer
+        Shell::Zsh => Some(format!(
+            "\n# Completes conventional commit headers, i.e. `feat(<TAB>` with the known scopes\n\
+             _{func}_header() {{\n  \
+               local -a headers\n  \
+               headers=(${{(f)\"$({bin_name} completions --query \"$PREFIX\" 2>/dev/null)\"}})\n  \
+               compadd -Q -S '' -- $headers\n\
+             }}\n",
+            func = bin_name.replace('-', "_")
+        )),
+        _ => None,
+    }
+}
+
+/// Completions for the word being typed as the start of a commit header.
+///
+/// `fe` completes to the types, `feat(ca` to `feat(<scope>)` for the scopes starting with `ca`.
+/// A leading quote is kept so the shell can replace the word as is
+pub fn complete_header(word: &str, types: &[CommitType], scopes: &[CommitScope]) -> Vec<String> {
+    let quote_len = word
+        .char_indices()
+        .find(|(_, c)| *c != '"' && *c != '\'')
+        .map_or(word.len(), |(idx, _)| idx);
+    let (quote, word) = word.split_at(quote_len);
+
+    match word.split_once('(') {
+        Some((commit_type, partial)) => scopes
+            .iter()
+            .filter(|scope| scope.name.starts_with(partial))
+            .map(|scope| format!("{}{}({})", quote, commit_type, scope.name))
+            .collect(),
+        None => types
+            .iter()
+            .filter(|t| t.name.starts_with(word))
+            .map(|t| format!("{}{}", quote, t.name))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use rstest::rstest;
+
+    fn mk_types() -> Vec<CommitType> {
+        ["feat", "fix", "docs"]
+            .iter()
+            .map(|name| CommitType::new(*name))
+            .collect()
+    }
+
+    fn mk_scopes() -> Vec<CommitScope> {
+        ["cache", "cli", "config"]
+            .iter()
+            .map(|name| CommitScope::new(*name))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::types("f", vec!["feat", "fix"])]
+    #[case::all_types("", vec!["feat", "fix", "docs"])]
+    #[case::scopes("feat(c", vec!["feat(cache)", "feat(cli)", "feat(config)"])]
+    #[case::partial_scope("fix(ca", vec!["fix(cache)"])]
+    #[case::quoted("\"feat(cl", vec!["\"feat(cli)"])]
+    #[case::unknown("chore(x", vec![])]
+    fn header(#[case] word: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(complete_header(word, &mk_types(), &mk_scopes()), expected);
+    }
+
+    #[derive(clap::Parser)]
+    struct Dummy {
+        #[arg(long)]
+        foo: bool,
+    }
+
+    #[rstest]
+    #[case::fish(Shell::Fish, Some("cch completions --query (commandline -ct)"))]
+    #[case::zsh(Shell::Zsh, Some("_cch_header()"))]
+    #[case::bash(Shell::Bash, None)]
+    fn script(#[case] shell: Shell, #[case] expected: Option<&str>) {
+        let mut out = Vec::new();
+        generate(shell, &mut Dummy::command(), "cch", &mut out);
+        let script = String::from_utf8(out).unwrap();
+
+        assert!(script.contains("foo"), "{}", script);
+        match expected {
+            Some(expected) => assert!(script.contains(expected), "{}", script),
+            None => assert!(!script.contains("--query"), "{}", script),
+        }
+    }
+}
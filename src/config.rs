@@ -1,16 +1,25 @@
-use anyhow::{Ok, Result};
 use const_format::formatcp;
-use directories::ProjectDirs;
+use directories::{BaseDirs, ProjectDirs};
 use git2::Repository;
 use itertools::Itertools;
-use log::debug;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::str::FromStr;
+use toml_edit::{value, DocumentMut, Item, Table, TomlError};
 
+use crate::cache::repo_cache_path;
 use crate::commit_scopes::CommitScope;
 use crate::commit_types::CommitType;
+use crate::entity::Entity;
+use crate::error::{Error, Result};
+use crate::i18n::Message;
+use crate::output::Template;
+use crate::scope_name::{IgnoredScopes, ScopeNameValidator};
+use crate::utils::{head_commit, parse_since};
 
 pub const DEFAULT_CONFIG_PATH_IN_REPO: &str =
     formatcp!(".dev{}conventional-commit-helper.toml", MAIN_SEPARATOR);
@@ -19,12 +28,120 @@ const CONFIG_FILE_NAME: &str = "conventional-commit-helper.toml";
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
 pub struct GeneralConfig {
     pub scopes: Option<GeneralScopeConfig>,
+    pub pseudo_types: Option<PseudoTypesConfig>,
 }
 
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
 pub struct GeneralScopeConfig {
     pub ignored: Option<Vec<String>>,
     pub disable_history_search: Option<bool>,
+    /// Commits walked when the history is mined from scratch (i.e. the cache is cold)
+    pub max_commits: Option<usize>,
+    /// Commits authored before are not mined: a date ("2024-01-31") or a time ago ("6 months")
+    pub since: Option<String>,
+    /// Commits reachable from this revision are not mined. [`SINCE_LATEST_TAG`] is the latest
+    /// release tag (matching `versioning.tag_prefix`)
+    pub since_ref: Option<String>,
+    /// How the history is mined without the cache, [`MiningStrategy::Auto`] if not set
+    pub strategy: Option<MiningStrategy>,
+    /// `auto`: repositories with at least this many commits are mined as with `recent`,
+    /// [`DEFAULT_RECENT_THRESHOLD`] if not set
+    pub recent_threshold: Option<usize>,
+    /// Commits mined by `recent`, [`DEFAULT_RECENT_WINDOW`] if not set
+    pub recent_window: Option<usize>,
+    /// `auto`: repositories with at least this many commits are mined as with `cache_required`,
+    /// [`DEFAULT_CACHE_REQUIRED_THRESHOLD`] if not set
+    pub cache_required_threshold: Option<usize>,
+    /// Scopes offered when neither the config nor the history has any, i.e. in new repositories
+    pub fallback: Option<Vec<String>>,
+    /// Deprecated scope -> its replacement. Deprecated scopes are not offered, their history counts
+    /// for the replacement
+    pub deprecated: Option<BTreeMap<String, String>>,
+}
+
+/// How the history is mined when there's no cache to read the scopes from. Creating and updating
+/// the cache always mines the whole history (within `max_commits`, `since` and `since_ref`)
+#[derive(Debug, Deserialize, Clone, Copy, Eq, PartialEq, Serialize, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MiningStrategy {
+    /// Picked by the number of commits, see the thresholds. If any of `max_commits`, `since` and
+    /// `since_ref` is set, the history is mined as with `full`
+    #[default]
+    Auto,
+    /// Whole history
+    Full,
+    /// Only the last `recent_window` commits
+    Recent,
+    /// Refuse to mine the history, the scopes come only from the cache
+    CacheRequired,
+}
+
+pub const DEFAULT_RECENT_THRESHOLD: usize = 10_000;
+pub const DEFAULT_RECENT_WINDOW: usize = 5_000;
+pub const DEFAULT_CACHE_REQUIRED_THRESHOLD: usize = 500_000;
+
+/// Value of `general.scopes.since_ref` meaning the latest release tag
+pub const SINCE_LATEST_TAG: &str = "latest-tag";
+
+/// How to treat a subject generated by git itself (`Revert "..."`, `Merge ...`)
+///
+/// In the config file this is a string: "ignore", "error" or the name of the commit type the
+/// subject should be counted as.
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum PseudoTypeAction {
+    Ignore,
+    Error,
+    Type(String),
+}
+
+impl From<String> for PseudoTypeAction {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "ignore" => Self::Ignore,
+            "error" => Self::Error,
+            _ => Self::Type(value),
+        }
+    }
+}
+
+impl From<PseudoTypeAction> for String {
+    fn from(value: PseudoTypeAction) -> Self {
+        match value {
+            PseudoTypeAction::Ignore => "ignore".to_string(),
+            PseudoTypeAction::Error => "error".to_string(),
+            PseudoTypeAction::Type(t) => t,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash)]
+pub struct PseudoTypesConfig {
+    #[serde(default = "PseudoTypesConfig::default_revert")]
+    pub revert: PseudoTypeAction,
+    #[serde(default = "PseudoTypesConfig::default_merge")]
+    pub merge: PseudoTypeAction,
+}
+
+impl PseudoTypesConfig {
+    fn default_revert() -> PseudoTypeAction {
+        PseudoTypeAction::Type("revert".to_string())
+    }
+
+    fn default_merge() -> PseudoTypeAction {
+        PseudoTypeAction::Ignore
+    }
+}
+
+/// Reverts are counted as "revert" type (with the reverted subject still being parsed), merges are
+/// ignored
+impl Default for PseudoTypesConfig {
+    fn default() -> Self {
+        Self {
+            revert: Self::default_revert(),
+            merge: Self::default_merge(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash, Default)]
@@ -36,10 +153,235 @@ pub enum RegenerateOnStale {
     Never,
 }
 
+/// What to do when the cache is stale but was not regenerated (`regenerate_on_stale` is "never"
+/// or the prompt was declined)
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnStaleNoRegen {
+    /// Use the outdated cache content
+    UseStale,
+    /// Use only the scopes from the config
+    Skip,
+    /// Search the history as if there was no cache
+    #[default]
+    FallbackToHistory,
+}
+
+/// Where the cache is stored if the path is not set explicitly
+#[derive(Debug, Deserialize, Clone, Copy, Eq, PartialEq, Serialize, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheLocation {
+    /// Per-user cache directory
+    #[default]
+    User,
+    /// System-wide directory shared by all users of the machine
+    System,
+    /// Inside the git directory of the repository, see [`crate::cache::repo_cache_path`]
+    Repo,
+}
+
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
 pub struct CacheConfig {
     #[serde(default)]
     pub regenerate_on_stale: RegenerateOnStale,
+    #[serde(default)]
+    pub on_stale_no_regen: OnStaleNoRegen,
+    #[serde(default)]
+    pub location: CacheLocation,
+    /// Never lock or write the cache (i.e. on read-only CI cache mounts). Stale cache is handled
+    /// according to `on_stale_no_regen`
+    #[serde(default)]
+    pub read_only: bool,
+    /// Location of the cache file. `--cache-path` takes precedence over this
+    pub path: Option<PathBuf>,
+    /// Write the scopes mined from the history into the cache (creating it if needed) when the
+    /// repository is not cached yet
+    #[serde(default)]
+    pub auto_create: bool,
+    /// How long to wait for another process updating the cache, [`DEFAULT_LOCK_TIMEOUT_MS`] if
+    /// not set
+    pub lock_timeout_ms: Option<u64>,
+    /// How often to check whether the other process is done, [`DEFAULT_LOCK_RETRY_MS`] if not set
+    pub lock_retry_ms: Option<u64>,
+}
+
+pub const DEFAULT_LOCK_TIMEOUT_MS: u64 = 10_000;
+pub const DEFAULT_LOCK_RETRY_MS: u64 = 50;
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash)]
+pub struct TrackerConfig {
+    /// Regex matching the issue reference. If it has a capture group, the first group is the ID
+    pub pattern: String,
+    /// URL template, `{id}` is replaced with the issue ID
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash, Default)]
+pub enum ChangelogPreset {
+    #[default]
+    #[serde(rename = "angular")]
+    Angular,
+    #[serde(rename = "keepachangelog")]
+    KeepAChangelog,
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct ChangelogConfig {
+    #[serde(default)]
+    pub preset: ChangelogPreset,
+    /// Path to the template file (relative to the repository root). Keys in the file override the
+    /// ones from the preset
+    pub template: Option<PathBuf>,
+    /// URL to compare two revisions. Placeholders: {from}, {to}
+    pub compare_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct ScopeVersioningConfig {
+    /// Defaults to "<scope>-v"
+    pub tag_prefix: Option<String>,
+    /// Paths (relative to the repository root) that belong to the scope
+    pub paths: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct VersioningConfig {
+    /// Prefix of the repository-wide release tags. Not set means "any tag"
+    pub tag_prefix: Option<String>,
+    /// Scopes that are versioned (and tagged) independently
+    pub scopes: Option<BTreeMap<String, ScopeVersioningConfig>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct SuggestConfig {
+    /// Shell command that reorders the scope suggestions
+    pub rerank_command: Option<String>,
+    /// Untracked files are matched against the scopes as if they were staged
+    #[serde(default)]
+    pub include_untracked: bool,
+    /// If nothing is staged, the files modified in the worktree are matched instead
+    #[serde(default)]
+    pub fallback_to_worktree: bool,
+    /// Type listed (and preselected) first, also used by the `prepare-commit-msg` hook when the
+    /// scope was not used before
+    pub default_type: Option<String>,
+    /// Checked before the built-in rules of `type --suggest`
+    #[serde(default)]
+    pub type_rules: Vec<TypeRule>,
+    /// Weight of the files changed in a scope before when matching the staged files,
+    /// [`DEFAULT_HISTORY_WEIGHT`] if not set
+    pub history_weight: Option<usize>,
+    /// Weight of the `scope_paths` of a scope when matching the staged files,
+    /// [`DEFAULT_PATHS_WEIGHT`] if not set
+    pub paths_weight: Option<usize>,
+}
+
+pub const DEFAULT_HISTORY_WEIGHT: usize = 1;
+pub const DEFAULT_PATHS_WEIGHT: usize = 1;
+
+/// Commit type suggested when all the staged files match the paths
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Hash)]
+pub struct TypeRule {
+    #[serde(rename = "type")]
+    pub commit_type: String,
+    /// Git pathspecs, i.e. "*.md" or "docs/"
+    pub paths: Vec<String>,
+}
+
+/// Commit message checks of `lint` (and [`crate::engine::SuggestionEngine::validate`])
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct LintConfig {
+    /// In characters, [`DEFAULT_MAX_SUBJECT_LENGTH`] if not set
+    pub max_subject_length: Option<usize>,
+}
+
+pub const DEFAULT_MAX_SUBJECT_LENGTH: usize = 72;
+
+/// Comment block listing the top types and scopes, appended to the message by the
+/// `prepare-commit-msg` hook
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct CommentBlockConfig {
+    /// On if not set
+    pub enabled: Option<bool>,
+    /// How many types are listed, [`DEFAULT_COMMENT_BLOCK_ITEMS`] if not set. 0 skips the section
+    pub types: Option<usize>,
+    /// Same as `types` for the scopes, the suggested one goes first
+    pub scopes: Option<usize>,
+    /// Line before the types, without the comment character
+    pub types_title: Option<String>,
+    pub scopes_title: Option<String>,
+    /// Line per type or scope in the `--template` syntax, [`DEFAULT_COMMENT_BLOCK_ITEM`] if not set
+    pub item: Option<String>,
+}
+
+pub const DEFAULT_COMMENT_BLOCK_ITEMS: usize = 5;
+pub const DEFAULT_COMMENT_BLOCK_ITEM: &str = "\t{name}  {description}";
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub comment_block: CommentBlockConfig,
+}
+
+/// Heuristics of `preflight`, all off by default
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct PreflightConfig {
+    /// Look for likely secrets (private keys, tokens, passwords) in the staged files
+    #[serde(default)]
+    pub secrets: bool,
+    /// Regexes matched on top of the built-in secret patterns
+    #[serde(default)]
+    pub secret_patterns: Vec<String>,
+    /// In bytes, staged files larger than that are reported
+    pub max_file_size: Option<u64>,
+}
+
+/// Scopes derived from the layout of the repository, see [`crate::commit_scopes::inferred`]
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct ProvidersConfig {
+    /// Package names of the JS workspaces (`package.json` workspaces, `pnpm-workspace.yaml`). On
+    /// if not set
+    pub workspaces: Option<bool>,
+    /// Last path segments of the patterns in `CODEOWNERS`. Off if not set
+    pub codeowners: Option<bool>,
+    #[serde(default)]
+    pub directories: DirectoriesProviderConfig,
+}
+
+/// Names of the directories of the worktree as scopes
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct DirectoriesProviderConfig {
+    /// Off if not set
+    pub enabled: Option<bool>,
+    /// Level of the directories, 1 (the top-level ones) if not set. With 2, `src/cache/` is "cache"
+    pub depth: Option<usize>,
+    /// Directory names that are not scopes (git pathspecs, i.e. ".*"), [`DEFAULT_IGNORED_DIRECTORIES`]
+    /// if not set. The directories inside the ignored ones are skipped too
+    pub ignore: Option<Vec<String>>,
+}
+
+pub const DEFAULT_IGNORED_DIRECTORIES: &[&str] =
+    &[".*", "build", "dist", "node_modules", "target", "vendor"];
+
+/// Language of the prompts, the wizard and the `lint` output, see [`crate::i18n`]
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct I18nConfig {
+    /// i.e. "de", English if not set
+    pub lang: Option<String>,
+    /// Language -> message -> text, on top of the built-in catalogs
+    #[serde(default)]
+    pub messages: BTreeMap<String, BTreeMap<Message, String>>,
+}
+
+/// Constraints on the scope names. Not set means no constraint
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize, Default, Hash)]
+pub struct ScopeNameRules {
+    /// Contents of a regex character class, i.e. "a-z0-9-"
+    pub charset: Option<String>,
+    /// In characters
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub forbid_edge_dashes: bool,
 }
 
 /// Holds the runtime configuration
@@ -57,6 +399,47 @@ pub struct Config {
 
     #[serde(default)]
     pub cache: CacheConfig,
+
+    /// Scope name -> people or teams who should review changes in that scope
+    pub reviewers: Option<BTreeMap<String, Vec<String>>>,
+
+    /// Scope name -> git pathspecs of the files that belong to the scope. Matched against the
+    /// staged files together with the history
+    pub scope_paths: Option<BTreeMap<String, Vec<String>>>,
+
+    /// Git pathspec -> scope name. The scope of the rules matching the staged files is suggested
+    /// regardless of the history
+    pub scope_rules: Option<BTreeMap<String, String>>,
+
+    /// Issue trackers to look for references in commits
+    pub trackers: Option<BTreeMap<String, TrackerConfig>>,
+
+    #[serde(default)]
+    pub changelog: ChangelogConfig,
+
+    #[serde(default)]
+    pub versioning: VersioningConfig,
+
+    #[serde(default)]
+    pub suggest: SuggestConfig,
+
+    #[serde(default)]
+    pub scope_names: ScopeNameRules,
+
+    #[serde(default)]
+    pub lint: LintConfig,
+
+    #[serde(default)]
+    pub i18n: I18nConfig,
+
+    #[serde(default)]
+    pub preflight: PreflightConfig,
+
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    #[serde(default)]
+    pub providers: ProvidersConfig,
 }
 
 /// Used internally to parse the file
@@ -71,6 +454,40 @@ struct ReadConfig {
     general: Option<GeneralConfig>,
 
     cache: Option<CacheConfig>,
+
+    reviewers: Option<BTreeMap<String, Vec<String>>>,
+
+    scope_paths: Option<BTreeMap<String, Vec<String>>>,
+
+    scope_rules: Option<BTreeMap<String, String>>,
+
+    trackers: Option<BTreeMap<String, TrackerConfig>>,
+
+    changelog: Option<ChangelogConfig>,
+
+    versioning: Option<VersioningConfig>,
+
+    suggest: Option<SuggestConfig>,
+
+    scope_names: Option<ScopeNameRules>,
+
+    lint: Option<LintConfig>,
+
+    i18n: Option<I18nConfig>,
+
+    preflight: Option<PreflightConfig>,
+
+    hooks: Option<HooksConfig>,
+
+    providers: Option<ProvidersConfig>,
+}
+
+/// Expands the leading `~` to the home directory, if there is one
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), BaseDirs::new()) {
+        (Ok(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => path,
+    }
 }
 
 impl Config {
@@ -81,114 +498,764 @@ impl Config {
     /// should result in Config with one CommitType
     ///
     /// Extracted for easier testing
-    fn from_str(toml_str: &str) -> Result<Self> {
-        let initial_result: ReadConfig = toml::from_str(toml_str)?;
+    pub(crate) fn from_str(toml_str: &str) -> Result<Self> {
+        let initial_result: ReadConfig =
+            toml::from_str(toml_str).map_err(|e| Error::ConfigInvalid {
+                path: None,
+                line: e
+                    .span()
+                    .map(|span| toml_str[..span.start].matches('\n').count() + 1),
+                message: e.message().to_string(),
+            })?;
         let commit_types: Option<Vec<CommitType>> = initial_result.commit_types.map(|x| {
             x.iter()
-                .map(|(k, v)| CommitType {
-                    name: k.clone(),
-                    description: v.clone(),
-                })
+                .map(|(k, v)| CommitType::with_description(k, v))
                 .collect()
         });
         let commit_scopes: Option<Vec<CommitScope>> = initial_result.commit_scopes.map(|x| {
             x.iter()
-                .map(|(k, v)| CommitScope {
-                    name: k.clone(),
-                    description: v.clone(),
-                })
+                .map(|(k, v)| CommitScope::with_description(k, v))
                 .collect()
         });
 
+        if let Some(since) = initial_result
+            .general
+            .as_ref()
+            .and_then(|g| g.scopes.as_ref())
+            .and_then(|s| s.since.as_deref())
+        {
+            if parse_since(since).is_none() {
+                return Err(Error::ConfigInvalid {
+                    path: None,
+                    line: None,
+                    message: format!(
+                        "general.scopes.since: {:?} is neither a date nor a time ago",
+                        since
+                    ),
+                });
+            }
+        }
+
+        let hooks = initial_result.hooks.unwrap_or_default();
+        if let Some(item) = &hooks.comment_block.item {
+            Template::from_str(item).map_err(|e| Error::ConfigInvalid {
+                path: None,
+                line: None,
+                message: format!("hooks.comment_block.item: {}", e),
+            })?;
+        }
+
+        let providers = initial_result.providers.unwrap_or_default();
+        if providers.directories.depth == Some(0) {
+            return Err(Error::ConfigInvalid {
+                path: None,
+                line: None,
+                message: "providers.directories.depth: must be at least 1".to_string(),
+            });
+        }
+
+        if let Some(ignored) = initial_result
+            .general
+            .as_ref()
+            .and_then(|g| g.scopes.as_ref())
+            .and_then(|s| s.ignored.as_ref())
+        {
+            IgnoredScopes::new(ignored)?;
+        }
+
+        let scope_names = initial_result.scope_names.unwrap_or_default();
+        let validator = ScopeNameValidator::new(&scope_names)?;
+        for scope in commit_scopes.iter().flatten() {
+            validator
+                .parse(&scope.name)
+                .map_err(|e| Error::ConfigInvalid {
+                    path: None,
+                    line: None,
+                    message: format!("scope {:?} {}", scope.name, e),
+                })?;
+        }
+        for name in initial_result
+            .general
+            .as_ref()
+            .and_then(|g| g.scopes.as_ref())
+            .and_then(|s| s.fallback.as_ref())
+            .into_iter()
+            .flatten()
+        {
+            validator.parse(name).map_err(|e| Error::ConfigInvalid {
+                path: None,
+                line: None,
+                message: format!("general.scopes.fallback: scope {:?} {}", name, e),
+            })?;
+        }
+        for (deprecated, replacement) in initial_result
+            .general
+            .as_ref()
+            .and_then(|g| g.scopes.as_ref())
+            .and_then(|s| s.deprecated.as_ref())
+            .into_iter()
+            .flatten()
+        {
+            for name in [deprecated, replacement] {
+                validator.parse(name).map_err(|e| Error::ConfigInvalid {
+                    path: None,
+                    line: None,
+                    message: format!("general.scopes.deprecated: scope {:?} {}", name, e),
+                })?;
+            }
+        }
+        for (pattern, name) in initial_result.scope_rules.iter().flatten() {
+            validator.parse(name).map_err(|e| Error::ConfigInvalid {
+                path: None,
+                line: None,
+                message: format!("scope_rules.{:?}: scope {:?} {}", pattern, name, e),
+            })?;
+        }
+
         Ok(Self {
             commit_scopes,
             commit_types,
             general: initial_result.general,
-            cache: initial_result.cache.unwrap_or_default(),
+            cache: initial_result
+                .cache
+                .map(|cache| CacheConfig {
+                    path: cache.path.map(expand_home),
+                    ..cache
+                })
+                .unwrap_or_default(),
+            reviewers: initial_result.reviewers,
+            scope_paths: initial_result.scope_paths,
+            scope_rules: initial_result.scope_rules,
+            trackers: initial_result.trackers,
+            changelog: initial_result.changelog.unwrap_or_default(),
+            versioning: initial_result.versioning.unwrap_or_default(),
+            suggest: initial_result.suggest.unwrap_or_default(),
+            scope_names,
+            lint: initial_result.lint.unwrap_or_default(),
+            i18n: initial_result.i18n.unwrap_or_default(),
+            preflight: initial_result.preflight.unwrap_or_default(),
+            hooks,
+            providers,
         })
     }
 
     fn from_file_optional(path: &Path) -> Result<Option<Self>> {
         match path.exists() {
             true => {
-                let content = fs::read_to_string(path)?;
+                let content = fs::read_to_string(path).map_err(|source| Error::ConfigRead {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
 
-                Ok(Some(Self::from_str(&content)?))
+                let config = Self::from_str(&content).map_err(|e| match e {
+                    Error::ConfigInvalid { line, message, .. } => Error::ConfigInvalid {
+                        path: Some(path.to_path_buf()),
+                        line,
+                        message,
+                    },
+                    e => e,
+                })?;
+                Ok(Some(config))
             }
             false => Ok(None),
         }
     }
 
     pub fn from_file(path: &Path) -> Result<Self> {
-        Self::from_file_optional(path)?.ok_or_else(|| anyhow::anyhow!("File not found: {:?}", path))
+        Self::from_file_optional(path)?.ok_or_else(|| Error::ConfigNotFound {
+            path: path.to_path_buf(),
+        })
     }
 
-    fn get_global_config_path() -> Option<PathBuf> {
+    pub fn get_global_config_path() -> Option<PathBuf> {
         ProjectDirs::from("com", "vtimofeenko", "conventional-commit-helper")
             .map(|proj_dirs| proj_dirs.config_dir().join(CONFIG_FILE_NAME))
     }
 
     fn merge(self, other: Self) -> Self {
-        let commit_types = self
-            .commit_types
-            .into_iter()
-            .flatten()
-            .chain(other.commit_types.into_iter().flatten())
-            .unique()
-            .collect();
-
-        let commit_scopes = self
-            .commit_scopes
-            .into_iter()
-            .flatten()
-            .chain(other.commit_scopes.into_iter().flatten())
-            .unique()
-            .collect();
+        // Not set in either means the defaults
+        fn merge_entities<T: Clone + Eq + std::hash::Hash>(
+            this: Option<Vec<T>>,
+            other: Option<Vec<T>>,
+        ) -> Option<Vec<T>> {
+            match (this, other) {
+                (None, None) => None,
+                (this, other) => Some(
+                    this.into_iter()
+                        .flatten()
+                        .chain(other.into_iter().flatten())
+                        .unique()
+                        .collect(),
+                ),
+            }
+        }
+        let commit_types = merge_entities(self.commit_types, other.commit_types);
+        let commit_scopes = merge_entities(self.commit_scopes, other.commit_scopes);
 
         let general = self.general.or(other.general);
-        let cache = self.cache;
+        let cache = CacheConfig {
+            path: self.cache.path.or(other.cache.path),
+            read_only: self.cache.read_only || other.cache.read_only,
+            auto_create: self.cache.auto_create || other.cache.auto_create,
+            lock_timeout_ms: self.cache.lock_timeout_ms.or(other.cache.lock_timeout_ms),
+            lock_retry_ms: self.cache.lock_retry_ms.or(other.cache.lock_retry_ms),
+            ..self.cache
+        };
+
+        // Per-scope reviewers from this config win
+        let reviewers = match (self.reviewers, other.reviewers) {
+            (Some(this), Some(mut other)) => {
+                other.extend(this);
+                Some(other)
+            }
+            (this, other) => this.or(other),
+        };
+        let scope_paths = match (self.scope_paths, other.scope_paths) {
+            (Some(this), Some(mut other)) => {
+                other.extend(this);
+                Some(other)
+            }
+            (this, other) => this.or(other),
+        };
+        let scope_rules = match (self.scope_rules, other.scope_rules) {
+            (Some(this), Some(mut other)) => {
+                other.extend(this);
+                Some(other)
+            }
+            (this, other) => this.or(other),
+        };
+
+        let trackers = self.trackers.or(other.trackers);
+        let changelog = self.changelog;
+        let versioning = self.versioning;
+        let suggest = SuggestConfig {
+            rerank_command: self.suggest.rerank_command.or(other.suggest.rerank_command),
+            include_untracked: self.suggest.include_untracked || other.suggest.include_untracked,
+            fallback_to_worktree: self.suggest.fallback_to_worktree
+                || other.suggest.fallback_to_worktree,
+            default_type: self.suggest.default_type.or(other.suggest.default_type),
+            type_rules: [self.suggest.type_rules, other.suggest.type_rules].concat(),
+            history_weight: self.suggest.history_weight.or(other.suggest.history_weight),
+            paths_weight: self.suggest.paths_weight.or(other.suggest.paths_weight),
+        };
+        let scope_names = ScopeNameRules {
+            charset: self.scope_names.charset.or(other.scope_names.charset),
+            max_length: self.scope_names.max_length.or(other.scope_names.max_length),
+            forbid_edge_dashes: self.scope_names.forbid_edge_dashes
+                || other.scope_names.forbid_edge_dashes,
+        };
+        let lint = LintConfig {
+            max_subject_length: self
+                .lint
+                .max_subject_length
+                .or(other.lint.max_subject_length),
+        };
+        // Texts from this config win, per message
+        let mut messages = other.i18n.messages;
+        for (lang, texts) in self.i18n.messages {
+            messages.entry(lang).or_default().extend(texts);
+        }
+        let i18n = I18nConfig {
+            lang: self.i18n.lang.or(other.i18n.lang),
+            messages,
+        };
+        let preflight = PreflightConfig {
+            secrets: self.preflight.secrets || other.preflight.secrets,
+            secret_patterns: [
+                self.preflight.secret_patterns,
+                other.preflight.secret_patterns,
+            ]
+            .concat(),
+            max_file_size: self
+                .preflight
+                .max_file_size
+                .or(other.preflight.max_file_size),
+        };
+
+        let (block, other_block) = (self.hooks.comment_block, other.hooks.comment_block);
+        let hooks = HooksConfig {
+            comment_block: CommentBlockConfig {
+                enabled: block.enabled.or(other_block.enabled),
+                types: block.types.or(other_block.types),
+                scopes: block.scopes.or(other_block.scopes),
+                types_title: block.types_title.or(other_block.types_title),
+                scopes_title: block.scopes_title.or(other_block.scopes_title),
+                item: block.item.or(other_block.item),
+            },
+        };
+        let (dirs, other_dirs) = (self.providers.directories, other.providers.directories);
+        let providers = ProvidersConfig {
+            workspaces: self.providers.workspaces.or(other.providers.workspaces),
+            codeowners: self.providers.codeowners.or(other.providers.codeowners),
+            directories: DirectoriesProviderConfig {
+                enabled: dirs.enabled.or(other_dirs.enabled),
+                depth: dirs.depth.or(other_dirs.depth),
+                ignore: dirs.ignore.or(other_dirs.ignore),
+            },
+        };
 
         Self {
-            commit_types: Some(commit_types),
-            commit_scopes: Some(commit_scopes),
+            commit_types,
+            commit_scopes,
             general,
             cache,
+            reviewers,
+            scope_paths,
+            scope_rules,
+            trackers,
+            changelog,
+            versioning,
+            suggest,
+            scope_names,
+            lint,
+            i18n,
+            preflight,
+            hooks,
+            providers,
         }
     }
 
     pub fn load(repo: &Repository, from_path: Option<PathBuf>) -> Result<Option<Self>> {
-        if let Some(path) = from_path {
-            debug!("Loading config from path: {:?}", path);
-            return Self::from_file(&path).map(Some);
+        let config = match from_path {
+            Some(path) => {
+                debug!("Loading config from path: {:?}", path);
+                Some(Self::from_file(&path)?)
+            }
+            None => Self::load_layers(repo)?,
+        };
+
+        Ok(config.map(|config| config.with_repo_cache_path(repo)))
+    }
+
+    /// Points `cache.path` into the repository if `cache.location` is "repo" and the path is not
+    /// set explicitly
+    pub fn with_repo_cache_path(mut self, repo: &Repository) -> Self {
+        if self.cache.location == CacheLocation::Repo && self.cache.path.is_none() {
+            self.cache.path = Some(repo_cache_path(repo));
         }
+        self
+    }
+
+    /// Repo config file as committed at HEAD, for the bare repositories. None if it's not there
+    fn from_head(repo: &Repository) -> Result<Option<Self>> {
+        let Some(head) = head_commit(repo)? else {
+            return Ok(None);
+        };
+        let tree = repo.find_commit(head)?.tree()?;
+        let Ok(entry) = tree.get_path(Path::new(DEFAULT_CONFIG_PATH_IN_REPO)) else {
+            return Ok(None);
+        };
+        let blob = repo.find_blob(entry.id())?;
 
-        let repo_config = Self::from_file_optional(
-            &repo
-                .workdir()
-                .expect("Repository should not be bare")
-                .join(DEFAULT_CONFIG_PATH_IN_REPO),
-        )?;
+        let config =
+            Self::from_str(&String::from_utf8_lossy(blob.content())).map_err(|e| match e {
+                Error::ConfigInvalid { line, message, .. } => Error::ConfigInvalid {
+                    path: Some(PathBuf::from(DEFAULT_CONFIG_PATH_IN_REPO)),
+                    line,
+                    message,
+                },
+                e => e,
+            })?;
+        Ok(Some(config))
+    }
+
+    /// Repo, global and git config merged
+    fn load_layers(repo: &Repository) -> Result<Option<Self>> {
+        let repo_config = match repo.workdir() {
+            Some(workdir) => Self::from_file_optional(&workdir.join(DEFAULT_CONFIG_PATH_IN_REPO))?,
+            // Nothing is checked out in a bare repository
+            None => Self::from_head(repo)?,
+        };
 
         let global_config_path = Self::get_global_config_path();
         let global_config = if let Some(path) = global_config_path {
             Self::from_file_optional(&path)?
         } else {
+            info!("Unable to determine the config directory (is there a home directory?), global config is disabled");
             None
         };
 
-        match (repo_config, global_config) {
-            (Some(repo), Some(global)) => Ok(Some(repo.merge(global))),
-            (Some(repo), None) => Ok(Some(repo)),
-            (None, Some(global)) => Ok(Some(global)),
-            (None, None) => Ok(None),
+        // Config files win over the git config
+        Ok([repo_config, global_config, Self::from_git_config(repo)?]
+            .into_iter()
+            .flatten()
+            .reduce(Self::merge))
+    }
+
+    /// Layer from the `conventionalCommit` section of the git config of the repository:
+    /// `conventionalCommit.scopes` (multi-valued, `name` or `name: description`) and
+    /// `conventionalCommit.defaultType`. The git config is read the way git reads it, including
+    /// `include` and `includeIf`. None if none of the keys is set
+    pub fn from_git_config(repo: &Repository) -> Result<Option<Self>> {
+        let git_config = repo.config()?.snapshot()?;
+
+        let mut scopes = Vec::new();
+        let mut entries = git_config.multivar("conventionalCommit.scopes", None)?;
+        while let Some(entry) = entries.next() {
+            if let Some(value) = entry?.value() {
+                let (name, description) = value.split_once(':').unwrap_or((value, ""));
+                match name.trim() {
+                    "" => warn!("Empty scope name in conventionalCommit.scopes: {:?}", value),
+                    name => scopes.push(CommitScope::with_description(name, description.trim())),
+                }
+            }
+        }
+        let default_type = git_config.get_string("conventionalCommit.defaultType").ok();
+
+        if scopes.is_empty() && default_type.is_none() {
+            return Ok(None);
+        }
+        debug!("Found conventionalCommit settings in the git config");
+
+        Ok(Some(Self {
+            commit_scopes: (!scopes.is_empty()).then_some(scopes),
+            suggest: SuggestConfig {
+                default_type,
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+    }
+
+    /// Value of the dotted key in the layout of the config file (i.e. `scopes.cli` is the
+    /// description of the scope), with the defaults applied. None if the key is unknown or not set
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let mut value = serde_json::to_value(self).expect("Config is serializable");
+        // Entities are lists internally, tables in the file
+        for section in ["types", "scopes"] {
+            if let Some(entities) = value.get_mut(section) {
+                *entities = entities
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entity| {
+                        Some((
+                            entity["name"].as_str()?.to_string(),
+                            entity["description"].clone(),
+                        ))
+                    })
+                    .sorted_by(|a, b| a.0.cmp(&b.0))
+                    .collect::<serde_json::Map<_, _>>()
+                    .into();
+            }
+        }
+
+        for part in key.split('.') {
+            value = match value {
+                Value::Object(mut fields) => fields.remove(part)?,
+                _ => return None,
+            };
+        }
+        without_nulls(value)
+    }
+}
+
+/// Unset values are nulls in the serialized config
+fn without_nulls(value: Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Object(fields) => Some(Value::Object(
+            fields
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, without_nulls(v)?)))
+                .collect(),
+        )),
+        Value::Array(items) => Some(Value::Array(
+            items.into_iter().filter_map(without_nulls).collect(),
+        )),
+        value => Some(value),
+    }
+}
+
+/// Adds the entities missing from the table of the document. Returns the added ones
+fn add_entities<K: Clone>(
+    doc: &mut DocumentMut,
+    table_name: &str,
+    entities: &[Entity<K>],
+) -> Result<Vec<Entity<K>>> {
+    let table = doc
+        .entry(table_name)
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| Error::ConfigInvalid {
+            path: None,
+            line: None,
+            message: format!("{} is not a table", table_name),
+        })?;
+
+    let mut added = Vec::new();
+    for entity in entities {
+        if !table.contains_key(&entity.name) {
+            table.insert(&entity.name, value(&entity.description));
+            added.push(entity.clone());
         }
     }
+
+    Ok(added)
+}
+
+/// Renders the scopes as a `[scopes]` table
+pub fn scopes_to_toml(scopes: &[CommitScope]) -> String {
+    let mut doc = DocumentMut::new();
+    add_entities(&mut doc, "scopes", scopes).expect("scopes is a table in the empty document");
+    doc.to_string()
+}
+
+/// Edits the config file in place, keeping the comments and the formatting. The file is created
+/// if it does not exist
+fn edit_config_file<T>(path: &Path, edit: impl FnOnce(&mut DocumentMut) -> Result<T>) -> Result<T> {
+    let content = match path.exists() {
+        true => fs::read_to_string(path).map_err(|source| Error::ConfigRead {
+            path: path.to_path_buf(),
+            source,
+        })?,
+        false => String::new(),
+    };
+    let with_path = |e| match e {
+        Error::ConfigInvalid { line, message, .. } => Error::ConfigInvalid {
+            path: Some(path.to_path_buf()),
+            line,
+            message,
+        },
+        e => e,
+    };
+
+    let mut doc: DocumentMut = content.parse().map_err(|e: TomlError| {
+        with_path(Error::ConfigInvalid {
+            path: None,
+            line: None,
+            message: e.to_string(),
+        })
+    })?;
+    let res = edit(&mut doc).map_err(with_path)?;
+
+    // Written config should load the same way as any other
+    Config::from_str(&doc.to_string()).map_err(with_path)?;
+
+    let write_err = |source| Error::ConfigWrite {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_err)?;
+    }
+    backup_config_file(path)?;
+    fs::write(path, doc.to_string()).map_err(write_err)?;
+
+    Ok(res)
+}
+
+/// How many backups of a config file are kept, the oldest ones are removed
+const MAX_CONFIG_BACKUPS: usize = 10;
+
+/// Extension of a backup taken when the config file did not exist, undoing it removes the file
+const ABSENT_BACKUP_EXTENSION: &str = "absent";
+
+/// Backups of the config file, oldest first. They are next to the file:
+/// `<file name>.<timestamp>-<counter>.bak` (or `.absent` if the file did not exist)
+pub fn config_backups(path: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}.", name.to_string_lossy());
+
+    let entries = fs::read_dir(dir).map_err(|source| Error::ConfigRead {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|backup| {
+            backup.file_name().is_some_and(|backup_name| {
+                let backup_name = backup_name.to_string_lossy();
+                backup_name.starts_with(&prefix)
+                    && (backup_name.ends_with(".bak")
+                        || backup_name.ends_with(&format!(".{}", ABSENT_BACKUP_EXTENSION)))
+            })
+        })
+        .sorted()
+        .collect())
+}
+
+/// Saves the current content of the file before it's rewritten, keeping the last
+/// [`MAX_CONFIG_BACKUPS`] backups
+fn backup_config_file(path: &Path) -> Result<()> {
+    let write_err = |backup: &Path| {
+        let backup = backup.to_path_buf();
+        move |source| Error::ConfigWrite {
+            path: backup,
+            source,
+        }
+    };
+    let extension = match path.exists() {
+        true => "bak",
+        false => ABSENT_BACKUP_EXTENSION,
+    };
+    let timestamp = crate::utils::time::now().format("%Y%m%dT%H%M%S%.3fZ");
+    // The counter keeps the order of several edits within a millisecond
+    let backup = (0..)
+        .map(|counter| {
+            path.with_file_name(format!(
+                "{}.{}-{:03}.{}",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                timestamp,
+                counter,
+                extension
+            ))
+        })
+        .find(|backup| !backup.exists())
+        .expect("Some counter is free");
+
+    debug!("Backing up {:?} to {:?}", path, backup);
+    match path.exists() {
+        true => fs::copy(path, &backup).map(|_| ()),
+        false => fs::write(&backup, ""),
+    }
+    .map_err(write_err(&backup))?;
+
+    let backups = config_backups(path)?;
+    for old in backups.iter().rev().skip(MAX_CONFIG_BACKUPS) {
+        debug!("Removing the old backup {:?}", old);
+        fs::remove_file(old).map_err(write_err(old))?;
+    }
+
+    Ok(())
+}
+
+/// Result of [`undo_config_change`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigUndo {
+    /// The file was restored from the backup
+    Restored(PathBuf),
+    /// The file did not exist before the change and was removed
+    Removed,
+    NothingToUndo,
+}
+
+/// Restores the config file from the latest backup and removes the backup, so that undoing
+/// again goes one more change back
+pub fn undo_config_change(path: &Path) -> Result<ConfigUndo> {
+    let Some(backup) = config_backups(path)?.pop() else {
+        return Ok(ConfigUndo::NothingToUndo);
+    };
+    let write_err = |source| Error::ConfigWrite {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let res = match backup
+        .extension()
+        .is_some_and(|e| e == ABSENT_BACKUP_EXTENSION)
+    {
+        true => {
+            if path.exists() {
+                fs::remove_file(path).map_err(write_err)?;
+            }
+            ConfigUndo::Removed
+        }
+        false => {
+            fs::copy(&backup, path).map_err(write_err)?;
+            ConfigUndo::Restored(backup.clone())
+        }
+    };
+    fs::remove_file(&backup).map_err(|source| Error::ConfigWrite {
+        path: backup.clone(),
+        source,
+    })?;
+
+    Ok(res)
+}
+
+/// Adds the scopes to the `[scopes]` table of the config file, keeping the existing scopes.
+/// Returns the added scopes
+pub fn add_scopes_to_file(path: &Path, scopes: &[CommitScope]) -> Result<Vec<CommitScope>> {
+    edit_config_file(path, |doc| add_entities(doc, "scopes", scopes))
+}
+
+/// Same as [`add_scopes_to_file`] for the `[types]` table. Note that configured types replace the
+/// default ones
+pub fn add_types_to_file(path: &Path, types: &[CommitType]) -> Result<Vec<CommitType>> {
+    edit_config_file(path, |doc| add_entities(doc, "types", types))
+}
+
+/// Sets the dotted key in the config file, creating the tables on the way. The value is parsed as
+/// a TOML value (`true`, `20`, `["a", "b"]`) if it is valid for the key, otherwise it is a string.
+/// Unknown keys are rejected
+pub fn set_value_in_file(path: &Path, key: &str, value: &str) -> Result<()> {
+    if let Some(typed) = value
+        .parse::<toml_edit::Value>()
+        .ok()
+        .filter(|typed| !typed.is_str())
+    {
+        if edit_config_file(path, |doc| set_value(doc, key, typed)).is_ok() {
+            return Ok(());
+        }
+    }
+
+    edit_config_file(path, |doc| set_value(doc, key, value.into()))
+}
+
+fn set_value(doc: &mut DocumentMut, key: &str, value: toml_edit::Value) -> Result<()> {
+    let invalid = |message: String| Error::ConfigInvalid {
+        path: None,
+        line: None,
+        message,
+    };
+    let parts = key.split('.').collect_vec();
+    let (last, tables) = parts.split_last().expect("split returns at least one part");
+
+    let mut table = doc.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for part in tables {
+        table = table
+            .entry(part)
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| invalid(format!("{:?} is not a table", part)))?;
+    }
+    table.insert(last, Item::Value(value));
+
+    match Config::from_str(&doc.to_string())?.get(key) {
+        Some(_) => Ok(()),
+        None => Err(invalid(format!("unknown key {:?}", key))),
+    }
+}
+
+/// Applies the cache location and read-only mode set outside of the config (i.e. CLI arguments),
+/// they take precedence over the config
+pub fn with_cache_overrides(
+    config: Option<Config>,
+    cache_path: Option<PathBuf>,
+    cache_read_only: bool,
+) -> Option<Config> {
+    if cache_path.is_none() && !cache_read_only {
+        return config;
+    }
+
+    let mut config = config.unwrap_or_default();
+    if let Some(path) = cache_path {
+        config.cache.path = Some(path);
+    }
+    config.cache.read_only |= cache_read_only;
+    Some(config)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use indoc::indoc;
+    use rstest::rstest;
+    use testdir::testdir;
 
     /// Make sure that the custom "turn key value" From actually works
     #[test]
@@ -203,17 +1270,12 @@ mod test {
         let res = Config::from_str(toml_str);
 
         let expected = Config {
-            commit_types: Some(vec![CommitType {
-                name: "foo".to_string(),
-                description: "bar".to_string(),
-            }]),
-
-            commit_scopes: Some(vec![CommitScope {
-                name: "foz".to_string(),
-                description: "baz".to_string(),
-            }]),
+            commit_types: Some(vec![CommitType::with_description("foo", "bar")]),
+
+            commit_scopes: Some(vec![CommitScope::with_description("foz", "baz")]),
             general: None,
             cache: CacheConfig::default(),
+            ..Default::default()
         };
 
         assert_eq!(res.unwrap(), expected)
@@ -233,6 +1295,23 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_pseudo_types_settings() {
+        let toml_str = indoc! {r#"
+            [general.pseudo_types]
+            merge = "chore"
+                "#};
+        let config: Config = Config::from_str(toml_str).unwrap();
+        let pseudo_types = config.general.unwrap().pseudo_types.unwrap();
+
+        assert_eq!(
+            pseudo_types.merge,
+            PseudoTypeAction::Type("chore".to_string())
+        );
+        // Unset value falls back to the default
+        assert_eq!(pseudo_types.revert, PseudoTypesConfig::default().revert);
+    }
+
     #[test]
     fn test_cache_settings() {
         let toml_str = indoc! {r#"
@@ -244,59 +1323,341 @@ mod test {
         assert_eq!(config.cache.regenerate_on_stale, RegenerateOnStale::Prompt)
     }
 
+    #[test]
+    fn test_cache_path() {
+        let toml_str = indoc! {r#"
+            [cache]
+            path = "~/foo/cache.bin"
+                "#};
+        let config: Config = Config::from_str(toml_str).unwrap();
+
+        assert_eq!(
+            config.cache.path.unwrap(),
+            BaseDirs::new().unwrap().home_dir().join("foo/cache.bin")
+        );
+        assert_eq!(config.cache.regenerate_on_stale, RegenerateOnStale::Always)
+    }
+
+    #[test]
+    fn test_invalid_config_line() {
+        let err = Config::from_str(indoc! {r#"
+            [types]
+            feat = 1
+            "#})
+        .unwrap_err();
+
+        assert!(
+            matches!(err, Error::ConfigInvalid { line: Some(2), .. }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_invalid_comment_block_item() {
+        let err = Config::from_str("[hooks.comment_block]\nitem = \"{name\"\n").unwrap_err();
+
+        assert!(
+            err.to_string().contains("hooks.comment_block.item"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_invalid_directories_depth() {
+        let err = Config::from_str("[providers.directories]\ndepth = 0\n").unwrap_err();
+
+        assert!(
+            err.to_string().contains("providers.directories.depth"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_add_scopes_to_file() {
+        let dir = testdir!();
+        let path = dir.join("config.toml");
+
+        let added = add_scopes_to_file(&path, &[CommitScope::with_description("foo", "bar")]);
+        assert_eq!(
+            added.unwrap(),
+            vec![CommitScope::with_description("foo", "bar")]
+        );
+
+        let added = add_scopes_to_file(
+            &path,
+            &[
+                CommitScope::with_description("foo", "other"),
+                CommitScope::new("baz"),
+            ],
+        );
+        assert_eq!(added.unwrap(), vec![CommitScope::new("baz")]);
+
+        assert_eq!(
+            Config::from_file(&path)
+                .unwrap()
+                .commit_scopes
+                .unwrap()
+                .into_iter()
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec![
+                CommitScope::new("baz"),
+                CommitScope::with_description("foo", "bar")
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::valid("foo-bar", true)]
+    #[case::charset("Foo", false)]
+    #[case::too_long("foo-bar-baz", false)]
+    #[case::edge_dash("foo-", false)]
+    fn test_scope_names(#[case] scope: &str, #[case] valid: bool) {
+        let res = Config::from_str(&format!(
+            indoc! {r#"
+            [scope_names]
+            charset = "a-z-"
+            max_length = 8
+            forbid_edge_dashes = true
+
+            [scopes]
+            {} = "description"
+            "#},
+            scope
+        ));
+
+        assert_eq!(res.is_ok(), valid, "{:?}", res);
+    }
+
+    #[test]
+    fn test_invalid_ignored_pattern() {
+        let err = Config::from_str("[general.scopes]\nignored = [\"deps(\"]\n").unwrap_err();
+
+        assert!(
+            err.to_string().contains("general.scopes.ignored"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_invalid_scope_rule() {
+        let err = Config::from_str(indoc! {r#"
+            [scope_names]
+            charset = "a-z"
+
+            [scope_rules]
+            "src/cache/**" = "Cache"
+            "#})
+        .unwrap_err();
+
+        assert!(
+            err.to_string().contains("scope_rules.\"src/cache/**\""),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cache_location() {
+        let toml_str = indoc! {r#"
+            [cache]
+            location = "system"
+                "#};
+        let config: Config = Config::from_str(toml_str).unwrap();
+
+        assert_eq!(config.cache.location, CacheLocation::System);
+        assert_eq!(
+            Config::from_str("").unwrap().cache.location,
+            CacheLocation::User
+        );
+    }
+
     #[test]
     fn test_config_merge() {
         let repo_config = Config {
-            commit_types: Some(vec![CommitType {
-                name: "foo".to_string(),
-                description: "bar".to_string(),
-            }]),
-            commit_scopes: Some(vec![CommitScope {
-                name: "foz".to_string(),
-                description: "baz".to_string(),
-            }]),
+            commit_types: Some(vec![CommitType::with_description("foo", "bar")]),
+            commit_scopes: Some(vec![CommitScope::with_description("foz", "baz")]),
             general: None,
             cache: CacheConfig {
                 regenerate_on_stale: RegenerateOnStale::Prompt,
+                ..Default::default()
             },
+            ..Default::default()
         };
 
         let global_config = Config {
-            commit_types: Some(vec![CommitType {
-                name: "foo".to_string(),
-                description: "bar".to_string(),
-            }]),
-            commit_scopes: Some(vec![CommitScope {
-                name: "global".to_string(),
-                description: "global".to_string(),
-            }]),
+            commit_types: Some(vec![CommitType::with_description("foo", "bar")]),
+            commit_scopes: Some(vec![CommitScope::with_description("global", "global")]),
             general: None,
             cache: CacheConfig::default(),
+            ..Default::default()
         };
 
         let merged = repo_config.merge(global_config);
 
         let expected = Config {
-            commit_types: Some(vec![CommitType {
-                name: "foo".to_string(),
-                description: "bar".to_string(),
-            }]),
+            commit_types: Some(vec![CommitType::with_description("foo", "bar")]),
             commit_scopes: Some(vec![
-                CommitScope {
-                    name: "foz".to_string(),
-                    description: "baz".to_string(),
-                },
-                CommitScope {
-                    name: "global".to_string(),
-                    description: "global".to_string(),
-                },
+                CommitScope::with_description("foz", "baz"),
+                CommitScope::with_description("global", "global"),
             ]),
             general: None,
             cache: CacheConfig {
                 regenerate_on_stale: RegenerateOnStale::Prompt,
+                ..Default::default()
             },
+            ..Default::default()
         };
 
         assert_eq!(merged, expected);
     }
+
+    /// Types not set anywhere stay unset, so that the defaults are used
+    #[test]
+    fn test_config_merge_keeps_defaults() {
+        let merged = Config::from_str("[scopes]\nfoo = \"\"\n")
+            .unwrap()
+            .merge(Config::default());
+
+        assert_eq!(merged.commit_types, None);
+    }
+
+    #[test]
+    fn test_git_config_layer() {
+        let dir = testdir!();
+        let repo = crate::test_utils::setup_repo_with_commits(&dir, &["init"]);
+        crate::test_utils::mk_config_with_scopes_only(&dir);
+        // Only included for this repository
+        let included = dir.join("work.gitconfig");
+        fs::write(
+            &included,
+            "[conventionalCommit]\n\tscopes = cli: Command line\n\tdefaultType = fix\n",
+        )
+        .unwrap();
+        let mut git_config = fs::OpenOptions::new()
+            .append(true)
+            .open(repo.path().join("config"))
+            .unwrap();
+        std::io::Write::write_all(
+            &mut git_config,
+            format!(
+                "[conventionalCommit]\n\tscopes = core\n[includeIf \"gitdir:{}/\"]\n\tpath = {}\n",
+                dir.to_string_lossy(),
+                included.to_string_lossy()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let config = Config::load(&repo, None).unwrap().unwrap();
+
+        assert_eq!(
+            config.commit_scopes.unwrap(),
+            vec![
+                CommitScope::with_description("foz", "baz"),
+                CommitScope::new("core"),
+                CommitScope::with_description("cli", "Command line"),
+            ]
+        );
+        assert_eq!(config.commit_types, None);
+        assert_eq!(config.suggest.default_type.as_deref(), Some("fix"));
+    }
+
+    #[rstest]
+    #[case::default("cache.regenerate_on_stale", Some(r#""always""#))]
+    #[case::entity("scopes.foz", Some(r#""baz""#))]
+    #[case::entities("types", Some(r#"{"bar":"","foo":"bar"}"#))]
+    #[case::table("lint", Some(r#"{"max_subject_length":50}"#))]
+    #[case::unset("suggest.default_type", None)]
+    #[case::unknown("lint.nope", None)]
+    #[case::past_value("lint.max_subject_length.nope", None)]
+    fn test_get(#[case] key: &str, #[case] expected: Option<&str>) {
+        let config = Config::from_str(indoc! {r#"
+            [types]
+            foo = "bar"
+            bar = ""
+            [scopes]
+            foz = "baz"
+            [lint]
+            max_subject_length = 50
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            config.get(key).map(|value| value.to_string()).as_deref(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_set_value_in_file() {
+        let path = testdir!().join("config.toml");
+        fs::write(&path, "# Keep me\n[scopes]\nfoz = \"baz\"\n").unwrap();
+
+        set_value_in_file(&path, "lint.max_subject_length", "50").unwrap();
+        // Not a valid integer for the key, kept as a string
+        set_value_in_file(&path, "scopes.cli", "42").unwrap();
+        set_value_in_file(&path, "scopes.foz", "qux").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            indoc! {r#"
+                # Keep me
+                [scopes]
+                foz = "qux"
+                cli = "42"
+
+                [lint]
+                max_subject_length = 50
+            "#}
+        );
+
+        assert!(set_value_in_file(&path, "lint.max_subject_lenght", "50").is_err());
+        assert!(set_value_in_file(&path, "lint.max_subject_length", "many").is_err());
+        assert!(set_value_in_file(&path, "scopes.foz.nested", "x").is_err());
+    }
+
+    #[test]
+    fn test_undo_config_change() {
+        let path = testdir!().join("config.toml");
+
+        set_value_in_file(&path, "lint.max_subject_length", "50").unwrap();
+        set_value_in_file(&path, "lint.max_subject_length", "60").unwrap();
+        assert!(set_value_in_file(&path, "lint.nope", "1").is_err());
+        assert_eq!(config_backups(&path).unwrap().len(), 2);
+
+        assert!(matches!(
+            undo_config_change(&path).unwrap(),
+            ConfigUndo::Restored(_)
+        ));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[lint]\nmax_subject_length = 50\n"
+        );
+        // The file was created by the first change
+        assert_eq!(undo_config_change(&path).unwrap(), ConfigUndo::Removed);
+        assert!(!path.exists());
+        assert_eq!(
+            undo_config_change(&path).unwrap(),
+            ConfigUndo::NothingToUndo
+        );
+    }
+
+    #[test]
+    fn test_config_backups_pruned() {
+        let path = testdir!().join("config.toml");
+
+        for length in 0..MAX_CONFIG_BACKUPS + 2 {
+            set_value_in_file(&path, "lint.max_subject_length", &length.to_string()).unwrap();
+        }
+
+        let backups = config_backups(&path).unwrap();
+        assert_eq!(backups.len(), MAX_CONFIG_BACKUPS);
+        // The one of the file before it was created is gone
+        assert!(backups.iter().all(|b| b.extension().unwrap() == "bak"));
+    }
 }
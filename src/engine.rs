@@ -0,0 +1,613 @@
+//! Single entry point to the suggestion logic, used by the CLI and meant for embedding.
+//!
+//! ```no_run
+//! use conventional_commit_helper::engine::SuggestionEngine;
+//! use std::time::Duration;
+//!
+//! let engine = SuggestionEngine::builder(".")
+//!     .cache_read_only(true)
+//!     .time_budget(Duration::from_millis(500))
+//!     .build()?;
+//!
+//! for scope in engine.scopes()? {
+//!     println!("{}", scope.name);
+//! }
+//! # Ok::<(), conventional_commit_helper::Error>(())
+//! ```
+use git2::Repository;
+use log::{debug, info};
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::commit_message::{
+    malformed_footers, parse_message, pseudo_types_config, resolve_subject, PseudoType,
+    ResolvedSubject,
+};
+use crate::commit_scopes::{
+    get_deprecated_scopes, try_get_commit_scopes_from_repo, try_get_scope_suggestions, CommitScope,
+    ScopeSuggestions,
+};
+use crate::commit_types::{
+    get_commit_types_from_repo_or_default, try_suggest_commit_type, CommitType,
+};
+use crate::config::{with_cache_overrides, Config, RegenerateOnStale, DEFAULT_MAX_SUBJECT_LENGTH};
+use crate::error::{Error, Result};
+use crate::i18n::{Message, Messages};
+use crate::prefix::{suggest_prefix, PrefixSuggestion};
+use crate::preflight::{preflight, Preflight};
+use crate::scope_name::ScopeNameValidator;
+use crate::utils::repo_from_path;
+use crate::warnings;
+
+/// Builds a [`SuggestionEngine`]
+#[derive(Debug, Clone)]
+pub struct SuggestionEngineBuilder {
+    repo_path: PathBuf,
+    config_path: Option<PathBuf>,
+    config: Option<Config>,
+    cache_path: Option<PathBuf>,
+    cache_read_only: bool,
+    include_untracked: bool,
+    interactive: bool,
+    time_budget: Option<Duration>,
+    lang: Option<String>,
+    since_ref: Option<String>,
+}
+
+impl SuggestionEngineBuilder {
+    /// Config file to use instead of the repo and global ones
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Use this config as is, without reading any config files
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Cache file location, takes precedence over the config
+    pub fn cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
+
+    /// Never lock or write the cache
+    pub fn cache_read_only(mut self, read_only: bool) -> Self {
+        self.cache_read_only = read_only;
+        self
+    }
+
+    /// Match the untracked files against the scopes too, as if they were staged. Same as
+    /// `suggest.include_untracked` in the config
+    pub fn include_untracked(mut self, include_untracked: bool) -> Self {
+        self.include_untracked = include_untracked;
+        self
+    }
+
+    /// Whether the user can be asked questions (i.e. whether the stale cache should be
+    /// regenerated). Off by default, `regenerate_on_stale = "prompt"` is treated as "never" then
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Language of the prompts and messages, takes precedence over `i18n.lang` in the config
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Do not mine the commits reachable from the revision, `"latest-tag"` is the latest release
+    /// tag. Takes precedence over `general.scopes.since_ref` in the config
+    pub fn since_ref(mut self, rev: impl Into<String>) -> Self {
+        self.since_ref = Some(rev.into());
+        self
+    }
+
+    /// Upper bound on the time [`SuggestionEngine::scopes`] and [`SuggestionEngine::suggest`]
+    /// may take. If it's exceeded, they return an error and the work is finished in background
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Opens the repository and loads the config
+    pub fn build(self) -> Result<SuggestionEngine> {
+        let repo = repo_from_path(&self.repo_path)?;
+
+        let config = match self.config {
+            Some(config) => Some(config.with_repo_cache_path(&repo)),
+            None => Config::load(&repo, self.config_path)?,
+        };
+        let mut config = with_cache_overrides(config, self.cache_path, self.cache_read_only);
+        if self.include_untracked {
+            config
+                .get_or_insert_with(Config::default)
+                .suggest
+                .include_untracked = true;
+        }
+        if let Some(rev) = self.since_ref {
+            config
+                .get_or_insert_with(Config::default)
+                .general
+                .get_or_insert_with(Default::default)
+                .scopes
+                .get_or_insert_with(Default::default)
+                .since_ref = Some(rev);
+        }
+        if let Some(lang) = self.lang {
+            config.get_or_insert_with(Config::default).i18n.lang = Some(lang);
+        }
+
+        if !self.interactive {
+            if let Some(config) = config.as_mut() {
+                if config.cache.regenerate_on_stale == RegenerateOnStale::Prompt {
+                    info!("Not interactive, the stale cache will not be regenerated");
+                    config.cache.regenerate_on_stale = RegenerateOnStale::Never;
+                }
+            }
+        }
+        debug!("Loaded config: {:?}", config);
+
+        Ok(SuggestionEngine {
+            repo,
+            config,
+            time_budget: self.time_budget,
+        })
+    }
+}
+
+/// Problem with a commit message found by [`SuggestionEngine::validate`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    /// Subject is not `type(scope): description`
+    NotConventional,
+    UnknownType {
+        commit_type: String,
+    },
+    UnknownScope {
+        scope: String,
+    },
+    /// Scope is in `general.scopes.deprecated`
+    DeprecatedScope {
+        scope: String,
+        replacement: String,
+    },
+    /// Scope name breaks the `[scope_names]` rules
+    InvalidScope {
+        scope: String,
+        reason: String,
+    },
+    /// Subject is longer than `lint.max_subject_length` characters
+    SubjectTooLong {
+        length: usize,
+        max: usize,
+    },
+    /// Body is not separated from the subject by a blank line
+    NoBlankLineAfterSubject,
+    MalformedFooter {
+        line: String,
+        reason: String,
+    },
+    /// Subject is a revert or a merge configured as "error" in `general.pseudo_types`
+    PseudoTypeNotAllowed {
+        pseudo_type: PseudoType,
+    },
+}
+
+impl Violation {
+    /// Description of the problem in the language of the messages
+    pub fn localized(&self, messages: &Messages) -> String {
+        match self {
+            Violation::NotConventional => messages.get(Message::NotConventional).to_string(),
+            Violation::UnknownType { commit_type } => messages.format(
+                Message::UnknownType,
+                &[("commit_type", &format!("{:?}", commit_type))],
+            ),
+            Violation::UnknownScope { scope } => {
+                messages.format(Message::UnknownScope, &[("scope", &format!("{:?}", scope))])
+            }
+            Violation::DeprecatedScope { scope, replacement } => messages.format(
+                Message::DeprecatedScope,
+                &[
+                    ("scope", &format!("{:?}", scope)),
+                    ("replacement", &format!("{:?}", replacement)),
+                ],
+            ),
+            Violation::InvalidScope { scope, reason } => messages.format(
+                Message::InvalidScope,
+                &[("scope", &format!("{:?}", scope)), ("reason", reason)],
+            ),
+            Violation::SubjectTooLong { length, max } => messages.format(
+                Message::SubjectTooLong,
+                &[("length", &length.to_string()), ("max", &max.to_string())],
+            ),
+            Violation::NoBlankLineAfterSubject => {
+                messages.get(Message::NoBlankLineAfterSubject).to_string()
+            }
+            Violation::MalformedFooter { line, reason } => messages.format(
+                Message::MalformedFooter,
+                &[("line", &format!("{:?}", line)), ("reason", reason)],
+            ),
+            Violation::PseudoTypeNotAllowed { pseudo_type } => messages.format(
+                Message::PseudoTypeNotAllowed,
+                &[("pseudo_type", &format!("{:?}", pseudo_type))],
+            ),
+        }
+    }
+}
+
+/// In English, see [`Violation::localized`]
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(&Messages::default()))
+    }
+}
+
+/// Types and scopes of a repository, see the [module docs](self) for an example
+pub struct SuggestionEngine {
+    repo: Repository,
+    config: Option<Config>,
+    time_budget: Option<Duration>,
+}
+
+impl SuggestionEngine {
+    /// Starts building an engine for the repository containing `repo_path`
+    pub fn builder(repo_path: impl Into<PathBuf>) -> SuggestionEngineBuilder {
+        SuggestionEngineBuilder {
+            repo_path: repo_path.into(),
+            config_path: None,
+            config: None,
+            cache_path: None,
+            cache_read_only: false,
+            include_untracked: false,
+            interactive: false,
+            time_budget: None,
+            lang: None,
+            since_ref: None,
+        }
+    }
+
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Effective config with the builder overrides applied
+    pub fn config(&self) -> &Option<Config> {
+        &self.config
+    }
+
+    /// User-facing strings in the configured language
+    pub fn messages(&self) -> Messages {
+        Messages::from_config(&self.config)
+    }
+
+    /// Known types, `suggest.default_type` (if set) goes first
+    pub fn types(&self) -> Result<Vec<CommitType>> {
+        let mut types = get_commit_types_from_repo_or_default(&self.repo, self.config.clone())?;
+        let default_type = self
+            .config
+            .as_ref()
+            .and_then(|c| c.suggest.default_type.as_deref());
+        if let Some(idx) = types
+            .iter()
+            .position(|t| Some(t.name.as_str()) == default_type)
+        {
+            let default_type = types.remove(idx);
+            types.insert(0, default_type);
+        }
+
+        Ok(types)
+    }
+
+    /// Known scopes, the one matching the staged files (if any) goes first
+    pub fn scopes(&self) -> Result<Vec<CommitScope>> {
+        self.within_budget(|repo, config| {
+            Ok(try_get_commit_scopes_from_repo(repo, config)?.unwrap_or_default())
+        })
+    }
+
+    /// Same as [`scopes`](Self::scopes), but also reports which scope was suggested
+    pub fn suggest(&self) -> Result<Option<ScopeSuggestions>> {
+        self.within_budget(try_get_scope_suggestions)
+    }
+
+    /// The most likely type of the staged changes according to `suggest.type_rules` and the
+    /// built-in rules (i.e. only Markdown files staged is "docs")
+    pub fn suggest_type(&self) -> Result<Option<CommitType>> {
+        try_suggest_commit_type(&self.repo, &self.config)
+    }
+
+    /// `type(scope): ` prefix for the staged files with the alternatives, see [`crate::prefix`]
+    pub fn suggest_prefix(&self) -> Result<Option<PrefixSuggestion>> {
+        suggest_prefix(
+            &self.repo,
+            &self.config,
+            self.suggest()?,
+            self.suggest_type()?,
+        )
+    }
+
+    /// Staged files with the problems found by the `[preflight]` heuristics and the suggested
+    /// prefix, see [`crate::preflight`]
+    pub fn preflight(&self) -> Result<Preflight> {
+        preflight(&self.repo, &self.config, || self.suggest_prefix())
+    }
+
+    /// Checks the commit message against the known types and scopes and the message layout
+    /// (subject length, blank line after the subject, footer syntax). Scopes are only checked if
+    /// there are any, the scope name is always checked against `[scope_names]`. Subjects ignored
+    /// through `general.pseudo_types` are always valid
+    pub fn validate(&self, message: &str) -> Result<Vec<Violation>> {
+        let pseudo_types = pseudo_types_config(&self.config);
+        let subject = message.lines().next().unwrap_or("");
+        let resolved = match resolve_subject(subject, &pseudo_types) {
+            Ok(resolved) => resolved,
+            Err(Error::PseudoTypeNotAllowed { pseudo_type, .. }) => {
+                return Ok(vec![Violation::PseudoTypeNotAllowed { pseudo_type }]);
+            }
+            Err(e) => return Err(e),
+        };
+        if resolved == ResolvedSubject::Ignored {
+            return Ok(Vec::new());
+        }
+
+        let Some(commit) = parse_message(message, &pseudo_types)? else {
+            return Ok(vec![Violation::NotConventional]);
+        };
+
+        let mut res = Vec::new();
+        // Pseudo-types are mapped onto the configured type, no need to check it
+        if matches!(resolved, ResolvedSubject::Regular(_))
+            && !self
+                .types()?
+                .iter()
+                .any(|t| t.name == commit.header.commit_type)
+        {
+            res.push(Violation::UnknownType {
+                commit_type: commit.header.commit_type.clone(),
+            });
+        }
+
+        if let Some(scope) = commit.header.scope {
+            let scope_names = ScopeNameValidator::from_config(&self.config)?;
+            let scopes = self.scopes()?;
+            if let Err(e) = scope_names.parse(&scope) {
+                res.push(Violation::InvalidScope {
+                    scope,
+                    reason: e.to_string(),
+                });
+            } else if let Some(replacement) = get_deprecated_scopes(&self.config).remove(&scope) {
+                res.push(Violation::DeprecatedScope { scope, replacement });
+            } else if !scopes.is_empty() && !scopes.iter().any(|s| s.name == scope) {
+                res.push(Violation::UnknownScope { scope });
+            }
+        }
+
+        let max = self
+            .config
+            .as_ref()
+            .and_then(|c| c.lint.max_subject_length)
+            .unwrap_or(DEFAULT_MAX_SUBJECT_LENGTH);
+        let length = subject.chars().count();
+        if length > max {
+            res.push(Violation::SubjectTooLong { length, max });
+        }
+
+        if message.lines().nth(1).is_some_and(|line| !line.is_empty()) {
+            res.push(Violation::NoBlankLineAfterSubject);
+        }
+
+        res.extend(
+            malformed_footers(message)
+                .into_iter()
+                .map(|(line, reason)| Violation::MalformedFooter {
+                    line,
+                    reason: reason.to_string(),
+                }),
+        );
+
+        Ok(res)
+    }
+
+    /// Runs `f` in background if there's a time budget, so that it can be abandoned
+    fn within_budget<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Repository, Option<Config>) -> Result<T> + Send + 'static,
+    {
+        let Some(budget) = self.time_budget else {
+            return f(&self.repo, self.config.clone());
+        };
+
+        let repo_path = self.repo.path().to_path_buf();
+        let config = self.config.clone();
+        let capturing = warnings::capturing();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            if capturing {
+                warnings::capture();
+            }
+            let res = Repository::open(repo_path)
+                .map_err(Error::from)
+                .and_then(|repo| f(&repo, config));
+            // Receiver is gone if the budget is exceeded, nothing to do about it
+            let _ = tx.send((res, warnings::take()));
+        });
+
+        match rx.recv_timeout(budget) {
+            Ok((res, captured)) => {
+                for captured in captured {
+                    warnings::warning(captured.kind, captured.message);
+                }
+                res
+            }
+            Err(RecvTimeoutError::Timeout) => Err(Error::TimeBudgetExceeded(budget)),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::WorkerFailed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{setup_config_file_in_path, setup_repo_with_commits};
+    use indoc::indoc;
+    use rstest::rstest;
+    use testdir::testdir;
+
+    fn mk_engine(dir: &std::path::Path) -> SuggestionEngine {
+        setup_repo_with_commits(dir, &["init", "feat(foo): bar"]);
+        setup_config_file_in_path(
+            dir,
+            indoc! {r#"
+                [types]
+                feat = "Feature"
+
+                [scope_names]
+                max_length = 5
+
+                [general.scopes]
+                deprecated = { old = "foo" }
+                "#},
+        );
+
+        SuggestionEngine::builder(dir)
+            .cache_path(dir.join("cache.bin"))
+            .build()
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case::valid("feat(foo): baz", vec![])]
+    #[case::no_scope("feat: baz", vec![])]
+    #[case::not_conventional("baz", vec![Violation::NotConventional])]
+    #[case::unknown_type("fix(foo): baz", vec![Violation::UnknownType { commit_type: "fix".to_string() }])]
+    #[case::unknown_scope("feat(qux): baz", vec![Violation::UnknownScope { scope: "qux".to_string() }])]
+    #[case::deprecated_scope("feat(old): baz", vec![Violation::DeprecatedScope { scope: "old".to_string(), replacement: "foo".to_string() }])]
+    #[case::invalid_scope("feat(quuxes): baz", vec![Violation::InvalidScope { scope: "quuxes".to_string(), reason: "is longer than 5 characters".to_string() }])]
+    #[case::ignored("Merge branch 'foo'", vec![])]
+    #[case::too_long(&format!("feat: {}", "a".repeat(70)), vec![Violation::SubjectTooLong { length: 76, max: 72 }])]
+    #[case::no_blank_line("feat: baz\nbody", vec![Violation::NoBlankLineAfterSubject])]
+    #[case::footer("feat: baz\n\nbreaking change: qux", vec![Violation::MalformedFooter { line: "breaking change: qux".to_string(), reason: "BREAKING CHANGE must be uppercase".to_string() }])]
+    fn validate(#[case] message: &str, #[case] expected: Vec<Violation>) {
+        let engine = mk_engine(&testdir!());
+
+        assert_eq!(engine.validate(message).unwrap(), expected);
+    }
+
+    #[test]
+    fn validate_pseudo_type_not_allowed() {
+        let dir = testdir!();
+        setup_repo_with_commits(&dir, &["init"]);
+        let config = Config::from_str(indoc! {r#"
+            [general.pseudo_types]
+            merge = "error"
+            "#})
+        .unwrap();
+        let engine = SuggestionEngine::builder(&dir)
+            .config(config)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.validate("Merge branch 'foo'").unwrap(),
+            vec![Violation::PseudoTypeNotAllowed {
+                pseudo_type: PseudoType::Merge
+            }]
+        );
+        assert_eq!(
+            engine.validate("Merge branch 'foo'").unwrap()[0].to_string(),
+            "Merge commits are not allowed"
+        );
+    }
+
+    /// Work done in background within the budget is the same as without the budget
+    #[test]
+    fn time_budget() {
+        let dir = testdir!();
+        let engine = mk_engine(&dir);
+        let budgeted = SuggestionEngine::builder(&dir)
+            .cache_path(dir.join("cache.bin"))
+            .time_budget(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        assert_eq!(budgeted.scopes().unwrap(), engine.scopes().unwrap());
+    }
+
+    #[test]
+    fn time_budget_exceeded() {
+        let dir = testdir!();
+        mk_engine(&dir);
+        let engine = SuggestionEngine::builder(&dir)
+            .cache_path(dir.join("cache.bin"))
+            .time_budget(Duration::from_nanos(1))
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            engine.scopes(),
+            Err(Error::TimeBudgetExceeded(budget)) if budget == Duration::from_nanos(1)
+        ));
+    }
+
+    /// Warnings of the worker thread are captured on the calling one
+    #[test]
+    fn time_budget_warnings() {
+        let dir = testdir!();
+        mk_engine(&dir);
+        let engine = SuggestionEngine::builder(&dir)
+            .cache_path(dir.join("cache.bin"))
+            .time_budget(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        warnings::capture();
+        engine.scopes().unwrap();
+
+        assert_eq!(
+            warnings::take()
+                .into_iter()
+                .map(|w| w.kind)
+                .collect::<Vec<_>>(),
+            [warnings::WarningKind::HistorySlow]
+        );
+    }
+
+    #[test]
+    fn non_interactive() {
+        let dir = testdir!();
+        setup_repo_with_commits(&dir, &["init"]);
+        let config = Config::from_str(indoc! {r#"
+            [cache]
+            regenerate_on_stale = "prompt"
+            "#})
+        .unwrap();
+
+        let engine = SuggestionEngine::builder(&dir)
+            .config(config.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            engine.config().as_ref().unwrap().cache.regenerate_on_stale,
+            RegenerateOnStale::Never
+        );
+
+        let engine = SuggestionEngine::builder(&dir)
+            .config(config)
+            .interactive(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            engine.config().as_ref().unwrap().cache.regenerate_on_stale,
+            RegenerateOnStale::Prompt
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Named things with a description that the user picks from: commit types and commit scopes.
+//!
+//! Both share the representation, only the kind differs, so that a type can't be passed where a
+//! scope is expected.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::sanitize::sanitize;
+
+/// What the entity is, i.e. [`Type`] or [`Scope`]
+pub trait EntityKind {
+    /// Human-readable name of the kind
+    const NAME: &'static str;
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Type {}
+
+impl EntityKind for Type {
+    const NAME: &'static str = "type";
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Scope {}
+
+impl EntityKind for Scope {
+    const NAME: &'static str = "scope";
+}
+
+/// Where an entity comes from, so that frontends can style or filter the entities by origin and
+/// users can tell why an unexpected one is listed
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// Listed in the config
+    Config,
+    /// Mined from the history on this run
+    History,
+    /// Mined from the history earlier, read from the cache
+    Cache,
+    /// Derived from the layout of the repository
+    InferredPath,
+    /// Built into the program
+    Default,
+}
+
+/// Serialized as `{"name": ..., "description": ...}` regardless of the kind. Ordered by name, then
+/// by description
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Hash, Ord, PartialOrd)]
+pub struct Entity<K> {
+    pub name: String,
+    pub description: String,
+    #[serde(skip)]
+    kind: PhantomData<K>,
+}
+
+impl<K> Entity<K> {
+    /// Entity without a description
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_description(name, "")
+    }
+
+    pub fn with_description(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            kind: PhantomData,
+        }
+    }
+}
+
+/// `name: description`, sanitized as both may come from commit messages
+impl<K> fmt::Display for Entity<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            sanitize(&self.name),
+            sanitize(&self.description)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let entity: Entity<Scope> = Entity::with_description("foo\u{1B}[31m", "bar");
+
+        assert_eq!(entity.to_string(), "foo: bar");
+    }
+
+    /// Same representation as before the kinds were introduced, existing caches stay readable
+    #[test]
+    fn serde() {
+        let entity: Entity<Type> = Entity::with_description("feat", "A new feature");
+
+        assert_eq!(
+            serde_json::to_string(&entity).unwrap(),
+            r#"{"name":"feat","description":"A new feature"}"#
+        );
+    }
+
+    #[test]
+    fn ordering() {
+        let mut entities: Vec<Entity<Scope>> = vec![
+            Entity::with_description("b", "a"),
+            Entity::with_description("a", "b"),
+            Entity::with_description("a", "a"),
+        ];
+        entities.sort();
+
+        assert_eq!(
+            entities,
+            vec![
+                Entity::with_description("a", "a"),
+                Entity::with_description("a", "b"),
+                Entity::with_description("b", "a"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Errors of the library. The binary reports them through `anyhow` and maps them onto exit codes.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::commit_message::PseudoType;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to read the cache at {path:?}. It may have been created by an older version, recreate it with `cache nuke` and `cache create`")]
+    CacheCorrupt {
+        path: PathBuf,
+        #[source]
+        source: bincode::Error,
+    },
+
+    #[error("Cache does not exist")]
+    CacheNotFound,
+
+    #[error("Failed to read the cache at {path:?}")]
+    CacheRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Cache is read-only (--cache-read-only or cache.read_only), refusing to modify it")]
+    CacheReadOnly,
+
+    #[error("Unable to determine the cache directory (is there a home directory?). Use --cache-path to point the cache at a writable location")]
+    CacheLocationUnknown,
+
+    #[error("cache.location = \"repo\" needs a repository to put the cache in. Use --cache-path outside of one")]
+    CacheLocationNoRepo,
+
+    #[error("The repository is too large to mine its history on every run (general.scopes.strategy). Create the cache with `cache create`")]
+    CacheRequired,
+
+    #[error("Failed to acquire cache file lock")]
+    CacheLock(#[source] io::Error),
+
+    #[error("Another process holds the lock of the cache at {path:?}, gave up after {timeout_ms} ms. Raise cache.lock_timeout_ms if its updates take longer")]
+    CacheLocked { path: PathBuf, timeout_ms: u64 },
+
+    /// Covers creating the cache directory, writing and removing the file and setting its
+    /// permissions
+    #[error("Failed to write the cache to {path:?}. Use --cache-path to point the cache at a writable location")]
+    CacheWrite {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to serialize the cache")]
+    CacheSerialize(#[source] bincode::Error),
+
+    #[error("No scopes detected in the repo")]
+    NoScopes,
+
+    #[error("Config file {path:?} not found")]
+    ConfigNotFound { path: PathBuf },
+
+    #[error("Failed to read the config at {path:?}")]
+    ConfigRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to write the config to {path:?}")]
+    ConfigWrite {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid config{}: {message}", config_location(.path.as_deref(), *.line))]
+    ConfigInvalid {
+        /// Not known when the config is parsed from a string
+        path: Option<PathBuf>,
+        /// 1-based
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[error("Failed to discover a repository")]
+    RepoNotFound(#[source] git2::Error),
+
+    /// Bare repositories, repositories without commits
+    #[error("{0}")]
+    RepoUnsupported(String),
+
+    #[error("{pseudo_type:?} commits are not allowed: {subject:?}")]
+    PseudoTypeNotAllowed {
+        pseudo_type: PseudoType,
+        subject: String,
+    },
+
+    #[error("Failed to get the changed files of commit {commit}")]
+    ChangedFiles {
+        commit: String,
+        #[source]
+        source: git2::Error,
+    },
+
+    #[error("Failed to parse the revision {rev:?}")]
+    InvalidRevision {
+        rev: String,
+        #[source]
+        source: git2::Error,
+    },
+
+    /// Files besides the cache and the config: bundles, changelog templates, lists of labels,
+    /// commit message files of the hooks
+    #[error("Failed to read {path:?}")]
+    FileRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to write {path:?}")]
+    FileWrite {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file was read, but its content is not what was expected (i.e. not a bundle)
+    #[error("{path:?}: {message}")]
+    InvalidFile { path: PathBuf, message: String },
+
+    #[error("{path:?} exists and was not installed by this program, use --force to replace it")]
+    HookExists { path: PathBuf },
+
+    /// `--template` and `hooks.comment_block.item`
+    #[error("{0}")]
+    InvalidTemplate(String),
+
+    /// External commands: `suggest.rerank_command`, curl
+    #[error("Failed to run {command:?}")]
+    Command {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{command:?} {message}")]
+    CommandFailed { command: String, message: String },
+
+    #[error("Failed to serialize the output")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Failed to serialize the output to TOML")]
+    SerializeToml(#[from] toml::ser::Error),
+
+    /// The work goes on in background, see [`crate::engine::SuggestionEngineBuilder::time_budget`]
+    #[error("Time budget of {0:?} exceeded")]
+    TimeBudgetExceeded(Duration),
+
+    /// Background worker of the time budget exited without a result
+    #[error("Suggestion worker failed")]
+    WorkerFailed,
+
+    /// The user could not be asked, i.e. whether to regenerate a stale cache
+    #[error("Failed to prompt the user")]
+    Prompt(#[from] dialoguer::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+fn config_location(path: Option<&Path>, line: Option<usize>) -> String {
+    let path = path.map(|p| format!(" {:?}", p)).unwrap_or_default();
+    let line = line.map(|l| format!(" at line {}", l)).unwrap_or_default();
+    path + &line
+}
+
+impl Error {
+    /// Exit code of the binary. Codes follow sysexits.h where one fits
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            // EX_DATAERR
+            Error::CacheCorrupt { .. }
+            | Error::PseudoTypeNotAllowed { .. }
+            | Error::InvalidRevision { .. }
+            | Error::InvalidFile { .. }
+            | Error::InvalidTemplate(_) => 65,
+            // EX_NOINPUT
+            Error::RepoNotFound(_)
+            | Error::RepoUnsupported(_)
+            | Error::CacheNotFound
+            | Error::CacheRequired
+            | Error::FileRead { .. } => 66,
+            // EX_CANTCREAT
+            Error::CacheReadOnly
+            | Error::CacheLocationUnknown
+            | Error::CacheLocationNoRepo
+            | Error::CacheLock(_)
+            | Error::CacheWrite { .. }
+            | Error::ConfigWrite { .. }
+            | Error::FileWrite { .. }
+            | Error::HookExists { .. } => 73,
+            // EX_IOERR
+            Error::CacheRead { .. } => 74,
+            // EX_TEMPFAIL
+            Error::CacheLocked { .. } | Error::TimeBudgetExceeded(_) => 75,
+            // EX_CONFIG
+            Error::ConfigNotFound { .. }
+            | Error::ConfigRead { .. }
+            | Error::ConfigInvalid { .. } => 78,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, None, "Invalid config: oops")]
+    #[case(Some("foo.toml"), None, "Invalid config \"foo.toml\": oops")]
+    #[case(
+        Some("foo.toml"),
+        Some(3),
+        "Invalid config \"foo.toml\" at line 3: oops"
+    )]
+    fn config_invalid(
+        #[case] path: Option<&str>,
+        #[case] line: Option<usize>,
+        #[case] expected: &str,
+    ) {
+        let err = Error::ConfigInvalid {
+            path: path.map(PathBuf::from),
+            line,
+            message: "oops".to_string(),
+        };
+
+        assert_eq!(err.to_string(), expected);
+    }
+}
@@ -0,0 +1,163 @@
+//! Types and scopes in the config formats of other tools (`export commitlint`, `export cocogitto`).
+//!
+//! The exported files are generated, the config of this program stays the single source of truth
+//! while the existing linting in CI keeps working. Scopes are the ones `lint` accepts: the
+//! configured ones and the ones from the history. No scopes means any scope is allowed.
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::commit_scopes::CommitScope;
+use crate::commit_types::CommitType;
+use crate::config::{Config, DEFAULT_MAX_SUBJECT_LENGTH};
+use crate::error::Result;
+
+/// Types cocogitto knows without configuration
+const COCOGITTO_TYPES: &[&str] = &[
+    "feat", "fix", "build", "chore", "ci", "docs", "style", "refactor", "perf", "test", "revert",
+];
+
+fn scope_names(scopes: &[CommitScope]) -> Vec<&str> {
+    scopes
+        .iter()
+        .map(|scope| scope.name.as_str())
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// `.commitlintrc.json` with the rules `lint` checks: known types and scopes, non-empty subject
+/// and the header length
+pub fn commitlint_config(
+    types: &[CommitType],
+    scopes: &[CommitScope],
+    config: &Option<Config>,
+) -> Value {
+    let max_length = config
+        .as_ref()
+        .and_then(|c| c.lint.max_subject_length)
+        .unwrap_or(DEFAULT_MAX_SUBJECT_LENGTH);
+
+    let mut rules = serde_json::Map::new();
+    rules.insert("type-empty".into(), json!([2, "never"]));
+    rules.insert(
+        "type-enum".into(),
+        json!([2, "always", types.iter().map(|t| &t.name).collect_vec()]),
+    );
+    if !scopes.is_empty() {
+        rules.insert(
+            "scope-enum".into(),
+            json!([2, "always", scope_names(scopes)]),
+        );
+    }
+    rules.insert("subject-empty".into(), json!([2, "never"]));
+    rules.insert("header-max-length".into(), json!([2, "always", max_length]));
+
+    json!({ "rules": rules })
+}
+
+#[derive(Serialize)]
+struct CogCommitType {
+    changelog_title: String,
+}
+
+#[derive(Serialize)]
+struct CogConfig<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag_prefix: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scopes: Vec<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    commit_types: BTreeMap<&'a str, CogCommitType>,
+}
+
+/// `cog.toml` with the scopes, the release tag prefix and the types cocogitto does not know. The
+/// changelog title of a type is its description (or the name, if there's none)
+pub fn cocogitto_config(
+    types: &[CommitType],
+    scopes: &[CommitScope],
+    config: &Option<Config>,
+) -> Result<String> {
+    let cog = CogConfig {
+        tag_prefix: config
+            .as_ref()
+            .and_then(|c| c.versioning.tag_prefix.as_deref()),
+        scopes: scope_names(scopes),
+        commit_types: types
+            .iter()
+            .filter(|t| !COCOGITTO_TYPES.contains(&t.name.as_str()))
+            .map(|t| {
+                let title = match t.description.is_empty() {
+                    true => t.name.clone(),
+                    false => t.description.clone(),
+                };
+                (
+                    t.name.as_str(),
+                    CogCommitType {
+                        changelog_title: title,
+                    },
+                )
+            })
+            .collect(),
+    };
+
+    Ok(toml::to_string(&cog)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn mk_types() -> Vec<CommitType> {
+        vec![
+            CommitType::with_description("feat", "A new feature"),
+            CommitType::with_description("hotfix", "Hotfixes"),
+            CommitType::new("wip"),
+        ]
+    }
+
+    fn mk_scopes() -> Vec<CommitScope> {
+        vec![
+            CommitScope::with_description("cli", "Command line"),
+            CommitScope::new("cache"),
+            CommitScope::new("cli"),
+        ]
+    }
+
+    #[test]
+    fn commitlint() {
+        let config = Config::from_str("[lint]\nmax_subject_length = 50\n").unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&commitlint_config(&mk_types(), &mk_scopes(), &Some(config)))
+                .unwrap(),
+            r#"{"rules":{"type-empty":[2,"never"],"type-enum":[2,"always",["feat","hotfix","wip"]],"scope-enum":[2,"always",["cache","cli"]],"subject-empty":[2,"never"],"header-max-length":[2,"always",50]}}"#
+        );
+        // Any scope goes
+        assert!(commitlint_config(&mk_types(), &[], &None)["rules"]
+            .get("scope-enum")
+            .is_none());
+    }
+
+    #[test]
+    fn cocogitto() {
+        let config = Config::from_str("[versioning]\ntag_prefix = \"v\"\n").unwrap();
+
+        assert_eq!(
+            cocogitto_config(&mk_types(), &mk_scopes(), &Some(config)).unwrap(),
+            indoc! {r#"
+                tag_prefix = "v"
+                scopes = ["cache", "cli"]
+
+                [commit_types.hotfix]
+                changelog_title = "Hotfixes"
+
+                [commit_types.wip]
+                changelog_title = "wip"
+            "#}
+        );
+        assert_eq!(cocogitto_config(&mk_types()[..1], &[], &None).unwrap(), "");
+    }
+}
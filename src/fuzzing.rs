@@ -0,0 +1,45 @@
+//! Entry points for the fuzz targets under `fuzz/`, built only with the `fuzzing` feature.
+//!
+//! Commit messages and config files come from repositories and users, so the parsers should
+//! handle any input without panicking. To run a target:
+//!
+//! ```shell
+//! cargo +nightly fuzz run scope_parser
+//! ```
+use crate::commit_message::parse_message as parse_full_message;
+use crate::commit_scopes::commit::get_scope_from_summary;
+use crate::config::{Config, PseudoTypeAction, PseudoTypesConfig};
+
+/// First byte picks the pseudo-type handling so that all of its branches are reached
+fn split_pseudo_types(data: &[u8]) -> Option<(PseudoTypesConfig, &str)> {
+    let (selector, message) = data.split_first()?;
+    let action = |bits: u8| match bits % 3 {
+        0 => PseudoTypeAction::Ignore,
+        1 => PseudoTypeAction::Error,
+        _ => PseudoTypeAction::Type("revert".to_string()),
+    };
+    let pseudo_types = PseudoTypesConfig {
+        revert: action(*selector),
+        merge: action(*selector / 3),
+    };
+
+    Some((pseudo_types, std::str::from_utf8(message).ok()?))
+}
+
+pub fn parse_scope(data: &[u8]) {
+    if let Some((pseudo_types, message)) = split_pseudo_types(data) {
+        let _ = get_scope_from_summary(message, &pseudo_types);
+    }
+}
+
+pub fn parse_message(data: &[u8]) {
+    if let Some((pseudo_types, message)) = split_pseudo_types(data) {
+        let _ = parse_full_message(message, &pseudo_types);
+    }
+}
+
+pub fn parse_config(data: &[u8]) {
+    if let Ok(toml_str) = std::str::from_utf8(data) {
+        let _ = Config::from_str(toml_str);
+    }
+}
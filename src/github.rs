@@ -0,0 +1,181 @@
+//! Import of the scopes from the GitHub issue labels.
+//!
+//! Teams often categorize issues with `area/*` labels already, those map onto the scopes nicely.
+//! The labels are fetched through the GitHub API with `curl` (behind the `github` feature) or read
+//! from a file produced by i.e. `gh label list --json name,description`.
+
+use log::warn;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::commit_scopes::CommitScope;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::scope_name::ScopeNameValidator;
+
+/// Environment variables the API token is taken from, in order
+pub const TOKEN_VARS: &[&str] = &["GITHUB_TOKEN", "GH_TOKEN"];
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Reads the labels from a JSON array of `{name, description}` objects
+pub fn read_labels(path: &Path) -> Result<Vec<Label>> {
+    let content = std::fs::read_to_string(path).map_err(|source| Error::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    serde_json::from_str(&content).map_err(|_| Error::InvalidFile {
+        path: path.to_path_buf(),
+        message: "not a list of labels".to_string(),
+    })
+}
+
+/// Fetches all labels of `owner/name` repository. The token is optional for public repositories
+#[cfg(feature = "github")]
+pub fn fetch_labels(repo: &str, token: Option<&str>) -> Result<Vec<Label>> {
+    use log::debug;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const PER_PAGE: usize = 100;
+    let curl_failed = |source| Error::Command {
+        command: "curl".to_string(),
+        source,
+    };
+
+    let mut res = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "https://api.github.com/repos/{}/labels?per_page={}&page={}",
+            repo, PER_PAGE, page
+        );
+        debug!("Fetching {}", url);
+
+        // Headers are passed on stdin so that the token does not show up in the process list
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--header", "@-", &url])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(curl_failed)?;
+        {
+            let mut stdin = child.stdin.take().expect("stdin should be piped");
+            writeln!(stdin, "Accept: application/vnd.github+json").map_err(curl_failed)?;
+            if let Some(token) = token {
+                writeln!(stdin, "Authorization: Bearer {}", token).map_err(curl_failed)?;
+            }
+        }
+        let output = child.wait_with_output().map_err(curl_failed)?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                command: "curl".to_string(),
+                message: format!("failed to fetch the labels of {:?}", repo),
+            });
+        }
+
+        let labels: Vec<Label> =
+            serde_json::from_slice(&output.stdout).map_err(|_| Error::CommandFailed {
+                command: "curl".to_string(),
+                message: "got an unexpected response from the GitHub API".to_string(),
+            })?;
+        let last_page = labels.len() < PER_PAGE;
+        res.extend(labels);
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(res)
+}
+
+/// Token from the environment, if any
+pub fn token_from_env() -> Option<String> {
+    TOKEN_VARS
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|t| !t.is_empty()))
+}
+
+/// Converts the labels starting with the prefix into scopes named after the rest of the label,
+/// sorted by name. Names breaking the `[scope_names]` rules are skipped.
+pub fn labels_to_scopes(
+    labels: &[Label],
+    prefix: &str,
+    config: &Option<Config>,
+) -> Result<Vec<CommitScope>> {
+    let scope_names = ScopeNameValidator::from_config(config)?;
+
+    Ok(labels
+        .iter()
+        .filter_map(|label| {
+            let name = label.name.strip_prefix(prefix)?.trim();
+            match scope_names.parse(name) {
+                Ok(name) => Some((
+                    name.into_string(),
+                    label.description.clone().unwrap_or_default(),
+                )),
+                Err(e) => {
+                    warn!("Skipping label {:?}: scope name {}", label.name, e);
+                    None
+                }
+            }
+        })
+        // Labels differing only in whitespace around the name
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(name, description)| CommitScope::with_description(name, description))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testdir::testdir;
+
+    fn mk_label(name: &str, description: Option<&str>) -> Label {
+        Label {
+            name: name.to_string(),
+            description: description.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn converts_labels() {
+        let labels = vec![
+            mk_label("area/cli", Some("Command line interface")),
+            mk_label("bug", Some("Something is broken")),
+            mk_label("area/cache", None),
+            mk_label("area/", None),
+        ];
+
+        assert_eq!(
+            labels_to_scopes(&labels, "area/", &None).unwrap(),
+            vec![
+                CommitScope::new("cache"),
+                CommitScope::with_description("cli", "Command line interface"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_gh_output() {
+        let dir = testdir!();
+        let path = dir.join("labels.json");
+        std::fs::write(
+            &path,
+            r#"[{"name":"area/cli","description":"CLI"},{"name":"bug","description":""}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_labels(&path).unwrap(),
+            vec![mk_label("area/cli", Some("CLI")), mk_label("bug", Some(""))]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Scopes and the directories they touch as a bipartite graph (`export graph`), to see the area
+//! boundaries mined from the history.
+//!
+//! Every file of a scope is counted in its top directory: the first `depth` components of its
+//! parent directory, "." for the files in the root. Directories touched by several scopes are
+//! marked as shared, many of them mean overlapping or ill-defined scopes.
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::commit_scopes::commit::ChangedFiles;
+use crate::commit_scopes::CommitScope;
+
+/// Scope -> directory edge
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Edge {
+    pub scope: String,
+    /// With a trailing slash, "." for the root
+    pub directory: String,
+    /// Files of the scope in the directory
+    pub files: usize,
+    /// Other scopes touch the directory too
+    pub shared: bool,
+}
+
+/// First `depth` components of the parent directory of the file
+fn top_dir(file: &str, depth: usize) -> String {
+    let components = Path::new(file)
+        .parent()
+        .map(|dir| {
+            dir.components()
+                .take(depth)
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect_vec()
+        })
+        .unwrap_or_default();
+
+    match components.is_empty() {
+        true => ".".to_string(),
+        false => format!("{}/", components.join("/")),
+    }
+}
+
+/// Edges sorted by scope, then by the number of files. Only the `limit` directories with the most
+/// files are kept per scope
+pub fn scope_graph(
+    scope_map: &HashMap<CommitScope, ChangedFiles>,
+    depth: usize,
+    limit: Option<usize>,
+) -> Vec<Edge> {
+    let edges = scope_map
+        .iter()
+        .sorted_by(|(a, _), (b, _)| a.name.cmp(&b.name))
+        .flat_map(|(scope, files)| {
+            files
+                .iter()
+                .map(|file| top_dir(file, depth))
+                .counts()
+                .into_iter()
+                .sorted_by(|(a_dir, a), (b_dir, b)| b.cmp(a).then(a_dir.cmp(b_dir)))
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|(directory, files)| (scope.name.clone(), directory, files))
+        })
+        .collect_vec();
+
+    let scopes_per_dir: HashMap<&str, HashSet<&str>> = edges
+        .iter()
+        .map(|(scope, dir, _)| (dir.as_str(), scope.as_str()))
+        .into_group_map()
+        .into_iter()
+        .map(|(dir, scopes)| (dir, scopes.into_iter().collect()))
+        .collect();
+
+    edges
+        .iter()
+        .map(|(scope, directory, files)| Edge {
+            shared: scopes_per_dir[directory.as_str()].len() > 1,
+            scope: scope.clone(),
+            directory: directory.clone(),
+            files: *files,
+        })
+        .collect()
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Graphviz graph, i.e. `export graph --format dot | dot -Tsvg > scopes.svg`
+pub fn to_dot(edges: &[Edge]) -> String {
+    let mut lines = vec![
+        "graph scopes {".to_string(),
+        "  rankdir=LR;".to_string(),
+        "  node [shape=box];".to_string(),
+    ];
+    for scope in edges.iter().map(|e| &e.scope).unique() {
+        lines.push(format!(
+            "  \"scope:{0}\" [label=\"{0}\", shape=ellipse];",
+            dot_escape(scope)
+        ));
+    }
+    for edge in edges.iter().unique_by(|e| &e.directory) {
+        let style = match edge.shared {
+            true => ", color=red",
+            false => "",
+        };
+        lines.push(format!(
+            "  \"dir:{0}\" [label=\"{0}\"{1}];",
+            dot_escape(&edge.directory),
+            style
+        ));
+    }
+    for edge in edges {
+        lines.push(format!(
+            "  \"scope:{}\" -- \"dir:{}\" [label=\"{}\"];",
+            dot_escape(&edge.scope),
+            dot_escape(&edge.directory),
+            edge.files
+        ));
+    }
+    lines.push("}".to_string());
+
+    lines.join("\n") + "\n"
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+/// Mermaid flowchart, renders in the Markdown of GitHub and GitLab
+pub fn to_mermaid(edges: &[Edge]) -> String {
+    let scopes = edges
+        .iter()
+        .map(|e| e.scope.as_str())
+        .unique()
+        .collect_vec();
+    let dirs = edges
+        .iter()
+        .unique_by(|e| &e.directory)
+        .map(|e| (e.directory.as_str(), e.shared))
+        .collect_vec();
+    let scope_id = |scope: &str| scopes.iter().position(|s| *s == scope).unwrap_or_default();
+    let dir_id = |dir: &str| dirs.iter().position(|(d, _)| *d == dir).unwrap_or_default();
+
+    let mut lines = vec!["graph LR".to_string()];
+    for (idx, scope) in scopes.iter().enumerate() {
+        lines.push(format!("  s{}([\"{}\"])", idx, mermaid_escape(scope)));
+    }
+    for (idx, (dir, _)) in dirs.iter().enumerate() {
+        lines.push(format!("  d{}[\"{}\"]", idx, mermaid_escape(dir)));
+    }
+    for edge in edges {
+        lines.push(format!(
+            "  s{} ---|{}| d{}",
+            scope_id(&edge.scope),
+            edge.files,
+            dir_id(&edge.directory)
+        ));
+    }
+    let shared = dirs
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, shared))| *shared)
+        .map(|(idx, _)| format!("d{}", idx))
+        .collect_vec();
+    if !shared.is_empty() {
+        lines.push("  classDef shared stroke:#d33,stroke-width:2px".to_string());
+        lines.push(format!("  class {} shared", shared.join(",")));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn mk_scope_map() -> HashMap<CommitScope, ChangedFiles> {
+        HashMap::from([
+            (
+                CommitScope::new("cli"),
+                HashSet::from([
+                    "src/cli/args.rs".to_string(),
+                    "src/cli/main.rs".to_string(),
+                    "src/lib.rs".to_string(),
+                    "README.md".to_string(),
+                ]),
+            ),
+            (
+                CommitScope::new("cache"),
+                HashSet::from(["src/cache/mod.rs".to_string(), "src/lib.rs".to_string()]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn edges() {
+        let edges = scope_graph(&mk_scope_map(), 1, Some(1));
+
+        assert_eq!(
+            edges
+                .iter()
+                .map(|e| (e.scope.as_str(), e.directory.as_str(), e.files, e.shared))
+                .collect_vec(),
+            vec![("cache", "src/", 2, true), ("cli", "src/", 3, true)]
+        );
+
+        let edges = scope_graph(&mk_scope_map(), 2, None);
+        assert_eq!(
+            edges
+                .iter()
+                .filter(|e| e.scope == "cli")
+                .map(|e| (e.directory.as_str(), e.files, e.shared))
+                .collect_vec(),
+            vec![("src/cli/", 2, false), (".", 1, false), ("src/", 1, true)]
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let edges = scope_graph(&mk_scope_map(), 1, None);
+
+        assert_eq!(
+            to_dot(&edges),
+            indoc! {r#"
+                graph scopes {
+                  rankdir=LR;
+                  node [shape=box];
+                  "scope:cache" [label="cache", shape=ellipse];
+                  "scope:cli" [label="cli", shape=ellipse];
+                  "dir:src/" [label="src/", color=red];
+                  "dir:." [label="."];
+                  "scope:cache" -- "dir:src/" [label="2"];
+                  "scope:cli" -- "dir:src/" [label="3"];
+                  "scope:cli" -- "dir:." [label="1"];
+                }
+            "#}
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let edges = scope_graph(&mk_scope_map(), 1, None);
+
+        assert_eq!(
+            to_mermaid(&edges),
+            indoc! {r#"
+                graph LR
+                  s0(["cache"])
+                  s1(["cli"])
+                  d0["src/"]
+                  d1["."]
+                  s0 ---|2| d0
+                  s1 ---|3| d0
+                  s1 ---|1| d1
+                  classDef shared stroke:#d33,stroke-width:2px
+                  class d0 shared
+            "#}
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Helpers to walk the commit history outside of the scope mining logic
+
+use git2::{DescribeFormatOptions, DescribeOptions, Repository, Revwalk};
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::utils::head_commit;
+
+/// Sets up a revwalk over a range.
+///
+/// Range can be specified as "from..to" or as a single revision (meaning "everything reachable
+/// from it"). No range means "everything reachable from HEAD".
+pub fn revwalk_for_range<'repo>(
+    repo: &'repo Repository,
+    range: Option<&str>,
+) -> Result<Revwalk<'repo>> {
+    let mut revwalk = repo.revwalk()?;
+
+    match range {
+        Some(range) if range.contains("..") => {
+            debug!("Walking the range {:?}", range);
+            revwalk
+                .push_range(range)
+                .map_err(|source| Error::InvalidRevision {
+                    rev: range.to_string(),
+                    source,
+                })?;
+        }
+        Some(rev) => {
+            debug!("Walking from revision {:?}", rev);
+            let oid = repo
+                .revparse_single(rev)
+                .map_err(|source| Error::InvalidRevision {
+                    rev: rev.to_string(),
+                    source,
+                })?
+                .peel_to_commit()?
+                .id();
+            revwalk.push(oid)?;
+        }
+        // Nothing to walk if there are no commits yet
+        None => {
+            if let Some(head) = head_commit(repo)? {
+                revwalk.push(head)?;
+            }
+        }
+    };
+
+    Ok(revwalk)
+}
+
+/// Finds the most recent tag reachable from HEAD, optionally only considering tags matching a
+/// glob pattern.
+///
+/// Returns None if there are no such tags.
+pub fn latest_tag(repo: &Repository, pattern: Option<&str>) -> Result<Option<String>> {
+    latest_tag_from(repo, "HEAD", pattern)
+}
+
+/// Same as [`latest_tag`], but the tag should be reachable from the given revision
+pub fn latest_tag_from(
+    repo: &Repository,
+    rev: &str,
+    pattern: Option<&str>,
+) -> Result<Option<String>> {
+    let mut options = DescribeOptions::new();
+    options.describe_tags();
+    if let Some(pattern) = pattern {
+        options.pattern(pattern);
+    }
+
+    let object = match repo.revparse_single(rev) {
+        Ok(object) => object.peel_to_commit()?.into_object(),
+        Err(e) => {
+            debug!("Revision {:?} not found: {}", rev, e);
+            return Ok(None);
+        }
+    };
+
+    let res = match object.describe(&options) {
+        // Abbreviated size 0 => only the tag name, no "-N-g<hash>" suffix
+        Ok(describe) => Ok(Some(
+            describe.format(Some(DescribeFormatOptions::new().abbreviated_size(0)))?,
+        )),
+        Err(e) => {
+            debug!("No tag found: {}", e);
+            Ok(None)
+        }
+    };
+
+    res
+}
+
+/// Splits "from..to" into its parts. A single revision is treated as "to".
+pub fn split_range(range: &str) -> (Option<&str>, &str) {
+    match range.split_once("..") {
+        Some((from, to)) => (
+            (!from.is_empty()).then_some(from),
+            if to.is_empty() { "HEAD" } else { to },
+        ),
+        None => (None, range),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use rstest::rstest;
+    use testdir::testdir;
+
+    #[rstest]
+    #[case::full("v1..v2", (Some("v1"), "v2"))]
+    #[case::open_end("v1..", (Some("v1"), "HEAD"))]
+    #[case::single("v2", (None, "v2"))]
+    fn splits_ranges(#[case] range: &str, #[case] expected: (Option<&str>, &str)) {
+        assert_eq!(split_range(range), expected);
+    }
+
+    #[test]
+    fn finds_latest_tag() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat: foo", "fix: bar"]);
+        assert_eq!(latest_tag(&repo, None).unwrap(), None);
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.tag_lightweight("v0.1.0", head.parent(0).unwrap().as_object(), false)
+            .unwrap();
+        repo.tag_lightweight("foo-v0.2.0", head.as_object(), false)
+            .unwrap();
+
+        assert_eq!(
+            latest_tag(&repo, None).unwrap(),
+            Some("foo-v0.2.0".to_string())
+        );
+        assert_eq!(
+            latest_tag(&repo, Some("v*")).unwrap(),
+            Some("v0.1.0".to_string())
+        );
+    }
+}
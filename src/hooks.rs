@@ -0,0 +1,420 @@
+//! Git hooks calling back into the program (`hook install` / `hook uninstall`).
+//!
+//! `prepare-commit-msg` pre-populates the message of a plain `git commit` with `type(scope): `,
+//! the scope being the one suggested for the staged files. `commit-msg` runs `lint` on the
+//! message. Installed hooks are marked, so that hooks written by someone else are never touched.
+//!
+//! `prepare-commit-msg` also appends a comment block with the top types and scopes (see
+//! `[hooks.comment_block]`), like the status comments of git, for the editors without completion.
+use git2::Repository;
+use itertools::Itertools;
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::commit_scopes::CommitScope;
+use crate::commit_types::CommitType;
+use crate::config::{
+    CommentBlockConfig, Config, DEFAULT_COMMENT_BLOCK_ITEM, DEFAULT_COMMENT_BLOCK_ITEMS,
+};
+use crate::error::{Error, Result};
+use crate::output::Template;
+use crate::prefix::{format_prefix, TypeHistory};
+
+/// Marks the hooks written by [`install_hooks`]
+const MARKER: &str = "# Installed by conventional-commit-helper";
+
+/// Everything below this line is removed by `git commit --verbose`
+const SCISSORS: &str = "------------------------ >8 ------------------------";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Hook {
+    PrepareCommitMsg,
+    CommitMsg,
+}
+
+impl Hook {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Hook::PrepareCommitMsg => "prepare-commit-msg",
+            Hook::CommitMsg => "commit-msg",
+        }
+    }
+
+    /// Shell script calling `program`. If the program is not installed, the commit goes through
+    fn script(&self, program: &str) -> String {
+        let command = match self {
+            Hook::PrepareCommitMsg => "hook prepare-commit-msg \"$@\"",
+            Hook::CommitMsg => "lint \"$1\"",
+        };
+
+        format!(
+            "#!/bin/sh\n\
+             {MARKER}, remove with `{program} hook uninstall`\n\
+             command -v {program} >/dev/null 2>&1 || exit 0\n\
+             exec {program} {command}\n"
+        )
+    }
+}
+
+/// Hooks directory of the repository, `core.hooksPath` is respected
+pub fn hooks_dir(repo: &Repository) -> Result<PathBuf> {
+    let configured = repo.config()?.get_path("core.hooksPath").ok();
+
+    Ok(match configured {
+        Some(path) if path.is_relative() => repo
+            .workdir()
+            .expect("Repository should not be bare")
+            .join(path),
+        Some(path) => path,
+        None => repo.path().join("hooks"),
+    })
+}
+
+fn is_ours(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| content.contains(MARKER))
+}
+
+/// Writes the hooks calling `program`. Existing hooks not installed by [`install_hooks`] are only
+/// replaced with `force`. Returns the paths of the written hooks
+pub fn install_hooks(
+    repo: &Repository,
+    hooks: &[Hook],
+    program: &str,
+    force: bool,
+) -> Result<Vec<PathBuf>> {
+    let dir = hooks_dir(repo)?;
+    fs::create_dir_all(&dir).map_err(|source| Error::FileWrite {
+        path: dir.clone(),
+        source,
+    })?;
+
+    let paths = hooks
+        .iter()
+        .map(|hook| (hook, dir.join(hook.file_name())))
+        .collect_vec();
+    if !force {
+        if let Some((_, path)) = paths.iter().find(|(_, p)| p.exists() && !is_ours(p)) {
+            return Err(Error::HookExists { path: path.clone() });
+        }
+    }
+
+    for (hook, path) in &paths {
+        debug!("Writing {:?}", path);
+        let write_failed = |source| Error::FileWrite {
+            path: path.clone(),
+            source,
+        };
+        fs::write(path, hook.script(program)).map_err(write_failed)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(write_failed)?;
+        }
+    }
+
+    Ok(paths.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Removes the hooks installed by [`install_hooks`], returning their paths
+pub fn uninstall_hooks(repo: &Repository) -> Result<Vec<PathBuf>> {
+    let dir = hooks_dir(repo)?;
+    let mut res = Vec::new();
+
+    for hook in [Hook::PrepareCommitMsg, Hook::CommitMsg] {
+        let path = dir.join(hook.file_name());
+        match path.exists() {
+            true if is_ours(&path) => {
+                fs::remove_file(&path).map_err(|source| Error::FileWrite {
+                    path: path.clone(),
+                    source,
+                })?;
+                res.push(path);
+            }
+            true => info!("{:?} was not installed by this program, keeping it", path),
+            false => {}
+        }
+    }
+
+    Ok(res)
+}
+
+/// `type(scope): ` for the scope, see [`TypeHistory::type_for_scope`] for how the type is picked.
+/// None if there's no type
+pub fn commit_prefix(
+    repo: &Repository,
+    config: &Option<Config>,
+    scope: &str,
+) -> Result<Option<String>> {
+    Ok(TypeHistory::load(repo, config)?
+        .type_for_scope(Some(scope), config)
+        .map(|(commit_type, _)| format_prefix(&commit_type, Some(scope))))
+}
+
+/// Puts the prefix in front of the message file, unless the message already has a subject
+pub fn prepend_prefix(path: &Path, prefix: &str) -> Result<bool> {
+    let message = fs::read_to_string(path).map_err(|source| Error::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if message
+        .lines()
+        .next()
+        .is_some_and(|line| !line.trim().is_empty())
+    {
+        info!("Message already has a subject, not touching it");
+        return Ok(false);
+    }
+
+    fs::write(path, format!("{}{}", prefix, message)).map_err(|source| Error::FileWrite {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(true)
+}
+
+/// `core.commentChar` of the repository, "#" if it's not set or is "auto"
+pub fn comment_char(repo: &Repository) -> Result<String> {
+    Ok(match repo.config()?.get_string("core.commentChar") {
+        Ok(value) if !value.is_empty() && value != "auto" => value,
+        _ => "#".to_string(),
+    })
+}
+
+/// Commented lines listing the types and the scopes, empty if disabled in the config
+pub fn comment_block(
+    types: &[CommitType],
+    scopes: &[CommitScope],
+    config: &CommentBlockConfig,
+    comment_char: &str,
+) -> Result<String> {
+    if config.enabled == Some(false) {
+        return Ok(String::new());
+    }
+    let template =
+        Template::from_str(config.item.as_deref().unwrap_or(DEFAULT_COMMENT_BLOCK_ITEM))?;
+    let section = |title: &Option<String>, default_title: &str, items: Result<String>| {
+        let title = title.as_deref().unwrap_or(default_title);
+        items.map(|items| format!("{}\n{}", title, items))
+    };
+
+    let types_count = config
+        .types
+        .unwrap_or(DEFAULT_COMMENT_BLOCK_ITEMS)
+        .min(types.len());
+    let scopes_count = config
+        .scopes
+        .unwrap_or(DEFAULT_COMMENT_BLOCK_ITEMS)
+        .min(scopes.len());
+    let mut lines = String::new();
+    if types_count > 0 {
+        lines += &section(
+            &config.types_title,
+            "Types:",
+            template.render(&types[..types_count]),
+        )?;
+    }
+    if scopes_count > 0 {
+        lines += &section(
+            &config.scopes_title,
+            "Scopes:",
+            template.render(&scopes[..scopes_count]),
+        )?;
+    }
+
+    // "#\tfeat" like in the status comments of git, "# Types:" otherwise
+    Ok(lines
+        .lines()
+        .map(|line| match line.starts_with('\t') {
+            true => format!("{}{}", comment_char, line.trim_end()),
+            false => format!("{} {}", comment_char, line).trim_end().to_string(),
+        })
+        .map(|line| line + "\n")
+        .collect())
+}
+
+/// Adds the block at the end of the message file, before the diff of `git commit --verbose`
+pub fn append_comment_block(path: &Path, block: &str, comment_char: &str) -> Result<()> {
+    if block.is_empty() {
+        return Ok(());
+    }
+    let message = fs::read_to_string(path).map_err(|source| Error::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let scissors = format!("{} {}", comment_char, SCISSORS);
+    let (before, after) = match message.find(&scissors) {
+        Some(idx) => message.split_at(idx),
+        None => (message.as_str(), ""),
+    };
+    let separator = match before.is_empty() || before.ends_with('\n') {
+        true => "",
+        false => "\n",
+    };
+
+    // Empty comment line sets the block apart, as in the git template
+    fs::write(
+        path,
+        format!(
+            "{}{}{}\n{}{}",
+            before, separator, comment_char, block, after
+        ),
+    )
+    .map_err(|source| Error::FileWrite {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    #[test]
+    fn install_uninstall() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        let hooks = hooks_dir(&repo).unwrap();
+        fs::create_dir_all(&hooks).unwrap();
+        fs::write(hooks.join("commit-msg"), "#!/bin/sh\nmy-linter\n").unwrap();
+
+        let installed = install_hooks(&repo, &[Hook::PrepareCommitMsg], "cch", false).unwrap();
+        assert_eq!(installed, vec![hooks.join("prepare-commit-msg")]);
+        let script = fs::read_to_string(&installed[0]).unwrap();
+        assert!(
+            script.contains("exec cch hook prepare-commit-msg"),
+            "{}",
+            script
+        );
+
+        // Someone else's hook is not replaced silently
+        assert!(install_hooks(&repo, &[Hook::CommitMsg], "cch", false).is_err());
+
+        assert_eq!(uninstall_hooks(&repo).unwrap(), installed);
+        assert!(!installed[0].exists());
+        assert!(hooks.join("commit-msg").exists());
+    }
+
+    #[test]
+    fn hooks_path() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        repo.config()
+            .unwrap()
+            .set_str("core.hooksPath", ".githooks")
+            .unwrap();
+
+        assert_eq!(hooks_dir(&repo).unwrap(), dir.join(".githooks"));
+    }
+
+    #[test]
+    fn prefix() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "fix(foo): a",
+                "fix(foo): b",
+                "feat(foo): c",
+                "feat(bar): d",
+            ],
+        );
+
+        assert_eq!(
+            commit_prefix(&repo, &None, "foo").unwrap().as_deref(),
+            Some("fix(foo): ")
+        );
+        // Not in the history, the most used type overall. Tie goes to "feat"
+        assert_eq!(
+            commit_prefix(&repo, &None, "baz").unwrap().as_deref(),
+            Some("feat(baz): ")
+        );
+        // Default type wins over the most used one, not over the one used with the scope
+        let config = Some(Config::from_str("[suggest]\ndefault_type = \"chore\"\n").unwrap());
+        assert_eq!(
+            commit_prefix(&repo, &config, "baz").unwrap().as_deref(),
+            Some("chore(baz): ")
+        );
+        assert_eq!(
+            commit_prefix(&repo, &config, "foo").unwrap().as_deref(),
+            Some("fix(foo): ")
+        );
+
+        let message = dir.join("COMMIT_EDITMSG");
+        fs::write(&message, "\n# Please enter the commit message\n").unwrap();
+        assert!(prepend_prefix(&message, "fix(foo): ").unwrap());
+        assert!(!prepend_prefix(&message, "fix(foo): ").unwrap());
+        assert_eq!(
+            fs::read_to_string(&message).unwrap(),
+            "fix(foo): \n# Please enter the commit message\n"
+        );
+    }
+
+    #[test]
+    fn block() {
+        let types = vec![
+            CommitType::with_description("feat", "A new feature"),
+            CommitType::with_description("fix", "A bug fix"),
+        ];
+        let scopes = vec![CommitScope::new("cli"), CommitScope::new("cache")];
+
+        assert_eq!(
+            comment_block(&types, &scopes, &CommentBlockConfig::default(), "#").unwrap(),
+            "# Types:\n#\tfeat  A new feature\n#\tfix  A bug fix\n# Scopes:\n#\tcli\n#\tcache\n"
+        );
+
+        let config = CommentBlockConfig {
+            types: Some(0),
+            scopes: Some(1),
+            scopes_title: Some("Suggested scope:".to_string()),
+            item: Some("  - {name}".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            comment_block(&types, &scopes, &config, ";").unwrap(),
+            "; Suggested scope:\n;   - cli\n"
+        );
+
+        let disabled = CommentBlockConfig {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(comment_block(&types, &scopes, &disabled, "#").unwrap(), "");
+    }
+
+    #[test]
+    fn block_before_scissors() {
+        let dir = testdir!();
+        let message = dir.join("COMMIT_EDITMSG");
+        fs::write(
+            &message,
+            indoc! {"
+
+                # Please enter the commit message
+                # ------------------------ >8 ------------------------
+                diff --git a/foo b/foo
+            "},
+        )
+        .unwrap();
+
+        append_comment_block(&message, "# Scopes:\n#\tcli\n", "#").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&message).unwrap(),
+            indoc! {"
+
+                # Please enter the commit message
+                #
+                # Scopes:
+                #\tcli
+                # ------------------------ >8 ------------------------
+                diff --git a/foo b/foo
+            "}
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! User-facing strings of the prompts, the `commit` wizard and the `lint` output, so that they can
+//! be translated.
+//!
+//! Every string has a [`Message`] key. The text is looked up in `[i18n.messages.<lang>]` of the
+//! config, then in the built-in catalog of the language, then in the English one. `{name}` in the
+//! text is replaced with the argument of the same name, i.e. `{scope}` in
+//! [`Message::UnknownScope`].
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+
+/// Languages with a built-in catalog
+pub const BUILTIN_LANGS: &[&str] = &["en", "de"];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    TypePrompt,
+    ScopePrompt,
+    /// Scope prompt when there are no scopes to pick from
+    ScopeInputPrompt,
+    /// Item of the scope list for a commit without a scope
+    NoScope,
+    SubjectPrompt,
+    SubjectEmpty,
+    BodyPrompt,
+    /// First comment line of the body edited in `$EDITOR`, `{header}`
+    BodyEditorHelp,
+    BreakingPrompt,
+    CacheStalePrompt,
+    TypeRequired,
+    SubjectRequired,
+    NothingToCommit,
+    NoAuthor,
+    /// `{header}`, `{violations}`
+    InvalidMessage,
+    NotConventional,
+    /// `{commit_type}`
+    UnknownType,
+    /// `{scope}`
+    UnknownScope,
+    /// `{scope}`, `{replacement}`
+    DeprecatedScope,
+    /// Question whether to use the replacement of the deprecated scope, `{scope}`, `{replacement}`
+    ReplaceDeprecatedScopePrompt,
+    /// `{scope}`, `{reason}`
+    InvalidScope,
+    /// `{length}`, `{max}`
+    SubjectTooLong,
+    NoBlankLineAfterSubject,
+    /// `{line}`, `{reason}`
+    MalformedFooter,
+    /// `{pseudo_type}`
+    PseudoTypeNotAllowed,
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::TypePrompt => "Type",
+        Message::ScopePrompt => "Scope",
+        Message::ScopeInputPrompt => "Scope (empty for none)",
+        Message::NoScope => "(no scope)",
+        Message::SubjectPrompt => "Subject",
+        Message::SubjectEmpty => "Subject can't be empty",
+        Message::BodyPrompt => "Body (empty for none)",
+        Message::BodyEditorHelp => {
+            "Body and footers of {header}. Lines starting with \"#\" are ignored"
+        }
+        Message::BreakingPrompt => "Breaking change?",
+        Message::CacheStalePrompt => "Cache is stale. Regenerate?",
+        Message::TypeRequired => "Commit type is required when not running in a terminal",
+        Message::SubjectRequired => "Subject is required when not running in a terminal",
+        Message::NothingToCommit => "Nothing to commit, stage the changes first",
+        Message::NoAuthor => {
+            "Failed to determine the author, set user.name and user.email in git config"
+        }
+        Message::InvalidMessage => "{header} is not a valid commit message: {violations}",
+        Message::NotConventional => "Subject is not a conventional commit header",
+        Message::UnknownType => "Unknown type {commit_type}",
+        Message::UnknownScope => "Unknown scope {scope}",
+        Message::DeprecatedScope => "Scope {scope} is deprecated, use {replacement}",
+        Message::ReplaceDeprecatedScopePrompt => {
+            "Scope {scope} is deprecated. Use {replacement} instead?"
+        }
+        Message::InvalidScope => "Scope {scope} {reason}",
+        Message::SubjectTooLong => "Subject is {length} characters long, at most {max}",
+        Message::NoBlankLineAfterSubject => "Subject should be followed by a blank line",
+        Message::MalformedFooter => "Malformed footer {line}: {reason}",
+        Message::PseudoTypeNotAllowed => "{pseudo_type} commits are not allowed",
+    }
+}
+
+fn german(message: Message) -> &'static str {
+    match message {
+        Message::TypePrompt => "Typ",
+        Message::ScopePrompt => "Bereich",
+        Message::ScopeInputPrompt => "Bereich (leer für keinen)",
+        Message::NoScope => "(kein Bereich)",
+        Message::SubjectPrompt => "Betreff",
+        Message::SubjectEmpty => "Der Betreff darf nicht leer sein",
+        Message::BodyPrompt => "Text (leer für keinen)",
+        Message::BodyEditorHelp => {
+            "Text und Footer von {header}. Zeilen, die mit \"#\" beginnen, werden ignoriert"
+        }
+        Message::BreakingPrompt => "Inkompatible Änderung?",
+        Message::CacheStalePrompt => "Der Cache ist veraltet. Neu erzeugen?",
+        Message::TypeRequired => "Ohne Terminal muss der Commit-Typ angegeben werden",
+        Message::SubjectRequired => "Ohne Terminal muss der Betreff angegeben werden",
+        Message::NothingToCommit => "Nichts zu committen, zuerst die Änderungen stagen",
+        Message::NoAuthor => "Autor unbekannt, user.name und user.email in der git config setzen",
+        Message::InvalidMessage => "{header} ist keine gültige Commit-Nachricht: {violations}",
+        Message::NotConventional => "Der Betreff ist kein Conventional-Commit-Header",
+        Message::UnknownType => "Unbekannter Typ {commit_type}",
+        Message::UnknownScope => "Unbekannter Bereich {scope}",
+        Message::DeprecatedScope => "Bereich {scope} ist veraltet, {replacement} verwenden",
+        Message::ReplaceDeprecatedScopePrompt => {
+            "Bereich {scope} ist veraltet. Stattdessen {replacement} verwenden?"
+        }
+        Message::InvalidScope => "Bereich {scope}: {reason}",
+        Message::SubjectTooLong => "Der Betreff ist {length} Zeichen lang, höchstens {max}",
+        Message::NoBlankLineAfterSubject => "Auf den Betreff sollte eine Leerzeile folgen",
+        Message::MalformedFooter => "Fehlerhafter Footer {line}: {reason}",
+        Message::PseudoTypeNotAllowed => "{pseudo_type}-Commits sind nicht erlaubt",
+    }
+}
+
+/// Primary language subtag: "de" for "de-AT" or "de_DE.UTF-8"
+fn normalize(lang: &str) -> String {
+    lang.split(['-', '_', '.'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Texts of the messages in one language
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Messages {
+    lang: String,
+    overrides: BTreeMap<Message, String>,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            lang: "en".to_string(),
+            overrides: BTreeMap::new(),
+        }
+    }
+}
+
+impl Messages {
+    /// Messages in `i18n.lang`, English if not set. Languages without a built-in catalog need
+    /// `[i18n.messages.<lang>]`, missing texts are English
+    pub fn from_config(config: &Option<Config>) -> Self {
+        let Some(i18n) = config.as_ref().map(|c| &c.i18n) else {
+            return Self::default();
+        };
+        let Some(lang) = i18n.lang.as_deref() else {
+            return Self::default();
+        };
+        let overrides = i18n
+            .messages
+            .get(lang)
+            .or_else(|| i18n.messages.get(&normalize(lang)))
+            .cloned()
+            .unwrap_or_default();
+        let lang = normalize(lang);
+        if overrides.is_empty() && !BUILTIN_LANGS.contains(&lang.as_str()) {
+            warn!(
+                "No messages for the language {:?}, add them to [i18n.messages.{}] in the config",
+                lang, lang
+            );
+        }
+
+        Self { lang, overrides }
+    }
+
+    /// Text of the message without the arguments substituted
+    pub fn get(&self, message: Message) -> &str {
+        match self.overrides.get(&message) {
+            Some(text) => text,
+            None => match self.lang.as_str() {
+                "de" => german(message),
+                _ => english(message),
+            },
+        }
+    }
+
+    /// Text of the message with `{name}` replaced by the argument. Unknown names are kept as is
+    pub fn format(&self, message: Message, args: &[(&str, &str)]) -> String {
+        let text = self.get(message);
+        let mut res = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            res.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after
+                .find('}')
+                .and_then(|end| args.iter().find(|(name, _)| *name == &after[..end]))
+            {
+                Some((name, value)) => {
+                    res.push_str(value);
+                    rest = &after[name.len() + 1..];
+                }
+                None => {
+                    res.push('{');
+                    rest = after;
+                }
+            }
+        }
+        res.push_str(rest);
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::default(None, "Unknown scope \"foo\"")]
+    #[case::builtin(Some("de"), "Unbekannter Bereich \"foo\"")]
+    #[case::region(Some("de_DE.UTF-8"), "Unbekannter Bereich \"foo\"")]
+    #[case::unknown(Some("xx"), "Unknown scope \"foo\"")]
+    fn language(#[case] lang: Option<&str>, #[case] expected: &str) {
+        let mut config = Config::default();
+        config.i18n.lang = lang.map(String::from);
+
+        let messages = Messages::from_config(&Some(config));
+
+        assert_eq!(
+            messages.format(Message::UnknownScope, &[("scope", "\"foo\"")]),
+            expected
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let config = Config::from_str(indoc! {r#"
+            [i18n]
+            lang = "fr"
+
+            [i18n.messages.fr]
+            subject_prompt = "Sujet"
+            unknown_scope = "Portée inconnue {scope}"
+        "#})
+        .unwrap();
+
+        let messages = Messages::from_config(&Some(config));
+
+        assert_eq!(messages.get(Message::SubjectPrompt), "Sujet");
+        assert_eq!(
+            messages.format(Message::UnknownScope, &[("scope", "cli")]),
+            "Portée inconnue cli"
+        );
+        // Not translated
+        assert_eq!(messages.get(Message::BodyPrompt), "Body (empty for none)");
+    }
+
+    #[rstest]
+    #[case::all("{a} and {b}", "1 and 2")]
+    #[case::unknown("{a} {c}", "1 {c}")]
+    #[case::unclosed("{a} {b", "1 {b")]
+    #[case::value_not_substituted("{d}{b}", "{b}2")]
+    fn format(#[case] text: &str, #[case] expected: &str) {
+        let messages = Messages {
+            lang: "en".to_string(),
+            overrides: BTreeMap::from([(Message::NotConventional, text.to_string())]),
+        };
+
+        assert_eq!(
+            messages.format(
+                Message::NotConventional,
+                &[("a", "1"), ("b", "2"), ("d", "{b}")]
+            ),
+            expected
+        );
+    }
+}
@@ -1,4 +1,70 @@
+//! Library behind the `conventional-commit-helper` binary, for editor plugins and other tools that
+//! need the commit types and scopes of a repository without shelling out.
+//!
+//! The items re-exported here are the stable API. All the modules return the typed [`Error`], not
+//! `anyhow`; the interactive parts of the CLI live in the binary. [`engine::SuggestionEngine`]
+//! wraps them with the CLI overrides (cache location, time budget).
+//!
+//! ```no_run
+//! use conventional_commit_helper::{
+//!     get_commit_types_from_repo_or_default, try_get_commit_scopes_from_repo, Config,
+//! };
+//!
+//! let repo = git2::Repository::discover(".")?;
+//! let config = Config::load(&repo, None)?;
+//!
+//! for commit_type in get_commit_types_from_repo_or_default(&repo, config.clone())? {
+//!     println!("{}", commit_type.name);
+//! }
+//! for scope in try_get_commit_scopes_from_repo(&repo, config)?.unwrap_or_default() {
+//!     println!("{}", scope.name);
+//! }
+//! # Ok::<(), conventional_commit_helper::Error>(())
+//! ```
+pub mod audit;
+pub mod bundle;
+pub mod cache;
+pub mod changelog;
+pub mod commit_log;
+pub mod commit_message;
+pub mod commit_scopes;
+pub mod commit_types;
+pub mod completions;
+pub mod config;
+pub mod engine;
+pub mod entity;
+pub mod error;
+pub mod export;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod github;
+pub mod graph;
+pub mod history;
+pub mod hooks;
+pub mod i18n;
+pub mod links;
+pub mod output;
+pub mod owners;
+pub mod paths;
+pub mod prefix;
+pub mod preflight;
+pub mod reviewers;
+pub mod sanitize;
+pub mod scope_name;
+pub mod stats;
+pub mod utils;
+pub mod versioning;
+pub mod warnings;
+
+pub use cache::Cache;
+pub use commit_scopes::{distance, try_get_commit_scopes_from_repo, CommitScope};
+pub use commit_types::{get_commit_types_from_repo_or_default, CommitType};
+pub use config::Config;
+pub use error::{Error, Result};
+
 pub mod test_utils {
+    use crate::utils::time;
+    use chrono::{DateTime, Duration, Utc};
     use git2::{Oid, Repository, Signature};
     use itertools::Itertools;
     use log::debug;
@@ -74,6 +140,23 @@ pub mod test_utils {
         setup_repo_with_commits_and_files(tmpdir, commit_msgs, &[])
     }
 
+    /// Adds a commit on top of HEAD in an existing repo, writing the message into the file
+    pub fn add_commit(repo: &Repository, commit_msg: &str, file: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::write(workdir.join(file), commit_msg).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let sig = Signature::now("nobody", "nobody@example.com").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+
+        repo.commit(Some("HEAD"), &sig, &sig, commit_msg, &tree, &[&head])
+            .unwrap()
+    }
+
     const TYPES_ONLY_CONFIG: &str = r#"
                 [types]
                 foo = "bar"
@@ -96,6 +179,36 @@ pub mod test_utils {
         setup_config_file_in_path(tmpdir, &(TYPES_ONLY_CONFIG.to_owned() + SCOPES_ONLY_CONFIG));
     }
 
+    /// Clock frozen by [`freeze_time`]
+    pub struct FrozenClock {
+        time: DateTime<Utc>,
+    }
+
+    impl FrozenClock {
+        pub fn now(&self) -> DateTime<Utc> {
+            self.time
+        }
+
+        /// Moves the frozen clock forward
+        pub fn advance(&mut self, by: Duration) {
+            self.time += by;
+            time::freeze(self.time);
+        }
+    }
+
+    impl Drop for FrozenClock {
+        fn drop(&mut self) {
+            time::unfreeze();
+        }
+    }
+
+    /// Freezes the clock of the library (`utils::time::now`) at `at` on the current thread until
+    /// the returned guard is dropped
+    pub fn freeze_time(at: DateTime<Utc>) -> FrozenClock {
+        time::freeze(at);
+        FrozenClock { time: at }
+    }
+
     pub fn setup_config_file_in_path(tmpdir: &Path, content: &str) -> PathBuf {
         debug!("Setting up config file at {:?}", tmpdir);
         let config_path = tmpdir.join(".dev/conventional-commit-helper.toml");
@@ -104,4 +217,175 @@ pub mod test_utils {
 
         config_path
     }
+
+    /// Commit graph replayed by [`replay_fixture`], usually read from JSON with [`load_fixture`].
+    ///
+    /// Meant for the anonymized shapes of real repositories: only the messages and the paths
+    /// matter, file contents are generated.
+    #[derive(Debug, Clone, serde::Deserialize)]
+    pub struct RepoFixture {
+        pub commits: Vec<FixtureCommit>,
+    }
+
+    #[derive(Debug, Clone, serde::Deserialize)]
+    pub struct FixtureCommit {
+        /// What the later commits refer to in `parents`. Defaults to the position in the list
+        pub id: Option<String>,
+        pub message: String,
+        /// Paths written by the commit. `{1..100}` in a path expands into 100 paths
+        #[serde(default)]
+        pub files: Vec<String>,
+        #[serde(default)]
+        pub deleted: Vec<String>,
+        /// Previous commit if not set, `[]` for another root commit, two or more for a merge
+        pub parents: Option<Vec<String>>,
+        /// Creates a chain of this many commits, `{n}` in the message and the paths is replaced
+        /// with the number of the commit in the chain. `id` refers to the last one
+        #[serde(default = "default_repeat")]
+        pub repeat: usize,
+    }
+
+    fn default_repeat() -> usize {
+        1
+    }
+
+    /// Expands `{from..to}` (inclusive) in the path
+    fn expand_path(path: &str) -> Vec<String> {
+        let range = fancy_regex::Regex::new(r"\{(\d+)\.\.(\d+)\}").unwrap();
+        match range.captures(path).unwrap() {
+            Some(captures) => {
+                let from: usize = captures[1].parse().unwrap();
+                let to: usize = captures[2].parse().unwrap();
+                let span = captures.get(0).unwrap();
+                (from..=to)
+                    .map(|i| format!("{}{}{}", &path[..span.start()], i, &path[span.end()..]))
+                    .collect()
+            }
+            None => vec![path.to_string()],
+        }
+    }
+
+    /// Creates a repository in `tmpdir` with the commits of the fixture, HEAD pointing to the last
+    /// one. The worktree is checked out
+    pub fn replay_fixture(tmpdir: &Path, fixture: &RepoFixture) -> Repository {
+        let repo = Repository::init(tmpdir).unwrap();
+        let mut ids: std::collections::HashMap<String, Oid> = Default::default();
+        let mut previous: Option<Oid> = None;
+        // Commit times are spaced out for a stable order
+        let mut time = 1_700_000_000;
+
+        for (position, commit) in fixture.commits.iter().enumerate() {
+            let mut parents: Vec<Oid> = match &commit.parents {
+                Some(parents) => parents
+                    .iter()
+                    .map(|id| {
+                        *ids.get(id)
+                            .unwrap_or_else(|| panic!("Unknown parent {:?}", id))
+                    })
+                    .collect(),
+                None => previous.into_iter().collect(),
+            };
+
+            for n in 1..=commit.repeat {
+                let substitute = |s: &str| s.replace("{n}", &n.to_string());
+                let tree_of = |id: Oid| repo.find_commit(id).unwrap().tree().unwrap();
+                let mut index = git2::Index::new().unwrap();
+                if let Some((first, others)) = parents.split_first() {
+                    // Merges take the changes of all parents, the first one wins on conflicts
+                    let mut tree = tree_of(*first);
+                    for other in others {
+                        let base = tree_of(repo.merge_base(*first, *other).unwrap());
+                        let mut merged = repo
+                            .merge_trees(
+                                &base,
+                                &tree,
+                                &tree_of(*other),
+                                Some(git2::MergeOptions::new().file_favor(git2::FileFavor::Ours)),
+                            )
+                            .unwrap();
+                        assert!(!merged.has_conflicts(), "Conflicting merge {}", position);
+                        tree = repo
+                            .find_tree(merged.write_tree_to(&repo).unwrap())
+                            .unwrap();
+                    }
+                    index.read_tree(&tree).unwrap();
+                }
+
+                for path in commit
+                    .files
+                    .iter()
+                    .flat_map(|p| expand_path(&substitute(p)))
+                {
+                    let content = format!("{} {}\n", position, substitute(&commit.message));
+                    let entry = git2::IndexEntry {
+                        ctime: git2::IndexTime::new(0, 0),
+                        mtime: git2::IndexTime::new(0, 0),
+                        dev: 0,
+                        ino: 0,
+                        mode: 0o100644,
+                        uid: 0,
+                        gid: 0,
+                        file_size: content.len() as u32,
+                        id: repo.blob(content.as_bytes()).unwrap(),
+                        flags: 0,
+                        flags_extended: 0,
+                        path: path.into_bytes(),
+                    };
+                    index.add(&entry).unwrap();
+                }
+                for path in commit
+                    .deleted
+                    .iter()
+                    .flat_map(|p| expand_path(&substitute(p)))
+                {
+                    index.remove_path(Path::new(&path)).unwrap();
+                }
+
+                let tree = repo.find_tree(index.write_tree_to(&repo).unwrap()).unwrap();
+                let sig = Signature::new("nobody", "nobody@example.com", &git2::Time::new(time, 0))
+                    .unwrap();
+                time += 60;
+                let parent_commits = parents
+                    .iter()
+                    .map(|id| repo.find_commit(*id).unwrap())
+                    .collect_vec();
+                let id = repo
+                    .commit(
+                        None,
+                        &sig,
+                        &sig,
+                        &substitute(&commit.message),
+                        &tree,
+                        &parent_commits.iter().collect_vec(),
+                    )
+                    .unwrap();
+
+                parents = vec![id];
+                previous = Some(id);
+            }
+
+            let id = commit.id.clone().unwrap_or_else(|| position.to_string());
+            ids.insert(id, previous.unwrap());
+        }
+
+        if let Some(head) = previous {
+            let head = repo.find_commit(head).unwrap();
+            repo.branch("master", &head, true).unwrap();
+            repo.set_head("refs/heads/master").unwrap();
+            repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+                .unwrap();
+        }
+
+        repo
+    }
+
+    /// Reads a JSON [`RepoFixture`] and replays it in `tmpdir`
+    pub fn load_fixture(tmpdir: &Path, fixture: &Path) -> Repository {
+        let content = fs::read_to_string(fixture)
+            .unwrap_or_else(|e| panic!("Failed to read the fixture {:?}: {}", fixture, e));
+        let fixture: RepoFixture = serde_json::from_str(&content)
+            .unwrap_or_else(|e| panic!("Invalid fixture {:?}: {}", fixture, e));
+
+        replay_fixture(tmpdir, &fixture)
+    }
 }
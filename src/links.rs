@@ -0,0 +1,207 @@
+//! Extraction of issue tracker references from commits
+
+use fancy_regex::Regex;
+use git2::Repository;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::commit_message::parse_footers;
+use crate::config::{Config, TrackerConfig};
+use crate::error::{Error, Result};
+use crate::history::revwalk_for_range;
+
+/// Tracker used when none are configured. Matches GitHub-style `#123` references.
+const DEFAULT_TRACKER_NAME: &str = "github";
+const DEFAULT_TRACKER_PATTERN: &str = r"#(\d+)";
+
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct IssueLink {
+    pub tracker: String,
+    pub id: String,
+    /// Rendered URL, if tracker has a URL template
+    pub url: Option<String>,
+    /// Hashes of commits that reference the issue
+    pub commits: Vec<String>,
+}
+
+struct Tracker {
+    name: String,
+    pattern: Regex,
+    url: Option<String>,
+}
+
+fn get_trackers(config: &Option<Config>) -> Result<Vec<Tracker>> {
+    let configured = config.as_ref().and_then(|c| c.trackers.clone());
+
+    let trackers = configured.unwrap_or_else(|| {
+        debug!("No trackers configured, using the default one");
+        BTreeMap::from([(
+            DEFAULT_TRACKER_NAME.to_string(),
+            TrackerConfig {
+                pattern: DEFAULT_TRACKER_PATTERN.to_string(),
+                url: None,
+            },
+        )])
+    });
+
+    trackers
+        .into_iter()
+        .map(|(name, tracker)| {
+            Ok(Tracker {
+                pattern: Regex::new(&tracker.pattern).map_err(|e| Error::ConfigInvalid {
+                    path: None,
+                    line: None,
+                    message: format!("trackers.{}.pattern: {:?} {}", name, tracker.pattern, e),
+                })?,
+                name,
+                url: tracker.url,
+            })
+        })
+        .collect()
+}
+
+/// Finds the issue IDs in the text. If the pattern has a capture group -- the first group is the
+/// ID, otherwise the whole match is.
+fn find_ids(pattern: &Regex, text: &str) -> Vec<String> {
+    pattern
+        .captures_iter(text)
+        .filter_map(|c| match c {
+            Ok(c) => c
+                .get(1)
+                .or_else(|| c.get(0))
+                .map(|m| m.as_str().to_string()),
+            Err(e) => {
+                warn!("Error while matching the tracker pattern: {:?}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Collects issue references from the subjects and footers of commits in the range.
+///
+/// Bodies are not considered as they tend to mention issues in passing.
+pub fn get_links(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<IssueLink>> {
+    let trackers = get_trackers(config)?;
+    // (tracker, id) -> link. BTreeMap keeps the output stable
+    let mut res: BTreeMap<(String, String), IssueLink> = BTreeMap::new();
+
+    for oid in revwalk_for_range(repo, range)? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(message) = commit.message() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+
+        let searched: Vec<String> = std::iter::once(commit.summary().unwrap_or("").to_string())
+            .chain(parse_footers(message).into_iter().map(|f| f.value))
+            .collect();
+
+        for tracker in &trackers {
+            for id in searched
+                .iter()
+                .flat_map(|text| find_ids(&tracker.pattern, text))
+            {
+                let link = res
+                    .entry((tracker.name.clone(), id.clone()))
+                    .or_insert_with(|| IssueLink {
+                        tracker: tracker.name.clone(),
+                        url: tracker.url.as_ref().map(|url| url.replace("{id}", &id)),
+                        id,
+                        commits: Vec::new(),
+                    });
+                let hash = commit.id().to_string();
+                if !link.commits.contains(&hash) {
+                    link.commits.push(hash);
+                }
+            }
+        }
+    }
+
+    Ok(res.into_values().collect())
+}
+
+/// Renders links as a markdown list
+pub fn to_markdown(links: &[IssueLink]) -> String {
+    links
+        .iter()
+        .map(|link| {
+            let commits = link
+                .commits
+                .iter()
+                .map(|c| c.chars().take(7).collect::<String>())
+                .collect::<Vec<_>>()
+                .join(", ");
+            match &link.url {
+                Some(url) => format!("- [{}]({}) ({})\n", link.id, url, commits),
+                None => format!("- {} ({})\n", link.id, commits),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    #[test]
+    fn default_tracker() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "feat: foo (#1)",
+                "fix: bar\n\nSome body mentioning #3\n\nCloses #2",
+            ],
+        );
+
+        let res = get_links(&repo, &None, None).unwrap();
+
+        assert_eq!(
+            res.iter().map(|l| l.id.as_str()).collect::<Vec<_>>(),
+            vec!["1", "2"]
+        );
+        assert!(res.iter().all(|l| l.url.is_none()));
+    }
+
+    #[test]
+    fn configured_trackers() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "feat: foo\n\nRefs: PROJ-12",
+                "fix: bar\n\nRefs: PROJ-12",
+            ],
+        );
+        let config = Config::from_str(indoc! {r#"
+            [trackers.jira]
+            pattern = "[A-Z]+-\\d+"
+            url = "https://jira.example.com/browse/{id}"
+            "#})
+        .unwrap();
+
+        let res = get_links(&repo, &Some(config), None).unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].commits.len(), 2);
+        assert_eq!(
+            to_markdown(&res),
+            format!(
+                "- [PROJ-12](https://jira.example.com/browse/PROJ-12) ({}, {})\n",
+                &res[0].commits[0][..7],
+                &res[0].commits[1][..7]
+            )
+        );
+    }
+}
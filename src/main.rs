@@ -1,18 +1,36 @@
-use clap::{Parser, Subcommand};
+use anyhow::{bail, Context};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::Verbosity;
-use log::{debug, info};
+use log::{debug, info, warn};
 use serde::Serialize;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use self::commit_types::get_default_commit_types;
-use self::config::Config;
-use self::utils::{repo_from_path, validate_repo, PrintableEntity};
+use conventional_commit_helper::commit_message::strip_comments;
+use conventional_commit_helper::commit_scopes::{get_deprecated_scopes, ScopeDetails};
+use conventional_commit_helper::commit_types::{
+    commit_type_source, get_commit_types_from_repo_or_default, get_default_commit_types, CommitType,
+};
+use conventional_commit_helper::config::{
+    add_scopes_to_file, scopes_to_toml, set_value_in_file, undo_config_change,
+    with_cache_overrides, Config, ConfigUndo, DEFAULT_CONFIG_PATH_IN_REPO,
+};
+use conventional_commit_helper::engine::{SuggestionEngine, Violation};
+use conventional_commit_helper::entity::{Entity, Source};
+use conventional_commit_helper::error::Error;
+use conventional_commit_helper::i18n::Message;
+use conventional_commit_helper::output::{self, Template};
+use conventional_commit_helper::sanitize::sanitize;
+use conventional_commit_helper::utils::repo_from_path;
+use conventional_commit_helper::{
+    audit, bundle, cache, changelog, commit_log, commit_scopes, completions, export, github, graph,
+    hooks, links, owners, paths, reviewers, stats, utils, versioning, warnings,
+};
 
-mod cache;
-mod commit_scopes;
-mod commit_types;
-mod config;
-mod utils;
+mod commit_wizard;
+mod plugins;
 
 #[derive(Subcommand, Debug)]
 enum CacheCommand {
@@ -20,6 +38,19 @@ enum CacheCommand {
     Create,
     /// Updates the cache for a repo
     Update,
+    /// Updates the cache of every cached repo, each with its own config. Repos that no longer exist
+    /// are skipped. Exits with 1 if any update failed
+    UpdateAll {
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Removes the cache entries of the repos that no longer exist (or are no longer git repos)
+    Prune {
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
     /// Drops cache for a repo
     Drop,
     /// Deletes the whole cache
@@ -28,6 +59,154 @@ enum CacheCommand {
     Show,
 }
 
+#[derive(Subcommand, Debug)]
+enum ScopeCommand {
+    /// Print the files historically associated with a scope
+    Files {
+        name: String,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Print the best matching scopes for files, based on the history
+    ForFile {
+        /// Paths relative to the repository root
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HookCommand {
+    /// Append the just created commit to the cache
+    PostCommit,
+    /// Pre-populate the message of a plain `git commit` with `type(scope): ` suggested for the
+    /// staged files and append the top types and scopes as comments (see `hooks.comment_block`
+    /// in the config)
+    PrepareCommitMsg {
+        /// File with the commit message
+        file: PathBuf,
+        /// Where the message comes from (message, template, merge, squash or commit). The
+        /// message is only touched if it's not given
+        source: Option<String>,
+        sha: Option<String>,
+    },
+    /// Write the prepare-commit-msg hook (and, optionally, the commit-msg one) calling this
+    /// program into the hooks directory of the repository
+    Install {
+        /// Also install the commit-msg hook running `lint`
+        #[arg(long)]
+        commit_msg: bool,
+        /// Replace the hooks not installed by this program
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove the hooks installed by `hook install`
+    Uninstall,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigImportCommand {
+    /// Convert the GitHub issue labels with the prefix into scopes. Prints the `[scopes]` table
+    /// unless --write is given
+    GithubLabels {
+        /// GitHub repository ("owner/name"). The token is taken from GITHUB_TOKEN or GH_TOKEN
+        #[arg(required_unless_present = "from_file", conflicts_with = "from_file")]
+        repo: Option<String>,
+        /// Read the labels from a JSON file instead, i.e. produced by `gh label list --json
+        /// name,description`
+        #[arg(long)]
+        from_file: Option<PathBuf>,
+        /// Only the labels with this prefix are imported, the prefix is stripped
+        #[arg(long, default_value = "area/")]
+        prefix: String,
+        /// Add the scopes missing from the repository config file to it
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ExportCommand {
+    /// Write the effective config, the scopes mined from the history, the type statistics and the
+    /// unreleased changes into one file
+    Bundle { file: PathBuf },
+    /// commitlint config (`.commitlintrc.json`) with the known types and scopes, printed if no
+    /// file is given
+    Commitlint { file: Option<PathBuf> },
+    /// Graph of the scopes and the top directories they touch, shared directories are marked.
+    /// Graphviz by default, `--format mermaid` for Markdown
+    Graph {
+        /// Directory components kept, 2 turns `src/cli/args.rs` into `src/cli/`
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+        /// Directories with the most files kept per scope
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// cocogitto config (`cog.toml`) with the known scopes, the release tag prefix and the types
+    /// cocogitto does not know, printed if no file is given
+    Cocogitto { file: Option<PathBuf> },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCommand {
+    /// Compare the scopes in the config with the ones used in the history. Prints the missing
+    /// scopes as a `[scopes]` table to add to the config
+    Scopes {
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ImportCommand {
+    /// Add the scopes and types configured in the bundle to the repository config and seed the
+    /// cache with the bundle's scopes
+    Bundle { file: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Bootstrap the config from other sources
+    Import {
+        #[command(subcommand)]
+        command: ConfigImportCommand,
+    },
+    /// Print the effective value of a dotted key (i.e. `general.scopes.disable_history_search`),
+    /// with the defaults applied. Exits with 1 if the key is not set
+    Get { key: String },
+    /// Set the dotted key in the repository config file (or the one given with --config). The
+    /// value is parsed as TOML if it is valid for the key (`true`, `20`, `["a"]`), otherwise it is
+    /// a string
+    Set {
+        key: String,
+        value: String,
+        /// Write to the global config file instead
+        #[arg(long)]
+        global: bool,
+    },
+    /// Revert the last change of the repository config file (or the one given with --config)
+    /// made by this program. Every change made by `config set`, `config import github-labels
+    /// --write` and `import bundle` is backed up next to the file (the last 10 are kept), undoing
+    /// again goes further back. Exits with 1 if there's nothing to undo
+    Undo {
+        /// Revert the global config file instead
+        #[arg(long)]
+        global: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum OwnersCommand {
+    /// Print a CODEOWNERS skeleton with the paths grouped per scope
+    Suggest,
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Cache operations
@@ -37,23 +216,286 @@ enum Command {
     },
     /// Show commit types
     Type {
-        /// Print output in JSON format
-        #[arg(long)]
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
         json: bool,
+        /// Put the type most likely for the staged files first (i.e. "docs" if only Markdown files
+        /// are staged), see `suggest.type_rules` in the config. The JSON output gets the
+        /// `suggested` field
+        #[arg(long)]
+        suggest: bool,
     },
     /// Show commit scopes
     Scope {
-        /// Print output in JSON format
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+        /// Do not promote the scope matching the staged files, list the scopes alphabetically.
+        /// Cached scopes are listed as is, without checking the cache freshness. Falls back to the
+        /// regular lookup if the repository is not cached
+        #[arg(long)]
+        no_suggest: bool,
+        /// Print at most this many scopes
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Skip this many scopes from the start of the list
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        /// Add the data behind the ranking and the suggestion to the structured output (`details`
+        /// field): the commits and the last use of the scope, the overlap of its files with the
+        /// staged ones and the number of its files. For clients doing their own scoring
+        #[arg(long)]
+        detailed: bool,
+        #[command(subcommand)]
+        command: Option<ScopeCommand>,
+    },
+    /// Print the commit header prefix for the staged files, i.e. `feat(cli):`. Made for `git commit
+    /// -m "$(conventional-commit-helper suggest) add foo"`, so the trailing space is not printed.
+    /// The structured output has the confidence (0 to 1) and the alternatives. Exits with 1 if
+    /// there's nothing to suggest
+    Suggest {
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Check the staged changes and print the suggestion as a single JSON, for wrappers
+    /// pre-populating the commit message. Exits with 1 if nothing is staged or there are problems
+    ///
+    /// The secret and file size heuristics are configured in `[preflight]`, they are off by
+    /// default. `comments` has the lines to put in the message template
+    Preflight,
+    /// Generate the changelog
+    Changelog {
+        /// Commit range (e.g. "v1.0.0..HEAD"). Defaults to the commits since the latest tag
+        range: Option<String>,
+        /// Title of the release
+        #[arg(long, default_value = "Unreleased")]
+        title: String,
+        /// Format the notes for a GitHub release of the tag (can be piped to `gh release create
+        /// <tag> -F -`)
+        #[arg(long, value_name = "TAG", conflicts_with_all = ["title", "range"])]
+        github_release: Option<String>,
+        /// Only include the commits of this scope. If the scope is versioned independently, its
+        /// tags and paths are used
+        #[arg(long)]
+        scope: Option<String>,
+    },
+    /// Calculate the next release version (tag) from the commits since the latest one
+    NextVersion {
+        /// Calculate the version of an independently versioned scope
+        #[arg(long)]
+        scope: Option<String>,
+    },
+    /// Show issue tracker references from the commits
+    Links {
+        /// Commit range (e.g. "v1.0.0..HEAD"). Defaults to the whole history of HEAD
+        range: Option<String>,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// List conventional commits matching a scope and/or a type
+    Log {
+        /// Range of commits to consider ("from..to" or a single revision). Defaults to everything
+        /// reachable from HEAD
+        range: Option<String>,
+        #[arg(long)]
+        scope: Option<String>,
+        #[arg(long = "type")]
+        commit_type: Option<String>,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Print per-scope commit statistics
+    Stats {
+        /// Range of commits to consider ("from..to" or a single revision). Defaults to everything
+        /// reachable from HEAD
+        range: Option<String>,
+        /// Produce weekly per-scope commit counts instead of totals
+        #[arg(long)]
+        heatmap: bool,
+        /// Report how many files every two scopes have in common over the whole history (matrix
+        /// in JSON and CSV)
+        #[arg(long, conflicts_with_all = ["heatmap", "range"])]
+        co_occurrence: bool,
+        /// Same as `--format json`
+        #[arg(long, hide = true, conflicts_with = "csv")]
+        json: bool,
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Entry points for git hooks
+    Hook {
+        #[command(subcommand)]
+        command: HookCommand,
+    },
+    /// Print the shell completion script
+    ///
+    /// The zsh and fish scripts also complete the commit headers (`feat(<TAB>`) through
+    /// `--query`, which only reads the config and the cache
+    #[command(arg_required_else_help = true)]
+    Completions {
+        #[arg(conflicts_with = "query")]
+        shell: Option<clap_complete::Shell>,
+        /// Print the completions of a partial commit header, one per line
+        #[arg(long, value_name = "WORD", allow_hyphen_values = true)]
+        query: Option<String>,
+    },
+    /// Print the filesystem paths used by the program
+    Paths {
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Config operations
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Check the config against the history
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Export the knowledge about the repository
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
+    /// Import the knowledge about the repository exported elsewhere
+    Import {
+        #[command(subcommand)]
+        command: ImportCommand,
+    },
+    /// Code ownership helpers
+    Owners {
+        #[command(subcommand)]
+        command: OwnersCommand,
+    },
+    /// Suggest reviewers for the change based on its scopes
+    Reviewers {
+        /// Scope to look up the reviewers for (can be repeated). If not specified, the scope
+        /// suggested for the staged files is used
         #[arg(long)]
+        scope: Vec<String>,
+        /// Collect the scopes from the commits in the range instead (e.g. "main..HEAD")
+        #[arg(long, conflicts_with = "scope")]
+        range: Option<String>,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
         json: bool,
     },
+    /// Check a commit message against the conventional commits grammar and the config. Exits with
+    /// 1 if there are problems
+    ///
+    /// Checks the type, the scope, the subject length (`lint.max_subject_length`), the blank line
+    /// after the subject and the footers syntax. Comment lines are stripped from the message files,
+    /// as git does, so that it can be used as a `commit-msg` hook
+    Lint {
+        /// File with the message, "-" or nothing to read the message from stdin
+        #[arg(conflicts_with = "head")]
+        file: Option<PathBuf>,
+        /// Check the message of the HEAD commit instead
+        #[arg(long)]
+        head: bool,
+        /// Same as `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+    /// Commit the staged changes with a conventional commit message
+    ///
+    /// Asks for the type, scope (the one matching the staged files is preselected), subject, body
+    /// and whether it's a breaking change. Parts given as options are not asked for, without a
+    /// terminal the type and the subject are required. Git hooks are not run
+    Commit {
+        #[arg(long = "type")]
+        commit_type: Option<String>,
+        #[arg(long)]
+        scope: Option<String>,
+        #[arg(long, short = 'm')]
+        subject: Option<String>,
+        #[arg(long)]
+        body: Option<String>,
+        #[arg(long)]
+        breaking: bool,
+        /// Write the body and the footers in $EDITOR, with the comments stripped like git does
+        #[arg(long, short = 'e')]
+        edit: bool,
+        /// Print the message instead of committing, i.e. for `git commit -eF <(...)`
+        #[arg(long, conflicts_with = "output_file")]
+        print_only: bool,
+        /// Write the message to the file instead of committing
+        #[arg(long, value_name = "PATH")]
+        output_file: Option<PathBuf>,
+    },
+    /// Commands provided by `conventional-commit-helper-<name>` executables on PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+impl Command {
+    /// Whether the subcommand prints JSON regardless of `--format`
+    fn json(&self) -> bool {
+        match self {
+            Command::Cache {
+                command: CacheCommand::UpdateAll { json } | CacheCommand::Prune { json },
+            }
+            | Command::Scope {
+                command: Some(ScopeCommand::Files { json, .. } | ScopeCommand::ForFile { json, .. }),
+                ..
+            }
+            | Command::Audit {
+                command: AuditCommand::Scopes { json },
+            }
+            | Command::Type { json, .. }
+            | Command::Scope { json, .. }
+            | Command::Suggest { json }
+            | Command::Links { json, .. }
+            | Command::Log { json, .. }
+            | Command::Stats { json, .. }
+            | Command::Paths { json }
+            | Command::Reviewers { json, .. }
+            | Command::Lint { json, .. } => *json,
+            Command::Preflight => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the subcommand works with the staged files or the files in the working tree, which
+    /// bare repositories do not have
+    fn needs_worktree(&self) -> bool {
+        match self {
+            Command::Type { suggest, .. } => *suggest,
+            Command::Config {
+                command: ConfigCommand::Set { global, .. } | ConfigCommand::Undo { global },
+            } => !global,
+            Command::Config {
+                command:
+                    ConfigCommand::Import {
+                        command: ConfigImportCommand::GithubLabels { write, .. },
+                    },
+            } => *write,
+            Command::Export {
+                command: ExportCommand::Bundle { .. },
+            }
+            | Command::Suggest { .. }
+            | Command::Preflight
+            | Command::Hook { .. }
+            | Command::Import { .. }
+            | Command::Commit { .. }
+            | Command::External(_) => true,
+            _ => false,
+        }
+    }
 }
 
 /// Tiny helper for conventional commits (https://www.conventionalcommits.org).
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Path to the non-bare git repository.
+    /// Path to the git repository. Bare repositories are read-only: `type`, `scope` and the other
+    /// commands reading the history work, the ones using the staged files do not
     #[arg(long, default_value = ".")]
     repo_path: PathBuf,
 
@@ -61,26 +503,331 @@ struct Args {
     #[arg(long)]
     config: Option<PathBuf>,
 
+    /// Path to the cache file. Useful when there is no writable home directory
+    #[arg(long)]
+    cache_path: Option<PathBuf>,
+
+    /// Never lock or write the cache, i.e. on read-only CI cache mounts. Stale cache is handled
+    /// according to `cache.on_stale_no_regen`
+    #[arg(long)]
+    cache_read_only: bool,
+
+    /// Match the untracked files against the scopes as if they were staged, i.e. to get a
+    /// suggestion before staging anything. Same as `suggest.include_untracked` in the config
+    #[arg(long)]
+    include_untracked: bool,
+
+    /// Do not mine the scopes of the commits reachable from the revision, i.e. the ones before the
+    /// last release with "latest-tag". Same as `general.scopes.since_ref` in the config
+    #[arg(long, value_name = "REV")]
+    since_ref: Option<String>,
+
+    /// Language of the prompts, the `commit` wizard and the `lint` output, i.e. "de". Same as
+    /// `i18n.lang` in the config
+    #[arg(long)]
+    lang: Option<String>,
+
+    /// Output format of the commands printing data. `tsv` prints one line per item with the fields
+    /// separated by tabs, without a header. `dot` and `mermaid` are for `export graph` only
+    #[arg(long, global = true)]
+    format: Option<Format>,
+
+    /// Line of the output for every item, i.e. "{name}\t{description}" for `type` and `scope`.
+    /// `{field}` is a field of the JSON output, `{}` the item itself. Implies `--format template`
+    #[arg(long, global = true)]
+    template: Option<Template>,
+
+    /// Emit the JSON output of `type` and `scope` in the shape of another version: 1 without the
+    /// fields added since, 2 adds `suggested`, 3 adds `source` (default), 4 puts the list in
+    /// `items` and the warnings in `warnings`
+    #[arg(long, value_name = "VERSION")]
+    json_compat: Option<JsonSchema>,
+
+    /// Put the JSON output of `type` and `scope` in `items` and the warnings in `warnings`, with
+    /// the items of any `--json-compat` version
+    #[arg(long)]
+    json_warnings: bool,
+
     #[command(flatten)]
     verbose: Verbosity,
 
+    /// Fail if the command took longer than this many milliseconds. For the latency tests of the
+    /// interactive use (completions, `scope` with the cache)
+    #[arg(long, value_name = "MS", hide = true)]
+    assert_latency_ms: Option<u128>,
+
     /// Command to execute
     #[command(subcommand)]
     command: Option<Command>,
 }
 
-fn default_print(output: &[impl PrintableEntity]) {
-    output
+fn default_print<K>(output: &[Entity<K>]) {
+    output.iter().for_each(|x| println!("{}", x));
+}
+
+/// Prefixes the suggested entry with `*` so that it can be told apart from the rest. Meant for
+/// terminals only, so that scripts get the same output as before
+fn marked_print<K>(output: &[Entity<K>], suggested: Option<&str>) {
+    output.iter().for_each(|x| {
+        let marker = match Some(x.name.as_str()) == suggested {
+            true => "*",
+            false => " ",
+        };
+        println!("{} {}", marker, x)
+    });
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Eq, PartialEq, Default)]
+enum Format {
+    #[default]
+    Plain,
+    Json,
+    Yaml,
+    Tsv,
+    Template,
+    Dot,
+    Mermaid,
+}
+
+/// How the data is printed, resolved from `--format`, `--template` and `--json` of the subcommand
+#[derive(Debug, Clone)]
+struct Output {
+    format: Format,
+    template: Option<Template>,
+}
+
+impl Output {
+    fn from_args(format: Option<Format>, template: Option<Template>) -> anyhow::Result<Self> {
+        let format = match (format, &template) {
+            (None | Some(Format::Template), Some(_)) => Format::Template,
+            (Some(Format::Template), None) => bail!("--format template requires --template"),
+            (Some(format), Some(_)) => {
+                bail!("--template can't be used with --format {:?}", format)
+            }
+            (format, None) => format.unwrap_or_default(),
+        };
+
+        Ok(Self { format, template })
+    }
+
+    /// `--json` of the subcommand wins over `--format`
+    fn with_json(&self, json: bool) -> Self {
+        match json {
+            true => Self {
+                format: Format::Json,
+                template: None,
+            },
+            false => self.clone(),
+        }
+    }
+
+    /// Prints the output in the format, `plain` prints the plain text
+    fn print<T: Serialize + ?Sized>(&self, output: &T, plain: impl FnOnce()) -> anyhow::Result<()> {
+        match self.format {
+            Format::Plain => plain(),
+            Format::Json => println!("{}", to_json(output)?),
+            Format::Yaml => print!("{}", output::to_yaml(output)?),
+            Format::Tsv => print!("{}", output::to_tsv(output)?),
+            Format::Template => print!(
+                "{}",
+                self.template
+                    .as_ref()
+                    .expect("Template format is only set with a template")
+                    .render(output)?
+            ),
+            Format::Dot | Format::Mermaid => {
+                bail!(
+                    "--format {:?} is only supported by `export graph`",
+                    self.format
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// JSON of the output. Objects get the captured warnings (if any) in the `warnings` field, for the
+/// other outputs they are logged
+fn to_json<T: Serialize + ?Sized>(output: &T) -> anyhow::Result<String> {
+    let mut value = serde_json::to_value(output)?;
+    let captured = warnings::take();
+    match value.as_object_mut() {
+        Some(object) if !captured.is_empty() => {
+            object.insert("warnings".to_string(), serde_json::to_value(captured)?);
+        }
+        _ => captured.iter().for_each(|w| warn!("{}", w.message)),
+    }
+
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Version of the JSON shape of the entities. Fields are only added in the new versions
+#[derive(ValueEnum, Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Default)]
+enum JsonSchema {
+    // `{name, description}`
+    #[value(name = "1")]
+    V1,
+    // Adds `suggested` to scopes
+    #[value(name = "2")]
+    V2,
+    // Adds `source`
+    #[value(name = "3")]
+    #[default]
+    V3,
+    // `{items, warnings}` with the version 3 items, so that the warnings are not on stderr. JSON
+    // only, the other formats get the version 3 shape
+    #[value(name = "4")]
+    V4,
+}
+
+/// `--json-compat` and `--json-warnings`
+#[derive(Debug, Clone, Copy)]
+struct JsonShape {
+    schema: JsonSchema,
+    with_warnings: bool,
+}
+
+/// Entities with the warnings, the version 4 JSON shape
+#[derive(Serialize)]
+struct JsonEnvelope<T> {
+    items: T,
+    warnings: Vec<warnings::Warning>,
+}
+
+/// Outcome of `cache update-all` for a repo
+#[derive(Serialize)]
+struct RepoUpdate {
+    repo: PathBuf,
+    #[serde(flatten)]
+    status: RepoUpdateStatus,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum RepoUpdateStatus {
+    Updated,
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+/// Scope (or type) in the JSON output
+#[derive(Serialize)]
+#[serde(bound = "")]
+struct JsonEntity<'a, K> {
+    #[serde(flatten)]
+    entity: &'a Entity<K>,
+    /// Whether this entity matches the staged files best, only if there was anything to match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<Source>,
+    /// Only with `scope --detailed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a ScopeDetails>,
+}
+
+/// Prints the types or the scopes, the structured formats get the shape of the JSON schema
+/// version. `suggested` is None if nothing was matched against the staged files (i.e. `type`
+/// without `--suggest`). Details (if any) are in the same order as the entities
+fn print_entities<K>(
+    out: &Output,
+    output: &[Entity<K>],
+    suggested: Option<Option<&str>>,
+    sources: &HashMap<String, Source>,
+    details: Option<&[ScopeDetails]>,
+    shape: JsonShape,
+    plain: impl FnOnce(),
+) -> anyhow::Result<()> {
+    let entities = |with_source: bool| {
+        output
+            .iter()
+            .enumerate()
+            .map(|(idx, entity)| JsonEntity {
+                entity,
+                suggested: suggested.map(|suggested| Some(entity.name.as_str()) == suggested),
+                source: sources.get(&entity.name).copied().filter(|_| with_source),
+                details: details.map(|details| &details[idx]),
+            })
+            .collect::<Vec<_>>()
+    };
+    let with_warnings =
+        (shape.with_warnings || shape.schema == JsonSchema::V4) && out.format == Format::Json;
+    match shape.schema {
+        JsonSchema::V1 => print_items(out, output, with_warnings, plain),
+        JsonSchema::V2 => print_items(out, entities(false), with_warnings, plain),
+        JsonSchema::V3 | JsonSchema::V4 => print_items(out, entities(true), with_warnings, plain),
+    }
+}
+
+/// Prints the items, in [`JsonEnvelope`] with the warnings if asked to
+fn print_items<T: Serialize>(
+    out: &Output,
+    items: T,
+    with_warnings: bool,
+    plain: impl FnOnce(),
+) -> anyhow::Result<()> {
+    match with_warnings {
+        true => out.print(
+            &JsonEnvelope {
+                items,
+                warnings: warnings::take(),
+            },
+            plain,
+        ),
+        false => out.print(&items, plain),
+    }
+}
+
+fn types_sources(types: &[CommitType], config: &Option<Config>) -> HashMap<String, Source> {
+    types
         .iter()
-        .for_each(|x| println!("{}: {}", x.name(), x.description()));
+        .map(|t| (t.name.clone(), commit_type_source(config, t)))
+        .collect()
+}
+
+/// Config file in the repository, the one edited by the `import` commands
+fn repo_config_path(repo: &git2::Repository) -> anyhow::Result<PathBuf> {
+    Ok(utils::workdir(repo)?.join(DEFAULT_CONFIG_PATH_IN_REPO))
+}
+
+#[cfg(feature = "github")]
+fn fetch_labels(github_repo: &str) -> anyhow::Result<Vec<github::Label>> {
+    Ok(github::fetch_labels(
+        github_repo,
+        github::token_from_env().as_deref(),
+    )?)
 }
 
-fn json_print<T: Serialize>(output: &Vec<T>) -> anyhow::Result<()> {
-    println!("{}", serde_json::to_string(output)?);
-    Ok(())
+#[cfg(not(feature = "github"))]
+fn fetch_labels(_github_repo: &str) -> anyhow::Result<Vec<github::Label>> {
+    bail!("Built without the `github` feature, use --from-file")
 }
 
-fn main() -> anyhow::Result<()> {
+/// Library errors get their own exit codes, everything else exits with 1
+fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|e| e.downcast_ref::<Error>())
+        .map_or(1, Error::exit_code)
+}
+
+fn main() {
+    let res = run();
+    // Captured warnings the output did not carry
+    warnings::release();
+    match res {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(exit_code(&e));
+        }
+    }
+}
+
+/// Runs the command, returns the exit code
+fn run() -> anyhow::Result<i32> {
     let args = Args::parse();
 
     env_logger::Builder::new()
@@ -89,40 +836,222 @@ fn main() -> anyhow::Result<()> {
 
     debug!("Launched with args: {:?}", args);
 
+    let started = Instant::now();
+    let latency_budget = args.assert_latency_ms;
+    let code = run_command(args)?;
+
+    let elapsed = started.elapsed().as_millis();
+    debug!("Finished in {} ms", elapsed);
+    if let Some(budget) = latency_budget.filter(|budget| elapsed > *budget) {
+        bail!(
+            "Took {} ms, more than the latency budget of {} ms",
+            elapsed,
+            budget
+        );
+    }
+    Ok(code)
+}
+
+/// Exit code is not 0 for the checks that failed and the lookups that found nothing
+fn run_command(args: Args) -> anyhow::Result<i32> {
+    let out = Output::from_args(args.format, args.template.clone())?;
+    let json_shape = JsonShape {
+        schema: args.json_compat.unwrap_or_default(),
+        with_warnings: args.json_warnings,
+    };
+
     // Handle no given command. This should be done first so nothing is really validated.
     let Some(command) = args.command else {
         info!("Running in default mode, just printing the types");
-        default_print(&get_default_commit_types());
-        return Ok(());
+        let output = get_default_commit_types();
+        out.print(&output, || default_print(&output))?;
+        return Ok(0);
     };
 
     debug!("Running '{:?}'", command);
 
-    let repo = repo_from_path(&args.repo_path)?;
+    // JSON output carries the warnings instead of stderr
+    if out.format == Format::Json || command.json() {
+        warnings::capture();
+    }
 
-    validate_repo(&repo)?;
+    // Paths are useful outside of a repository too, i.e. for packagers
+    if let Command::Paths { json } = command {
+        let repo = repo_from_path(&args.repo_path).ok();
+        // Broken config should not prevent showing where it is
+        let config = repo
+            .as_ref()
+            .and_then(|repo| Config::load(repo, args.config.clone()).ok().flatten());
+        let config = with_cache_overrides(config, args.cache_path, args.cache_read_only);
+        let output = paths::get_paths(repo.as_ref(), args.config.as_deref(), &config);
+        out.with_json(json)
+            .print(&output, || print!("{}", paths::to_text(&output)))?;
+        return Ok(0);
+    }
 
-    let config = Config::load(&repo, args.config)?;
-    debug!("Loaded config: {:?}", config);
+    // Completions are called by the shell on every keypress: no history is mined and errors are
+    // not shown
+    if let Command::Completions { shell, query } = command {
+        if let Some(shell) = shell {
+            completions::generate(
+                shell,
+                &mut Args::command(),
+                env!("CARGO_BIN_NAME"),
+                &mut std::io::stdout(),
+            );
+        }
+        if let Some(word) = query {
+            let repo = repo_from_path(&args.repo_path).ok();
+            let config = repo
+                .as_ref()
+                .and_then(|repo| Config::load(repo, args.config.clone()).ok().flatten());
+            let config = with_cache_overrides(config, args.cache_path, true);
+            let types = match &repo {
+                Some(repo) => get_commit_types_from_repo_or_default(repo, config.clone())?,
+                None => get_default_commit_types(),
+            };
+            let scopes = repo
+                .and_then(|repo| commit_scopes::try_get_cached_scopes(&repo, &config).ok())
+                .flatten()
+                .or_else(|| config.as_ref().and_then(|c| c.commit_scopes.clone()))
+                .filter(|scopes| !scopes.is_empty())
+                .or_else(|| commit_scopes::fallback_scopes(&config))
+                .unwrap_or_default();
+            completions::complete_header(&word, &types, &scopes)
+                .iter()
+                .for_each(|header| println!("{}", header));
+        }
+        return Ok(0);
+    }
+
+    let engine_builder = |repo_path: &Path| {
+        let mut builder = SuggestionEngine::builder(repo_path)
+            .cache_read_only(args.cache_read_only)
+            .include_untracked(args.include_untracked)
+            .interactive(std::io::stdin().is_terminal());
+        if let Some(path) = &args.config {
+            builder = builder.config_path(path);
+        }
+        if let Some(path) = &args.cache_path {
+            builder = builder.cache_path(path);
+        }
+        if let Some(lang) = &args.lang {
+            builder = builder.lang(lang);
+        }
+        if let Some(rev) = &args.since_ref {
+            builder = builder.since_ref(rev);
+        }
+        builder
+    };
+
+    // Commands on all the cached repos run outside of a repository too. The current one (if any)
+    // only points at the cache
+    let cache_config = || {
+        let repo = repo_from_path(&args.repo_path).ok();
+        let config = repo
+            .as_ref()
+            .and_then(|repo| Config::load(repo, args.config.clone()).ok().flatten());
+        with_cache_overrides(config, args.cache_path.clone(), args.cache_read_only)
+    };
+
+    if let Command::Cache {
+        command: CacheCommand::Prune { json },
+    } = command
+    {
+        let pruned = cache::prune_cache(&cache_config())?;
+        out.with_json(json)
+            .print(&pruned, || match pruned.is_empty() {
+                true => println!("Nothing to prune"),
+                false => pruned
+                    .iter()
+                    .for_each(|repo_path| println!("Pruned {}", repo_path.display())),
+            })?;
+        return Ok(0);
+    }
+
+    // Every cached repo is updated with its own config, the current one does not matter
+    if let Command::Cache {
+        command: CacheCommand::UpdateAll { json },
+    } = command
+    {
+        let config = cache_config();
+        let cache_path = cache::get_cache_path(&config)?;
+
+        let mut output = Vec::new();
+        for repo_path in cache::cached_repos(&config)? {
+            let status = match repo_path.exists() {
+                false => RepoUpdateStatus::Skipped {
+                    reason: "path does not exist".to_string(),
+                },
+                true => match engine_builder(&repo_path)
+                    .cache_path(&cache_path)
+                    .build()
+                    .and_then(|engine| cache::update_cache_for_repo(engine.repo(), engine.config()))
+                {
+                    Ok(_) => RepoUpdateStatus::Updated,
+                    // Through anyhow for the chain of causes
+                    Err(e) => RepoUpdateStatus::Failed {
+                        error: format!("{:#}", anyhow::Error::from(e)),
+                    },
+                },
+            };
+            output.push(RepoUpdate {
+                repo: repo_path,
+                status,
+            });
+        }
+
+        out.with_json(json).print(&output, || {
+            output.iter().for_each(|update| match &update.status {
+                RepoUpdateStatus::Updated => println!("Updated {}", update.repo.display()),
+                RepoUpdateStatus::Skipped { reason } => {
+                    println!("Skipped {}: {}", update.repo.display(), reason)
+                }
+                RepoUpdateStatus::Failed { error } => {
+                    println!("Failed {}: {}", update.repo.display(), error)
+                }
+            })
+        })?;
+        if output
+            .iter()
+            .any(|update| matches!(update.status, RepoUpdateStatus::Failed { .. }))
+        {
+            return Ok(1);
+        }
+        return Ok(0);
+    }
+
+    let config_path = args.config.clone();
+    let engine = engine_builder(&args.repo_path).build()?;
+    let messages = engine.messages();
+    let repo = engine.repo();
+    if command.needs_worktree() {
+        utils::require_worktree(repo)?;
+    }
+    let config = engine.config().clone();
 
     match command {
         Command::Cache { command } => match command {
             CacheCommand::Create => {
                 println!("Creating the cache");
-                let cache_path = cache::create_cache()?;
+                let cache_path = cache::create_cache(&config)?;
                 println!("Cache created at {}", cache_path.to_string_lossy());
                 info!("Populating the cache for the repo after cache creation");
-                cache::update_cache_for_repo(&repo)?
+                cache::update_cache_for_repo(repo, &config)?;
             }
             CacheCommand::Update => {
                 println!("Updating the cache");
-                cache::update_cache_for_repo(&repo)?;
+                cache::update_cache_for_repo(repo, &config)?;
                 println!("Cache updated");
             }
 
+            CacheCommand::UpdateAll { .. } | CacheCommand::Prune { .. } => {
+                unreachable!("Handled before opening the repository")
+            }
+
             CacheCommand::Drop => {
                 println!("Dropping the cache for the repo");
-                if let Some(repo_path) = cache::drop_cache_for_repo(&repo)? {
+                if let Some(repo_path) = cache::drop_cache_for_repo(repo, &config)? {
                     println!("Dropped the cache for repo at '{:?}'", repo_path);
                 } else {
                     println!(
@@ -134,7 +1063,7 @@ fn main() -> anyhow::Result<()> {
 
             CacheCommand::Nuke => {
                 println!("Removing the whole cache");
-                if cache::nuke_cache()? {
+                if cache::nuke_cache(&config)? {
                     println!("Cache is no more. It ceased to be.");
                 } else {
                     println!("Cache does not exist");
@@ -142,36 +1071,642 @@ fn main() -> anyhow::Result<()> {
             }
 
             CacheCommand::Show => {
-                let cache = cache::show_cache()?;
+                let cache = cache::show_cache(&config)?;
                 println!("Cached repos:");
-                for (k, v) in cache.entries {
+                for v in cache.entries.into_values() {
                     println!(
-                        "- {}: timestamp: {}, hash: {}",
-                        k.to_string_lossy(),
+                        "- {}: timestamp: {}, hash: {}, owner: {}",
+                        v.path.to_string_lossy(),
                         v.timestamp,
-                        v.head_commit_hash
+                        v.head_commit_hash,
+                        v.owner
                     );
                 }
             }
         },
-        Command::Type { json } => {
-            let output = commit_types::get_commit_types_from_repo_or_default(config)?;
+        Command::Type {
+            json,
+            suggest: false,
+        } => {
+            let output = engine.types()?;
+            let sources = types_sources(&output, &config);
+            print_entities(
+                &out.with_json(json),
+                &output,
+                None,
+                &sources,
+                None,
+                json_shape,
+                || default_print(&output),
+            )?;
+        }
+        Command::Type {
+            json,
+            suggest: true,
+        } => {
+            let mut output = engine.types()?;
+            let suggested = engine.suggest_type()?;
+            if let Some(suggested) = &suggested {
+                output.retain(|t| t.name != suggested.name);
+                output.insert(0, suggested.clone());
+            }
+
+            let suggested = suggested.as_ref().map(|t| t.name.as_str());
+            let sources = types_sources(&output, &config);
+            print_entities(
+                &out.with_json(json),
+                &output,
+                Some(suggested),
+                &sources,
+                None,
+                json_shape,
+                || match suggested.is_some() && std::io::stdout().is_terminal() {
+                    true => marked_print(&output, suggested),
+                    false => default_print(&output),
+                },
+            )?;
+        }
+        Command::Scope {
+            command: Some(command),
+            ..
+        } => match command {
+            ScopeCommand::Files { name, json } => {
+                let Some(output) = commit_scopes::get_files_for_scope(repo, &config, &name)? else {
+                    bail!("Scope {:?} was not found in the history", name);
+                };
+
+                out.with_json(json).print(&output, || {
+                    output
+                        .iter()
+                        .for_each(|file| println!("{}", sanitize(file)))
+                })?;
+            }
+            ScopeCommand::ForFile { paths, json } => {
+                let output = commit_scopes::get_scopes_for_files(repo, &config, &paths)?;
+
+                out.with_json(json).print(&output, || {
+                    output
+                        .iter()
+                        .for_each(|f| println!("{}: {}", f.file, f.scopes.join(", ")))
+                })?;
+            }
+        },
+        Command::Scope {
+            json,
+            no_suggest,
+            limit,
+            offset,
+            detailed,
+            ..
+        } => {
+            let cached = match no_suggest {
+                true => commit_scopes::try_get_cached_scopes(repo, &config)?,
+                false => None,
+            };
+            let (output, suggested, sources) = match cached {
+                Some(scopes) => {
+                    let sources = commit_scopes::scope_sources(&config, &scopes, Source::Cache);
+                    (scopes, None, sources)
+                }
+                None if no_suggest => {
+                    let mut suggestions = engine.suggest()?.unwrap_or_default();
+                    suggestions.scopes.sort();
+                    (suggestions.scopes, None, suggestions.sources)
+                }
+                None => engine
+                    .suggest()?
+                    .map(|s| (s.scopes, s.suggested, s.sources))
+                    .unwrap_or_default(),
+            };
+            let output: Vec<_> = output
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+
+            let suggested = suggested.as_ref().map(|s| s.name.as_str());
+            let details = match detailed {
+                true => Some(commit_scopes::get_scope_details(repo, &config, &output)?),
+                false => None,
+            };
+            print_entities(
+                &out.with_json(json),
+                &output,
+                Some(suggested),
+                &sources,
+                details.as_deref(),
+                json_shape,
+                || match suggested.is_some() && std::io::stdout().is_terminal() {
+                    true => marked_print(&output, suggested),
+                    false => default_print(&output),
+                },
+            )?;
+        }
+        Command::Suggest { json } => {
+            let Some(suggestion) = engine.suggest_prefix()? else {
+                info!("Nothing to suggest, no type rule matched and the history is empty");
+                return Ok(1);
+            };
+            out.with_json(json).print(&suggestion, || {
+                println!("{}", suggestion.best.prefix.trim_end())
+            })?;
+        }
+        Command::Preflight => {
+            let preflight = engine.preflight()?;
+            println!("{}", to_json(&preflight)?);
+            if !preflight.ok {
+                return Ok(1);
+            }
+        }
+        Command::Changelog {
+            range,
+            title,
+            github_release,
+            scope,
+        } => {
+            let changelog_config = config
+                .as_ref()
+                .map(|c| c.changelog.clone())
+                .unwrap_or_default();
+            let mut template = changelog::get_template(
+                &changelog_config,
+                &changelog::template_dir(repo, config_path.as_deref()),
+            )?;
+            let target = versioning::get_target(&config, scope.as_deref());
+            let tag_pattern = target.tag_pattern();
+            let (range, title) = match &github_release {
+                Some(tag) => {
+                    template = changelog::github_release_template(template);
+                    (
+                        changelog::resolve_github_release_range(repo, tag, tag_pattern.as_deref())?,
+                        tag.clone(),
+                    )
+                }
+                None => (
+                    changelog::resolve_range(repo, range, tag_pattern.as_deref())?,
+                    title,
+                ),
+            };
+            let entries = versioning::filter_entries(
+                repo,
+                changelog::get_entries(repo, &config, range.as_deref())?,
+                &target,
+            )?;
+
+            let release = changelog::Release {
+                version: title,
+                date: utils::time::now().format("%Y-%m-%d").to_string(),
+                range,
+                compare_url: changelog_config.compare_url,
+            };
 
-            match json {
-                true => json_print(&output)?,
-                false => default_print(&output),
+            print!("{}", changelog::render(&template, &release, &entries));
+            if github_release.is_some() {
+                print!("{}", changelog::render_contributors(&entries));
+            }
+        }
+        Command::NextVersion { scope } => {
+            let target = versioning::get_target(&config, scope.as_deref());
+            println!("{}", versioning::get_next_version(repo, &config, &target)?);
+        }
+        Command::Links { range, json } => {
+            let output = links::get_links(repo, &config, range.as_deref())?;
+            out.with_json(json)
+                .print(&output, || print!("{}", links::to_markdown(&output)))?;
+        }
+        Command::Log {
+            range,
+            scope,
+            commit_type,
+            json,
+        } => {
+            let output = commit_log::get_log(
+                repo,
+                &config,
+                range.as_deref(),
+                scope.as_deref(),
+                commit_type.as_deref(),
+            )?;
+
+            out.with_json(json).print(&output, || {
+                output
+                    .iter()
+                    .for_each(|e| println!("{} {}", &e.hash[..7], sanitize(&e.subject)))
+            })?;
+        }
+        Command::Stats {
+            range,
+            heatmap,
+            co_occurrence,
+            json,
+            csv,
+        } => {
+            let out = out.with_json(json);
+            if co_occurrence {
+                let output = stats::get_co_occurrence(repo, &config)?;
+                if csv {
+                    print!("{}", stats::co_occurrence_to_csv(&output));
+                } else {
+                    out.print(&output, || {
+                        output
+                            .pairs()
+                            .iter()
+                            .for_each(|(a, b, shared)| println!("{}, {}: {}", a, b, shared))
+                    })?;
+                }
+            } else if heatmap {
+                let output = stats::get_heatmap(repo, &config, range.as_deref())?;
+                if csv {
+                    print!("{}", stats::heatmap_to_csv(&output));
+                } else {
+                    out.print(&output, || {
+                        output
+                            .iter()
+                            .for_each(|a| println!("{} {}: {}", a.week, a.scope, a.commits))
+                    })?;
+                }
+            } else {
+                let output = stats::get_scope_counts(repo, &config, range.as_deref())?;
+                if csv {
+                    print!("{}", stats::counts_to_csv(&output));
+                } else {
+                    out.print(&output, || {
+                        output.iter().for_each(|c| match c.breaking {
+                            0 => println!("{}: {}", c.scope, c.commits),
+                            breaking => {
+                                println!("{}: {} ({} breaking)", c.scope, c.commits, breaking)
+                            }
+                        })
+                    })?;
+                }
             }
         }
-        Command::Scope { json } => {
-            let output = commit_scopes::try_get_commit_scopes_from_repo(&repo, config)?
-                .unwrap_or_else(Vec::new);
+        Command::Lint { file, head, json } => {
+            let message = match file {
+                _ if head => repo
+                    .head()?
+                    .peel_to_commit()?
+                    .message()
+                    .context("HEAD commit message is not valid UTF-8")?
+                    .to_string(),
+                Some(path) if path.as_os_str() != "-" => strip_comments(
+                    &std::fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read {:?}", path))?,
+                ),
+                _ => strip_comments(&std::io::read_to_string(std::io::stdin())?),
+            };
+            let violations = engine.validate(&message)?;
 
-            match json {
-                true => json_print(&output)?,
-                false => default_print(&output),
+            out.with_json(json).print(&violations, || {
+                violations
+                    .iter()
+                    .for_each(|v| println!("{}", v.localized(&messages)))
+            })?;
+            if !violations.is_empty() {
+                return Ok(1);
+            }
+        }
+        Command::Commit {
+            commit_type,
+            scope,
+            subject,
+            body,
+            breaking,
+            edit,
+            print_only,
+            output_file,
+        } => {
+            let answers = commit_wizard::Answers {
+                commit_type,
+                scope,
+                subject,
+                body,
+                breaking,
+                edit,
+            };
+            let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+            let mut draft = match interactive {
+                true => {
+                    let (scopes, suggested) = match answers.scope {
+                        Some(_) => Default::default(),
+                        None => engine
+                            .suggest()?
+                            .map(|s| (s.scopes, s.suggested))
+                            .unwrap_or_default(),
+                    };
+                    commit_wizard::prompt(
+                        answers,
+                        &engine.types()?,
+                        &scopes,
+                        suggested.as_ref().map(|s| s.name.as_str()),
+                        &messages,
+                    )?
+                }
+                false => commit_wizard::draft_from_answers(answers, &messages)?,
+            };
+            if let Some(replacement) = draft
+                .scope
+                .as_ref()
+                .and_then(|scope| get_deprecated_scopes(&config).remove(scope))
+                .filter(|_| interactive)
+            {
+                commit_wizard::offer_replacement(&mut draft, &replacement, &messages)?;
             }
+
+            let message = draft.message();
+            // Unknown and deprecated scopes are only warned about
+            let (scope_warnings, violations): (Vec<_>, Vec<_>) =
+                engine.validate(&message)?.into_iter().partition(|v| {
+                    matches!(
+                        v,
+                        Violation::UnknownScope { .. } | Violation::DeprecatedScope { .. }
+                    )
+                });
+            if !violations.is_empty() {
+                bail!(
+                    "{}",
+                    messages.format(
+                        Message::InvalidMessage,
+                        &[
+                            ("header", &format!("{:?}", draft.header())),
+                            (
+                                "violations",
+                                &violations
+                                    .iter()
+                                    .map(|v| v.localized(&messages))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        ]
+                    )
+                );
+            }
+            scope_warnings
+                .iter()
+                .for_each(|v| warn!("{}", v.localized(&messages)));
+
+            if print_only {
+                print!("{}", message);
+                return Ok(0);
+            }
+            if let Some(file) = output_file {
+                std::fs::write(&file, &message)
+                    .with_context(|| format!("Failed to write {:?}", file))?;
+                return Ok(0);
+            }
+
+            let id = commit_wizard::create_commit(repo, &message, &messages)?;
+            // Same as the post-commit hook, which is not run for this commit
+            if let Err(e) = cache::append_head_to_cache(repo, &config) {
+                warn!("Failed to add the commit to the cache: {:?}", e);
+            }
+            println!("[{}] {}", &id.to_string()[..7], sanitize(&draft.header()));
+        }
+        Command::External(args) => {
+            let (name, args) = args
+                .split_first()
+                .expect("clap always passes the subcommand name");
+            let code = plugins::run_plugin(name, args, utils::workdir(repo)?, &config)?;
+            if code != 0 {
+                return Ok(code);
+            }
+        }
+        Command::Hook { command } => match command {
+            HookCommand::PostCommit => {
+                cache::append_head_to_cache(repo, &config)?;
+            }
+            HookCommand::PrepareCommitMsg { file, source, .. } => {
+                if let Some(source) = source {
+                    info!("Message comes from {:?}, not touching it", source);
+                    return Ok(0);
+                }
+                let suggestions = engine.suggest()?.unwrap_or_default();
+                match &suggestions.suggested {
+                    Some(scope) => {
+                        if let Some(prefix) = hooks::commit_prefix(repo, &config, &scope.name)? {
+                            hooks::prepend_prefix(&file, &prefix)?;
+                        }
+                    }
+                    None => info!("No scope suggested for the staged files"),
+                }
+
+                let block_config = config
+                    .as_ref()
+                    .map(|c| c.hooks.comment_block.clone())
+                    .unwrap_or_default();
+                let comment_char = hooks::comment_char(repo)?;
+                let block = hooks::comment_block(
+                    &engine.types()?,
+                    &suggestions.scopes,
+                    &block_config,
+                    &comment_char,
+                )?;
+                hooks::append_comment_block(&file, &block, &comment_char)?;
+            }
+            HookCommand::Install { commit_msg, force } => {
+                let mut to_install = vec![hooks::Hook::PrepareCommitMsg];
+                if commit_msg {
+                    to_install.push(hooks::Hook::CommitMsg);
+                }
+                let program = env!("CARGO_BIN_NAME");
+                for path in hooks::install_hooks(repo, &to_install, program, force)? {
+                    println!("Installed {}", path.to_string_lossy());
+                }
+            }
+            HookCommand::Uninstall => {
+                let removed = hooks::uninstall_hooks(repo)?;
+                if removed.is_empty() {
+                    println!("No hooks installed by this program");
+                }
+                for path in removed {
+                    println!("Removed {}", path.to_string_lossy());
+                }
+            }
+        },
+        Command::Paths { .. } | Command::Completions { .. } => {
+            unreachable!("Handled before the repository is opened")
+        }
+        Command::Config { command } => match command {
+            ConfigCommand::Import {
+                command:
+                    ConfigImportCommand::GithubLabels {
+                        repo: github_repo,
+                        from_file,
+                        prefix,
+                        write,
+                    },
+            } => {
+                let labels = match (from_file, github_repo) {
+                    (Some(path), _) => github::read_labels(&path)?,
+                    (None, Some(github_repo)) => fetch_labels(&github_repo)?,
+                    (None, None) => unreachable!("clap requires one of them"),
+                };
+                let scopes = github::labels_to_scopes(&labels, &prefix, &config)?;
+
+                if write {
+                    let path = config_path.map_or_else(|| repo_config_path(repo), Ok)?;
+                    let added = add_scopes_to_file(&path, &scopes)?;
+                    println!(
+                        "Added {} scope(s) to {}",
+                        added.len(),
+                        path.to_string_lossy()
+                    );
+                    added.iter().for_each(|scope| println!("{}", scope));
+                } else {
+                    print!("{}", scopes_to_toml(&scopes));
+                }
+            }
+            ConfigCommand::Get { key } => {
+                let Some(value) = config.clone().unwrap_or_default().get(&key) else {
+                    info!("{:?} is not set", key);
+                    return Ok(1);
+                };
+
+                // Same notation as in the config file, except for the strings
+                out.print(&value, || match &value {
+                    serde_json::Value::String(s) => println!("{}", s),
+                    serde_json::Value::Object(_) => print!(
+                        "{}",
+                        toml::to_string(&value).expect("JSON without nulls is valid TOML")
+                    ),
+                    value => println!(
+                        "{}",
+                        toml::Value::try_from(value).expect("JSON without nulls is valid TOML")
+                    ),
+                })?;
+            }
+            ConfigCommand::Set { key, value, global } => {
+                let path = match global {
+                    true => Config::get_global_config_path()
+                        .context("Unable to determine the global config directory")?,
+                    false => config_path.map_or_else(|| repo_config_path(repo), Ok)?,
+                };
+                set_value_in_file(&path, &key, &value)?;
+            }
+            ConfigCommand::Undo { global } => {
+                let path = match global {
+                    true => Config::get_global_config_path()
+                        .context("Unable to determine the global config directory")?,
+                    false => config_path.map_or_else(|| repo_config_path(repo), Ok)?,
+                };
+                match undo_config_change(&path)? {
+                    ConfigUndo::Restored(backup) => println!(
+                        "Restored {} from {}",
+                        path.to_string_lossy(),
+                        backup.to_string_lossy()
+                    ),
+                    ConfigUndo::Removed => println!(
+                        "Removed {}, it did not exist before",
+                        path.to_string_lossy()
+                    ),
+                    ConfigUndo::NothingToUndo => {
+                        info!("No backups of {:?}", path);
+                        return Ok(1);
+                    }
+                }
+            }
+        },
+        Command::Audit {
+            command: AuditCommand::Scopes { json },
+        } => {
+            let output = audit::audit_scopes(repo, &config)?;
+
+            out.with_json(json)
+                .print(&output, || print!("{}", audit::to_text(&output)))?;
+        }
+        Command::Export {
+            command: ExportCommand::Graph { depth, limit },
+        } => {
+            let scope_map = commit_scopes::get_scope_map(repo, &config)?.unwrap_or_default();
+            let edges = graph::scope_graph(&scope_map, depth, limit);
+            match out.format {
+                Format::Plain | Format::Dot => print!("{}", graph::to_dot(&edges)),
+                Format::Mermaid => print!("{}", graph::to_mermaid(&edges)),
+                _ => out.print(&edges, || unreachable!("Plain is handled above"))?,
+            }
+        }
+        Command::Export {
+            command: ExportCommand::Bundle { file },
+        } => {
+            bundle::write_bundle(&bundle::create_bundle(repo, &config)?, &file)?;
+            println!("Bundle written to {}", file.to_string_lossy());
+        }
+        Command::Export {
+            command: command @ (ExportCommand::Commitlint { .. } | ExportCommand::Cocogitto { .. }),
+        } => {
+            let (types, scopes) = (engine.types()?, engine.scopes()?);
+            let (content, file) = match command {
+                ExportCommand::Commitlint { file } => (
+                    serde_json::to_string_pretty(&export::commitlint_config(
+                        &types, &scopes, &config,
+                    ))? + "\n",
+                    file,
+                ),
+                ExportCommand::Cocogitto { file } => {
+                    (export::cocogitto_config(&types, &scopes, &config)?, file)
+                }
+                ExportCommand::Bundle { .. } | ExportCommand::Graph { .. } => {
+                    unreachable!("Handled above")
+                }
+            };
+            match file {
+                Some(file) => {
+                    std::fs::write(&file, content)
+                        .with_context(|| format!("Failed to write {:?}", file))?;
+                    println!("Config written to {}", file.to_string_lossy());
+                }
+                None => print!("{}", content),
+            }
+        }
+        Command::Import {
+            command: ImportCommand::Bundle { file },
+        } => {
+            let path = config_path.map_or_else(|| repo_config_path(repo), Ok)?;
+            let summary = bundle::import_bundle(repo, &config, bundle::read_bundle(&file)?, &path)?;
+            println!(
+                "Added {} scope(s) and {} type(s) to {}",
+                summary.added_scopes.len(),
+                summary.added_types.len(),
+                path.to_string_lossy()
+            );
+            match summary.cache_seeded {
+                true => println!("Cache seeded with the bundle's scopes"),
+                false => println!(
+                    "Cache not seeded: the repository is already cached or does not have the bundle's HEAD"
+                ),
+            }
+        }
+        Command::Owners { command } => match command {
+            OwnersCommand::Suggest => {
+                let scope_map = commit_scopes::get_scope_map(repo, &config)?.unwrap_or_default();
+                let paths = owners::get_paths_per_scope(&scope_map);
+                print!("{}", owners::render_codeowners(&paths, &config));
+            }
+        },
+        Command::Reviewers { scope, range, json } => {
+            let scopes = if !scope.is_empty() {
+                scope
+            } else if let Some(range) = range {
+                reviewers::get_scopes_in_range(repo, &config, &range)?
+            } else {
+                info!("No scopes given, using the suggested one");
+                engine
+                    .suggest()?
+                    .and_then(|s| s.suggested)
+                    .map(|s| vec![s.name])
+                    .unwrap_or_default()
+            };
+            debug!("Looking up reviewers for scopes {:?}", scopes);
+
+            let output = reviewers::get_reviewers_for_scopes(&config, &scopes);
+
+            out.with_json(json).print(&output, || {
+                reviewers::flatten_reviewers(&output)
+                    .iter()
+                    .for_each(|r| println!("{}", r))
+            })?;
         }
     };
 
-    Ok(())
+    Ok(0)
 }
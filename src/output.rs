@@ -0,0 +1,334 @@
+//! Output formats besides the plain text and JSON (`--format`), for pickers like fzf or rofi.
+//!
+//! Everything is rendered from the JSON shape of the output, so that the field names are the same
+//! across the formats. Lists become one item per line (TSV, templates); anything else is a single
+//! item. Strings are sanitized in the line-based formats, control characters would break them.
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::sanitize::sanitize;
+
+/// Items of the output: the elements of a list or the value itself
+fn items(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items,
+        value => vec![value],
+    }
+}
+
+/// Value as a TSV cell or a template field. Lists are joined with commas
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => sanitize(s).into_owned(),
+        Value::Array(items) => items.iter().map(cell).join(","),
+        Value::Bool(_) | Value::Number(_) | Value::Object(_) => value.to_string(),
+    }
+}
+
+/// One line per item with the fields separated by tabs, in the order of the JSON output. No header
+pub fn to_tsv<T: Serialize + ?Sized>(output: &T) -> Result<String> {
+    Ok(items(serde_json::to_value(output)?)
+        .iter()
+        .map(|item| match item {
+            Value::Object(fields) => fields.values().map(cell).join("\t"),
+            item => cell(item),
+        })
+        .map(|line| line + "\n")
+        .collect())
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    match plain {
+        true => key.to_string(),
+        false => Value::from(key).to_string(),
+    }
+}
+
+/// Renders on a single line: scalars (strings are double-quoted, JSON escapes are valid YAML) and
+/// empty collections
+fn yaml_inline(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) if items.is_empty() => Some("[]".to_string()),
+        Value::Object(fields) if fields.is_empty() => Some("{}".to_string()),
+        Value::Array(_) | Value::Object(_) => None,
+        scalar => Some(scalar.to_string()),
+    }
+}
+
+fn yaml_lines(value: &Value) -> Vec<String> {
+    if let Some(inline) = yaml_inline(value) {
+        return vec![inline];
+    }
+
+    let mut res = Vec::new();
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                let lines = yaml_lines(item);
+                res.push(format!("- {}", lines[0]));
+                res.extend(lines[1..].iter().map(|line| format!("  {}", line)));
+            }
+        }
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                match yaml_inline(value) {
+                    Some(inline) => res.push(format!("{}: {}", yaml_key(key), inline)),
+                    None => {
+                        res.push(format!("{}:", yaml_key(key)));
+                        res.extend(
+                            yaml_lines(value)
+                                .into_iter()
+                                .map(|line| format!("  {}", line)),
+                        );
+                    }
+                }
+            }
+        }
+        _ => unreachable!("Scalars are inline"),
+    }
+
+    res
+}
+
+/// YAML document with the same structure as the JSON output
+pub fn to_yaml<T: Serialize + ?Sized>(output: &T) -> Result<String> {
+    Ok(yaml_lines(&serde_json::to_value(output)?)
+        .into_iter()
+        .map(|line| line + "\n")
+        .collect())
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Part {
+    Literal(String),
+    /// Empty name is the item itself, for lists of strings
+    Field(String),
+}
+
+/// `--template` mini-language: `{field}` is replaced with the field of the item, `{}` with the item
+/// itself. `{{` and `}}` are literal braces; `\t`, `\n` and `\\` are escapes, so that the template
+/// can be passed in shell quotes as is
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed || name.contains('{') {
+                        return Err(Error::InvalidTemplate(format!(
+                            "Unclosed {{ in the template {:?}",
+                            template
+                        )));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name.trim().to_string()));
+                }
+                '}' => {
+                    return Err(Error::InvalidTemplate(format!(
+                        "Unmatched }} in the template {:?}, use }}}} for a literal one",
+                        template
+                    )))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    fn render_item(&self, item: &Value) -> Result<String> {
+        let mut res = String::new();
+        for part in &self.parts {
+            match (part, item) {
+                (Part::Literal(s), _) => res.push_str(s),
+                (Part::Field(name), item) if name.is_empty() => res.push_str(&cell(item)),
+                (Part::Field(name), Value::Object(fields)) => match fields.get(name) {
+                    Some(value) => res.push_str(&cell(value)),
+                    None => {
+                        return Err(Error::InvalidTemplate(format!(
+                            "Unknown field {:?} in the template, available: {}",
+                            name,
+                            fields.keys().join(", ")
+                        )))
+                    }
+                },
+                (Part::Field(name), _) => {
+                    return Err(Error::InvalidTemplate(format!(
+                        "Unknown field {:?} in the template, the output has no fields, use {{}}",
+                        name
+                    )))
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// One line per item
+    pub fn render<T: Serialize + ?Sized>(&self, output: &T) -> Result<String> {
+        items(serde_json::to_value(output)?)
+            .iter()
+            .map(|item| self.render_item(item).map(|line| line + "\n"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit_types::CommitType;
+    use indoc::indoc;
+    use rstest::rstest;
+
+    fn mk_types() -> Vec<CommitType> {
+        vec![
+            CommitType::with_description("feat", "A new feature"),
+            CommitType::with_description("fix", "A bug fix\twith a tab"),
+        ]
+    }
+
+    #[rstest]
+    #[case::fields(
+        "{name}\\t{description}",
+        "feat\tA new feature\nfix\tA bug fixwith a tab\n"
+    )]
+    #[case::braces("{{{name}}}", "{feat}\n{fix}\n")]
+    #[case::spaces("{ name }: ", "feat: \nfix: \n")]
+    fn render(#[case] template: &str, #[case] expected: &str) {
+        let template: Template = template.parse().unwrap();
+
+        assert_eq!(template.render(&mk_types()).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::unclosed("{name")]
+    #[case::unmatched("name}")]
+    #[case::nested("{na{me}")]
+    #[case::unclosed_last("{name} {description")]
+    fn invalid_template(#[case] template: &str) {
+        assert!(template.parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn template_fields() {
+        let template: Template = "{scope}".parse().unwrap();
+        let err = template.render(&mk_types()).unwrap_err();
+
+        assert!(
+            err.to_string().contains("available: name, description"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            "- {}"
+                .parse::<Template>()
+                .unwrap()
+                .render(&["a", "b"])
+                .unwrap(),
+            "- a\n- b\n"
+        );
+    }
+
+    #[test]
+    fn tsv() {
+        assert_eq!(
+            to_tsv(&mk_types()).unwrap(),
+            "feat\tA new feature\nfix\tA bug fixwith a tab\n"
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        file: &'static str,
+        scopes: Vec<&'static str>,
+        extra: Vec<()>,
+        #[serde(rename = "odd key")]
+        odd_key: Option<u32>,
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(
+            to_yaml(&mk_types()).unwrap(),
+            indoc! {r#"
+                - name: "feat"
+                  description: "A new feature"
+                - name: "fix"
+                  description: "A bug fix\twith a tab"
+            "#}
+        );
+        assert_eq!(
+            to_yaml(&Nested {
+                file: "src/main.rs",
+                scopes: vec!["cli", "core"],
+                extra: vec![],
+                odd_key: None,
+            })
+            .unwrap(),
+            indoc! {r#"
+                file: "src/main.rs"
+                scopes:
+                  - "cli"
+                  - "core"
+                extra: []
+                "odd key": null
+            "#}
+        );
+        assert_eq!(to_yaml(&Vec::<String>::new()).unwrap(), "[]\n");
+    }
+}
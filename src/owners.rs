@@ -0,0 +1,126 @@
+//! CODEOWNERS skeleton generation from the scope -> files map
+
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::commit_scopes::commit::ChangedFiles;
+use crate::commit_scopes::CommitScope;
+use crate::config::Config;
+
+/// Parent directory of the file in CODEOWNERS notation. Files in the root have no directory.
+fn parent_dir(file: &str) -> Option<String> {
+    Path::new(file)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| format!("{}/", p.to_string_lossy()))
+}
+
+/// Groups the files of each scope into CODEOWNERS paths.
+///
+/// If all the files ever changed in a directory belong to the same scope, the whole directory is
+/// suggested, otherwise the individual files are.
+pub fn get_paths_per_scope(
+    scope_map: &HashMap<CommitScope, ChangedFiles>,
+) -> BTreeMap<String, Vec<String>> {
+    // directory -> scopes that touched files in it
+    let mut dir_scopes: HashMap<String, HashSet<&str>> = HashMap::new();
+    for (scope, files) in scope_map {
+        for dir in files.iter().filter_map(|f| parent_dir(f)) {
+            dir_scopes.entry(dir).or_default().insert(&scope.name);
+        }
+    }
+
+    scope_map
+        .iter()
+        .map(|(scope, files)| {
+            let paths = files
+                .iter()
+                .map(|file| match parent_dir(file) {
+                    Some(dir) if dir_scopes[&dir].len() == 1 => dir,
+                    _ => file.clone(),
+                })
+                .unique()
+                .sorted()
+                .collect();
+            (scope.name.clone(), paths)
+        })
+        .collect()
+}
+
+/// Renders the CODEOWNERS skeleton. Owners come from the `reviewers` config; paths of scopes
+/// without reviewers are commented out so that the skeleton is safe to commit as is.
+pub fn render_codeowners(
+    paths_per_scope: &BTreeMap<String, Vec<String>>,
+    config: &Option<Config>,
+) -> String {
+    let reviewers = config.as_ref().and_then(|c| c.reviewers.as_ref());
+
+    paths_per_scope
+        .iter()
+        .map(|(scope, paths)| {
+            let owners = reviewers.and_then(|r| r.get(scope)).map(|r| r.join(" "));
+            let mut lines = vec![format!("# {}", scope)];
+            lines.extend(paths.iter().map(|path| match &owners {
+                Some(owners) => format!("/{} {}", path, owners),
+                None => format!("# /{}", path),
+            }));
+            lines.join("\n") + "\n"
+        })
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn mk_scope_map() -> HashMap<CommitScope, ChangedFiles> {
+        HashMap::from([
+            (
+                CommitScope::new("cache".to_string()),
+                HashSet::from([
+                    "src/cache/mod.rs".to_string(),
+                    "src/cache/lock.rs".to_string(),
+                    "src/main.rs".to_string(),
+                ]),
+            ),
+            (
+                CommitScope::new("docs".to_string()),
+                HashSet::from(["README.md".to_string(), "src/lib.rs".to_string()]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn groups_paths() {
+        let res = get_paths_per_scope(&mk_scope_map());
+
+        assert_eq!(res["cache"], vec!["src/cache/", "src/main.rs"]);
+        assert_eq!(res["docs"], vec!["README.md", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn renders() {
+        let config = Config::from_str(indoc! {r#"
+            [reviewers]
+            cache = ["@alice", "@bob"]
+            "#})
+        .unwrap();
+
+        let res = render_codeowners(&get_paths_per_scope(&mk_scope_map()), &Some(config));
+
+        assert_eq!(
+            res,
+            indoc! {"
+                # cache
+                /src/cache/ @alice @bob
+                /src/main.rs @alice @bob
+
+                # docs
+                # /README.md
+                # /src/lib.rs
+            "}
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Filesystem locations used by the program, for packagers and for debugging XDG overrides
+
+use git2::Repository;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::cache::get_cache_path;
+use crate::config::{Config, DEFAULT_CONFIG_PATH_IN_REPO};
+
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct UsedPath {
+    pub name: String,
+    /// None if the path cannot be resolved on this system (i.e. no home directory)
+    pub path: Option<PathBuf>,
+    pub exists: bool,
+}
+
+impl UsedPath {
+    fn new(name: &str, path: Option<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            exists: path.as_ref().is_some_and(|p| p.exists()),
+            path,
+        }
+    }
+}
+
+/// Lists the paths in the order they are looked up.
+///
+/// If the config path is given explicitly, it's the only config file that's read. Cache path
+/// comes from the config, if it's set there.
+pub fn get_paths(
+    repo: Option<&Repository>,
+    config_path: Option<&Path>,
+    config: &Option<Config>,
+) -> Vec<UsedPath> {
+    let config_paths = match config_path {
+        Some(path) => vec![UsedPath::new("config", Some(path.to_path_buf()))],
+        None => vec![
+            UsedPath::new(
+                "repo config",
+                repo.and_then(|r| r.workdir())
+                    .map(|w| w.join(DEFAULT_CONFIG_PATH_IN_REPO)),
+            ),
+            UsedPath::new("global config", Config::get_global_config_path()),
+        ],
+    };
+
+    config_paths
+        .into_iter()
+        .chain(std::iter::once(UsedPath::new(
+            "cache",
+            get_cache_path(config).ok(),
+        )))
+        .collect()
+}
+
+/// Renders the paths as "name: path", marking the ones that do not exist
+pub fn to_text(paths: &[UsedPath]) -> String {
+    paths
+        .iter()
+        .map(|p| match (&p.path, p.exists) {
+            (Some(path), true) => format!("{}: {}\n", p.name, path.to_string_lossy()),
+            (Some(path), false) => format!("{}: {} (missing)\n", p.name, path.to_string_lossy()),
+            (None, _) => format!("{}: unavailable\n", p.name),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{setup_config_file_in_path, setup_repo_with_commits};
+    use testdir::testdir;
+
+    #[test]
+    fn repo_config() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+        let config_path = setup_config_file_in_path(&dir, "");
+
+        let res = get_paths(Some(&repo), None, &None);
+
+        assert_eq!(
+            res[0],
+            UsedPath {
+                name: "repo config".to_string(),
+                path: Some(config_path),
+                exists: true
+            }
+        );
+        assert_eq!(
+            res.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            vec!["repo config", "global config", "cache"]
+        );
+    }
+
+    #[test]
+    fn explicit_config() {
+        let dir = testdir!();
+        let config_path = dir.join("nonexistent.toml");
+
+        let cache_path = dir.join("cache.bin");
+
+        let config = Config::from_str(&format!(
+            "[cache]\npath = {:?}",
+            cache_path.to_string_lossy()
+        ))
+        .unwrap();
+
+        let res = get_paths(None, Some(&config_path), &Some(config));
+
+        assert_eq!(
+            res.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            vec!["config", "cache"]
+        );
+        assert_eq!(
+            to_text(&res),
+            format!(
+                "config: {} (missing)\ncache: {} (missing)\n",
+                config_path.to_string_lossy(),
+                cache_path.to_string_lossy()
+            )
+        );
+    }
+}
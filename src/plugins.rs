@@ -0,0 +1,78 @@
+//! External executable plugins.
+//!
+//! Any unknown subcommand `foo` is dispatched to `conventional-commit-helper-foo` found on PATH.
+//! The plugin receives a JSON [`PluginContext`] on stdin, its stdout and stderr are passed through
+//! as is. Plugins that produce machine-readable output are expected to print JSON.
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde::Serialize;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use conventional_commit_helper::config::Config;
+
+pub const PLUGIN_PREFIX: &str = concat!(env!("CARGO_PKG_NAME"), "-");
+
+/// What the plugin gets on stdin
+#[derive(Debug, Serialize)]
+pub struct PluginContext<'a> {
+    /// Version of the calling binary
+    pub version: &'a str,
+    /// Working directory of the repository
+    pub repo_path: &'a Path,
+    /// Arguments after the plugin name
+    pub args: &'a [String],
+    /// Loaded configuration, if any
+    pub config: &'a Option<Config>,
+}
+
+/// Runs the plugin, returning its exit code
+pub fn run_plugin(
+    name: &str,
+    args: &[String],
+    repo_path: &Path,
+    config: &Option<Config>,
+) -> Result<i32> {
+    let executable = format!("{}{}", PLUGIN_PREFIX, name);
+    debug!("Running plugin {:?} with args {:?}", executable, args);
+
+    let mut child = match Command::new(&executable)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => bail!(
+            "Unknown command {:?}: no {:?} plugin found on PATH",
+            name,
+            executable
+        ),
+        Err(e) => return Err(e).with_context(|| format!("Failed to run {:?}", executable)),
+    };
+
+    let context = PluginContext {
+        version: env!("CARGO_PKG_VERSION"),
+        repo_path,
+        args,
+        config,
+    };
+    {
+        // Scoped so that stdin is closed before waiting on the plugin
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        if let Err(e) = stdin.write_all(serde_json::to_string(&context)?.as_bytes()) {
+            // Plugin does not have to read the context
+            if e.kind() != ErrorKind::BrokenPipe {
+                return Err(e).context("Failed to pass the context to the plugin");
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {:?}", executable))?;
+
+    // Killed by a signal => no code
+    Ok(status.code().unwrap_or(1))
+}
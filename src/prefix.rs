@@ -0,0 +1,313 @@
+//! Ready-to-use `type(scope): ` prefix for the staged files (`suggest` subcommand).
+//!
+//! The scope is the one matching the staged files best. The type comes from the rules of
+//! `type --suggest` if they match, otherwise it's the type used the most with the scope in the
+//! recent history (see [`TypeHistory`]). Every prefix gets a confidence between 0 and 1: the share
+//! of the staged files the scope changed before, times the share of the type among the commits of
+//! the scope.
+use git2::Repository;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::commit_message::{parse_subject, pseudo_types_config, Header};
+use crate::commit_scopes::{get_scope_details, CommitScope, ScopeDetails, ScopeSuggestions};
+use crate::commit_types::CommitType;
+use crate::config::Config;
+use crate::error::Result;
+use crate::utils::head_commit;
+
+/// How many recent commits are looked at to pick the type
+const HISTORY_DEPTH: usize = 500;
+
+/// Confidence in a scope that matches the staged files only by the directories (i.e. new files)
+const DIRECTORY_MATCH_CONFIDENCE: f64 = 0.5;
+
+/// How many other prefixes are listed besides the best one
+const MAX_ALTERNATIVES: usize = 4;
+
+/// Headers of the recent commits, to pick the type for a scope
+pub struct TypeHistory {
+    headers: Vec<Header>,
+}
+
+impl TypeHistory {
+    /// Reads the last [`HISTORY_DEPTH`] commits from HEAD. Empty if there are no commits yet
+    pub fn load(repo: &Repository, config: &Option<Config>) -> Result<Self> {
+        let pseudo_types = pseudo_types_config(config);
+        let mut revwalk = repo.revwalk()?;
+        if let Some(head) = head_commit(repo)? {
+            revwalk.push(head)?;
+        }
+
+        let mut headers = Vec::new();
+        for oid in revwalk.take(HISTORY_DEPTH) {
+            let commit = repo.find_commit(oid?)?;
+            if let Some(header) = commit
+                .summary()
+                .map(|summary| parse_subject(summary, &pseudo_types))
+                .transpose()?
+                .flatten()
+            {
+                headers.push(header);
+            }
+        }
+
+        Ok(Self { headers })
+    }
+
+    /// The most used type among the types with its share. Ties go to the alphabetically first type
+    fn most_used<'a>(types: impl Iterator<Item = &'a str>) -> Option<(String, f64)> {
+        let counts = types.counts();
+        let total = counts.values().sum::<usize>();
+        counts
+            .into_iter()
+            .max_by(|(a_type, a), (b_type, b)| a.cmp(b).then(b_type.cmp(a_type)))
+            .map(|(commit_type, count)| (commit_type.to_string(), count as f64 / total as f64))
+    }
+
+    /// Share of the type among all the commits
+    fn share(&self, commit_type: &str) -> f64 {
+        match self.headers.len() {
+            0 => 0.0,
+            total => {
+                let count = self
+                    .headers
+                    .iter()
+                    .filter(|h| h.commit_type == commit_type)
+                    .count();
+                count as f64 / total as f64
+            }
+        }
+    }
+
+    /// Type for the scope with its share. The type used the most with the scope, if the scope was
+    /// not used recently -- `suggest.default_type` or the type used the most at all. None if
+    /// there's neither
+    pub fn type_for_scope(
+        &self,
+        scope: Option<&str>,
+        config: &Option<Config>,
+    ) -> Option<(String, f64)> {
+        let with_scope = scope.and_then(|scope| {
+            Self::most_used(
+                self.headers
+                    .iter()
+                    .filter(|h| h.scope.as_deref() == Some(scope))
+                    .map(|h| h.commit_type.as_str()),
+            )
+        });
+        let default_type = || {
+            config
+                .as_ref()
+                .and_then(|c| c.suggest.default_type.clone())
+                .map(|commit_type| {
+                    let share = self.share(&commit_type);
+                    (commit_type, share)
+                })
+        };
+
+        with_scope
+            .or_else(default_type)
+            .or_else(|| Self::most_used(self.headers.iter().map(|h| h.commit_type.as_str())))
+    }
+}
+
+/// `type(scope): ` or `type: ` if there's no scope
+pub fn format_prefix(commit_type: &str, scope: Option<&str>) -> String {
+    match scope {
+        Some(scope) => format!("{}({}): ", commit_type, scope),
+        None => format!("{}: ", commit_type),
+    }
+}
+
+/// Prefix with the parts it was made of
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Prefix {
+    pub prefix: String,
+    #[serde(rename = "type")]
+    pub commit_type: String,
+    pub scope: Option<String>,
+    /// Between 0 and 1, rounded to two digits
+    pub confidence: f64,
+}
+
+/// Best prefix for the staged files and the other likely ones
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrefixSuggestion {
+    #[serde(flatten)]
+    pub best: Prefix,
+    /// Most confident first
+    pub alternatives: Vec<Prefix>,
+}
+
+/// Share of the staged files the scope changed before (or that match its `scope_paths`, if more),
+/// a match by the directories only is [`DIRECTORY_MATCH_CONFIDENCE`]
+fn scope_confidence(details: &ScopeDetails) -> f64 {
+    let metrics = &details.metrics;
+    match (
+        metrics.overlap.max(details.declared_files),
+        metrics.shared_dirs,
+    ) {
+        _ if details.staged_files == 0 => 0.0,
+        (0, 0) => 0.0,
+        (0, _) => DIRECTORY_MATCH_CONFIDENCE,
+        (overlap, _) => overlap as f64 / details.staged_files as f64,
+    }
+}
+
+/// Combines the scope suggestions with the type inferred from the staged files. None if there's
+/// no type to put in the prefix (no rule matched and the history is empty)
+pub fn suggest_prefix(
+    repo: &Repository,
+    config: &Option<Config>,
+    scopes: Option<ScopeSuggestions>,
+    suggested_type: Option<CommitType>,
+) -> Result<Option<PrefixSuggestion>> {
+    let history = TypeHistory::load(repo, config)?;
+    let mk_prefix = |scope: Option<&str>, scope_confidence: f64| {
+        let (commit_type, type_confidence) = match &suggested_type {
+            Some(commit_type) => (commit_type.name.clone(), 1.0),
+            None => history.type_for_scope(scope, config)?,
+        };
+        Some(Prefix {
+            prefix: format_prefix(&commit_type, scope),
+            commit_type,
+            scope: scope.map(String::from),
+            confidence: (scope_confidence * type_confidence * 100.0).round() / 100.0,
+        })
+    };
+
+    let ScopeSuggestions {
+        scopes, suggested, ..
+    } = scopes.unwrap_or_default();
+    let candidates: Vec<CommitScope> = suggested.iter().chain(&scopes).cloned().unique().collect();
+    let details = get_scope_details(repo, config, &candidates)?;
+    let mut prefixes = candidates
+        .iter()
+        .zip(&details)
+        .filter(|(scope, details)| {
+            Some(*scope) == suggested.as_ref() || scope_confidence(details) > 0.0
+        })
+        .filter_map(|(scope, details)| mk_prefix(Some(&scope.name), scope_confidence(details)))
+        .collect_vec();
+    // The suggested scope stays first even if another one looks as likely
+    let best = match suggested {
+        Some(_) if !prefixes.is_empty() => prefixes.remove(0),
+        // Only the type then, the confidence is the one of the type
+        _ => match mk_prefix(None, 1.0) {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        },
+    };
+    prefixes.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    prefixes.truncate(MAX_ALTERNATIVES);
+
+    Ok(Some(PrefixSuggestion {
+        best,
+        alternatives: prefixes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit_scopes::try_get_scope_suggestions;
+    use crate::test_utils::{setup_repo_with_commits, setup_repo_with_commits_and_files};
+    use std::fs;
+    use std::path::Path;
+    use testdir::testdir;
+
+    fn stage(repo: &Repository, file: &str) {
+        let path = repo.workdir().unwrap().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "changed").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+    }
+
+    #[test]
+    fn type_for_scope() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "fix(foo): a",
+                "fix(foo): b",
+                "feat(foo): c",
+                "feat(bar): d",
+            ],
+        );
+        let history = TypeHistory::load(&repo, &None).unwrap();
+
+        assert_eq!(
+            history.type_for_scope(Some("foo"), &None),
+            Some(("fix".to_string(), 2.0 / 3.0))
+        );
+        // Not in the history, the most used type overall. Tie goes to "feat"
+        assert_eq!(
+            history.type_for_scope(Some("baz"), &None),
+            Some(("feat".to_string(), 0.5))
+        );
+        let config = Some(Config::from_str("[suggest]\ndefault_type = \"chore\"\n").unwrap());
+        assert_eq!(
+            history.type_for_scope(None, &config),
+            Some(("chore".to_string(), 0.0))
+        );
+    }
+
+    #[test]
+    fn prefix_from_history() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &[
+                "init",
+                "fix(foo): a",
+                "feat(foo): b",
+                "fix(foo): c",
+                "feat(bar): d",
+            ],
+            &["init", "one", "one", "two", "two"],
+        );
+        stage(&repo, "one");
+        stage(&repo, "two");
+        let scopes = try_get_scope_suggestions(&repo, None).unwrap();
+
+        let res = suggest_prefix(&repo, &None, scopes, None).unwrap().unwrap();
+
+        assert_eq!(res.best.prefix, "fix(foo): ");
+        assert_eq!(res.best.confidence, 0.67);
+        assert_eq!(
+            res.alternatives
+                .iter()
+                .map(|p| (p.prefix.as_str(), p.confidence))
+                .collect_vec(),
+            vec![("feat(bar): ", 0.5)]
+        );
+    }
+
+    #[test]
+    fn prefix_from_type_rules() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat(foo): a"]);
+        stage(&repo, "README.md");
+
+        let res = suggest_prefix(&repo, &None, None, Some(CommitType::new("docs")))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(res.best.prefix, "docs: ");
+        assert_eq!(res.best.confidence, 1.0);
+        assert!(res.alternatives.is_empty());
+    }
+
+    #[test]
+    fn nothing_to_suggest() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init"]);
+
+        assert_eq!(suggest_prefix(&repo, &None, None, None).unwrap(), None);
+    }
+}
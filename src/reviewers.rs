@@ -0,0 +1,115 @@
+//! Mapping of scopes to the people who should review the changes in them
+
+use git2::Repository;
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::commit_message::pseudo_types_config;
+use crate::commit_scopes::commit::get_scope_from_summary;
+use crate::config::Config;
+use crate::error::Result;
+use crate::history::revwalk_for_range;
+
+#[derive(Debug, Serialize, Eq, PartialEq)]
+pub struct ScopeReviewers {
+    pub scope: String,
+    pub reviewers: Vec<String>,
+}
+
+/// Looks up reviewers for every scope. Scopes without configured reviewers are kept so that the
+/// output explains why nobody was suggested for them.
+pub fn get_reviewers_for_scopes(config: &Option<Config>, scopes: &[String]) -> Vec<ScopeReviewers> {
+    let reviewers = config.as_ref().and_then(|c| c.reviewers.as_ref());
+
+    scopes
+        .iter()
+        .unique()
+        .map(|scope| ScopeReviewers {
+            scope: scope.clone(),
+            reviewers: reviewers
+                .and_then(|r| r.get(scope))
+                .cloned()
+                .unwrap_or_else(|| {
+                    debug!("No reviewers configured for scope {:?}", scope);
+                    Vec::new()
+                }),
+        })
+        .collect()
+}
+
+/// Collects the scopes of all commits in the range
+pub fn get_scopes_in_range(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: &str,
+) -> Result<Vec<String>> {
+    let pseudo_types = pseudo_types_config(config);
+    let mut res = Vec::new();
+
+    for oid in revwalk_for_range(repo, Some(range))? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(summary) = commit.summary() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        if let Some(scope) = get_scope_from_summary(summary, &pseudo_types) {
+            res.push(scope);
+        }
+    }
+
+    Ok(res.into_iter().unique().collect())
+}
+
+/// All reviewers across scopes without duplicates, in the order of the scopes
+pub fn flatten_reviewers(scope_reviewers: &[ScopeReviewers]) -> Vec<String> {
+    scope_reviewers
+        .iter()
+        .flat_map(|s| s.reviewers.iter().cloned())
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    fn mk_config() -> Option<Config> {
+        Some(
+            Config::from_str(indoc! {r#"
+                [reviewers]
+                foo = ["@alice", "@org/team"]
+                bar = ["@bob", "@alice"]
+                "#})
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn reviewers_are_looked_up_and_flattened() {
+        let res = get_reviewers_for_scopes(
+            &mk_config(),
+            &["foo".to_string(), "bar".to_string(), "baz".to_string()],
+        );
+
+        assert_eq!(res.len(), 3);
+        assert!(res[2].reviewers.is_empty());
+        assert_eq!(flatten_reviewers(&res), vec!["@alice", "@org/team", "@bob"]);
+    }
+
+    #[test]
+    fn scopes_in_range() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &["init", "feat(foo): one", "fix(bar): two", "fix(foo): three"],
+        );
+
+        let res = get_scopes_in_range(&repo, &None, "HEAD~2..HEAD").unwrap();
+
+        assert_eq!(res, vec!["foo", "bar"]);
+    }
+}
@@ -0,0 +1,96 @@
+//! Commit messages are untrusted input that ends up in terminals and editors (i.e. through the
+//! completions), so the text taken from them is cleaned up before it's shown.
+use log::warn;
+use std::borrow::Cow;
+
+/// Invisible characters that change how the text around them is displayed: bidi overrides and
+/// isolates, zero-width characters, BOM
+fn is_format_char(c: char) -> bool {
+    matches!(c,
+        '\u{200B}'..='\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2060}'..='\u{2069}'
+        | '\u{FEFF}'
+    )
+}
+
+/// Strips ANSI escape sequences, control characters and invisible formatting characters
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| c.is_control() || is_format_char(c)) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // CSI sequence: ESC [ parameters... final byte in @..~
+            '\u{1B}' if chars.peek() == Some(&'[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            c if c.is_control() || is_format_char(c) => {}
+            c => res.push(c),
+        }
+    }
+
+    Cow::Owned(res)
+}
+
+/// Whether the name mixes ASCII letters with non-ASCII ones, i.e. a Cyrillic "а" in an otherwise
+/// Latin name. Such names are likely to be spoofed.
+pub fn is_mixed_script(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .any(|c| c.is_alphabetic() && !c.is_ascii_alphabetic())
+}
+
+/// Sanitizes a scope name taken from a commit message. Returns None if nothing is left.
+pub fn sanitize_scope(name: &str) -> Option<String> {
+    let res = sanitize(name).trim().to_string();
+    if is_mixed_script(&res) {
+        warn!(
+            "Scope {:?} mixes Latin and non-Latin letters, it may be made to look like another scope",
+            res
+        );
+    }
+
+    (!res.is_empty()).then_some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::clean("foo bar", "foo bar")]
+    #[case::ansi("\u{1B}[31mfoo\u{1B}[0m", "foo")]
+    #[case::control("foo\u{7}\u{8}bar", "foobar")]
+    #[case::bidi("foo\u{202E}rab", "foorab")]
+    #[case::zero_width("fo\u{200D}o", "foo")]
+    #[case::non_latin("кэш", "кэш")]
+    fn sanitizes(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(sanitize(input), expected);
+    }
+
+    #[rstest]
+    #[case("cache", false)]
+    #[case("кэш", false)]
+    #[case("cаche", true)] // Cyrillic "а"
+    #[case("cache-2", false)]
+    fn mixed_script(#[case] name: &str, #[case] expected: bool) {
+        assert_eq!(is_mixed_script(name), expected);
+    }
+
+    #[test]
+    fn empty_scope() {
+        assert_eq!(sanitize_scope("\u{200D}"), None);
+        assert_eq!(sanitize_scope("fo\u{200D}o"), Some("foo".to_string()));
+    }
+}
@@ -0,0 +1,211 @@
+//! Constraints on scope names (`[scope_names]` in the config) and the ignored scopes
+//! (`general.scopes.ignored`).
+//!
+//! Names from the history that break the rules are skipped, names in the config that break them
+//! are an error and commit messages using them are invalid.
+use fancy_regex::Regex;
+use thiserror::Error;
+
+use crate::config::{Config, ScopeNameRules};
+use crate::error::{Error, Result};
+
+/// Why the scope name is not acceptable
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum ScopeNameError {
+    #[error("is empty")]
+    Empty,
+    #[error("is longer than {0} characters")]
+    TooLong(usize),
+    #[error("has a character outside of [{charset}]: {c:?}")]
+    Charset { charset: String, c: char },
+    #[error("starts or ends with a dash")]
+    EdgeDash,
+}
+
+/// Scope name that satisfies the rules it was checked against
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct ScopeName(String);
+
+impl ScopeName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+/// Compiled [`ScopeNameRules`]
+#[derive(Debug, Clone)]
+pub struct ScopeNameValidator {
+    charset: Option<(String, Regex)>,
+    max_length: Option<usize>,
+    forbid_edge_dashes: bool,
+}
+
+impl ScopeNameValidator {
+    pub fn new(rules: &ScopeNameRules) -> Result<Self> {
+        let charset = rules
+            .charset
+            .as_ref()
+            .map(|charset| {
+                Regex::new(&format!("^[{}]$", charset))
+                    .map(|regex| (charset.clone(), regex))
+                    .map_err(|e| Error::ConfigInvalid {
+                        path: None,
+                        line: None,
+                        message: format!("scope_names.charset {:?} is invalid: {}", charset, e),
+                    })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            charset,
+            max_length: rules.max_length,
+            forbid_edge_dashes: rules.forbid_edge_dashes,
+        })
+    }
+
+    /// Validator for the rules from the config, no rules if there's no config
+    pub fn from_config(config: &Option<Config>) -> Result<Self> {
+        Self::new(
+            &config
+                .as_ref()
+                .map(|c| c.scope_names.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn parse(&self, name: &str) -> Result<ScopeName, ScopeNameError> {
+        if name.is_empty() {
+            return Err(ScopeNameError::Empty);
+        }
+
+        if let Some(max_length) = self.max_length {
+            if name.chars().count() > max_length {
+                return Err(ScopeNameError::TooLong(max_length));
+            }
+        }
+
+        if let Some((charset, regex)) = &self.charset {
+            let mut buf = [0; 4];
+            if let Some(c) = name
+                .chars()
+                .find(|c| !regex.is_match(c.encode_utf8(&mut buf)).unwrap_or(false))
+            {
+                return Err(ScopeNameError::Charset {
+                    charset: charset.clone(),
+                    c,
+                });
+            }
+        }
+
+        if self.forbid_edge_dashes && (name.starts_with('-') || name.ends_with('-')) {
+            return Err(ScopeNameError::EdgeDash);
+        }
+
+        Ok(ScopeName(name.to_string()))
+    }
+}
+
+/// Compiled `general.scopes.ignored`: every pattern is a regex matching the whole name, so plain
+/// names match only themselves
+#[derive(Debug, Clone, Default)]
+pub struct IgnoredScopes(Vec<Regex>);
+
+impl IgnoredScopes {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| Error::ConfigInvalid {
+                    path: None,
+                    line: None,
+                    message: format!("general.scopes.ignored: {:?} is invalid: {}", pattern, e),
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    /// Ignored scopes from the config, none if there's no config
+    pub fn from_config(config: &Option<Config>) -> Result<Self> {
+        Self::new(
+            &config
+                .as_ref()
+                .and_then(|c| c.general.as_ref())
+                .and_then(|g| g.scopes.as_ref())
+                .and_then(|s| s.ignored.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0
+            .iter()
+            .any(|regex| regex.is_match(name).unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn mk_validator() -> ScopeNameValidator {
+        ScopeNameValidator::new(&ScopeNameRules {
+            charset: Some("a-z0-9-".to_string()),
+            max_length: Some(8),
+            forbid_edge_dashes: true,
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::valid("foo-bar", Ok(()))]
+    #[case::empty("", Err(ScopeNameError::Empty))]
+    #[case::too_long("foo-bar-baz", Err(ScopeNameError::TooLong(8)))]
+    #[case::charset("Foo", Err(ScopeNameError::Charset { charset: "a-z0-9-".to_string(), c: 'F' }))]
+    #[case::leading_dash("-foo", Err(ScopeNameError::EdgeDash))]
+    #[case::trailing_dash("foo-", Err(ScopeNameError::EdgeDash))]
+    fn parse(#[case] name: &str, #[case] expected: Result<(), ScopeNameError>) {
+        assert_eq!(
+            mk_validator()
+                .parse(name)
+                .map(|n| assert_eq!(n.as_str(), name)),
+            expected
+        );
+    }
+
+    #[test]
+    fn ignored() {
+        let ignored = IgnoredScopes::new(&["foo".to_string(), "release-.*".to_string()]).unwrap();
+
+        assert!(ignored.contains("foo"));
+        assert!(!ignored.contains("foobar"));
+        assert!(ignored.contains("release-1.2"));
+        assert!(!ignored.contains("pre-release-1"));
+        assert!(IgnoredScopes::new(&["(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn no_rules() {
+        let validator = ScopeNameValidator::new(&ScopeNameRules::default()).unwrap();
+
+        assert!(validator.parse("-Anything goes-").is_ok());
+    }
+
+    #[test]
+    fn invalid_charset() {
+        let rules = ScopeNameRules {
+            charset: Some("z-a".to_string()),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            ScopeNameValidator::new(&rules),
+            Err(Error::ConfigInvalid { .. })
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+//! Repository activity statistics based on the commit scopes
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate};
+use git2::Repository;
+use itertools::Itertools;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::commit_message::{parse_footers, parse_subject, pseudo_types_config};
+use crate::commit_scopes::commit::ChangedFiles;
+use crate::commit_scopes::{get_scope_map, CommitScope};
+use crate::config::Config;
+use crate::error::Result;
+use crate::history::revwalk_for_range;
+
+/// Number of commits in a scope
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct ScopeCount {
+    pub scope: String,
+    pub commits: usize,
+    /// Commits marked with `!` or having a `BREAKING CHANGE` footer
+    pub breaking: usize,
+}
+
+/// Number of commits of a type
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct TypeCount {
+    #[serde(rename = "type")]
+    pub commit_type: String,
+    pub commits: usize,
+}
+
+/// Number of commits in a scope during a week
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct ScopeActivity {
+    /// Monday of the week
+    pub week: NaiveDate,
+    pub scope: String,
+    pub commits: usize,
+}
+
+/// Commit with a scope in the subject
+struct ScopedCommit {
+    scope: String,
+    /// Seconds since epoch
+    time: i64,
+    breaking: bool,
+}
+
+/// Returns every scoped commit in the range.
+///
+/// Only the subject is parsed for most commits: the footers are looked at only for the scoped
+/// commits that are not marked as breaking in the subject already.
+fn get_scoped_commits(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<ScopedCommit>> {
+    let pseudo_types = pseudo_types_config(config);
+    let mut res = Vec::new();
+
+    for oid in revwalk_for_range(repo, range)? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(message) = commit.message() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        let subject = message.lines().next().unwrap_or("");
+
+        let Some(header) = parse_subject(subject, &pseudo_types)? else {
+            continue;
+        };
+        if let Some(scope) = header.scope {
+            res.push(ScopedCommit {
+                scope,
+                time: commit.time().seconds(),
+                breaking: header.breaking
+                    || parse_footers(message)
+                        .iter()
+                        .any(|f| f.is_breaking_change()),
+            });
+        }
+    }
+
+    Ok(res)
+}
+
+/// Monday of the week the timestamp falls into (UTC)
+fn week_start(timestamp: i64) -> NaiveDate {
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .date_naive();
+
+    date - Duration::days(date.weekday().num_days_from_monday().into())
+}
+
+/// Counts commits per scope, most active scopes first
+pub fn get_scope_counts(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<ScopeCount>> {
+    Ok(get_scoped_commits(repo, config, range)?
+        .into_iter()
+        .into_group_map_by(|c| c.scope.clone())
+        .into_iter()
+        .map(|(scope, commits)| ScopeCount {
+            scope,
+            commits: commits.len(),
+            breaking: commits.iter().filter(|c| c.breaking).count(),
+        })
+        .sorted_by(|a, b| b.commits.cmp(&a.commits).then(a.scope.cmp(&b.scope)))
+        .collect())
+}
+
+/// Counts conventional commits per type, most used types first
+pub fn get_type_counts(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<TypeCount>> {
+    let pseudo_types = pseudo_types_config(config);
+    let mut types = Vec::new();
+
+    for oid in revwalk_for_range(repo, range)? {
+        let commit = repo.find_commit(oid?)?;
+        let Some(summary) = commit.summary() else {
+            warn!("Commit {} has a non-UTF8 message, skipping", commit.id());
+            continue;
+        };
+        if let Some(header) = parse_subject(summary, &pseudo_types)? {
+            types.push(header.commit_type);
+        }
+    }
+
+    Ok(types
+        .into_iter()
+        .counts()
+        .into_iter()
+        .map(|(commit_type, commits)| TypeCount {
+            commit_type,
+            commits,
+        })
+        .sorted_by(|a, b| {
+            b.commits
+                .cmp(&a.commits)
+                .then(a.commit_type.cmp(&b.commit_type))
+        })
+        .collect())
+}
+
+/// Buckets the scoped commits into weeks
+fn bucket_by_week(commits: Vec<(String, i64)>) -> Vec<ScopeActivity> {
+    let mut buckets: BTreeMap<(NaiveDate, String), usize> = BTreeMap::new();
+    for (scope, timestamp) in commits {
+        *buckets.entry((week_start(timestamp), scope)).or_default() += 1;
+    }
+
+    buckets
+        .into_iter()
+        .map(|((week, scope), commits)| ScopeActivity {
+            week,
+            scope,
+            commits,
+        })
+        .collect()
+}
+
+/// Weekly per-scope commit counts, sorted by week and scope. Weeks without any activity in a
+/// scope are omitted.
+pub fn get_heatmap(
+    repo: &Repository,
+    config: &Option<Config>,
+    range: Option<&str>,
+) -> Result<Vec<ScopeActivity>> {
+    Ok(bucket_by_week(
+        get_scoped_commits(repo, config, range)?
+            .into_iter()
+            .map(|c| (c.scope, c.time))
+            .collect(),
+    ))
+}
+
+/// Renders the heatmap as CSV with a header
+pub fn heatmap_to_csv(heatmap: &[ScopeActivity]) -> String {
+    std::iter::once("week,scope,commits".to_string())
+        .chain(
+            heatmap
+                .iter()
+                .map(|a| format!("{},{},{}", a.week, a.scope, a.commits)),
+        )
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Number of files changed under both scopes, for every pair of scopes. A high number hints at
+/// the coupling between the areas
+#[derive(Debug, Serialize, Eq, PartialEq, Clone)]
+pub struct CoOccurrence {
+    /// Sorted by name
+    pub scopes: Vec<String>,
+    /// `matrix[i][j]` is the number of files shared by `scopes[i]` and `scopes[j]`. The diagonal
+    /// holds the number of files of the scope
+    pub matrix: Vec<Vec<usize>>,
+}
+
+impl CoOccurrence {
+    /// Pairs of different scopes sharing files, most shared files first
+    pub fn pairs(&self) -> Vec<(&str, &str, usize)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .tuple_combinations()
+            .map(|((i, a), (j, b))| (a.as_str(), b.as_str(), self.matrix[i][j]))
+            .filter(|(_, _, shared)| *shared > 0)
+            .sorted_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)))
+            .collect()
+    }
+}
+
+fn co_occurrence(scope_map: &HashMap<CommitScope, ChangedFiles>) -> CoOccurrence {
+    let scopes: Vec<(&String, &ChangedFiles)> = scope_map
+        .iter()
+        .map(|(scope, files)| (&scope.name, files))
+        .sorted_by_key(|(name, _)| *name)
+        .collect();
+
+    CoOccurrence {
+        matrix: scopes
+            .iter()
+            .map(|(_, a)| {
+                scopes
+                    .iter()
+                    .map(|(_, b)| a.intersection(b).count())
+                    .collect()
+            })
+            .collect(),
+        scopes: scopes.into_iter().map(|(name, _)| name.clone()).collect(),
+    }
+}
+
+/// Scope co-occurrence over the whole history, based on the files the scopes touched
+pub fn get_co_occurrence(repo: &Repository, config: &Option<Config>) -> Result<CoOccurrence> {
+    Ok(co_occurrence(
+        &get_scope_map(repo, config)?.unwrap_or_default(),
+    ))
+}
+
+/// Renders the co-occurrence matrix as CSV, scope names in the header and in the first column
+pub fn co_occurrence_to_csv(co_occurrence: &CoOccurrence) -> String {
+    std::iter::once(format!(",{}", co_occurrence.scopes.join(",")))
+        .chain(
+            co_occurrence
+                .scopes
+                .iter()
+                .zip(&co_occurrence.matrix)
+                .map(|(scope, row)| format!("{},{}", scope, row.iter().join(","))),
+        )
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Renders the scope counts as CSV with a header
+pub fn counts_to_csv(counts: &[ScopeCount]) -> String {
+    std::iter::once("scope,commits,breaking".to_string())
+        .chain(
+            counts
+                .iter()
+                .map(|c| format!("{},{},{}", c.scope, c.commits, c.breaking)),
+        )
+        .map(|line| line + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_repo_with_commits;
+    use indoc::indoc;
+    use testdir::testdir;
+
+    #[test]
+    fn counts() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "feat(foo): a",
+                "fix(bar): b",
+                "fix(foo): c",
+                "chore: d",
+            ],
+        );
+
+        assert_eq!(
+            get_scope_counts(&repo, &None, None).unwrap(),
+            vec![
+                ScopeCount {
+                    scope: "foo".to_string(),
+                    commits: 2,
+                    breaking: 0,
+                },
+                ScopeCount {
+                    scope: "bar".to_string(),
+                    commits: 1,
+                    breaking: 0,
+                },
+            ]
+        );
+    }
+
+    /// Both `!` and the footer count, the footer is found in the body of the commit
+    #[test]
+    fn breaking_counts() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(
+            &dir,
+            &[
+                "init",
+                "feat(foo)!: a",
+                "fix(foo): b\n\nSome body\n\nBREAKING CHANGE: it broke",
+                "fix(foo): c\n\nRefs: #1",
+                "fix(bar): d\n\nBREAKING-CHANGE: it broke too",
+            ],
+        );
+
+        assert_eq!(
+            counts_to_csv(&get_scope_counts(&repo, &None, None).unwrap()),
+            indoc! {"
+                scope,commits,breaking
+                foo,3,2
+                bar,1,1
+            "}
+        );
+    }
+
+    #[test]
+    fn co_occurrence_matrix() {
+        let scope_map = HashMap::from([
+            (
+                CommitScope::new("foo"),
+                ChangedFiles::from(["a".to_string(), "b".to_string()]),
+            ),
+            (
+                CommitScope::new("bar"),
+                ChangedFiles::from(["b".to_string(), "c".to_string()]),
+            ),
+            (
+                CommitScope::new("baz"),
+                ChangedFiles::from(["d".to_string()]),
+            ),
+        ]);
+
+        let res = co_occurrence(&scope_map);
+
+        assert_eq!(
+            co_occurrence_to_csv(&res),
+            indoc! {"
+                ,bar,baz,foo
+                bar,2,0,1
+                baz,0,1,0
+                foo,1,0,2
+            "}
+        );
+        assert_eq!(res.pairs(), vec![("bar", "foo", 1)]);
+    }
+
+    #[test]
+    fn heatmap_buckets() {
+        // 2024-01-01 is a Monday
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let ts = |date: NaiveDate| date.and_hms_opt(12, 0, 0).unwrap().and_utc().timestamp();
+
+        let res = bucket_by_week(vec![
+            ("foo".to_string(), ts(monday)),
+            ("foo".to_string(), ts(monday + Duration::days(6))),
+            ("bar".to_string(), ts(monday + Duration::days(3))),
+            ("foo".to_string(), ts(monday + Duration::days(7))),
+        ]);
+
+        assert_eq!(
+            heatmap_to_csv(&res),
+            indoc! {"
+                week,scope,commits
+                2024-01-01,bar,1
+                2024-01-01,foo,2
+                2024-01-08,foo,1
+            "}
+        );
+    }
+}
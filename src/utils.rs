@@ -1,74 +1,146 @@
-use anyhow::{bail, Context, Result};
-use git2::Repository;
-use std::path::Path;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use git2::{ErrorCode, Oid, Repository, RepositoryState};
+use std::path::{Path, PathBuf};
 
-pub trait PrintableEntity {
-    fn name(&self) -> &str;
-    fn description(&self) -> &str;
-}
+use crate::error::{Error, Result};
 
-/// Takes a path, returns a repository containing that path.
+/// Takes a path, returns a repository containing that path. Bare repositories are read-only: the
+/// history and the cache work, the staged files are always empty
 pub fn repo_from_path(path_in_repo: &Path) -> Result<Repository> {
-    let repo = Repository::discover(path_in_repo).context("Failed to discover a repository")?;
+    Repository::discover(path_in_repo).map_err(Error::RepoNotFound)
+}
+
+/// Staged files, the config file edits and the like need a working tree
+pub fn require_worktree(repo: &Repository) -> Result<()> {
+    workdir(repo).map(|_| ())
+}
 
-    match repo.is_bare() {
-        true => bail!("Bare repositories are not supported"),
-        false => Ok(repo),
+/// Working tree of the repository, error if it's bare
+pub fn workdir(repo: &Repository) -> Result<&Path> {
+    repo.workdir().ok_or_else(|| {
+        Error::RepoUnsupported(
+            "Bare repositories have no working tree, the command needs one".to_string(),
+        )
+    })
+}
+
+/// Commit HEAD points to, detached (i.e. mid-rebase) or not. None if the current branch has no
+/// commits yet
+pub fn head_commit(repo: &Repository) -> Result<Option<Oid>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?.id())),
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn validate_repo(repo: &Repository) -> Result<()> {
-    if repo.is_bare() {
-        bail!("Bare repositories are not supported");
-    };
+/// Git directory shared by the linked worktrees of the repository (`git rev-parse
+/// --git-common-dir`): the one of the main repository. The git directory itself if the repository
+/// is not a linked worktree
+pub fn common_dir(repo: &Repository) -> PathBuf {
+    // Linked worktrees point to it from the `commondir` file of their git directory
+    std::fs::read_to_string(repo.path().join("commondir"))
+        .ok()
+        .and_then(|dir| repo.path().join(dir.trim()).canonicalize().ok())
+        .unwrap_or_else(|| repo.path().to_path_buf())
+}
 
-    Ok(())
+/// A rebase, merge, cherry-pick, revert or bisect is in progress. HEAD moves with every step, so
+/// the cache is not refreshed for it
+pub fn head_is_transient(repo: &Repository) -> bool {
+    repo.state() != RepositoryState::Clean
 }
 
-pub mod time {
+/// Point in time given as a date ("2024-01-31"), a timestamp ("2024-01-31T12:00:00Z") or a time
+/// ago ("2 weeks", "6 months ago", "1.year.ago"). Months are 30 days, years are 365 days
+pub fn parse_since(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.to_utc());
+    }
+
+    let ago = value.replace('.', " ");
+    let mut parts = ago.split_whitespace();
+    let count: i64 = parts.next()?.parse().ok()?;
+    let days = match parts.next()?.trim_end_matches('s') {
+        "day" => 1,
+        "week" => 7,
+        "month" => 30,
+        "year" => 365,
+        _ => return None,
+    };
+    match (parts.next(), parts.next()) {
+        (None | Some("ago"), None) => Some(time::now() - Duration::days(count * days)),
+        _ => None,
+    }
+}
 
-    #[cfg(not(test))]
+/// Every read of the current time goes through [`time::now`], so that the TTL and staleness logic
+/// can be tested with a frozen clock (see `test_utils::freeze_time`)
+pub mod time {
     use chrono::{DateTime, Utc};
+    use std::cell::Cell;
 
-    #[cfg(not(test))]
+    thread_local! {
+        static FROZEN: Cell<Option<DateTime<Utc>>> = const { Cell::new(None) };
+    }
 
     pub fn now() -> DateTime<Utc> {
-        Utc::now()
+        FROZEN.with(Cell::get).unwrap_or_else(Utc::now)
+    }
+
+    /// Makes [`now`] return `time` on the current thread until [`unfreeze`] is called
+    pub fn freeze(time: DateTime<Utc>) {
+        FROZEN.with(|frozen| frozen.set(Some(time)));
+    }
+
+    pub fn unfreeze() {
+        FROZEN.with(|frozen| frozen.set(None));
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{freeze_time, setup_repo_with_commits};
+    use rstest::rstest;
+    use testdir::testdir;
+
+    #[rstest]
+    #[case::date("2024-01-31", Some("2024-01-31T00:00:00Z"))]
+    #[case::timestamp("2024-01-31T12:00:00+02:00", Some("2024-01-31T10:00:00Z"))]
+    #[case::days("3 days", Some("2024-02-27T12:00:00Z"))]
+    #[case::weeks_ago("2 weeks ago", Some("2024-02-16T12:00:00Z"))]
+    #[case::git_style("1.month.ago", Some("2024-01-31T12:00:00Z"))]
+    #[case::year("1 year", Some("2023-03-02T12:00:00Z"))]
+    #[case::unknown_unit("3 fortnights", None)]
+    #[case::garbage("yesterday", None)]
+    #[case::trailing("3 days later", None)]
+    fn since(#[case] value: &str, #[case] expected: Option<&str>) {
+        let _clock = freeze_time("2024-03-01T12:00:00Z".parse().unwrap());
+
+        assert_eq!(
+            parse_since(value),
+            expected.map(|e| e.parse::<DateTime<Utc>>().unwrap())
+        );
+    }
+
+    #[test]
+    fn head() {
+        let dir = testdir!();
+        let empty = Repository::init(dir.join("empty")).unwrap();
+        assert_eq!(head_commit(&empty).unwrap(), None);
+
+        let repo = setup_repo_with_commits(&dir.join("repo"), &["init", "feat(foo): bar"]);
+        let first = repo.revparse_single("HEAD~1").unwrap().id();
+        repo.set_head_detached(first).unwrap();
+        assert_eq!(head_commit(&repo).unwrap(), Some(first));
+        assert!(!head_is_transient(&repo));
 
-    #[cfg(test)]
-    pub use mock_time::now;
-
-    #[cfg(test)]
-    pub mod mock_time {
-        use chrono::{DateTime, Utc};
-        use std::cell::RefCell;
-
-        thread_local! {
-            static MOCK_TIME: RefCell<Option<DateTime<Utc>>> = RefCell::new(None);
-        }
-
-        pub fn now() -> DateTime<Utc> {
-            MOCK_TIME.with(|time| {
-                if let Some(mock) = *time.borrow() {
-                    mock
-                } else {
-                    Utc::now()
-                }
-            })
-        }
-
-        pub fn set(time: DateTime<Utc>) {
-            MOCK_TIME.with(|mock_time| {
-                *mock_time.borrow_mut() = Some(time);
-            });
-        }
-
-        #[allow(dead_code)]
-        pub fn clear() {
-            MOCK_TIME.with(|mock_time| {
-                *mock_time.borrow_mut() = None;
-            });
-        }
+        std::fs::create_dir(repo.path().join("rebase-merge")).unwrap();
+        assert!(head_is_transient(&repo));
     }
 }
@@ -0,0 +1,283 @@
+//! Release versions derived from the tags and the conventional commits since them
+//!
+//! Monorepos may tag the scopes independently (`cache-v1.2.3`). In this case the latest tag with
+//! the scope's prefix is used as the base and only the commits touching the scope (either by
+//! the configured paths or by the scope in the header) are counted.
+
+use git2::Repository;
+use log::{debug, info};
+use std::fmt;
+use std::path::Path;
+
+use crate::changelog::{filter_by_scope, get_entries, resolve_range, ChangelogEntry};
+use crate::commit_scopes::commit::get_changed_files_from_commit;
+use crate::config::Config;
+use crate::error::Result;
+use crate::history::latest_tag;
+
+/// What the version/changelog is calculated for: the whole repo or a single scope
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Target {
+    pub scope: Option<String>,
+    /// Tag prefix, None means "any tag"
+    pub tag_prefix: Option<String>,
+    /// Paths the scope lives in. None means "use the commit scope"
+    pub paths: Option<Vec<String>>,
+}
+
+impl Target {
+    pub fn tag_pattern(&self) -> Option<String> {
+        self.tag_prefix.as_ref().map(|p| format!("{}*", p))
+    }
+}
+
+pub fn get_target(config: &Option<Config>, scope: Option<&str>) -> Target {
+    let versioning = config
+        .as_ref()
+        .map(|c| c.versioning.clone())
+        .unwrap_or_default();
+
+    match scope {
+        None => Target {
+            scope: None,
+            tag_prefix: versioning.tag_prefix,
+            paths: None,
+        },
+        Some(scope) => {
+            let scope_config = versioning
+                .scopes
+                .and_then(|mut s| s.remove(scope))
+                .unwrap_or_default();
+            Target {
+                scope: Some(scope.to_string()),
+                tag_prefix: Some(
+                    scope_config
+                        .tag_prefix
+                        .unwrap_or_else(|| format!("{}-v", scope)),
+                ),
+                paths: scope_config.paths,
+            }
+        }
+    }
+}
+
+fn touches_paths(files: impl IntoIterator<Item = String>, paths: &[String]) -> bool {
+    files
+        .into_iter()
+        .any(|file| paths.iter().any(|p| Path::new(&file).starts_with(p)))
+}
+
+/// Keeps only the entries relevant to the target
+pub fn filter_entries(
+    repo: &Repository,
+    entries: Vec<ChangelogEntry>,
+    target: &Target,
+) -> Result<Vec<ChangelogEntry>> {
+    match (&target.scope, &target.paths) {
+        (None, _) => Ok(entries),
+        (Some(_), Some(paths)) => {
+            debug!("Filtering commits by paths {:?}", paths);
+            let mut res = Vec::new();
+            for entry in entries {
+                let commit = repo.find_commit(git2::Oid::from_str(&entry.hash)?)?;
+                if touches_paths(get_changed_files_from_commit(&commit, repo)?, paths) {
+                    res.push(entry);
+                }
+            }
+            Ok(res)
+        }
+        (Some(scope), None) => Ok(filter_by_scope(entries, scope)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// Parses "1.2.3", ignoring the pre-release/build suffix
+    pub fn parse(s: &str) -> Option<Self> {
+        let core = s.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let res = Self {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+        };
+        parts.next().is_none().then_some(res)
+    }
+
+    pub fn bump(self, bump: Bump) -> Self {
+        match bump {
+            Bump::Major => Self {
+                major: self.major + 1,
+                minor: 0,
+                patch: 0,
+            },
+            Bump::Minor => Self {
+                minor: self.minor + 1,
+                patch: 0,
+                ..self
+            },
+            Bump::Patch => Self {
+                patch: self.patch + 1,
+                ..self
+            },
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+/// Breaking => major, feat => minor, anything else => patch. No commits => no bump
+pub fn get_bump(entries: &[ChangelogEntry]) -> Option<Bump> {
+    entries
+        .iter()
+        .map(|e| {
+            if e.commit.is_breaking() {
+                Bump::Major
+            } else if e.commit.header.commit_type == "feat" {
+                Bump::Minor
+            } else {
+                Bump::Patch
+            }
+        })
+        .max()
+}
+
+/// Splits the tag into the prefix and the version
+fn split_tag(tag: &str, prefix: Option<&str>) -> Option<(String, Version)> {
+    let prefix = match prefix {
+        Some(prefix) => prefix.to_string(),
+        // Anything before the first digit
+        None => tag.chars().take_while(|c| !c.is_ascii_digit()).collect(),
+    };
+
+    let version = Version::parse(tag.strip_prefix(&prefix)?)?;
+    Some((prefix, version))
+}
+
+/// Calculates the next release tag for the target
+pub fn get_next_version(
+    repo: &Repository,
+    config: &Option<Config>,
+    target: &Target,
+) -> Result<String> {
+    let tag = latest_tag(repo, target.tag_pattern().as_deref())?;
+
+    let (prefix, current) = match tag
+        .as_deref()
+        .and_then(|t| split_tag(t, target.tag_prefix.as_deref()))
+    {
+        Some(res) => res,
+        None => {
+            info!("No version tag found, starting from 0.0.0");
+            (
+                target.tag_prefix.clone().unwrap_or_else(|| "v".to_string()),
+                Version::default(),
+            )
+        }
+    };
+
+    let range = resolve_range(repo, tag.map(|t| format!("{}..HEAD", t)), None)?;
+    let entries = filter_entries(repo, get_entries(repo, config, range.as_deref())?, target)?;
+
+    let next = match get_bump(&entries) {
+        Some(bump) => {
+            debug!("Bumping {} by {:?}", current, bump);
+            current.bump(bump)
+        }
+        None => {
+            info!("No conventional commits since the last release");
+            current
+        }
+    };
+
+    Ok(format!("{}{}", prefix, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{setup_repo_with_commits, setup_repo_with_commits_and_files};
+    use indoc::indoc;
+    use rstest::rstest;
+    use testdir::testdir;
+
+    #[rstest]
+    #[case::plain("1.2.3", Some((1, 2, 3)))]
+    #[case::prerelease("1.2.3-rc.1", Some((1, 2, 3)))]
+    #[case::short("1.2", None)]
+    #[case::garbage("foo", None)]
+    fn parses_versions(#[case] s: &str, #[case] expected: Option<(u64, u64, u64)>) {
+        assert_eq!(
+            Version::parse(s),
+            expected.map(|(major, minor, patch)| Version {
+                major,
+                minor,
+                patch
+            })
+        );
+    }
+
+    #[test]
+    fn next_version_for_repo() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits(&dir, &["init", "feat: one", "fix: two"]);
+        assert_eq!(
+            get_next_version(&repo, &None, &get_target(&None, None)).unwrap(),
+            "v0.1.0"
+        );
+
+        let head = repo.revparse_single("HEAD").unwrap();
+        repo.tag_lightweight("v0.1.0", &head, false).unwrap();
+        assert_eq!(
+            get_next_version(&repo, &None, &get_target(&None, None)).unwrap(),
+            "v0.1.0"
+        );
+    }
+
+    /// Scope tags are only looked up with the scope prefix and only commits touching the scope
+    /// paths are counted
+    #[test]
+    fn next_version_for_scope() {
+        let dir = testdir!();
+        let repo = setup_repo_with_commits_and_files(
+            &dir,
+            &["init", "fix: one", "feat: two", "feat!: three"],
+            &["init", "cache.rs", "cache.rs", "other.rs"],
+        );
+        let tagged = repo.revparse_single("HEAD~2").unwrap();
+        repo.tag_lightweight("cache-v1.0.0", &tagged, false)
+            .unwrap();
+        let config = Some(
+            Config::from_str(indoc! {r#"
+                [versioning.scopes.cache]
+                paths = ["cache.rs"]
+                "#})
+            .unwrap(),
+        );
+
+        let target = get_target(&config, Some("cache"));
+        assert_eq!(target.tag_prefix, Some("cache-v".to_string()));
+
+        assert_eq!(
+            get_next_version(&repo, &config, &target).unwrap(),
+            "cache-v1.1.0"
+        );
+    }
+}
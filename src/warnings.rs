@@ -0,0 +1,107 @@
+//! Hints for the user that don't stop the command: stale cache served, slow history search and the
+//! like.
+//!
+//! They are logged as warnings unless [`capture`] was called on the thread, then they are kept
+//! until [`take`] so that the JSON output can carry them (the `warnings` field) for the frontends
+//! to show, instead of the text on stderr. The threads doing the work for a capturing one pass
+//! theirs back, see [`capturing`].
+use log::warn;
+use serde::Serialize;
+use std::cell::RefCell;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// Scopes were mined from the history, the cache would be faster
+    HistorySlow,
+    /// Stale cache was served, skipped or replaced by the history
+    CacheStale,
+    /// Cache could not be loaded, created or regenerated
+    CacheUnavailable,
+    /// Rerank command failed or returned unknown scopes
+    RerankFailed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<Warning>>> = const { RefCell::new(None) };
+}
+
+/// Keeps the warnings of the current thread until [`take`] instead of logging them
+pub fn capture() {
+    CAPTURED.with(|captured| {
+        captured.borrow_mut().get_or_insert_with(Vec::new);
+    });
+}
+
+/// Whether the current thread keeps the warnings. A worker thread of a capturing one should
+/// [`capture`] too and hand the [`take`]n warnings over, to be raised again with [`warning`]
+pub fn capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+/// Logs the warning, or keeps it if the thread captures the warnings. The same warning is kept
+/// once, i.e. when the scopes are looked up several times
+pub fn warning(kind: WarningKind, message: impl Into<String>) {
+    let warning = Warning {
+        kind,
+        message: message.into(),
+    };
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(warnings) if warnings.contains(&warning) => (),
+        Some(warnings) => warnings.push(warning),
+        None => warn!("{}", warning.message),
+    });
+}
+
+/// Warnings kept since [`capture`] or the previous call. Capturing goes on
+pub fn take() -> Vec<Warning> {
+    CAPTURED.with(|captured| {
+        captured
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    })
+}
+
+/// Logs the warnings kept so far and stops capturing
+pub fn release() {
+    let warnings = CAPTURED.with(|captured| captured.borrow_mut().take());
+    for warning in warnings.unwrap_or_default() {
+        warn!("{}", warning.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captured() {
+        warning(WarningKind::HistorySlow, "logged");
+        assert!(take().is_empty());
+
+        capture();
+        warning(WarningKind::CacheStale, "kept");
+        warning(WarningKind::CacheStale, "kept");
+        assert_eq!(
+            take(),
+            vec![Warning {
+                kind: WarningKind::CacheStale,
+                message: "kept".to_string()
+            }]
+        );
+        assert!(take().is_empty());
+
+        warning(WarningKind::RerankFailed, "kept until released");
+        release();
+        warning(WarningKind::HistorySlow, "logged");
+        assert!(take().is_empty());
+    }
+}
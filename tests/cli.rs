@@ -1,20 +1,20 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use conventional_commit_helper::test_utils::{
-    mk_config_full, mk_config_with_scopes_only, mk_config_with_types_only, setup_repo_with_commits,
+    add_commit, load_fixture, mk_config_full, mk_config_with_scopes_only,
+    mk_config_with_types_only, setup_config_file_in_path, setup_repo_with_commits,
     setup_repo_with_commits_and_files,
 };
+use indoc::indoc;
 use predicates::prelude::*;
 use std::path::Path;
 use std::sync::Once;
 
 use predicate::str::{contains, starts_with};
 
-static BIN_NAME: &str = "conventional-commit-helper"; // Default binary name
-
 /// Ensure that when run without parameters the program succeeds
 #[test]
 fn default_run_no_args() {
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.assert().success();
 
     // The two default types should be present
@@ -34,7 +34,7 @@ fn check_custom_repo_with_config() {
     mk_config_with_types_only(dir.path());
 
     // Setup command
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("-vvv");
     cmd.arg("type");
     // Change CWD to the fake repo
@@ -57,7 +57,7 @@ fn check_custom_repo_with_config_and_scopes() {
     mk_config_with_scopes_only(dir.path());
 
     // Setup command
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("-vvv");
     cmd.arg("scope");
     // Change CWD to the fake repo
@@ -84,7 +84,7 @@ fn all_together_now() {
 
     // Test types
     // Setup command
-    let mut cmd_types = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd_types = cargo_bin_cmd!();
     cmd_types.arg("-vvv");
     cmd_types.arg("type");
     // Change CWD to the fake repo
@@ -98,7 +98,7 @@ fn all_together_now() {
 
     // Test scopes
     // Setup command
-    let mut cmd_scopes = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd_scopes = cargo_bin_cmd!();
     cmd_scopes.arg("-vvv");
     cmd_scopes.arg("scope");
     // Change CWD to the fake repo
@@ -130,7 +130,7 @@ fn valid_scope_is_suggested() {
 
     // Test scopes
     // Setup command
-    let mut cmd_scopes = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd_scopes = cargo_bin_cmd!();
     cmd_scopes.arg("-vvv");
     cmd_scopes.arg("scope");
     // Change CWD to the fake repo
@@ -150,6 +150,16 @@ fn valid_scope_is_suggested() {
 
     // now "z_bar" is first as it matches the current files
     cmd_scopes.assert().stdout(starts_with("z_bar"));
+
+    // unless the suggestion is disabled
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(dir.join("no_cache.bin"))
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout(starts_with("baz"));
 }
 
 /// This test validates basic cache manipulations. It does not look into the cache itself.
@@ -169,8 +179,7 @@ fn cache_ops() {
         &["init", "one", "two"],
     );
 
-    Command::cargo_bin(BIN_NAME)
-        .unwrap()
+    cargo_bin_cmd!()
         .env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -183,8 +192,7 @@ fn cache_ops() {
     // Check that cache exists
     assert!(cache_path.exists());
 
-    Command::cargo_bin(BIN_NAME)
-        .unwrap()
+    cargo_bin_cmd!()
         .env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -194,8 +202,7 @@ fn cache_ops() {
         .assert()
         .success();
 
-    Command::cargo_bin(BIN_NAME)
-        .unwrap()
+    cargo_bin_cmd!()
         .env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -207,8 +214,7 @@ fn cache_ops() {
 
     // Check that cache still exists
     assert!(cache_path.exists());
-    Command::cargo_bin(BIN_NAME)
-        .unwrap()
+    cargo_bin_cmd!()
         .env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -222,6 +228,104 @@ fn cache_ops() {
     assert!(!cache_path.exists());
 }
 
+/// Every cached repo is updated, the removed ones are skipped
+#[test]
+fn cache_update_all() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let first = dir.path().join("first");
+    let second = dir.path().join("second");
+    setup_repo_with_commits(&first, &["init", "feat(foo): a"]);
+    setup_repo_with_commits(&second, &["second init", "feat(bar): b"]);
+    for repo_path in [&first, &second] {
+        cargo_bin_cmd!()
+            .env("XDG_CACHE_HOME", dir.path())
+            .arg("--repo-path")
+            .arg(repo_path)
+            .args(["cache", "create"])
+            .assert()
+            .success();
+    }
+    std::fs::remove_dir_all(&second).unwrap();
+
+    // Works outside of a repository too
+    cargo_bin_cmd!()
+        .env("XDG_CACHE_HOME", dir.path())
+        .current_dir(dir.path())
+        .args(["cache", "update-all"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "Updated {}\nSkipped {}: path does not exist\n",
+            first.display(),
+            second.display()
+        ));
+}
+
+#[test]
+fn cache_prune() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let kept = dir.path().join("kept");
+    let deleted = dir.path().join("deleted");
+    setup_repo_with_commits(&kept, &["init", "feat(foo): a"]);
+    setup_repo_with_commits(&deleted, &["other init", "feat(bar): b"]);
+    for repo_path in [&kept, &deleted] {
+        cargo_bin_cmd!()
+            .env("XDG_CACHE_HOME", dir.path())
+            .arg("--repo-path")
+            .arg(repo_path)
+            .args(["cache", "create"])
+            .assert()
+            .success();
+    }
+    std::fs::remove_dir_all(&deleted).unwrap();
+
+    cargo_bin_cmd!()
+        .env("XDG_CACHE_HOME", dir.path())
+        .current_dir(dir.path())
+        .args(["cache", "prune"])
+        .assert()
+        .success()
+        .stdout(format!("Pruned {}\n", deleted.display()));
+
+    cargo_bin_cmd!()
+        .env("XDG_CACHE_HOME", dir.path())
+        .current_dir(dir.path())
+        .args(["cache", "prune"])
+        .assert()
+        .success()
+        .stdout("Nothing to prune\n");
+}
+
+/// `config set` can be undone
+#[test]
+fn config_undo() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init"]);
+    let config_path = setup_config_file_in_path(dir.path(), "# Hand-written\n");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "set", "lint.max_subject_length", "50"])
+        .assert()
+        .success();
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "undo"])
+        .assert()
+        .success()
+        .stdout(starts_with("Restored"));
+    assert_eq!(
+        std::fs::read_to_string(&config_path).unwrap(),
+        "# Hand-written\n"
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "undo"])
+        .assert()
+        .code(1);
+}
+
 #[test]
 fn cache_show() {
     init_logger();
@@ -235,8 +339,7 @@ fn cache_show() {
         &["init", "one", "two"],
     );
 
-    Command::cargo_bin(BIN_NAME)
-        .unwrap()
+    cargo_bin_cmd!()
         .env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -246,7 +349,7 @@ fn cache_show() {
         .assert()
         .success();
 
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.env("XDG_CACHE_HOME", dir.path())
         .arg("-vvv")
         .arg("--repo-path")
@@ -259,10 +362,236 @@ fn cache_show() {
         .stdout(contains(repo_path.to_str().unwrap()));
 }
 
+/// Linked worktree shares the cache entry of the main repository, the staged files are the ones of
+/// the worktree
+#[test]
+fn cache_worktree() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let worktree_path = dir.path().join("wt");
+    let repo = setup_repo_with_commits_and_files(
+        &repo_path,
+        &["init", "foo(one): quux", "foo(two): quux"],
+        &["init", "one", "two"],
+    );
+    let worktree = repo.worktree("wt", &worktree_path, None).unwrap();
+    let worktree_repo = git2::Repository::open_from_worktree(&worktree).unwrap();
+    stage(&repo, "two");
+    stage(&worktree_repo, "one");
+    let run = |path: &Path, args: &[&str]| {
+        cargo_bin_cmd!()
+            .env("XDG_CACHE_HOME", dir.path())
+            .current_dir(path)
+            .args(args)
+            .assert()
+            .success()
+    };
+
+    run(&worktree_path, &["cache", "create"]);
+    run(&worktree_path, &["scope"]).stdout(starts_with("one"));
+    run(&repo_path, &["scope"]).stdout(starts_with("two"));
+    run(&repo_path, &["cache", "show"]).stdout(
+        contains(repo_path.to_str().unwrap()).and(contains(worktree_path.to_str().unwrap()).not()),
+    );
+}
+
+/// Cache can be placed anywhere; unusable locations are reported and do not break suggestions
+/// `cache.location = "repo"` keeps the cache in the git directory
+#[test]
+fn cache_in_repo() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init", "foo(baz): quux"]);
+    setup_config_file_in_path(dir.path(), "[cache]\nlocation = \"repo\"\n");
+    let cache_path = dir
+        .path()
+        .join(".git/conventional-commit-helper/commit_scope_cache.bin");
+
+    cargo_bin_cmd!()
+        .env("XDG_CACHE_HOME", dir.path().join("xdg"))
+        .current_dir(dir.path())
+        .args(["cache", "create"])
+        .assert()
+        .success();
+    assert!(cache_path.exists());
+    assert!(!dir.path().join("xdg").exists());
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("paths")
+        .assert()
+        .success()
+        .stdout(contains(format!("cache: {}\n", cache_path.display())));
+}
+
+#[test]
+fn cache_path_override() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let cache_path = dir.path().join("elsewhere/cache.bin");
+    let _repo = setup_repo_with_commits(&repo_path, &["init", "foo(baz): quux"]);
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .success();
+    assert!(cache_path.exists());
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "show"])
+        .assert()
+        .success()
+        .stdout(contains(repo_path.to_str().unwrap()));
+
+    // Parent of the cache is a file => cache cannot be created
+    let not_a_dir = dir.path().join("not_a_dir");
+    std::fs::write(&not_a_dir, "").unwrap();
+    let bad_cache_path = not_a_dir.join("cache.bin");
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&bad_cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .failure()
+        .stderr(contains("Failed to write the cache"));
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&bad_cache_path)
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout("baz: \n");
+}
+
+/// Cached scopes are listed as is with --no-suggest
+#[test]
+fn scope_no_suggest() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let cache_path = dir.path().join("cache.bin");
+    let repo = setup_repo_with_commits(&repo_path, &["init", "foo(baz): quux"]);
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .success();
+
+    // New scope is not in the cache
+    add_commit(&repo, "foo(bar): quux", "new_file");
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("baz: \n");
+
+    // Not cached => regular lookup
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(dir.path().join("other_cache.bin"))
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("bar: \nbaz: \n");
+}
+
+/// Read-only cache is never modified, stale data is handled per config
+#[test]
+fn cache_read_only() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let cache_path = dir.path().join("cache.bin");
+    let repo = setup_repo_with_commits(&repo_path, &["init", "foo(baz): quux"]);
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .success();
+    let cache = std::fs::read(&cache_path).unwrap();
+
+    // Cache is stale now
+    add_commit(&repo, "foo(bar): quux", "new_file");
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .arg("--cache-read-only")
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout(contains("bar: "));
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .arg("--cache-read-only")
+        .args(["cache", "nuke"])
+        .assert()
+        .code(73)
+        .stderr(contains("Cache is read-only"));
+
+    assert_eq!(std::fs::read(&cache_path).unwrap(), cache);
+}
+
+/// Scope list can be paginated
+#[test]
+fn scope_pagination() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(
+        dir.path(),
+        &["init", "feat(a): one", "feat(b): two", "feat(c): three"],
+    );
+
+    cargo_bin_cmd!()
+        .args(["scope", "--offset", "1", "--limit", "1"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout("b: \n");
+
+    cargo_bin_cmd!()
+        .args(["scope", "--offset", "2", "--json"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(contains(r#"[{"name":"c""#));
+}
+
 /// Ensures that whatever changes I make, `--help` will print usage info
 #[test]
 fn test_help_message() {
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
 
     cmd.arg("--help");
 
@@ -279,14 +608,42 @@ fn test_json_output() {
     mk_config_with_types_only(dir.path());
 
     // Setup command
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("type");
     cmd.arg("--json");
     cmd.current_dir(dir.path());
 
-    cmd.assert()
+    cmd.assert().success().stdout(contains(
+        r#"{"name":"foo","description":"bar","source":"config"}"#,
+    ));
+}
+
+/// Nonstandard types used in the history are listed after the default ones
+#[test]
+fn types_from_history() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits(
+        dir.path(),
+        &["init", "wip: a", "feat(foo): b", "wip(foo): c"],
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["type", "--json"])
+        .assert()
         .success()
-        .stdout(contains(r#"{"name":"foo","description":"bar"}"#));
+        .stdout(
+            contains(r#"{"name":"feat","description":"A new feature","source":"default"}"#).and(
+                contains(r#"{"name":"wip","description":"","source":"history"}]"#),
+            ),
+        );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("lint")
+        .write_stdin("wip: more\n")
+        .assert()
+        .success();
 }
 
 /// Check failure if running against something other than a git repo
@@ -294,23 +651,71 @@ fn test_json_output() {
 fn test_not_a_git_repo() {
     let dir = assert_fs::TempDir::new().unwrap();
 
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.current_dir(dir.path());
     cmd.arg("type");
     cmd.assert().failure();
 }
 
-/// Check that bare repo does not work
+/// Bare repo is read-only: types and scopes (with the committed config) work, staged files
+/// features do not
 #[test]
 fn test_bare_repo() {
     let dir = assert_fs::TempDir::new().unwrap();
-    let repo_path = dir.path();
-    git2::Repository::init_bare(repo_path).unwrap();
+    let origin_path = dir.path().join("origin");
+    let bare_path = dir.path().join("bare.git");
+    let origin = setup_repo_with_commits_and_files(
+        &origin_path,
+        &["init", "feat(foo): a", "fix(bar): b"],
+        &["init", "one", "two"],
+    );
+    setup_config_file_in_path(
+        &origin_path,
+        "[scopes]
+baz = \"Baz\"\n",
+    );
+    let mut index = origin.index().unwrap();
+    index
+        .add_path(Path::new(".dev/conventional-commit-helper.toml"))
+        .unwrap();
+    let tree = origin.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("nobody", "nobody@example.com").unwrap();
+    let head = origin.head().unwrap().peel_to_commit().unwrap();
+    origin
+        .commit(Some("HEAD"), &sig, &sig, "chore: config", &tree, &[&head])
+        .unwrap();
+    git2::build::RepoBuilder::new()
+        .bare(true)
+        .clone(origin_path.to_str().unwrap(), &bare_path)
+        .unwrap();
+    let run = |args: &[&str]| {
+        cargo_bin_cmd!()
+            .env("XDG_CACHE_HOME", dir.path())
+            .arg("--repo-path")
+            .arg(&bare_path)
+            .args(args)
+            .assert()
+    };
+
+    run(&["type"]).success().stdout(contains("feat"));
+    run(&["scope"])
+        .success()
+        .stdout(contains("bar: \n").and(contains("baz: Baz\n")));
+    run(&["cache", "create"]).success();
+    run(&["scope", "--no-suggest"])
+        .success()
+        .stdout("bar: \nbaz: Baz\nfoo: \n");
 
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
-    cmd.arg("--repo-path").arg(repo_path);
-    cmd.arg("type");
-    cmd.assert().failure();
+    run(&["changelog", "--title", "1.0.0"]).success().stdout(
+        starts_with("## 1.0.0")
+            .and(contains("a"))
+            .and(contains("b")),
+    );
+
+    run(&["suggest"])
+        .code(66)
+        .stderr(contains("Bare repositories have no working tree"));
+    run(&["type", "--suggest"]).code(66);
 }
 
 /// Nonexistent config file should lead to an error
@@ -319,7 +724,7 @@ fn test_invalid_config_path() {
     let dir = assert_fs::TempDir::new().unwrap();
     let _ = setup_repo_with_commits(dir.path(), &["init"]);
 
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("--config").arg("non-existent-file.toml");
     cmd.arg("type");
     cmd.current_dir(dir.path());
@@ -335,7 +740,7 @@ fn test_malformed_config_file() {
     let config_path = dir.path().join("config.toml");
     std::fs::write(&config_path, "not a valid toml file").unwrap();
 
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("--config").arg(&config_path);
     cmd.arg("type");
     cmd.current_dir(dir.path());
@@ -354,7 +759,7 @@ fn test_repo_path_argument() {
     mk_config_with_types_only(&repo_path);
 
     // Setup command
-    let mut cmd = Command::cargo_bin(BIN_NAME).unwrap();
+    let mut cmd = cargo_bin_cmd!();
     cmd.arg("--repo-path").arg(&repo_path);
     cmd.arg("type");
 
@@ -362,6 +767,189 @@ fn test_repo_path_argument() {
 
     cmd.assert().success().stdout(contains("foo"));
 }
+/// Reviewers are looked up for the scope suggested from the staged files
+#[test]
+fn reviewers_for_suggested_scope() {
+    init_logger();
+
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "foo(z_bar): quux", "foo(baz): quux"],
+        &["init", "one", "two"],
+    );
+    setup_config_file_in_path(
+        dir.path(),
+        indoc! {r#"
+            [reviewers]
+            z_bar = ["@alice"]
+            baz = ["@bob"]
+            "#},
+    );
+
+    // Nothing staged -- nothing suggested
+    cargo_bin_cmd!()
+        .arg("reviewers")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout("");
+
+    let mut index = repo.index().unwrap();
+    std::fs::write(dir.join("one"), "test writing").unwrap();
+    let _ = index.add_path(Path::new("one"));
+    let _ = index.write();
+
+    cargo_bin_cmd!()
+        .arg("reviewers")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout("@alice\n");
+
+    cargo_bin_cmd!()
+        .args(["reviewers", "--scope", "baz"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout("@bob\n");
+}
+
+/// Changelog can be narrowed down to a single scope
+#[test]
+fn changelog_for_scope() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits(
+        dir.path(),
+        &[
+            "init",
+            "feat(foo): one",
+            "feat(bar): two",
+            "fix(foo): three",
+        ],
+    );
+
+    cargo_bin_cmd!()
+        .args(["changelog", "--scope", "foo", "--title", "foo-1.0.0"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(starts_with("## foo-1.0.0"))
+        .stdout(contains("one"))
+        .stdout(contains("three"))
+        .stdout(contains("two").not());
+}
+
+/// Files associated with a scope are printed, unknown scopes are reported
+#[test]
+fn scope_files() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits_and_files(
+        dir.path(),
+        &[
+            "init",
+            "feat(foo): one",
+            "feat(bar): two",
+            "fix(foo): three",
+        ],
+        &["init", "a", "b", "c"],
+    );
+
+    cargo_bin_cmd!()
+        .args(["scope", "files", "foo"])
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout("a\nc\n");
+
+    cargo_bin_cmd!()
+        .args(["scope", "files", "baz"])
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("\"baz\" was not found"));
+}
+
+/// Package names of the JS workspaces are offered and suggested for the files of the package
+#[test]
+fn js_workspace_scopes() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init", "Add the packages"]);
+    let mut index = repo.index().unwrap();
+    for (file, content) in [
+        ("package.json", r#"{"workspaces": ["packages/*"]}"#),
+        (
+            "packages/ui/package.json",
+            r#"{"name": "@acme/ui", "description": "Components"}"#,
+        ),
+        ("packages/api/package.json", r#"{"name": "@acme/api"}"#),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        index.add_path(Path::new(file)).unwrap();
+    }
+    index.write().unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("api: \nui: Components\n");
+
+    std::fs::write(dir.path().join("packages/ui/button.js"), "").unwrap();
+    index.add_path(Path::new("packages/ui/button.js")).unwrap();
+    index.write().unwrap();
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "--json"])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            r#"[{"name":"ui","description":"Components","suggested":true,"source":"inferred-path""#,
+        ));
+}
+
+/// Unknown subcommands are dispatched to executables on PATH
+#[cfg(unix)]
+#[test]
+fn external_plugin() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits(dir.path(), &["init"]);
+    let bin_dir = dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    let plugin = bin_dir.join("conventional-commit-helper-echo");
+    // Echoes the context back
+    std::fs::write(&plugin, "#!/bin/sh\ncat\n").unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    cargo_bin_cmd!()
+        .args(["echo", "--foo"])
+        .env("PATH", &path)
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(contains(r#""args":["--foo"]"#));
+
+    cargo_bin_cmd!()
+        .args(["nonexistent"])
+        .env("PATH", &path)
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(contains(
+            "no \"conventional-commit-helper-nonexistent\" plugin",
+        ));
+}
+
 // Ensure logger is initialized only once for all tests
 static INIT: Once = Once::new();
 
@@ -374,3 +962,1063 @@ fn init_logger() {
             .init();
     });
 }
+
+/// Broken config is reported with its location and a dedicated exit code
+#[test]
+fn invalid_config() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init"]);
+    setup_config_file_in_path(dir.path(), "[types]\nfeat = 1\n");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("type")
+        .assert()
+        .code(78)
+        .stderr(contains("conventional-commit-helper.toml\" at line 2"));
+}
+
+/// Labels are added to the existing config, keeping what's there
+#[test]
+fn import_github_labels() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init"]);
+    let config_path = setup_config_file_in_path(
+        dir.path(),
+        indoc! {r#"
+            # Keep me
+            [scopes]
+            cli = "Existing description"
+            "#},
+    );
+    let labels = dir.path().join("labels.json");
+    std::fs::write(
+        &labels,
+        r#"[{"name":"area/cli","description":"CLI"},{"name":"area/cache","description":"Cache"},{"name":"bug","description":""}]"#,
+    )
+    .unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args([
+            "config",
+            "import",
+            "github-labels",
+            "--write",
+            "--from-file",
+        ])
+        .arg(&labels)
+        .assert()
+        .success()
+        .stdout(contains("Added 1 scope(s)").and(contains("cache: Cache")));
+
+    let config = std::fs::read_to_string(config_path).unwrap();
+    assert!(config.starts_with("# Keep me"), "{}", config);
+    assert!(config.contains(r#"cli = "Existing description""#));
+    assert!(config.contains(r#"cache = "Cache""#));
+}
+
+/// Bundle exported from one checkout seeds another one
+#[test]
+fn export_import_bundle() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    let _repo =
+        setup_repo_with_commits_and_files(&source, &["init", "feat(foo): a"], &["init", "one"]);
+    mk_config_with_scopes_only(&source);
+    let target = dir.path().join("target");
+    git2::Repository::clone(source.to_str().unwrap(), &target).unwrap();
+    let bundle = dir.path().join("bundle.json");
+
+    cargo_bin_cmd!()
+        .current_dir(&source)
+        .args(["export", "bundle"])
+        .arg(&bundle)
+        .assert()
+        .success();
+
+    cargo_bin_cmd!()
+        .current_dir(&target)
+        .arg("--cache-path")
+        .arg(dir.path().join("cache.bin"))
+        .args(["import", "bundle"])
+        .arg(&bundle)
+        .assert()
+        .success()
+        .stdout(contains("Added 1 scope(s)").and(contains("Cache seeded")));
+
+    cargo_bin_cmd!()
+        .current_dir(&target)
+        .arg("--cache-path")
+        .arg(dir.path().join("cache.bin"))
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout(contains("foo").and(contains("foz: baz")));
+}
+
+/// Stale cache is served as is while another process regenerates it
+#[test]
+fn concurrent_regeneration() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init", "feat(scope1): message"]);
+    let cache_path = dir.path().join("cache.bin");
+    let config = conventional_commit_helper::config::with_cache_overrides(
+        None,
+        Some(cache_path.clone()),
+        false,
+    );
+    conventional_commit_helper::cache::create_cache(&config).unwrap();
+    conventional_commit_helper::cache::update_cache_for_repo(&repo, &config).unwrap();
+    add_commit(&repo, "feat(scope2): message", "new_file");
+
+    let clone_path = dir.path().join("clone");
+    git2::Repository::clone(dir.path().to_str().unwrap(), &clone_path).unwrap();
+
+    // Locks are per process, the test acts as the other process
+    let cache = conventional_commit_helper::Cache::load(&cache_path).unwrap();
+    let lock = conventional_commit_helper::cache::try_lock_regeneration(&cache, &repo, &config)
+        .unwrap()
+        .unwrap();
+
+    // Another checkout of the same repository shares the entry and the lock
+    for path in [dir.path(), &clone_path] {
+        cargo_bin_cmd!()
+            .current_dir(path)
+            .arg("--cache-path")
+            .arg(&cache_path)
+            .arg("scope")
+            .assert()
+            .success()
+            .stdout(contains("scope1").and(contains("scope2").not()));
+    }
+
+    drop(lock);
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout(contains("scope2"));
+}
+
+/// JSON output carries the warnings instead of stderr
+#[test]
+fn json_warnings() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    setup_repo_with_commits(dir.path(), &["init", "feat(foo): a"]);
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["-v", "--json-compat", "4", "scope", "--json"])
+        .assert()
+        .success()
+        .stdout(
+            r#"{"items":[{"name":"foo","description":"","suggested":false,"source":"history"}],"warnings":[{"kind":"history_slow","message":"Git history scope lookups are a bit slow. Consider using the cache (see --help)"}]}"#
+                .to_string()
+                + "\n",
+        )
+        .stderr(contains("a bit slow").not());
+    // Any version of the items, without the version pin
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["--json-compat", "1", "--json-warnings", "scope", "--json"])
+        .assert()
+        .success()
+        .stdout(
+            r#"{"items":[{"name":"foo","description":""}],"warnings":[{"kind":"history_slow","message":"Git history scope lookups are a bit slow. Consider using the cache (see --help)"}]}"#
+                .to_string()
+                + "\n",
+        );
+    // Lists can't carry them
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["-v", "scope", "--json"])
+        .assert()
+        .success()
+        .stdout(starts_with("[").and(contains("warnings").not()))
+        .stderr(contains("a bit slow"));
+}
+
+/// Warnings the output can't carry are logged on the failures too
+#[test]
+fn warnings_on_failure() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    setup_repo_with_commits(dir.path(), &["init", "feat(foo): a"]);
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["-v", "lint", "--json"])
+        .write_stdin("feat(bar): b")
+        .assert()
+        .code(1)
+        .stdout(contains("unknown_scope"))
+        .stderr(contains("a bit slow"));
+}
+
+/// Cache updates wait for the other process holding the lock and give up after the timeout
+#[test]
+fn cache_lock_timeout() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    setup_repo_with_commits(dir.path(), &["init", "feat(scope1): message"]);
+    let cache_path = dir.path().join("cache.bin");
+    setup_config_file_in_path(dir.path(), "[cache]\nlock_timeout_ms = 100\n");
+    let config = conventional_commit_helper::config::with_cache_overrides(
+        None,
+        Some(cache_path.clone()),
+        false,
+    );
+    conventional_commit_helper::cache::create_cache(&config).unwrap();
+
+    // Locks are per process, the test acts as the other process
+    let lock = conventional_commit_helper::cache::Cache::lock(&cache_path, &config).unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "update"])
+        .assert()
+        .code(75)
+        .stderr(contains("gave up after 100 ms"));
+
+    drop(lock);
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "update"])
+        .assert()
+        .success();
+}
+
+/// Without a terminal the commit is composed from the options only
+#[test]
+fn commit_from_options() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init", "feat(foo): a"]);
+    let mut git_config = repo.config().unwrap();
+    git_config.set_str("user.name", "nobody").unwrap();
+    git_config
+        .set_str("user.email", "nobody@example.com")
+        .unwrap();
+    std::fs::write(dir.path().join("new_file"), "content").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("new_file")).unwrap();
+    index.write().unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["commit", "--type", "bogus", "-m", "nope"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown type"));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["commit", "--type", "feat", "--scope", "foo", "--breaking"])
+        .args(["-m", "add new file", "--body", "Details"])
+        .assert()
+        .success()
+        .stdout(contains("feat(foo)!: add new file"));
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(
+        head.message(),
+        Some("feat(foo)!: add new file\n\nDetails\n")
+    );
+}
+
+/// `--print-only` and `--output-file` emit the message, nothing is committed
+#[test]
+fn commit_print_only() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init", "feat(foo): a"]);
+    let head = repo.head().unwrap().target().unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["commit", "--type", "feat", "--scope", "foo", "--print-only"])
+        .args(["-m", "add new file", "--body", "Details"])
+        .assert()
+        .success()
+        .stdout("feat(foo): add new file\n\nDetails\n");
+
+    let output_file = dir.path().join("message");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["commit", "--type", "feat", "-m", "add new file"])
+        .arg("--output-file")
+        .arg(&output_file)
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(
+        std::fs::read_to_string(&output_file).unwrap(),
+        "feat: add new file\n"
+    );
+
+    assert_eq!(repo.head().unwrap().target().unwrap(), head);
+}
+
+/// Messages are read from stdin, files (as a commit-msg hook would pass them) and HEAD
+#[test]
+fn lint() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init", "feat(foz): a"]);
+    mk_config_with_scopes_only(dir.path());
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("lint")
+        .write_stdin("feat(foz): fine\n\nRefs: #1\n")
+        .assert()
+        .success()
+        .stdout("");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["lint", "--json"])
+        .write_stdin("feat(qux): unknown scope\nno blank line\n")
+        .assert()
+        .code(1)
+        .stdout(contains(r#""kind":"unknown_scope""#).and(contains("no_blank_line_after_subject")));
+
+    let message = dir.path().join("COMMIT_EDITMSG");
+    std::fs::write(
+        &message,
+        "feat: fine\n# Please enter the commit message for your changes.\n",
+    )
+    .unwrap();
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("lint")
+        .arg(&message)
+        .assert()
+        .success();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["lint", "--head"])
+        .assert()
+        .success();
+}
+
+/// Deprecated scopes are not listed, `lint` points to the replacement and `commit` warns
+#[test]
+fn deprecated_scopes() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init", "feat(cfg): a", "fix(core): b"]);
+    setup_config_file_in_path(
+        dir.path(),
+        indoc! {r#"
+            [general.scopes]
+            deprecated = { cfg = "config" }
+            "#},
+    );
+    let mut git_config = repo.config().unwrap();
+    git_config.set_str("user.name", "nobody").unwrap();
+    git_config
+        .set_str("user.email", "nobody@example.com")
+        .unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("config: \ncore: \n");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["lint", "--json"])
+        .write_stdin("feat(cfg): old scope\n")
+        .assert()
+        .code(1)
+        .stdout(contains(
+            r#"{"kind":"deprecated_scope","scope":"cfg","replacement":"config"}"#,
+        ));
+
+    stage(&repo, "new_file");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args([
+            "-v",
+            "commit",
+            "--type",
+            "feat",
+            "--scope",
+            "cfg",
+            "-m",
+            "old scope",
+        ])
+        .assert()
+        .success()
+        .stderr(contains("Scope \"cfg\" is deprecated, use \"config\""));
+}
+
+/// Installed prepare-commit-msg hook pre-populates the message with the suggested scope
+#[test]
+fn hook_install_prepare_commit_msg() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "fix(foo): a", "feat(bar): b"],
+        &["init", "one", "two"],
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["hook", "install", "--commit-msg"])
+        .assert()
+        .success()
+        .stdout(contains("prepare-commit-msg").and(contains("commit-msg")));
+    let hook = dir.path().join(".git/hooks/prepare-commit-msg");
+    assert!(std::fs::read_to_string(&hook)
+        .unwrap()
+        .contains("hook prepare-commit-msg"));
+
+    std::fs::write(dir.path().join("one"), "changed").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("one")).unwrap();
+    index.write().unwrap();
+    let message = dir.path().join(".git/COMMIT_EDITMSG");
+    std::fs::write(&message, "\n# Please enter the commit message\n").unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["hook", "prepare-commit-msg"])
+        .arg(&message)
+        .assert()
+        .success();
+    let written = std::fs::read_to_string(&message).unwrap();
+    assert!(written.starts_with("fix(foo): \n"), "{}", written);
+    assert!(
+        written.ends_with("# Scopes:\n#\tfoo\n#\tbar\n"),
+        "{}",
+        written
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["hook", "uninstall"])
+        .assert()
+        .success();
+    assert!(!hook.exists());
+}
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{}.json", name))
+}
+
+fn stage(repo: &git2::Repository, file: &str) {
+    std::fs::write(repo.workdir().unwrap().join(file), "changed").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(file)).unwrap();
+    index.write().unwrap();
+}
+
+/// Branches merged into each other, an octopus merge and a deleted file
+#[test]
+fn fixture_merge_storm() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = load_fixture(dir.path(), &fixture("merge_storm"));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("stats")
+        .assert()
+        .success()
+        .stdout(
+            contains("api: 7\n")
+                .and(contains("ui: 6\n"))
+                .and(contains("docs: 3\n")),
+        );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "files", "api"])
+        .assert()
+        .success()
+        .stdout(contains("api/errors.rs").and(contains("api/route5.rs")));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "for-file", "ui/page3.tsx"])
+        .assert()
+        .success()
+        .stdout("ui/page3.tsx: ui\n");
+}
+
+/// Hundreds of commits in a single scope do not drown the others
+#[test]
+fn fixture_deep_history() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = load_fixture(dir.path(), &fixture("deep_history"));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["stats", "--csv"])
+        .assert()
+        .success()
+        .stdout(contains("core,201,1\n").and(contains("cli,50,0\n")));
+
+    stage(&repo, "cli/args.rs");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout(starts_with("cli"));
+}
+
+/// Scope covering thousands of files
+#[test]
+fn fixture_huge_file_count() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = load_fixture(dir.path(), &fixture("huge_file_count"));
+
+    let output = cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "files", "generated"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().lines().count(),
+        3000
+    );
+
+    stage(&repo, "small/mod.rs");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout(starts_with("small"));
+}
+
+/// Headers are completed from the config and the cache, the history is not looked at
+#[test]
+fn completions_query() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let cache_path = dir.path().join("cache.bin");
+    let repo = setup_repo_with_commits(&repo_path, &["init", "foo(baz): quux"]);
+    mk_config_with_scopes_only(&repo_path);
+    let query = |word: &str| {
+        cargo_bin_cmd!()
+            .arg("--repo-path")
+            .arg(&repo_path)
+            .arg("--cache-path")
+            .arg(&cache_path)
+            .args(["completions", "--query", word])
+            .assert()
+            .success()
+    };
+
+    // No cache yet, only the config scopes
+    query("feat(").stdout("feat(foz)\n");
+    query("fi").stdout("fix\n");
+
+    cargo_bin_cmd!()
+        .arg("--repo-path")
+        .arg(&repo_path)
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .success();
+    add_commit(&repo, "foo(bar): quux", "new_file");
+
+    query("feat(ba").stdout("feat(baz)\n");
+
+    cargo_bin_cmd!()
+        .args(["completions", "fish"])
+        .assert()
+        .success()
+        .stdout(contains("completions --query"));
+}
+
+/// Repositories without any scopes get the fallback ones, in the completions too
+#[test]
+fn fallback_scopes() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    setup_repo_with_commits(dir.path(), &["init"]);
+    setup_config_file_in_path(
+        dir.path(),
+        "[general]\nscopes.fallback = [\"core\", \"deps\"]\n",
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout("core: \ndeps: \n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["completions", "--query", "feat(d"])
+        .assert()
+        .success()
+        .stdout("feat(deps)\n");
+}
+
+/// Scopes missing from the config are printed as a snippet to paste into it
+#[test]
+fn audit_scopes() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits(dir.path(), &["init", "foo(qux): quux"]);
+    mk_config_with_scopes_only(dir.path());
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["audit", "scopes"])
+        .assert()
+        .success()
+        .stdout(contains("missing from the config:\n  qux\n"))
+        .stdout(contains("never used in the history:\n  foz\n"))
+        .stdout(contains("[scopes]\nqux = \"\"\n"));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["audit", "scopes", "--json"])
+        .assert()
+        .success()
+        .stdout(starts_with(r#"{"undeclared":["qux"],"unused":["foz"]"#));
+}
+
+/// `--template` implies `--format template`, other formats can't have a template
+#[test]
+fn format_template() {
+    cargo_bin_cmd!()
+        .args(["--template", "{name}:{description}"])
+        .assert()
+        .success()
+        .stdout(contains("feat:A new feature\n"));
+
+    cargo_bin_cmd!()
+        .args(["--format", "template"])
+        .assert()
+        .failure()
+        .stderr(contains("requires --template"));
+
+    cargo_bin_cmd!()
+        .args(["--format", "json", "--template", "{name}"])
+        .assert()
+        .failure();
+
+    cargo_bin_cmd!()
+        .args(["--template", "{nope}"])
+        .assert()
+        .failure()
+        .stderr(contains("available: name, description"));
+}
+
+/// Values are printed in the file notation, strings as is. Unset keys exit with 1
+#[test]
+fn config_get_set() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _ = setup_repo_with_commits(dir.path(), &["init"]);
+    mk_config_with_scopes_only(dir.path());
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "get", "cache.regenerate_on_stale"])
+        .assert()
+        .success()
+        .stdout("always\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "get", "general.scopes.disable_history_search"])
+        .assert()
+        .code(1)
+        .stdout("");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args([
+            "config",
+            "set",
+            "general.scopes.disable_history_search",
+            "true",
+        ])
+        .assert()
+        .success();
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "get", "general.scopes.disable_history_search"])
+        .assert()
+        .success()
+        .stdout("true\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "get", "scopes", "--format", "json"])
+        .assert()
+        .success()
+        .stdout("{\"foz\":\"baz\"}\n");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["config", "set", "cache.regenrate_on_stale", "never"])
+        .assert()
+        .code(78)
+        .stderr(contains("unknown key"));
+}
+
+/// `--detailed` adds the raw match data, scopes never used get zeros
+#[test]
+fn scope_detailed() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "foo(baz): quux", "foo(baz): quuz"],
+        &["init", "one", "two"],
+    );
+    mk_config_with_scopes_only(dir.path());
+    stage(&repo, "one");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "--json", "--detailed"])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            r#"[{"name":"baz","description":"","suggested":true,"source":"history","details":{"commits":2,"last_used":"#,
+        ))
+        .stdout(contains(
+            r#""staged_files":1,"declared_files":0,"overlap":1,"weighted_overlap":1,"shared_dirs":0,"files":2}}"#,
+        ))
+        .stdout(contains(
+            r#"{"name":"foz","description":"baz","suggested":false,"source":"config","details":{"commits":0,"last_used":null,"staged_files":1,"declared_files":0,"overlap":0,"weighted_overlap":0,"shared_dirs":0,"files":0}}"#,
+        ));
+}
+
+#[test]
+fn type_suggest() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(dir.path(), &["init"], &["init"]);
+    stage(&repo, "README.md");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["type", "--suggest", "--json"])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            r#"[{"name":"docs","description":"Documentation only changes","suggested":true,"source":"default"},"#,
+        ))
+        .stdout(contains(r#"{"name":"feat","#));
+
+    // Source files have no rule, the order is the usual one
+    stage(&repo, "main.rs");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["type", "--suggest", "--format", "tsv"])
+        .assert()
+        .success()
+        .stdout(starts_with("feat\t"))
+        .stdout(contains("\tfalse\tdefault\n").and(contains("\ttrue\t").not()));
+}
+
+#[test]
+fn suggest_prefix() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &[
+            "init",
+            "fix(baz): quux",
+            "feat(baz): quuz",
+            "fix(baz): corge",
+        ],
+        &["init", "one", "one", "one"],
+    );
+    stage(&repo, "one");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("suggest")
+        .assert()
+        .success()
+        .stdout("fix(baz):\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["suggest", "--json"])
+        .assert()
+        .success()
+        .stdout(
+            r#"{"prefix":"fix(baz): ","type":"fix","scope":"baz","confidence":0.67,"alternatives":[],"warnings":[{"kind":"history_slow","message":"Git history scope lookups are a bit slow. Consider using the cache (see --help)"}]}"#
+                .to_string()
+                + "\n",
+        );
+}
+
+/// Freshly `git init`ed project: scopes from the config, the one matching the staged files by
+/// `scope_paths` is suggested
+#[test]
+fn zero_commits() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    setup_config_file_in_path(
+        dir.path(),
+        indoc! {r#"
+            [scopes]
+            core = "Core"
+
+            [scope_paths]
+            core = ["*.rs"]
+
+            [suggest]
+            default_type = "feat"
+        "#},
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("scope")
+        .assert()
+        .success()
+        .stdout("core: Core\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("suggest")
+        .assert()
+        .success()
+        .stdout("feat:\n");
+
+    stage(&repo, "main.rs");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["scope", "--json"])
+        .assert()
+        .success()
+        .stdout(contains(
+            r#""name":"core","description":"Core","suggested":true"#,
+        ));
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["suggest", "--json"])
+        .assert()
+        .success()
+        .stdout(contains(
+            r#""prefix":"feat(core): ","type":"feat","scope":"core","confidence":0.0"#,
+        ));
+}
+
+#[test]
+fn suggest_prefix_from_type_rules() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits(dir.path(), &["init"]);
+
+    // Nothing staged, no history to pick the type from
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("suggest")
+        .assert()
+        .code(1)
+        .stdout("");
+
+    stage(&repo, "README.md");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("suggest")
+        .assert()
+        .success()
+        .stdout("docs:\n");
+}
+
+#[test]
+fn lint_translated() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init", "feat(foz): a"]);
+    setup_config_file_in_path(
+        dir.path(),
+        indoc! {r#"
+            [scopes]
+            foz = "baz"
+
+            [i18n.messages.fr]
+            unknown_scope = "Portée inconnue {scope}"
+        "#},
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["--lang", "de", "lint"])
+        .write_stdin("feat(qux): unknown scope\n")
+        .assert()
+        .code(1)
+        .stdout("Unbekannter Bereich \"qux\"\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["--lang", "fr", "lint"])
+        .write_stdin("feat(qux): unknown scope\nno blank line\n")
+        .assert()
+        .code(1)
+        .stdout("Portée inconnue \"qux\"\nSubject should be followed by a blank line\n");
+}
+
+#[test]
+fn export_other_tools() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init", "feat(foo): a"]);
+    mk_config_full(dir.path());
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["export", "cocogitto"])
+        .assert()
+        .success()
+        .stdout(indoc! {r#"
+            scopes = ["foo", "foz"]
+
+            [commit_types.foo]
+            changelog_title = "bar"
+        "#});
+
+    let file = dir.path().join(".commitlintrc.json");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["export", "commitlint"])
+        .arg(&file)
+        .assert()
+        .success();
+    let exported: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+    assert_eq!(
+        exported["rules"]["type-enum"],
+        serde_json::json!([2, "always", ["foo"]])
+    );
+    assert_eq!(
+        exported["rules"]["scope-enum"],
+        serde_json::json!([2, "always", ["foo", "foz"]])
+    );
+}
+
+#[test]
+fn scope_since_latest_tag() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "feat(old): a", "feat(new): b"],
+        &["init", "one", "two"],
+    );
+    let released = repo.revparse_single("HEAD~1").unwrap();
+    repo.tag_lightweight("v1.0.0", &released, false).unwrap();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["--since-ref", "latest-tag", "scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("new: \n");
+}
+
+/// Large repositories (here: any) are not mined on every run, only into the cache
+#[test]
+fn scope_cache_required() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits(dir.path(), &["init", "foo(baz): quux"]);
+    setup_config_file_in_path(
+        dir.path(),
+        "[general.scopes]\ncache_required_threshold = 1\n",
+    );
+    let cache_path = dir.path().join("cache.bin");
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .code(66)
+        .stderr(contains("cache create"));
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["cache", "create"])
+        .assert()
+        .success();
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("--cache-path")
+        .arg(&cache_path)
+        .args(["scope", "--no-suggest"])
+        .assert()
+        .success()
+        .stdout("baz: \n");
+}
+
+#[test]
+fn preflight() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "fix(baz): quux"],
+        &["init", "one"],
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("preflight")
+        .assert()
+        .code(1)
+        .stdout(contains(r#""kind":"nothing_staged""#));
+
+    stage(&repo, "one");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("preflight")
+        .assert()
+        .success()
+        .stdout(contains(
+            r##""comments":["# Suggested: fix(baz): (confidence 1)"]"##,
+        ));
+
+    setup_config_file_in_path(dir.path(), "[preflight]\nmax_file_size = 1\n");
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .arg("preflight")
+        .assert()
+        .code(1)
+        .stdout(contains(r#""kind":"size""#));
+}
+
+#[test]
+fn export_graph() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let _repo = setup_repo_with_commits_and_files(
+        dir.path(),
+        &["init", "feat(foo): a", "feat(bar): b"],
+        &["init", "one", "two"],
+    );
+
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["export", "graph"])
+        .assert()
+        .success()
+        .stdout(contains(r#""scope:foo" -- "dir:." [label="1"];"#));
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["export", "graph", "--format", "mermaid"])
+        .assert()
+        .success()
+        .stdout(starts_with("graph LR\n").and(contains("class d0 shared")));
+    cargo_bin_cmd!()
+        .current_dir(dir.path())
+        .args(["type", "--format", "dot"])
+        .assert()
+        .failure();
+}
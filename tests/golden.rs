@@ -0,0 +1,196 @@
+//! Golden tests for the output of the subcommands.
+//!
+//! Every case runs the binary against the same fixture repo and compares the output with
+//! `tests/golden/<case>.txt`. Commit hashes, dates and the path of the repo are replaced with
+//! placeholders as they change on every run. Commands changing the state of the repo or the
+//! cache (`cache`, `hook`) get the commands to run before them.
+//!
+//! If an output change is intended, regenerate the files and review the diff:
+//!
+//! ```shell
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+use assert_cmd::cargo::cargo_bin_cmd;
+use conventional_commit_helper::test_utils::{
+    setup_config_file_in_path, setup_repo_with_commits_and_files,
+};
+use fancy_regex::Regex;
+use indoc::indoc;
+use rstest::{fixture, rstest};
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = indoc! {r#"
+    [scopes]
+    cli = "Command line interface"
+
+    [reviewers]
+    cache = ["@alice"]
+    "#};
+
+/// Repo the commands are run in. Kept alive for the duration of the test
+#[fixture]
+fn repo() -> assert_fs::TempDir {
+    let dir = assert_fs::TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("src")).unwrap();
+    setup_repo_with_commits_and_files(
+        dir.path(),
+        &[
+            "init",
+            "feat(cache): add the cache",
+            "fix(cache): do not crash on a missing cache\n\nRefs: #12",
+            "feat(cli)!: rename the flags",
+            "docs: describe the flags",
+        ],
+        &[
+            "init",
+            "src/cache.rs",
+            "src/cache.rs",
+            "src/main.rs",
+            "README.md",
+        ],
+    );
+    setup_config_file_in_path(dir.path(), CONFIG);
+
+    dir
+}
+
+/// Hashes, dates and the temporary repo differ between runs
+fn normalize(output: &str, repo: &Path) -> String {
+    let hash = Regex::new(r"\b[0-9a-f]{7,40}\b").unwrap();
+    let date = Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap();
+    let time = Regex::new(r"\d{2}:\d{2}:\d{2}").unwrap();
+
+    let output = output.replace(&repo.to_string_lossy().into_owned(), "<repo>");
+    let output = hash.replace_all(&output, "<hash>");
+    let output = date.replace_all(&output, "<date>");
+    time.replace_all(&output, "<time>").into_owned()
+}
+
+/// Runs the binary in the repo, fails the test if the command fails
+fn run(repo: &Path, args: &[&str]) -> String {
+    let output = cargo_bin_cmd!()
+        .current_dir(repo)
+        // Isolate from the global config and cache
+        .env("XDG_CONFIG_HOME", repo.join("config"))
+        .env("XDG_CACHE_HOME", repo.join("cache"))
+        .env("USER", "alice")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Compares the output with the golden file, or writes it with UPDATE_GOLDEN
+fn assert_golden(case: &str, args: &[&str], actual: &str) {
+    let path = golden_path(case);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "{:?} does not exist, generate it with UPDATE_GOLDEN=1",
+            path
+        )
+    });
+    assert_eq!(
+        actual, expected,
+        "Output of {:?} changed. If that's intended, regenerate the golden files with UPDATE_GOLDEN=1",
+        args
+    );
+}
+
+fn golden_path(case: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", case))
+}
+
+#[rstest]
+#[case::types("types", &["type"])]
+#[case::types_json("types_json", &["type", "--json"])]
+#[case::scopes("scopes", &["scope"])]
+#[case::scopes_json("scopes_json", &["scope", "--json"])]
+#[case::scopes_json_compat("scopes_json_compat", &["--json-compat", "1", "scope", "--json"])]
+#[case::types_yaml("types_yaml", &["type", "--format", "yaml"])]
+#[case::scopes_tsv("scopes_tsv", &["scope", "--format", "tsv"])]
+#[case::scopes_template("scopes_template", &["scope", "--template", "{name}\\t{description}"])]
+#[case::scope_for_file_yaml("scope_for_file_yaml", &["--format", "yaml", "scope", "for-file", "src/cache.rs"])]
+#[case::scope_files("scope_files", &["scope", "files", "cache"])]
+#[case::scope_for_file("scope_for_file", &["scope", "for-file", "src/cache.rs"])]
+#[case::log("log", &["log"])]
+#[case::log_json("log_json", &["log", "--json"])]
+#[case::stats("stats", &["stats"])]
+#[case::stats_json("stats_json", &["stats", "--json"])]
+#[case::stats_heatmap_csv("stats_heatmap_csv", &["stats", "--heatmap", "--csv"])]
+#[case::stats_co_occurrence_csv("stats_co_occurrence_csv", &["stats", "--co-occurrence", "--csv"])]
+#[case::stats_co_occurrence_json("stats_co_occurrence_json", &["stats", "--co-occurrence", "--json"])]
+#[case::changelog("changelog", &["changelog"])]
+#[case::changelog_scope("changelog_scope", &["changelog", "--scope", "cache", "--title", "1.0.0"])]
+#[case::next_version("next_version", &["next-version"])]
+#[case::links("links", &["links"])]
+#[case::links_json("links_json", &["links", "--json"])]
+#[case::reviewers("reviewers", &["reviewers", "--scope", "cache"])]
+#[case::reviewers_json("reviewers_json", &["reviewers", "--scope", "cache", "--json"])]
+#[case::owners("owners", &["owners", "suggest"])]
+fn golden(repo: assert_fs::TempDir, #[case] case: &str, #[case] args: &[&str]) {
+    let actual = normalize(&run(repo.path(), args), repo.path());
+
+    assert_golden(case, args, &actual);
+}
+
+const CACHE_CREATE: &[&str] = &["cache", "create"];
+const HOOK_INSTALL: &[&str] = &["hook", "install", "--commit-msg"];
+
+#[rstest]
+#[case::cache_create("cache_create", &[], CACHE_CREATE)]
+#[case::cache_show("cache_show", &[CACHE_CREATE], &["cache", "show"])]
+#[case::cache_update("cache_update", &[CACHE_CREATE], &["cache", "update"])]
+#[case::cache_update_all("cache_update_all", &[CACHE_CREATE], &["cache", "update-all"])]
+#[case::cache_update_all_json("cache_update_all_json", &[CACHE_CREATE], &["cache", "update-all", "--json"])]
+#[case::cache_prune("cache_prune", &[CACHE_CREATE], &["cache", "prune"])]
+#[case::cache_prune_json("cache_prune_json", &[CACHE_CREATE], &["cache", "prune", "--json"])]
+#[case::cache_drop("cache_drop", &[CACHE_CREATE], &["cache", "drop"])]
+#[case::cache_nuke("cache_nuke", &[CACHE_CREATE], &["cache", "nuke"])]
+#[case::hook_install("hook_install", &[], HOOK_INSTALL)]
+#[case::hook_uninstall("hook_uninstall", &[HOOK_INSTALL], &["hook", "uninstall"])]
+fn golden_after(
+    repo: assert_fs::TempDir,
+    #[case] case: &str,
+    #[case] setup: &[&[&str]],
+    #[case] args: &[&str],
+) {
+    for setup in setup {
+        run(repo.path(), setup);
+    }
+    let actual = normalize(&run(repo.path(), args), repo.path());
+
+    assert_golden(case, args, &actual);
+}
+
+/// The hook writes the message file instead of stdout
+#[rstest]
+fn golden_prepare_commit_msg(repo: assert_fs::TempDir) {
+    std::fs::write(repo.path().join("src/cache.rs"), "changed").unwrap();
+    let git_repo = git2::Repository::open(repo.path()).unwrap();
+    let mut index = git_repo.index().unwrap();
+    index.add_path(Path::new("src/cache.rs")).unwrap();
+    index.write().unwrap();
+    let message = repo.path().join("COMMIT_EDITMSG");
+    std::fs::write(&message, "").unwrap();
+
+    let args = ["hook", "prepare-commit-msg", message.to_str().unwrap()];
+    run(repo.path(), &args);
+    let actual = normalize(&std::fs::read_to_string(&message).unwrap(), repo.path());
+
+    assert_golden("hook_prepare_commit_msg", &args, &actual);
+}
@@ -0,0 +1,72 @@
+//! Latency budget of the interactive use: shell completions and `scope` served from the cache.
+//!
+//! Every case runs the binary with the hidden `--assert-latency-ms` flag against a large fixture
+//! repo (`tests/fixtures/large_repo.json`, 1000 commits in 20 scopes) and fails if the command
+//! took longer than the budget. The history is mined once, when the cache is created.
+//!
+//! The budget is meant for a debug build on a laptop, slower machines can raise it:
+//!
+//! ```shell
+//! LATENCY_BUDGET_MS=1000 cargo test --test latency
+//! ```
+use assert_cmd::cargo::cargo_bin_cmd;
+use conventional_commit_helper::test_utils::load_fixture;
+use predicates::prelude::*;
+use std::path::Path;
+
+use predicate::str::{contains, starts_with};
+
+/// Milliseconds a cached lookup may take
+const BUDGET_MS: u64 = 300;
+
+fn budget() -> String {
+    std::env::var("LATENCY_BUDGET_MS").unwrap_or_else(|_| BUDGET_MS.to_string())
+}
+
+/// Cached lookups on the large repo with a file of the "search" scope staged. The fixture is
+/// replayed once, it takes a while
+#[test]
+fn latency_budget() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo_path = dir.path().join("repo");
+    let repo = load_fixture(
+        &repo_path,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/large_repo.json"),
+    );
+    std::fs::write(repo_path.join("search/part7.rs"), "changed").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("search/part7.rs")).unwrap();
+    index.write().unwrap();
+    cargo_bin_cmd!()
+        .current_dir(&repo_path)
+        .arg("--cache-path")
+        .arg(dir.path().join("cache.bin"))
+        .args(["cache", "create"])
+        .assert()
+        .success();
+    let run = |cache: &str, budget: &str, args: &[&str]| {
+        cargo_bin_cmd!()
+            .current_dir(&repo_path)
+            .arg("--cache-path")
+            .arg(dir.path().join(cache))
+            .args(["--assert-latency-ms", budget])
+            .args(args)
+            .assert()
+    };
+
+    for (args, expected) in [
+        (vec!["scope"], "search"),
+        (vec!["scope", "--json"], "[{\"name\":\"search\""),
+        (vec!["scope", "--no-suggest"], "api"),
+        (vec!["completions", "--query", "fix(se"], "fix(search)"),
+    ] {
+        run("cache.bin", &budget(), &args)
+            .success()
+            .stdout(starts_with(expected));
+    }
+
+    // The check itself: mining the history does not fit into no time
+    run("uncached.bin", "0", &["scope"])
+        .failure()
+        .stderr(contains("more than the latency budget of 0 ms"));
+}